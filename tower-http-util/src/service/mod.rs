@@ -49,7 +49,7 @@ pub trait HttpService<RequestBody>: Sealed<RequestBody> {
     }
 
     /// Same as `into_service` but operates on an HttpService reference.
-    fn as_service(&mut self) -> AsService<Self>
+    fn as_service(&mut self) -> AsService<'_, Self>
     where
         Self: Sized,
     {
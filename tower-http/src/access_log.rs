@@ -0,0 +1,429 @@
+//! Access logs in the Common and Combined Log Formats.
+//!
+//! `AccessLog` writes one line per response to a `LogWriter`, once the
+//! response body has been sent, in the format of the access logs of Apache
+//! and nginx:
+//!
+//! ```text
+//! 192.0.2.1 - alice [10/Oct/2000:13:55:36 +0000] "GET /a.gif HTTP/1.1" 200 2326 "http://example.com/" "Mozilla/5.0"
+//! ```
+//!
+//! The Common Log Format stops after the number of bytes of the response
+//! body; the Combined Log Format, the default, adds the `Referer` and
+//! `User-Agent`. The latency, from receiving the request to the end of the
+//! response body, can be appended in seconds.
+//!
+//! To hunt tail latency, a slow request threshold can be set, in which case
+//! only the requests taking at least that long are logged. Their lines end
+//! with the time spent waiting for the response headers and streaming the
+//! response body, such as `headers=0.250 body=1.500`.
+//!
+//! The client address is read from the `ClientInfo` extension inserted by
+//! `forwarded::SetClientInfo`, falling back to the `SocketAddr` extension of
+//! the peer, and the user from the `BasicPrincipal` extension. Calls failing
+//! with an error are not logged, as no response is sent.
+
+use crate::auth::BasicPrincipal;
+use crate::forwarded::ClientInfo;
+use bytes::Buf;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderName, REFERER, USER_AGENT};
+use http::{HeaderMap, Request, Response};
+use http_body::Body;
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tower_service::Service;
+
+/// Writes access log lines.
+///
+/// Also used by `dump`, whose records span several lines.
+pub trait LogWriter {
+    /// Write a line, or a record of several lines, without the trailing
+    /// newline.
+    fn write_line(&self, line: &str);
+}
+
+/// The format of access log lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// The Common Log Format.
+    Common,
+    /// The Combined Log Format, adding the `Referer` and `User-Agent`.
+    Combined,
+}
+
+/// Logs each response to a `LogWriter`.
+#[derive(Debug, Clone)]
+pub struct AccessLog<S, W> {
+    inner: S,
+    writer: Arc<W>,
+    config: Config,
+}
+
+/// Configure an `AccessLog`.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    config: Config,
+}
+
+/// Response future for `AccessLog`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, W>
+where
+    W: LogWriter,
+{
+    inner: F,
+    entry: Option<Entry<W>>,
+}
+
+/// A response body writing the access log line once it completes.
+#[derive(Debug)]
+pub struct LogBody<B, W>
+where
+    W: LogWriter,
+{
+    inner: B,
+    entry: Option<Entry<W>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Config {
+    format: Format,
+    latency: bool,
+    slow_threshold: Option<Duration>,
+}
+
+/// A log line waiting for the end of the response body.
+#[derive(Debug)]
+struct Entry<W>
+where
+    W: LogWriter,
+{
+    writer: Arc<W>,
+    config: Config,
+    start: Instant,
+    /// Everything up to the request line, included.
+    head: String,
+    referer: String,
+    user_agent: String,
+    /// The response status and when the response headers were ready,
+    /// unknown until then.
+    status: Option<(u16, Instant)>,
+    bytes: u64,
+}
+
+// ===== impl LogWriter =====
+
+impl<F> LogWriter for F
+where
+    F: Fn(&str),
+{
+    fn write_line(&self, line: &str) {
+        self(line)
+    }
+}
+
+impl<W> LogWriter for Mutex<W>
+where
+    W: io::Write,
+{
+    fn write_line(&self, line: &str) {
+        let mut writer = self.lock().unwrap();
+        let _ = writer
+            .write_all(line.as_bytes())
+            .and_then(|()| writer.write_all(b"\n"));
+    }
+}
+
+// ===== impl AccessLog =====
+
+impl<S, W> AccessLog<S, W> {
+    /// Create a new `AccessLog` writing lines in the Combined Log Format to
+    /// `writer`.
+    pub fn new(inner: S, writer: W) -> Self {
+        Builder::new().build(inner, writer)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, W, ReqBody, ResBody> Service<Request<ReqBody>> for AccessLog<S, W>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    W: LogWriter,
+{
+    type Response = Response<LogBody<ResBody, W>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, W>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let entry = Entry::new(self.writer.clone(), self.config, &req);
+        ResponseFuture {
+            inner: self.inner.call(req),
+            entry: Some(entry),
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            format: Format::Combined,
+            latency: false,
+            slow_threshold: None,
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder` writing lines in the Combined Log Format,
+    /// without the latency.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the format of the lines.
+    pub fn format(mut self, format: Format) -> Self {
+        self.config.format = format;
+        self
+    }
+
+    /// Append the latency of the request in seconds to the lines.
+    pub fn latency(mut self, latency: bool) -> Self {
+        self.config.latency = latency;
+        self
+    }
+
+    /// Only log requests taking at least `threshold`, from receiving the
+    /// request to the end of the response body, with the time spent waiting
+    /// for the response headers and streaming the response body.
+    pub fn slow_threshold(mut self, threshold: Duration) -> Self {
+        self.config.slow_threshold = Some(threshold);
+        self
+    }
+
+    /// Build an `AccessLog` writing to `writer`.
+    pub fn build<S, W>(self, inner: S, writer: W) -> AccessLog<S, W> {
+        AccessLog {
+            inner,
+            writer: Arc::new(writer),
+            config: self.config,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, W, B> Future for ResponseFuture<F, W>
+where
+    F: Future<Item = Response<B>>,
+    W: LogWriter,
+{
+    type Item = Response<LogBody<B, W>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+        let mut entry = self.entry.take().expect("polled after completion");
+        entry.status = Some((response.status().as_u16(), Instant::now()));
+        Ok(Async::Ready(response.map(|body| LogBody {
+            inner: body,
+            entry: Some(entry),
+        })))
+    }
+}
+
+// ===== impl LogBody =====
+
+impl<B, W> Body for LogBody<B, W>
+where
+    B: Body,
+    W: LogWriter,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let result = try_ready!(self.inner.poll_data());
+        match result {
+            Some(ref chunk) => {
+                if let Some(ref mut entry) = self.entry {
+                    entry.bytes += chunk.remaining() as u64;
+                }
+            }
+            None => self.entry = None,
+        }
+        Ok(Async::Ready(result))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl Entry =====
+
+impl<W> Entry<W>
+where
+    W: LogWriter,
+{
+    fn new<B>(writer: Arc<W>, config: Config, req: &Request<B>) -> Self {
+        let client = req
+            .extensions()
+            .get::<ClientInfo>()
+            .and_then(|info| info.ip)
+            .or_else(|| req.extensions().get::<SocketAddr>().map(|addr| addr.ip()));
+        let user = req
+            .extensions()
+            .get::<BasicPrincipal>()
+            .map(|principal| escape(principal.username()));
+        let target = req
+            .uri()
+            .path_and_query()
+            .map_or("/", |target| target.as_str());
+
+        let mut head = String::new();
+        write!(
+            head,
+            "{} - {} [{}] \"{} {} {:?}\"",
+            client.map_or_else(|| "-".to_owned(), |ip| ip.to_string()),
+            user.as_ref().map_or("-", |user| &**user),
+            clf_time(SystemTime::now()),
+            req.method(),
+            escape(target),
+            req.version(),
+        )
+        .unwrap();
+
+        let header = |name: HeaderName| {
+            req.headers()
+                .get(name)
+                .map_or_else(|| "-".to_owned(), |value| escape_bytes(value.as_bytes()))
+        };
+
+        Entry {
+            writer,
+            config,
+            start: Instant::now(),
+            head,
+            referer: header(REFERER),
+            user_agent: header(USER_AGENT),
+            status: None,
+            bytes: 0,
+        }
+    }
+}
+
+impl<W> Drop for Entry<W>
+where
+    W: LogWriter,
+{
+    fn drop(&mut self) {
+        // Without a status, no response was sent.
+        let (status, headers_at) = match self.status {
+            Some(status) => status,
+            None => return,
+        };
+        let latency = self.start.elapsed();
+        if self.config.slow_threshold.map_or(false, |t| latency < t) {
+            return;
+        }
+
+        let mut line = self.head.clone();
+        if self.bytes == 0 {
+            write!(line, " {} -", status).unwrap();
+        } else {
+            write!(line, " {} {}", status, self.bytes).unwrap();
+        }
+        if self.config.format == Format::Combined {
+            write!(line, " \"{}\" \"{}\"", self.referer, self.user_agent).unwrap();
+        }
+        if self.config.latency {
+            write!(line, " {:.3}", secs(latency)).unwrap();
+        }
+        if self.config.slow_threshold.is_some() {
+            let headers = headers_at.duration_since(self.start);
+            let body = headers_at.elapsed();
+            write!(line, " headers={:.3} body={:.3}", secs(headers), secs(body)).unwrap();
+        }
+        self.writer.write_line(&line);
+    }
+}
+
+fn secs(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9
+}
+
+/// Formats `time` as `10/Oct/2000:13:55:36 +0000`.
+fn clf_time(time: SystemTime) -> String {
+    // `Tue, 10 Oct 2000 13:55:36 GMT`
+    let date = httpdate::fmt_http_date(time);
+    let fields: Vec<&str> = date.split(' ').collect();
+    format!(
+        "{}/{}/{}:{} +0000",
+        fields[1], fields[2], fields[3], fields[4]
+    )
+}
+
+fn escape(s: &str) -> String {
+    escape_bytes(s.as_bytes())
+}
+
+/// Escapes quotes, backslashes and bytes outside of printable ASCII, like
+/// nginx.
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'"' | b'\\' => write!(escaped, "\\x{:02X}", b).unwrap(),
+            b' '..=b'~' => escaped.push(b as char),
+            _ => write!(escaped, "\\x{:02X}", b).unwrap(),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn formats_time() {
+        let time = UNIX_EPOCH + Duration::from_secs(971_186_136);
+        assert_eq!(clf_time(time), "10/Oct/2000:13:55:36 +0000");
+    }
+
+    #[test]
+    fn escapes_fields() {
+        assert_eq!(escape("Mozilla/5.0 (X11)"), "Mozilla/5.0 (X11)");
+        assert_eq!(escape("a\"b\\c\n"), "a\\x22b\\x5Cc\\x0A");
+        assert_eq!(escape_bytes(b"caf\xc3\xa9"), "caf\\xC3\\xA9");
+    }
+}
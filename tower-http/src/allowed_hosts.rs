@@ -0,0 +1,244 @@
+//! Middleware that rejects requests for unknown hosts.
+//!
+//! Servers sharing a listener between several sites, or building absolute
+//! URLs from the request, trust the `Host` header to be one of their own
+//! names. `AllowedHosts` checks the host of each request, taken from the
+//! request target when it is in absolute form (as with the `:authority` of
+//! HTTP/2 requests) or else from the `Host` header, against a list of
+//! allowed names:
+//!
+//! - requests for an allowed host are passed to the inner service;
+//! - requests for any other host are answered with
+//!   `421 Misdirected Request`;
+//! - requests without a host, with several `Host` headers, with a malformed
+//!   host or with a `Host` header disagreeing with the request target are
+//!   answered with `400 Bad Request`.
+//!
+//! Names are compared without their port, ignoring case and a trailing
+//! dot. A name starting with `*.` allows any subdomain of the rest of the
+//! name, but not the rest of the name itself.
+
+use futures::{Async, Future, Poll};
+use http::header::HOST;
+use http::uri::Authority;
+use http::{HttpTryFrom, Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Rejects requests for hosts not in an allow-list.
+#[derive(Debug, Clone)]
+pub struct AllowedHosts<S> {
+    inner: S,
+    hosts: Arc<Vec<Pattern>>,
+}
+
+/// Configure an `AllowedHosts` instance.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    hosts: Vec<Pattern>,
+}
+
+/// Response future for `AllowedHosts`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Pattern {
+    Exact(String),
+    /// A subdomain wildcard, holding the suffix with its leading dot.
+    Subdomains(String),
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+// ===== impl AllowedHosts =====
+
+impl<S> AllowedHosts<S> {
+    /// Create a new `AllowedHosts` allowing the hosts in `hosts`.
+    pub fn new<I>(inner: S, hosts: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        hosts
+            .into_iter()
+            .fold(Builder::new(), |builder, host| builder.allow(host.as_ref()))
+            .build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AllowedHosts<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let status = match host(&req) {
+            Some(host) => {
+                if self.hosts.iter().any(|pattern| pattern.matches(&host)) {
+                    None
+                } else {
+                    Some(StatusCode::MISDIRECTED_REQUEST)
+                }
+            }
+            None => Some(StatusCode::BAD_REQUEST),
+        };
+
+        let state = match status {
+            Some(status) => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = status;
+                State::Rejected(Some(res))
+            }
+            None => State::Accepted(self.inner.call(req)),
+        };
+        ResponseFuture { state }
+    }
+}
+
+/// Returns the normalized host name of `req`, or `None` if it is missing or
+/// ambiguous.
+fn host<B>(req: &Request<B>) -> Option<String> {
+    let mut headers = req.headers().get_all(HOST).iter();
+    let header = match (headers.next(), headers.next()) {
+        (Some(value), None) => {
+            let authority = Authority::try_from(value.to_str().ok()?).ok()?;
+            Some(normalize(authority.host()))
+        }
+        (None, _) => None,
+        (Some(_), Some(_)) => return None,
+    };
+    let target = req.uri().host().map(normalize);
+
+    match (target, header) {
+        (Some(target), Some(header)) => {
+            if target == header {
+                Some(target)
+            } else {
+                None
+            }
+        }
+        (Some(host), None) | (None, Some(host)) => Some(host),
+        (None, None) => None,
+    }
+    .filter(|host| !host.is_empty())
+}
+
+/// Lowercases `host` and removes its trailing dot.
+fn normalize(host: &str) -> String {
+    host.trim_end_matches('.').to_ascii_lowercase()
+}
+
+// ===== impl Pattern =====
+
+impl Pattern {
+    fn new(host: &str) -> Self {
+        if host.starts_with("*.") {
+            Pattern::Subdomains(normalize(&host[1..]))
+        } else {
+            Pattern::Exact(normalize(host))
+        }
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match *self {
+            Pattern::Exact(ref name) => host == name,
+            Pattern::Subdomains(ref suffix) => {
+                host.len() > suffix.len() && host.ends_with(&**suffix)
+            }
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Return a new builder allowing no host.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Allow requests for `host`.
+    ///
+    /// A `host` starting with `*.`, such as `*.example.com`, allows any
+    /// subdomain of `example.com`; allow `example.com` as well to accept
+    /// requests for the domain itself.
+    pub fn allow(mut self, host: &str) -> Self {
+        self.hosts.push(Pattern::new(host));
+        self
+    }
+
+    /// Build the `AllowedHosts` from the provided settings.
+    pub fn build<S>(self, inner: S) -> AllowedHosts<S> {
+        AllowedHosts {
+            inner,
+            hosts: Arc::new(self.hosts),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_patterns() {
+        let exact = Pattern::new("Example.com.");
+        assert!(exact.matches("example.com"));
+        assert!(!exact.matches("www.example.com"));
+
+        let subdomains = Pattern::new("*.example.com");
+        assert!(subdomains.matches("www.example.com"));
+        assert!(subdomains.matches("a.b.example.com"));
+        assert!(!subdomains.matches("example.com"));
+        assert!(!subdomains.matches("badexample.com"));
+    }
+}
@@ -0,0 +1,301 @@
+//! Middleware that determines the API version requested by clients.
+//!
+//! `ApiVersioning` reads the version requested by each request from a
+//! configurable `VersionSource`: a header such as `Accept-Version`, a
+//! parameter of the `Accept` media types such as
+//! `application/json; version=2`, or the first segment of the path, such
+//! as `/v2/users`. Requests for a supported version reach the inner service
+//! with an `ApiVersion` extension, and with the version segment removed
+//! from their path when it is taken from the path. Requests without a
+//! version get the default version if one is configured.
+//!
+//! Other requests are rejected with `406 Not Acceptable` when the version
+//! was negotiated through `Accept`, and `400 Bad Request` otherwise. The
+//! rejections carry an `Api-Supported-Versions` header listing the supported
+//! versions, and an `UnsupportedVersion` response extension describing the
+//! failure, from which error middleware can build a detailed body.
+
+use crate::util::MediaType;
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderName, HeaderValue, ACCEPT};
+use http::uri::{PathAndQuery, Uri};
+use http::{Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Name of the `Accept-Version` header.
+pub const ACCEPT_VERSION: &str = "accept-version";
+
+/// Name of the `Api-Supported-Versions` header.
+pub const API_SUPPORTED_VERSIONS: &str = "api-supported-versions";
+
+/// Determines and validates the API version of requests.
+#[derive(Debug, Clone)]
+pub struct ApiVersioning<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure an `ApiVersioning` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    source: VersionSource,
+    supported: Vec<String>,
+    default: Option<String>,
+}
+
+/// Where the requested version is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VersionSource {
+    /// The value of a header.
+    Header(HeaderName),
+    /// A parameter, named by the given lowercase name, of the media ranges
+    /// of `Accept`.
+    MediaTypeParam(String),
+    /// The first segment of the path.
+    PathPrefix,
+}
+
+/// The API version of a request, stored as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ApiVersion(String);
+
+/// Describes why a request was rejected, stored as a response extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsupportedVersion {
+    /// The requested version, or `None` if the request had none.
+    pub requested: Option<String>,
+    /// The supported versions.
+    pub supported: Vec<String>,
+}
+
+/// Response future for `ApiVersioning`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+struct Config {
+    source: VersionSource,
+    supported: Vec<String>,
+    default: Option<String>,
+    supported_header: HeaderValue,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+// ===== impl ApiVersioning =====
+
+impl<S> ApiVersioning<S> {
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ApiVersioning<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let config = &*self.config;
+        let requested = config.requested(&req);
+        let version = requested
+            .as_ref()
+            .or(config.default.as_ref())
+            .filter(|version| config.supported.contains(version));
+
+        let version = match version {
+            Some(version) => version.clone(),
+            None => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = match config.source {
+                    VersionSource::MediaTypeParam(_) => StatusCode::NOT_ACCEPTABLE,
+                    _ => StatusCode::BAD_REQUEST,
+                };
+                res.headers_mut()
+                    .insert(API_SUPPORTED_VERSIONS, config.supported_header.clone());
+                res.extensions_mut().insert(UnsupportedVersion {
+                    requested,
+                    supported: config.supported.clone(),
+                });
+                return ResponseFuture {
+                    state: State::Rejected(Some(res)),
+                };
+            }
+        };
+
+        if requested.is_some() && config.source == VersionSource::PathPrefix {
+            if let Some(uri) = strip_version(req.uri()) {
+                *req.uri_mut() = uri;
+            }
+        }
+        req.extensions_mut().insert(ApiVersion(version));
+        ResponseFuture {
+            state: State::Accepted(self.inner.call(req)),
+        }
+    }
+}
+
+/// Returns `uri` without the first segment of its path.
+fn strip_version(uri: &Uri) -> Option<Uri> {
+    let path = uri.path();
+    let rest = match path.get(1..)?.find('/') {
+        Some(i) => &path[i + 1..],
+        None => "/",
+    };
+    let mut path_and_query = rest.to_owned();
+    if let Some(query) = uri.query() {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::from_shared(Bytes::from(path_and_query)).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+// ===== impl Config =====
+
+impl Config {
+    /// Returns the version requested by `req`.
+    fn requested<B>(&self, req: &Request<B>) -> Option<String> {
+        match self.source {
+            VersionSource::Header(ref name) => {
+                let value = req.headers().get(name)?.to_str().ok()?.trim();
+                if value.is_empty() {
+                    None
+                } else {
+                    Some(value.to_owned())
+                }
+            }
+            VersionSource::MediaTypeParam(ref name) => req
+                .headers()
+                .get_all(ACCEPT)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .filter_map(MediaType::parse)
+                .filter_map(|range| range.param(name).map(str::to_owned))
+                .next(),
+            VersionSource::PathPrefix => {
+                let segment = req.uri().path().split('/').nth(1)?;
+                if segment.is_empty() {
+                    None
+                } else {
+                    Some(segment.to_owned())
+                }
+            }
+        }
+    }
+}
+
+// ===== impl ApiVersion =====
+
+impl ApiVersion {
+    /// Returns the version as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Return a new builder reading versions from `source`, supporting no
+    /// version.
+    pub fn new(source: VersionSource) -> Self {
+        Builder {
+            source,
+            supported: Vec::new(),
+            default: None,
+        }
+    }
+
+    /// Accept requests for `version`.
+    pub fn supported<T: Into<String>>(mut self, version: T) -> Self {
+        self.supported.push(version.into());
+        self
+    }
+
+    /// Serve requests without a version as requests for `version`.
+    ///
+    /// `version` must be supported as well.
+    pub fn default_version<T: Into<String>>(mut self, version: T) -> Self {
+        self.default = Some(version.into());
+        self
+    }
+
+    /// Build the `ApiVersioning` from the provided settings.
+    pub fn build<S>(self, inner: S) -> ApiVersioning<S> {
+        let supported_header = HeaderValue::from_str(&self.supported.join(", "))
+            .unwrap_or_else(|_| HeaderValue::from_static(""));
+        ApiVersioning {
+            inner,
+            config: Arc::new(Config {
+                source: self.source,
+                supported: self.supported,
+                default: self.default,
+                supported_header,
+            }),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_versions_from_paths() {
+        let strip = |uri: &'static str| strip_version(&Uri::from_static(uri)).unwrap();
+        assert_eq!(strip("/v1/users?page=2"), "/users?page=2");
+        assert_eq!(strip("/v1"), "/");
+        assert_eq!(strip("/v1/"), "/");
+        assert_eq!(strip("http://example.com/v2/a/b"), "http://example.com/a/b");
+    }
+}
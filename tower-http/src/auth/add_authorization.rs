@@ -0,0 +1,131 @@
+use http::header::{HeaderValue, AUTHORIZATION};
+use http::Request;
+use std::sync::{Arc, RwLock};
+use tower_service::Service;
+
+/// Adds an `Authorization` header to outgoing requests.
+///
+/// The header value is marked as sensitive. It can be replaced at runtime,
+/// for example after refreshing an access token, through the
+/// `AuthorizationHandle` returned by `handle`.
+#[derive(Debug, Clone)]
+pub struct AddAuthorization<S> {
+    inner: S,
+    handle: AuthorizationHandle,
+}
+
+/// Shared handle to the `Authorization` value used by an `AddAuthorization`
+/// and its clones.
+#[derive(Debug, Clone)]
+pub struct AuthorizationHandle {
+    value: Arc<RwLock<HeaderValue>>,
+}
+
+// ===== impl AddAuthorization =====
+
+impl<S> AddAuthorization<S> {
+    /// Create a new `AddAuthorization` sending the given header value.
+    pub fn new(inner: S, value: HeaderValue) -> Self {
+        AddAuthorization {
+            inner,
+            handle: AuthorizationHandle::new(value),
+        }
+    }
+
+    /// Send `Authorization: Basic` with the given credentials.
+    pub fn basic(inner: S, username: &str, password: &str) -> Self {
+        Self::new(inner, basic(username, password))
+    }
+
+    /// Send `Authorization: Bearer <token>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is not a valid header value.
+    pub fn bearer(inner: S, token: &str) -> Self {
+        Self::new(inner, bearer(token))
+    }
+
+    /// Returns a handle to replace the header value at runtime.
+    pub fn handle(&self) -> AuthorizationHandle {
+        self.handle.clone()
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for AddAuthorization<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        req.headers_mut().insert(AUTHORIZATION, self.handle.get());
+        self.inner.call(req)
+    }
+}
+
+// ===== impl AuthorizationHandle =====
+
+impl AuthorizationHandle {
+    fn new(mut value: HeaderValue) -> Self {
+        value.set_sensitive(true);
+        AuthorizationHandle {
+            value: Arc::new(RwLock::new(value)),
+        }
+    }
+
+    /// Returns the current header value.
+    pub fn get(&self) -> HeaderValue {
+        self.value.read().unwrap().clone()
+    }
+
+    /// Replace the header value.
+    pub fn set(&self, mut value: HeaderValue) {
+        value.set_sensitive(true);
+        *self.value.write().unwrap() = value;
+    }
+
+    /// Replace the header value with basic credentials.
+    pub fn set_basic(&self, username: &str, password: &str) {
+        self.set(basic(username, password));
+    }
+
+    /// Replace the header value with a bearer token.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is not a valid header value.
+    pub fn set_bearer(&self, token: &str) {
+        self.set(bearer(token));
+    }
+}
+
+fn basic(username: &str, password: &str) -> HeaderValue {
+    let encoded = base64::encode(&format!("{}:{}", username, password));
+    HeaderValue::from_str(&format!("Basic {}", encoded)).expect("base64 is a valid header value")
+}
+
+fn bearer(token: &str) -> HeaderValue {
+    HeaderValue::from_str(&format!("Bearer {}", token)).expect("token is not a valid header value")
+}
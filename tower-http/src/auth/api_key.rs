@@ -0,0 +1,369 @@
+use crate::util::with_query;
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderName, HeaderValue, CONTENT_TYPE, WWW_AUTHENTICATE};
+use http::{Request, Response, StatusCode};
+use percent_encoding::percent_decode;
+use std::sync::Arc;
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Authenticates requests by an API key.
+///
+/// The key is read from a header or a query parameter and passed to a
+/// `ValidateKey` implementation. Requests without a key, or with a key the
+/// validator does not know, are answered with `401 Unauthorized` and the
+/// configured `WWW-Authenticate` challenge, or with `403 Forbidden` if no
+/// challenge is configured; requests whose key is known but not allowed are
+/// answered with `403 Forbidden`.
+/// On success the principal is inserted as a request extension, and a key
+/// read from the query string is removed from the request URI so that it
+/// does not end up in logs further down the stack.
+///
+/// Like `AsyncRequireAuthorization`, the inner service must be `Clone`.
+#[derive(Debug, Clone)]
+pub struct RequireApiKey<S, V> {
+    inner: S,
+    validator: V,
+    config: Arc<ApiKeyConfig>,
+}
+
+/// Where to read API keys from, and how to reply on failure.
+#[derive(Debug, Clone)]
+pub struct ApiKeyConfig {
+    source: KeySource,
+    challenge: Option<HeaderValue>,
+    unauthorized: Rejection,
+    forbidden: Rejection,
+}
+
+/// Where an API key is read from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeySource {
+    /// A request header, such as `X-Api-Key`.
+    Header(HeaderName),
+    /// A query parameter, such as `api_key`.
+    Query(String),
+}
+
+/// Why an API key was refused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyRejection {
+    /// The key is unknown; answered with `401 Unauthorized`, or with
+    /// `403 Forbidden` if no challenge is configured.
+    Unauthorized,
+    /// The key is known but not allowed; answered with `403 Forbidden`.
+    Forbidden,
+}
+
+/// Validates API keys, possibly asynchronously.
+pub trait ValidateKey {
+    /// The authenticated principal, inserted as a request extension.
+    type Principal: Send + Sync + 'static;
+
+    /// Resolves to the principal owning the key.
+    type Future: Future<Item = Self::Principal, Error = KeyRejection>;
+
+    /// Validate `key`.
+    fn validate(&mut self, key: &str) -> Self::Future;
+}
+
+#[derive(Debug, Clone)]
+struct Rejection {
+    body: Bytes,
+    content_type: Option<HeaderValue>,
+}
+
+/// Response future for `RequireApiKey`.
+pub struct ApiKeyFuture<S, V, B>
+where
+    S: Service<Request<B>>,
+    V: ValidateKey,
+{
+    state: State<S, V::Future, S::Future, B>,
+    config: Arc<ApiKeyConfig>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<S, V, F, B> {
+    Validate {
+        validate: V,
+        service: S,
+        request: Request<B>,
+    },
+    Call(F),
+    Reject(KeyRejection),
+    Done,
+}
+
+// ===== impl RequireApiKey =====
+
+impl<S, V> RequireApiKey<S, V> {
+    /// Create a new `RequireApiKey` using the given configuration.
+    pub fn new(inner: S, validator: V, config: ApiKeyConfig) -> Self {
+        RequireApiKey {
+            inner,
+            validator,
+            config: Arc::new(config),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, V, ReqBody, ResBody> Service<Request<ReqBody>> for RequireApiKey<S, V>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    V: ValidateKey,
+    ResBody: From<Bytes>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ApiKeyFuture<S, V, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match self.config.extract(&req) {
+            Some(key) => {
+                let clone = self.inner.clone();
+                State::Validate {
+                    validate: self.validator.validate(&key),
+                    service: mem::replace(&mut self.inner, clone),
+                    request: req,
+                }
+            }
+            None => State::Reject(KeyRejection::Unauthorized),
+        };
+
+        ApiKeyFuture {
+            state,
+            config: self.config.clone(),
+        }
+    }
+}
+
+// ===== impl ApiKeyConfig =====
+
+impl ApiKeyConfig {
+    /// Read keys from the given header.
+    pub fn header(name: HeaderName) -> Self {
+        Self::new(KeySource::Header(name))
+    }
+
+    /// Read keys from the given query parameter.
+    pub fn query(name: &str) -> Self {
+        Self::new(KeySource::Query(name.to_owned()))
+    }
+
+    fn new(source: KeySource) -> Self {
+        ApiKeyConfig {
+            source,
+            challenge: None,
+            unauthorized: Rejection {
+                body: Bytes::new(),
+                content_type: None,
+            },
+            forbidden: Rejection {
+                body: Bytes::new(),
+                content_type: None,
+            },
+        }
+    }
+
+    /// Answer missing and unknown keys with `401 Unauthorized` and the given
+    /// `WWW-Authenticate` challenge, such as `ApiKey header="X-Api-Key"`.
+    ///
+    /// RFC 7235 requires a challenge on `401` responses, so without one
+    /// these requests are answered with `403 Forbidden`.
+    pub fn challenge(mut self, challenge: HeaderValue) -> Self {
+        self.challenge = Some(challenge);
+        self
+    }
+
+    /// Set the body and content type of responses to missing and unknown
+    /// keys.
+    pub fn unauthorized_body(mut self, body: Bytes, content_type: HeaderValue) -> Self {
+        self.unauthorized = Rejection {
+            body,
+            content_type: Some(content_type),
+        };
+        self
+    }
+
+    /// Set the body and content type of `403` responses.
+    pub fn forbidden_body(mut self, body: Bytes, content_type: HeaderValue) -> Self {
+        self.forbidden = Rejection {
+            body,
+            content_type: Some(content_type),
+        };
+        self
+    }
+
+    fn extract<B>(&self, req: &Request<B>) -> Option<String> {
+        let key = match self.source {
+            KeySource::Header(ref name) => req
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_owned),
+            KeySource::Query(ref name) => req.uri().query()?.split('&').find_map(|pair| {
+                let mut pair = pair.splitn(2, '=');
+                let key = pair.next()?;
+                let value = pair.next()?;
+                if key == name.as_str() {
+                    percent_decode(value.as_bytes())
+                        .decode_utf8()
+                        .ok()
+                        .map(|value| value.into_owned())
+                } else {
+                    None
+                }
+            }),
+        };
+        key.filter(|key| !key.is_empty())
+    }
+
+    fn strip<B>(&self, req: &mut Request<B>) {
+        let name = match self.source {
+            KeySource::Query(ref name) => name,
+            KeySource::Header(_) => return,
+        };
+        let query = match req.uri().query() {
+            Some(query) => query
+                .split('&')
+                .filter(|pair| pair.splitn(2, '=').next() != Some(name.as_str()))
+                .collect::<Vec<_>>()
+                .join("&"),
+            None => return,
+        };
+        if let Some(uri) = with_query(req.uri(), &query) {
+            *req.uri_mut() = uri;
+        }
+    }
+
+    fn reject<B>(&self, rejection: KeyRejection) -> Response<B>
+    where
+        B: From<Bytes>,
+    {
+        let (status, rejection) = match rejection {
+            KeyRejection::Unauthorized if self.challenge.is_some() => {
+                (StatusCode::UNAUTHORIZED, &self.unauthorized)
+            }
+            KeyRejection::Unauthorized => (StatusCode::FORBIDDEN, &self.unauthorized),
+            KeyRejection::Forbidden => (StatusCode::FORBIDDEN, &self.forbidden),
+        };
+
+        let mut res = Response::new(B::from(rejection.body.clone()));
+        *res.status_mut() = status;
+        if status == StatusCode::UNAUTHORIZED {
+            if let Some(ref challenge) = self.challenge {
+                res.headers_mut()
+                    .insert(WWW_AUTHENTICATE, challenge.clone());
+            }
+        }
+        if let Some(ref content_type) = rejection.content_type {
+            res.headers_mut().insert(CONTENT_TYPE, content_type.clone());
+        }
+        res
+    }
+}
+
+// ===== impl ValidateKey =====
+
+impl<F, Fut> ValidateKey for F
+where
+    F: FnMut(&str) -> Fut,
+    Fut: futures::IntoFuture<Error = KeyRejection>,
+    Fut::Item: Send + Sync + 'static,
+{
+    type Principal = Fut::Item;
+    type Future = Fut::Future;
+
+    fn validate(&mut self, key: &str) -> Self::Future {
+        self(key).into_future()
+    }
+}
+
+// ===== impl ApiKeyFuture =====
+
+impl<S, V, ReqBody, ResBody> Future for ApiKeyFuture<S, V, ReqBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    V: ValidateKey,
+    ResBody: From<Bytes>,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Validate {
+                    mut validate,
+                    mut service,
+                    mut request,
+                } => match validate.poll() {
+                    Ok(Async::Ready(principal)) => {
+                        self.config.strip(&mut request);
+                        request.extensions_mut().insert(principal);
+                        self.state = State::Call(service.call(request));
+                    }
+                    Ok(Async::NotReady) => {
+                        self.state = State::Validate {
+                            validate,
+                            service,
+                            request,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                    Err(rejection) => self.state = State::Reject(rejection),
+                },
+                State::Call(mut future) => {
+                    let poll = future.poll();
+                    if let Ok(Async::NotReady) = poll {
+                        self.state = State::Call(future);
+                    }
+                    return poll;
+                }
+                State::Reject(rejection) => {
+                    return Ok(Async::Ready(self.config.reject(rejection)));
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, V, B> fmt::Debug for ApiKeyFuture<S, V, B>
+where
+    S: Service<Request<B>>,
+    V: ValidateKey,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Validate { .. } => "Validate",
+            State::Call(_) => "Call",
+            State::Reject(_) => "Reject",
+            State::Done => "Done",
+        };
+        f.debug_struct("ApiKeyFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
@@ -0,0 +1,169 @@
+use futures::{Async, Future, Poll};
+use http::{Request, Response};
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Authorizes requests with a user supplied asynchronous authorizer.
+///
+/// This is the asynchronous counterpart to `RequireAuthorization`, for
+/// authorization that requires I/O such as a database lookup or a token
+/// introspection call. The authorizer resolves to the (possibly modified)
+/// request, which is then passed to the inner service, or fails with the
+/// response to send instead.
+///
+/// The inner service must be `Clone`: the service that was driven to
+/// readiness is moved into the response future, so that it can be called
+/// once authorization completes.
+#[derive(Debug, Clone)]
+pub struct AsyncRequireAuthorization<S, A> {
+    inner: S,
+    authorize: A,
+}
+
+/// Asynchronously authorizes a request.
+pub trait AsyncAuthorizeRequest<B> {
+    /// Body of the responses sent to rejected requests.
+    type ResponseBody;
+
+    /// Resolves to the authorized request, typically with an identity
+    /// extension inserted, or fails with the response to send instead.
+    type Future: Future<Item = Request<B>, Error = Response<Self::ResponseBody>>;
+
+    /// Authorize the request.
+    fn authorize(&mut self, request: Request<B>) -> Self::Future;
+}
+
+/// Response future for `AsyncRequireAuthorization`.
+pub struct AsyncResponseFuture<S, A, B>
+where
+    S: Service<Request<B>>,
+    A: AsyncAuthorizeRequest<B>,
+{
+    state: State<S, A::Future, S::Future>,
+}
+
+#[derive(Debug)]
+enum State<S, A, F> {
+    Authorize { authorize: A, service: S },
+    Call(F),
+    Done,
+}
+
+// ===== impl AsyncRequireAuthorization =====
+
+impl<S, A> AsyncRequireAuthorization<S, A> {
+    /// Create a new `AsyncRequireAuthorization` using the given authorizer.
+    pub fn new(inner: S, authorize: A) -> Self {
+        AsyncRequireAuthorization { inner, authorize }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, A, ReqBody, ResBody> Service<Request<ReqBody>> for AsyncRequireAuthorization<S, A>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    A: AsyncAuthorizeRequest<ReqBody, ResponseBody = ResBody>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = AsyncResponseFuture<S, A, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let clone = self.inner.clone();
+        let service = mem::replace(&mut self.inner, clone);
+
+        AsyncResponseFuture {
+            state: State::Authorize {
+                authorize: self.authorize.authorize(req),
+                service,
+            },
+        }
+    }
+}
+
+// ===== impl AsyncAuthorizeRequest =====
+
+impl<F, Fut, ReqBody, ResBody> AsyncAuthorizeRequest<ReqBody> for F
+where
+    F: FnMut(Request<ReqBody>) -> Fut,
+    Fut: Future<Item = Request<ReqBody>, Error = Response<ResBody>>,
+{
+    type ResponseBody = ResBody;
+    type Future = Fut;
+
+    fn authorize(&mut self, request: Request<ReqBody>) -> Fut {
+        self(request)
+    }
+}
+
+// ===== impl AsyncResponseFuture =====
+
+impl<S, A, ReqBody, ResBody> Future for AsyncResponseFuture<S, A, ReqBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    A: AsyncAuthorizeRequest<ReqBody, ResponseBody = ResBody>,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Authorize {
+                    mut authorize,
+                    mut service,
+                } => match authorize.poll() {
+                    Ok(Async::Ready(req)) => self.state = State::Call(service.call(req)),
+                    Ok(Async::NotReady) => {
+                        self.state = State::Authorize { authorize, service };
+                        return Ok(Async::NotReady);
+                    }
+                    Err(res) => return Ok(Async::Ready(res)),
+                },
+                State::Call(mut future) => {
+                    let poll = future.poll();
+                    if let Ok(Async::NotReady) = poll {
+                        self.state = State::Call(future);
+                    }
+                    return poll;
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, A, B> fmt::Debug for AsyncResponseFuture<S, A, B>
+where
+    S: Service<Request<B>>,
+    A: AsyncAuthorizeRequest<B>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Authorize { .. } => "Authorize",
+            State::Call(_) => "Call",
+            State::Done => "Done",
+        };
+        f.debug_struct("AsyncResponseFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
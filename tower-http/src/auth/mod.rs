@@ -0,0 +1,18 @@
+//! Authentication and authorization middleware.
+
+mod add_authorization;
+mod api_key;
+mod async_require_authorization;
+mod require_authorization;
+
+pub use self::add_authorization::{AddAuthorization, AuthorizationHandle};
+pub use self::api_key::{
+    ApiKeyConfig, ApiKeyFuture, KeyRejection, KeySource, RequireApiKey, ValidateKey,
+};
+pub use self::async_require_authorization::{
+    AsyncAuthorizeRequest, AsyncRequireAuthorization, AsyncResponseFuture,
+};
+pub use self::require_authorization::{
+    Basic, BasicPrincipal, Bearer, BearerPrincipal, BearerTokenPrincipal, BearerTokens,
+    RequireAuthorization, ResponseFuture, StaticCredentials, Validate, VerifyCredentials,
+};
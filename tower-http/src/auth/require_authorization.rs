@@ -0,0 +1,503 @@
+use crate::util::constant_time_eq;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, AUTHORIZATION, WWW_AUTHENTICATE};
+use http::{Request, Response, StatusCode};
+use std::sync::{Arc, RwLock};
+use tower_service::Service;
+
+/// Validates the `Authorization` header of requests.
+///
+/// Requests with a valid header have the principal returned by the
+/// validator inserted as a request extension and are passed to the inner
+/// service. Other requests are answered with `401 Unauthorized` and a
+/// `WWW-Authenticate` challenge, without calling the inner service.
+#[derive(Debug)]
+pub struct RequireAuthorization<S, V> {
+    inner: S,
+    validator: Arc<V>,
+}
+
+/// Validates credentials presented in an `Authorization` header.
+pub trait Validate {
+    /// The authenticated principal, inserted as a request extension.
+    type Principal: Send + Sync + 'static;
+
+    /// Validate an `Authorization` header value, returning the principal if
+    /// the credentials are valid.
+    fn validate(&self, authorization: &HeaderValue) -> Option<Self::Principal>;
+
+    /// The `WWW-Authenticate` challenge sent with `401` responses.
+    fn challenge(&self) -> HeaderValue;
+}
+
+/// Validates a static bearer token.
+#[derive(Debug, Clone)]
+pub struct Bearer {
+    token: HeaderValue,
+}
+
+/// Validates bearer tokens against a set that can be replaced at runtime.
+///
+/// Clones share the same set, so a handle kept outside the service can
+/// rotate credentials: add the new token, let clients migrate during the
+/// overlap window, then remove the old one. Updates swap the whole set at
+/// once, so a request never observes a partially updated set.
+#[derive(Debug, Clone, Default)]
+pub struct BearerTokens {
+    tokens: Arc<RwLock<Tokens>>,
+}
+
+/// Accepted tokens, by id.
+type Tokens = Arc<Vec<(String, HeaderValue)>>;
+
+/// The principal of requests authenticated by `BearerTokens`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BearerTokenPrincipal {
+    id: String,
+}
+
+/// The principal of requests authenticated by `Bearer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BearerPrincipal {
+    _p: (),
+}
+
+/// Validates basic credentials with a `VerifyCredentials` implementation.
+#[derive(Debug, Clone)]
+pub struct Basic<C = StaticCredentials> {
+    credentials: C,
+    challenge: HeaderValue,
+}
+
+/// Verifies a user name and password, for example against a password store.
+pub trait VerifyCredentials {
+    /// The authenticated principal, inserted as a request extension.
+    type Principal: Send + Sync + 'static;
+
+    /// Returns the principal if the credentials are valid.
+    fn verify(&self, username: &str, password: &str) -> Option<Self::Principal>;
+}
+
+/// A single, static user name and password.
+#[derive(Debug, Clone)]
+pub struct StaticCredentials {
+    username: String,
+    password: String,
+}
+
+/// The principal of requests authenticated by `Basic`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicPrincipal {
+    username: String,
+}
+
+/// Response future for `RequireAuthorization`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Authorized(F),
+    Unauthorized(Option<Response<B>>),
+}
+
+// ===== impl RequireAuthorization =====
+
+impl<S, V> RequireAuthorization<S, V> {
+    /// Create a new `RequireAuthorization` using the given validator.
+    pub fn new(inner: S, validator: V) -> Self {
+        RequireAuthorization {
+            inner,
+            validator: Arc::new(validator),
+        }
+    }
+
+    /// Returns the validator.
+    pub fn validator(&self) -> &V {
+        &self.validator
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> RequireAuthorization<S, Bearer> {
+    /// Require `Authorization: Bearer <token>`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is not a valid header value.
+    pub fn bearer(inner: S, token: &str) -> Self {
+        Self::new(inner, Bearer::new(token))
+    }
+}
+
+impl<S> RequireAuthorization<S, BearerTokens> {
+    /// Require `Authorization: Bearer <token>` with any of `tokens`.
+    pub fn bearer_tokens(inner: S, tokens: BearerTokens) -> Self {
+        Self::new(inner, tokens)
+    }
+}
+
+impl<S> RequireAuthorization<S, Basic> {
+    /// Require `Authorization: Basic` with the given credentials.
+    pub fn basic(inner: S, username: &str, password: &str) -> Self {
+        Self::new(inner, Basic::new(username, password))
+    }
+}
+
+impl<S, C> RequireAuthorization<S, Basic<C>> {
+    /// Require `Authorization: Basic` with credentials verified by
+    /// `credentials`, announcing `realm` in the challenge.
+    pub fn basic_with_verifier(inner: S, credentials: C, realm: &str) -> Self {
+        Self::new(inner, Basic::with_verifier(credentials).realm(realm))
+    }
+}
+
+impl<S, V> Clone for RequireAuthorization<S, V>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        RequireAuthorization {
+            inner: self.inner.clone(),
+            validator: self.validator.clone(),
+        }
+    }
+}
+
+impl<S, V, ReqBody, ResBody> Service<Request<ReqBody>> for RequireAuthorization<S, V>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    V: Validate,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let principal = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| self.validator.validate(value));
+
+        let state = match principal {
+            Some(principal) => {
+                req.extensions_mut().insert(principal);
+                State::Authorized(self.inner.call(req))
+            }
+            None => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::UNAUTHORIZED;
+                res.headers_mut()
+                    .insert(WWW_AUTHENTICATE, self.validator.challenge());
+                State::Unauthorized(Some(res))
+            }
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl Bearer =====
+
+impl Bearer {
+    /// Create a new `Bearer` validator accepting `token`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is not a valid header value.
+    pub fn new(token: &str) -> Self {
+        Bearer {
+            token: token_value(token),
+        }
+    }
+}
+
+impl Validate for Bearer {
+    type Principal = BearerPrincipal;
+
+    fn validate(&self, authorization: &HeaderValue) -> Option<BearerPrincipal> {
+        let token = bearer_token(authorization)?;
+        if constant_time_eq(token, self.token.as_bytes()) {
+            Some(BearerPrincipal { _p: () })
+        } else {
+            None
+        }
+    }
+
+    fn challenge(&self) -> HeaderValue {
+        HeaderValue::from_static("Bearer")
+    }
+}
+
+// ===== impl BearerTokens =====
+
+impl BearerTokens {
+    /// Create a new `BearerTokens` accepting the given `(id, token)` pairs.
+    ///
+    /// The id identifies the token in the `BearerTokenPrincipal` and when
+    /// removing it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a token is not a valid header value.
+    pub fn new<I, K, T>(tokens: I) -> Self
+    where
+        I: IntoIterator<Item = (K, T)>,
+        K: Into<String>,
+        T: AsRef<str>,
+    {
+        let this = BearerTokens::default();
+        this.replace(tokens);
+        this
+    }
+
+    /// Accept `token`, identified by `id`, replacing any token with the
+    /// same id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `token` is not a valid header value.
+    pub fn insert(&self, id: &str, token: &str) {
+        let token = token_value(token);
+        self.update(|tokens| {
+            tokens.retain(|(i, _)| i != id);
+            tokens.push((id.to_owned(), token));
+        });
+    }
+
+    /// Stop accepting the token identified by `id`.
+    pub fn remove(&self, id: &str) {
+        self.update(|tokens| tokens.retain(|(i, _)| i != id));
+    }
+
+    /// Replace all accepted tokens with the given `(id, token)` pairs.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a token is not a valid header value.
+    pub fn replace<I, K, T>(&self, tokens: I)
+    where
+        I: IntoIterator<Item = (K, T)>,
+        K: Into<String>,
+        T: AsRef<str>,
+    {
+        let tokens: Vec<_> = tokens
+            .into_iter()
+            .map(|(id, token)| (id.into(), token_value(token.as_ref())))
+            .collect();
+        *self.tokens.write().unwrap() = Arc::new(tokens);
+    }
+
+    /// Returns the ids of the accepted tokens.
+    pub fn ids(&self) -> Vec<String> {
+        let tokens = self.snapshot();
+        tokens.iter().map(|(id, _)| id.clone()).collect()
+    }
+
+    fn snapshot(&self) -> Tokens {
+        self.tokens.read().unwrap().clone()
+    }
+
+    fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Vec<(String, HeaderValue)>),
+    {
+        let mut guard = self.tokens.write().unwrap();
+        let mut tokens = (**guard).clone();
+        f(&mut tokens);
+        *guard = Arc::new(tokens);
+    }
+}
+
+impl Validate for BearerTokens {
+    type Principal = BearerTokenPrincipal;
+
+    fn validate(&self, authorization: &HeaderValue) -> Option<BearerTokenPrincipal> {
+        let token = bearer_token(authorization)?;
+        let tokens = self.snapshot();
+        // Compare against every token so the timing does not reveal which
+        // one matched.
+        let mut principal = None;
+        for (id, accepted) in tokens.iter() {
+            if constant_time_eq(token, accepted.as_bytes()) {
+                principal = Some(BearerTokenPrincipal { id: id.clone() });
+            }
+        }
+        principal
+    }
+
+    fn challenge(&self) -> HeaderValue {
+        HeaderValue::from_static("Bearer")
+    }
+}
+
+impl BearerTokenPrincipal {
+    /// Returns the id of the token that authenticated the request.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+}
+
+fn token_value(token: &str) -> HeaderValue {
+    HeaderValue::from_str(token).expect("token is not a valid header value")
+}
+
+/// Returns the token of a `Bearer` authorization. The scheme is
+/// case-insensitive, as per RFC 7235.
+fn bearer_token(authorization: &HeaderValue) -> Option<&[u8]> {
+    let value = authorization.as_bytes();
+    let space = value.iter().position(|&b| b == b' ')?;
+    if !value[..space].eq_ignore_ascii_case(b"bearer") {
+        return None;
+    }
+    let token = &value[space + 1..];
+    let start = token.iter().position(|&b| b != b' ').unwrap_or(token.len());
+    Some(&token[start..])
+}
+
+// ===== impl Basic =====
+
+impl Basic {
+    /// Create a new `Basic` validator accepting the given credentials.
+    pub fn new(username: &str, password: &str) -> Self {
+        Basic::with_verifier(StaticCredentials {
+            username: username.to_owned(),
+            password: password.to_owned(),
+        })
+    }
+}
+
+impl<C> Basic<C> {
+    /// Create a new `Basic` validator verifying credentials with `credentials`.
+    pub fn with_verifier(credentials: C) -> Self {
+        Basic {
+            credentials,
+            challenge: challenge("restricted"),
+        }
+    }
+
+    /// Set the realm announced in the `WWW-Authenticate` challenge.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `realm` contains characters that are not valid in a header
+    /// value.
+    pub fn realm(mut self, realm: &str) -> Self {
+        self.challenge = challenge(realm);
+        self
+    }
+}
+
+impl<C> Validate for Basic<C>
+where
+    C: VerifyCredentials,
+{
+    type Principal = C::Principal;
+
+    fn validate(&self, authorization: &HeaderValue) -> Option<C::Principal> {
+        let value = authorization.to_str().ok()?;
+        if value.len() < 6 || !value[..6].eq_ignore_ascii_case("basic ") {
+            return None;
+        }
+
+        let decoded = base64::decode(value[6..].trim()).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let mut parts = decoded.splitn(2, ':');
+        let username = parts.next()?;
+        let password = parts.next()?;
+
+        self.credentials.verify(username, password)
+    }
+
+    fn challenge(&self) -> HeaderValue {
+        self.challenge.clone()
+    }
+}
+
+fn challenge(realm: &str) -> HeaderValue {
+    let realm = realm.replace('\\', "\\\\").replace('"', "\\\"");
+    HeaderValue::from_str(&format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm))
+        .expect("realm is not a valid header value")
+}
+
+// ===== impl StaticCredentials =====
+
+impl VerifyCredentials for StaticCredentials {
+    type Principal = BasicPrincipal;
+
+    fn verify(&self, username: &str, password: &str) -> Option<BasicPrincipal> {
+        // Avoid short-circuiting so that timing does not reveal which of the
+        // two was wrong.
+        let username_ok = constant_time_eq(username.as_bytes(), self.username.as_bytes());
+        let password_ok = constant_time_eq(password.as_bytes(), self.password.as_bytes());
+
+        if username_ok & password_ok {
+            Some(BasicPrincipal {
+                username: self.username.clone(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl<F, P> VerifyCredentials for F
+where
+    F: Fn(&str, &str) -> Option<P>,
+    P: Send + Sync + 'static,
+{
+    type Principal = P;
+
+    fn verify(&self, username: &str, password: &str) -> Option<P> {
+        self(username, password)
+    }
+}
+
+// ===== impl BasicPrincipal =====
+
+impl BasicPrincipal {
+    /// Returns the authenticated user name.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Authorized(ref mut future) => future.poll(),
+            State::Unauthorized(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,166 @@
+//! Context header propagation.
+//!
+//! `ExtractBaggage` copies a configured set of context headers, such as
+//! `baggage` or `x-tenant-id`, from incoming requests into a `Baggage`
+//! request extension. Handlers copy that extension onto the requests they
+//! send, and `PropagateBaggage` writes the headers back out, so context
+//! travels across service boundaries without handlers touching headers.
+
+use http::header::{HeaderMap, HeaderName};
+use http::Request;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Name of the W3C `baggage` header.
+pub const BAGGAGE: &str = "baggage";
+
+/// Context headers captured from an incoming request.
+#[derive(Debug, Clone, Default)]
+pub struct Baggage {
+    headers: HeaderMap,
+}
+
+/// Captures context headers of incoming requests into a `Baggage` extension.
+#[derive(Debug, Clone)]
+pub struct ExtractBaggage<S> {
+    inner: S,
+    names: Arc<[HeaderName]>,
+}
+
+/// Writes the `Baggage` extension of outgoing requests back into headers.
+///
+/// Headers set explicitly on the outgoing request take precedence.
+#[derive(Debug, Clone)]
+pub struct PropagateBaggage<S> {
+    inner: S,
+}
+
+// ===== impl Baggage =====
+
+impl Baggage {
+    /// Returns the captured headers.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// Returns a mutable reference to the captured headers.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.headers
+    }
+}
+
+// ===== impl ExtractBaggage =====
+
+impl<S> ExtractBaggage<S> {
+    /// Create a new `ExtractBaggage` capturing the given headers.
+    pub fn new<I>(inner: S, names: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        let names: Vec<HeaderName> = names.into_iter().collect();
+        ExtractBaggage {
+            inner,
+            names: names.into(),
+        }
+    }
+
+    /// Create a new `ExtractBaggage` capturing the W3C `baggage` header.
+    pub fn w3c(inner: S) -> Self {
+        Self::new(inner, Some(HeaderName::from_static(BAGGAGE)))
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for ExtractBaggage<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let mut baggage = Baggage::default();
+
+        for name in self.names.iter() {
+            for value in req.headers().get_all(name).iter() {
+                baggage.headers.append(name.clone(), value.clone());
+            }
+        }
+
+        req.extensions_mut().insert(baggage);
+        self.inner.call(req)
+    }
+}
+
+// ===== impl PropagateBaggage =====
+
+impl<S> PropagateBaggage<S> {
+    /// Create a new `PropagateBaggage`.
+    pub fn new(inner: S) -> Self {
+        PropagateBaggage { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for PropagateBaggage<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if let Some(baggage) = req.extensions_mut().remove::<Baggage>() {
+            let headers = req.headers_mut();
+            for name in baggage.headers.keys() {
+                if headers.contains_key(name) {
+                    continue;
+                }
+                for value in baggage.headers.get_all(name).iter() {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+            req.extensions_mut().insert(baggage);
+        }
+
+        self.inner.call(req)
+    }
+}
@@ -0,0 +1,396 @@
+//! Limit the size of request and response bodies.
+//!
+//! `RequestBodyLimit` answers requests whose `Content-Length` exceeds the
+//! limit with `413 Payload Too Large` without calling the inner service.
+//! Other request bodies are wrapped in `Limited`, which fails with
+//! `LimitError::LengthLimitExceeded` once more than the limit has been read;
+//! the response of the inner service is then replaced with a `413`.
+//!
+//! Both `413` responses carry `Connection: close`, as the rest of the body is
+//! left unread, except for early rejections of requests expecting
+//! `100 Continue`, whose body is never transmitted.
+//!
+//! On the client side, `ResponseBodyLimit` wraps response bodies in
+//! `Limited`, so reading a response larger than the limit fails instead of
+//! buffering an unbounded amount of data from the upstream.
+//!
+//! Tunnels are not limited, as their bodies carry the upgraded connection.
+//! Since any client can ask for one, the request limit only applies until
+//! the inner service accepts the tunnel, by answering with
+//! `101 Switching Protocols`, or with a `2xx` response to a `CONNECT`
+//! request or a request carrying the `Upgrade` extension of the `upgrade`
+//! module. It is never lifted for requests whose body is framed by
+//! `Content-Length` or `Transfer-Encoding`. Response limits are lifted for
+//! upgrade responses.
+
+use crate::expect_continue::expects_continue;
+use crate::upgrade::{is_tunnel_request, is_upgrade_response, opens_tunnel};
+use bytes::Buf;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, CONNECTION, CONTENT_LENGTH, TRANSFER_ENCODING};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::error::Error;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Rejects request bodies larger than a limit with `413 Payload Too Large`.
+#[derive(Debug, Clone)]
+pub struct RequestBodyLimit<S> {
+    inner: S,
+    limit: u64,
+}
+
+/// Wraps response bodies in `Limited`.
+#[derive(Debug, Clone)]
+pub struct ResponseBodyLimit<S> {
+    inner: S,
+    limit: u64,
+}
+
+/// A body that fails once more than a limit has been read from it.
+#[derive(Debug)]
+pub struct Limited<B> {
+    inner: B,
+    remaining: u64,
+    exceeded: Option<Arc<AtomicBool>>,
+    lifted: Option<Arc<AtomicBool>>,
+}
+
+/// Error returned by `Limited`.
+#[derive(Debug)]
+pub enum LimitError<E> {
+    /// The body is larger than the limit.
+    LengthLimitExceeded,
+    /// The inner body failed.
+    Body(E),
+}
+
+/// Response future for `RequestBodyLimit`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+/// Response future for `ResponseBodyLimit`.
+#[derive(Debug)]
+pub struct LimitedResponseFuture<F> {
+    inner: F,
+    limit: u64,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted {
+        future: F,
+        exceeded: Arc<AtomicBool>,
+        lifted: Option<Arc<AtomicBool>>,
+        tunnel: bool,
+    },
+    Rejected(Option<Response<B>>),
+}
+
+// ===== impl RequestBodyLimit =====
+
+impl<S> RequestBodyLimit<S> {
+    /// Create a new `RequestBodyLimit` accepting bodies of at most `limit`
+    /// bytes.
+    pub fn new(inner: S, limit: u64) -> Self {
+        RequestBodyLimit { inner, limit }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequestBodyLimit<S>
+where
+    S: Service<Request<Limited<ReqBody>>, Response = Response<ResBody>>,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if content_length(req.headers()).map_or(false, |len| len > self.limit) {
+            let mut res = payload_too_large();
+            if expects_continue(&req) {
+                res.headers_mut().remove(CONNECTION);
+            }
+            return ResponseFuture {
+                state: State::Rejected(Some(res)),
+            };
+        }
+
+        let framed = is_framed(req.headers());
+        let tunnel = is_tunnel_request(&req);
+        // Unframed bodies may turn into a tunnel once the inner service
+        // accepts it.
+        let lifted = if framed {
+            None
+        } else {
+            Some(Arc::new(AtomicBool::new(false)))
+        };
+        let exceeded = Arc::new(AtomicBool::new(false));
+        let flag = exceeded.clone();
+        let lift = lifted.clone();
+        let req = req.map(|body| Limited {
+            inner: body,
+            remaining: self.limit,
+            exceeded: Some(flag),
+            lifted: lift,
+        });
+
+        ResponseFuture {
+            state: State::Accepted {
+                future: self.inner.call(req),
+                exceeded,
+                lifted,
+                tunnel,
+            },
+        }
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+fn is_framed(headers: &HeaderMap) -> bool {
+    headers.contains_key(CONTENT_LENGTH) || headers.contains_key(TRANSFER_ENCODING)
+}
+
+fn payload_too_large<B: Default>() -> Response<B> {
+    let mut res = Response::new(B::default());
+    *res.status_mut() = StatusCode::PAYLOAD_TOO_LARGE;
+    res.headers_mut()
+        .insert(CONNECTION, HeaderValue::from_static("close"));
+    res
+}
+
+// ===== impl ResponseBodyLimit =====
+
+impl<S> ResponseBodyLimit<S> {
+    /// Create a new `ResponseBodyLimit` allowing at most `limit` bytes to be
+    /// read from each response body.
+    pub fn new(inner: S, limit: u64) -> Self {
+        ResponseBodyLimit { inner, limit }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ResponseBodyLimit<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+{
+    type Response = Response<Limited<ResBody>>;
+    type Error = S::Error;
+    type Future = LimitedResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        LimitedResponseFuture {
+            inner: self.inner.call(req),
+            limit: self.limit,
+        }
+    }
+}
+
+// ===== impl Limited =====
+
+impl<B> Limited<B> {
+    /// Wrap `body`, failing once more than `limit` bytes have been read.
+    pub fn new(body: B, limit: u64) -> Self {
+        Limited {
+            inner: body,
+            remaining: limit,
+            exceeded: None,
+            lifted: None,
+        }
+    }
+
+    /// Returns a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for Limited<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = LimitError<B::Error>;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = match self.inner.poll_data().map_err(LimitError::Body)? {
+            Async::Ready(Some(data)) => data,
+            Async::Ready(None) => return Ok(Async::Ready(None)),
+            Async::NotReady => return Ok(Async::NotReady),
+        };
+
+        if self
+            .lifted
+            .as_ref()
+            .map_or(false, |lifted| lifted.load(Ordering::SeqCst))
+        {
+            return Ok(Async::Ready(Some(data)));
+        }
+
+        let len = data.remaining() as u64;
+        if len > self.remaining {
+            self.remaining = 0;
+            if let Some(ref exceeded) = self.exceeded {
+                exceeded.store(true, Ordering::SeqCst);
+            }
+            return Err(LimitError::LengthLimitExceeded);
+        }
+
+        self.remaining -= len;
+        Ok(Async::Ready(Some(data)))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers().map_err(LimitError::Body)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl LimitError =====
+
+impl<E: fmt::Display> fmt::Display for LimitError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LimitError::LengthLimitExceeded => f.write_str("length limit exceeded"),
+            LimitError::Body(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for LimitError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LimitError::LengthLimitExceeded => None,
+            LimitError::Body(ref e) => Some(e),
+        }
+    }
+}
+
+// ===== impl LimitedResponseFuture =====
+
+impl<F, B> Future for LimitedResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = Response<Limited<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+        let limit = if is_upgrade_response(&response) {
+            std::u64::MAX
+        } else {
+            self.limit
+        };
+        Ok(Async::Ready(response.map(|body| Limited::new(body, limit))))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted {
+                ref mut future,
+                ref exceeded,
+                ref lifted,
+                tunnel,
+            } => {
+                let result = future.poll();
+                // The inner service may have failed or responded because the
+                // body was cut off; either way the client gets a `413`.
+                match result {
+                    Ok(Async::NotReady) => Ok(Async::NotReady),
+                    _ if exceeded.load(Ordering::SeqCst) => Ok(Async::Ready(payload_too_large())),
+                    Ok(Async::Ready(response)) => {
+                        if let Some(ref lifted) = *lifted {
+                            if opens_tunnel(tunnel, &response) {
+                                lifted.store(true, Ordering::SeqCst);
+                            }
+                        }
+                        Ok(Async::Ready(response))
+                    }
+                    result => result,
+                }
+            }
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,971 @@
+//! Cache responses on the server side.
+//!
+//! `Cache` is a shared cache following RFC 7234. Responses to `GET`
+//! requests are stored when their status, `Cache-Control` and `Expires`
+//! headers allow it, and later `GET` and `HEAD` requests for the same URI
+//! are answered from the cache, without calling the inner service, for as
+//! long as the response stays fresh. Responses served from the cache carry
+//! an `Age` header.
+//!
+//! Freshness comes from `s-maxage`, `max-age` or `Expires`, in that order,
+//! or, for statuses cacheable by default, a tenth of the time since
+//! `Last-Modified`, up to a day. As the cache does not revalidate, responses
+//! with `Cache-Control: no-cache` or `Vary: *` are not stored. Neither are
+//! responses with `Set-Cookie`, whose cookies must not be handed to other
+//! clients. Requests with `Cache-Control: no-cache` bypass the cache, and
+//! successful unsafe requests, such as `POST`, invalidate the entry of their
+//! URI.
+//!
+//! Responses with a `Vary` header are stored as variants of their URI,
+//! along with the values of the request headers it names, and only served
+//! to requests with the same values, so that e.g. compressed responses are
+//! not sent to clients not accepting them. Up to 16 variants are kept per
+//! URI, dropping the least recently stored ones.
+//!
+//! Responses live in a `CacheStore`. `MemoryStore` keeps them in memory,
+//! evicting the least recently used ones; responses can be kept on disk or
+//! in a shared store by implementing the trait. As looking up the store is
+//! asynchronous, the inner service must be `Clone`. A store failing is
+//! treated as a miss, so that requests are still answered.
+//!
+//! Concurrent `GET` requests missing the same URI are coalesced, so that a
+//! popular response going stale does not send a stampede of requests to the
+//! inner service: only the first request calls it, and the others wait for
+//! its response to be stored, to be served from it. Should the response not
+//! be storable or be another variant, or the first request fail, they call
+//! the inner service themselves, as they do once the coalescing timeout, if
+//! any, has elapsed.
+//!
+//! In a client stack, the cache can act as a private cache instead, storing
+//! responses for a single user: `private` responses, responses with
+//! `Set-Cookie` and responses to requests with an `Authorization` header are
+//! stored, and `s-maxage` is ignored. Stale responses with an `ETag` or
+//! `Last-Modified` header are kept for a day to be revalidated with
+//! `If-None-Match` and `If-Modified-Since`, and `304 Not Modified` responses
+//! to revalidations are replaced with the stored response, updated with
+//! their headers.
+//!
+//! Storable response bodies are buffered before being sent, so that they can
+//! be stored once complete. As soon as a body grows larger than the maximum
+//! entry size, it is not stored, and the rest of it is streamed after the
+//! part already buffered.
+//!
+//! Upgrade requests, as defined by the `upgrade` module, bypass the cache
+//! altogether.
+
+mod policy;
+mod store;
+
+pub use self::store::{CacheStore, CachedResponse, MemoryStore};
+
+use self::policy::CacheControl;
+use crate::upgrade::is_upgrade_request;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::sync::oneshot;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, AGE, CONTENT_LENGTH, ETAG, HOST};
+use http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use http::{response, Method, Request, Response, StatusCode};
+use http_body::Body;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use std::{fmt, mem};
+use tokio_timer::Delay;
+use tower_service::Service;
+
+/// The maximum number of variants stored per URI.
+const MAX_VARIANTS: usize = 16;
+
+/// How long private caches keep stale responses for revalidation.
+const REVALIDATION_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The requests waiting for the response to another, by cache key.
+type Flights = Arc<Mutex<HashMap<String, Vec<oneshot::Sender<CachedResponse>>>>>;
+
+/// Caches responses, serving fresh ones without calling the inner service.
+#[derive(Debug, Clone)]
+pub struct Cache<S, T = MemoryStore> {
+    inner: S,
+    store: T,
+    config: Arc<Config>,
+    flights: Flights,
+}
+
+/// Configure a `Cache`.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    max_entries: usize,
+    config: Config,
+}
+
+/// Response body for `Cache`.
+///
+/// Yields the part of the body buffered before it was found too large to
+/// store, if any, then the rest of the body.
+#[derive(Debug)]
+pub struct CacheBody<B> {
+    prefix: Option<B>,
+    inner: B,
+}
+
+/// Response future for `Cache`.
+pub struct ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: CacheStore,
+{
+    state: State<S, T, ReqBody, ResBody>,
+    store: T,
+    config: Arc<Config>,
+    flights: Flights,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    max_entry_size: usize,
+    coalesce_timeout: Option<Duration>,
+    private: bool,
+}
+
+/// A `GET` request that missed the cache.
+#[derive(Debug)]
+struct Miss {
+    key: String,
+    headers: HeaderMap,
+    cc: CacheControl,
+    requested_at: SystemTime,
+    /// The fresh variants for other requests, stored along the response.
+    variants: Vec<CachedResponse>,
+    /// Set when other requests may wait for the response.
+    flight: Option<Flight>,
+}
+
+/// Removes the key from the in-flight map when the leading request ends,
+/// making the waiting requests call the inner service unless completed.
+struct Flight {
+    key: String,
+    done: bool,
+    flights: Flights,
+}
+
+enum State<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: CacheStore,
+{
+    Looking {
+        lookup: T::GetFuture,
+        service: S,
+        request: Request<ReqBody>,
+        key: String,
+        cc: CacheControl,
+    },
+    Waiting {
+        rx: oneshot::Receiver<CachedResponse>,
+        delay: Option<Delay>,
+        service: S,
+        request: Request<ReqBody>,
+        miss: Miss,
+    },
+    Fetching {
+        future: S::Future,
+        miss: Miss,
+    },
+    Revalidating {
+        future: S::Future,
+        miss: Miss,
+        cached: CachedResponse,
+    },
+    Buffering {
+        parts: response::Parts,
+        body: ResBody,
+        buf: BytesMut,
+        miss: Miss,
+        initial_age: Duration,
+        lifetime: Duration,
+    },
+    Invalidating {
+        future: S::Future,
+        key: String,
+    },
+    Writing {
+        write: T::WriteFuture,
+        response: Option<Response<ResBody>>,
+    },
+    Called(S::Future),
+    Done,
+}
+
+// ===== impl Cache =====
+
+impl<S> Cache<S> {
+    /// Create a new `Cache` storing up to 1024 responses of up to 1 MiB in
+    /// memory.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Create a new private `Cache` for a client stack, storing up to 1024
+    /// responses of up to 1 MiB in memory.
+    pub fn private(inner: S) -> Self {
+        Builder::new().private(true).build(inner)
+    }
+}
+
+impl<S, T> Cache<S, T> {
+    /// Create a new `Cache` storing responses of up to 1 MiB in `store`.
+    pub fn with_store(inner: S, store: T) -> Self {
+        Builder::new().build_with_store(inner, store)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for Cache<S, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    S::Error: From<ResBody::Error>,
+    T: CacheStore + Clone,
+    ResBody: Body + From<Bytes>,
+{
+    type Response = Response<CacheBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, T, ReqBody, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let key = cache_key(&req);
+        let method = req.method().clone();
+        let cc = CacheControl::parse(req.headers());
+        // Other variants are looked up to be kept along a new response.
+        let state = if is_upgrade_request(&req) {
+            State::Called(self.inner.call(req))
+        } else if method == Method::GET || (method == Method::HEAD && !cc.no_cache) {
+            let clone = self.inner.clone();
+            State::Looking {
+                lookup: self.store.get(&key),
+                service: mem::replace(&mut self.inner, clone),
+                request: req,
+                key,
+                cc,
+            }
+        } else if is_safe(&method) {
+            State::Called(self.inner.call(req))
+        } else {
+            State::Invalidating {
+                future: self.inner.call(req),
+                key,
+            }
+        };
+
+        ResponseFuture {
+            state,
+            store: self.store.clone(),
+            config: self.config.clone(),
+            flights: self.flights.clone(),
+        }
+    }
+}
+
+/// Keys entries by the host and target of requests.
+fn cache_key<B>(req: &Request<B>) -> String {
+    let host = req
+        .uri()
+        .authority_part()
+        .map(|authority| authority.as_str())
+        .or_else(|| req.headers().get(HOST).and_then(|host| host.to_str().ok()))
+        .unwrap_or("");
+    let target = req
+        .uri()
+        .path_and_query()
+        .map_or("/", |target| target.as_str());
+    format!("{}{}", host, target)
+}
+
+fn is_safe(method: &Method) -> bool {
+    *method == Method::GET
+        || *method == Method::HEAD
+        || *method == Method::OPTIONS
+        || *method == Method::TRACE
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            max_entries: 1024,
+            config: Config {
+                max_entry_size: 1024 * 1024,
+                coalesce_timeout: None,
+                private: false,
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder` storing up to 1024 responses of up to 1 MiB.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Store at most `max` responses in memory.
+    ///
+    /// This has no effect on caches built with `build_with_store`.
+    pub fn max_entries(mut self, max: usize) -> Self {
+        self.max_entries = max;
+        self
+    }
+
+    /// Only store response bodies of at most `max` bytes.
+    pub fn max_entry_size(mut self, max: usize) -> Self {
+        self.config.max_entry_size = max;
+        self
+    }
+
+    /// Stop waiting for the response to a concurrent request for the same
+    /// URI after `timeout`, calling the inner service instead.
+    ///
+    /// By default, requests wait until the concurrent request ends.
+    pub fn coalesce_timeout(mut self, timeout: Duration) -> Self {
+        self.config.coalesce_timeout = Some(timeout);
+        self
+    }
+
+    /// Act as a private cache, in a client stack, rather than as a shared
+    /// one.
+    pub fn private(mut self, private: bool) -> Self {
+        self.config.private = private;
+        self
+    }
+
+    /// Build a `Cache` wrapping `inner`, storing responses in memory.
+    pub fn build<S>(self, inner: S) -> Cache<S> {
+        let store = MemoryStore::new(self.max_entries);
+        self.build_with_store(inner, store)
+    }
+
+    /// Build a `Cache` wrapping `inner`, storing responses in `store`.
+    pub fn build_with_store<S, T>(self, inner: S, store: T) -> Cache<S, T> {
+        Cache {
+            inner,
+            store,
+            config: Arc::new(self.config),
+            flights: Flights::default(),
+        }
+    }
+}
+
+// ===== impl Miss =====
+
+impl Miss {
+    fn new(key: String, headers: HeaderMap, cc: CacheControl) -> Self {
+        Miss {
+            key,
+            headers,
+            cc,
+            requested_at: SystemTime::now(),
+            variants: Vec::new(),
+            flight: None,
+        }
+    }
+
+    /// Decides whether to store a response, returning its initial age and
+    /// freshness lifetime if so.
+    fn storable(&self, parts: &response::Parts, config: &Config) -> Option<(Duration, Duration)> {
+        let cc = CacheControl::parse(&parts.headers);
+        let shared = !config.private;
+        let storable = policy::is_storable(
+            &self.headers,
+            &self.cc,
+            parts.status,
+            &parts.headers,
+            &cc,
+            shared,
+        );
+        if !storable {
+            return None;
+        }
+
+        let too_large = parts
+            .headers
+            .get(CONTENT_LENGTH)
+            .and_then(|len| len.to_str().ok())
+            .and_then(|len| len.parse::<u64>().ok())
+            .map_or(false, |len| len > config.max_entry_size as u64);
+        if too_large {
+            return None;
+        }
+
+        let now = SystemTime::now();
+        let lifetime = policy::freshness_lifetime(parts.status, &parts.headers, &cc, shared, now);
+        let initial_age = policy::initial_age(&parts.headers, self.requested_at, now);
+        let revalidatable = config.private && policy::has_validators(&parts.headers);
+        match lifetime {
+            Some(lifetime) if initial_age < lifetime || revalidatable => {
+                Some((initial_age, lifetime))
+            }
+            None if revalidatable => Some((initial_age, Duration::default())),
+            _ => None,
+        }
+    }
+
+    /// Adds the variant of a response to the request, returning the variants
+    /// to store and how long to keep them.
+    fn into_variants(
+        self,
+        cached: CachedResponse,
+        config: &Config,
+    ) -> (Vec<CachedResponse>, Duration) {
+        let mut variants = self.variants;
+        variants.insert(0, cached);
+        variants.truncate(MAX_VARIANTS);
+        let ttl = variants
+            .iter()
+            .map(|variant| {
+                let fresh = variant
+                    .lifetime
+                    .checked_sub(variant.age())
+                    .unwrap_or_default();
+                if config.private && policy::has_validators(&variant.headers) {
+                    fresh.checked_add(REVALIDATION_TTL).unwrap_or(fresh)
+                } else {
+                    fresh
+                }
+            })
+            .max()
+            .unwrap_or_default();
+        (variants, ttl)
+    }
+}
+
+// ===== impl Flight =====
+
+impl Flight {
+    /// Sends the stored response to the waiting requests.
+    fn complete(mut self, cached: &CachedResponse) {
+        self.done = true;
+        let waiters = self.flights.lock().unwrap().remove(&self.key);
+        for tx in waiters.into_iter().flatten() {
+            let _ = tx.send(cached.clone());
+        }
+    }
+}
+
+impl Drop for Flight {
+    fn drop(&mut self) {
+        // Dropping the senders makes the waiting requests call the inner
+        // service themselves.
+        if !self.done {
+            self.flights.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+impl fmt::Debug for Flight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Flight").field("key", &self.key).finish()
+    }
+}
+
+// ===== impl CachedResponse =====
+
+impl CachedResponse {
+    /// Returns whether this variant was selected by the same request header
+    /// values as `headers`.
+    fn matches(&self, headers: &HeaderMap) -> bool {
+        policy::vary(&self.headers).map_or(false, |names| {
+            names
+                .iter()
+                .all(|name| policy::same_values(&self.request_headers, headers, name))
+        })
+    }
+
+    /// Returns whether the response may answer a request with the given
+    /// `Cache-Control` directives.
+    fn is_fresh_for(&self, cc: &CacheControl) -> bool {
+        let age = self.age();
+        age < self.lifetime
+            && cc.max_age.map_or(true, |max_age| age <= max_age)
+            && cc.min_fresh.map_or(true, |min_fresh| {
+                age.checked_add(min_fresh)
+                    .map_or(false, |age| age < self.lifetime)
+            })
+    }
+
+    /// Returns the response updated with the headers of a `304 Not Modified`
+    /// response to a revalidation sent at `requested_at`.
+    fn revalidated(&self, not_modified: &HeaderMap, requested_at: SystemTime) -> Self {
+        let mut headers = self.headers.clone();
+        for name in not_modified.keys() {
+            if name == CONTENT_LENGTH {
+                continue;
+            }
+            headers.remove(name);
+            for value in not_modified.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+
+        let now = SystemTime::now();
+        let cc = CacheControl::parse(&headers);
+        CachedResponse {
+            lifetime: policy::freshness_lifetime(self.status, &headers, &cc, false, now)
+                .unwrap_or_default(),
+            initial_age: policy::initial_age(not_modified, requested_at, now),
+            stored_at: now,
+            headers,
+            ..self.clone()
+        }
+    }
+
+    /// Makes `headers` those of a request revalidating this response.
+    fn add_validators(&self, headers: &mut HeaderMap) {
+        if let Some(etag) = self.headers.get(ETAG) {
+            headers.insert(IF_NONE_MATCH, etag.clone());
+        }
+        if let Some(last_modified) = self.headers.get(LAST_MODIFIED) {
+            headers.insert(IF_MODIFIED_SINCE, last_modified.clone());
+        }
+    }
+
+    fn to_response<B: From<Bytes>>(&self, head: bool) -> Response<B> {
+        let body = if head {
+            Bytes::new()
+        } else {
+            self.body.clone()
+        };
+        let mut res = Response::new(B::from(body));
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers.clone();
+        res.headers_mut()
+            .insert(AGE, HeaderValue::from(self.age().as_secs()));
+        res
+    }
+}
+
+// ===== impl CacheBody =====
+
+impl<B> CacheBody<B> {
+    fn new(inner: B) -> Self {
+        CacheBody {
+            prefix: None,
+            inner,
+        }
+    }
+}
+
+impl<B> Body for CacheBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        if let Some(ref mut prefix) = self.prefix {
+            if let Some(data) = try_ready!(prefix.poll_data()) {
+                return Ok(Async::Ready(Some(data)));
+            }
+        }
+        self.prefix = None;
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.prefix.as_ref().map_or(true, Body::is_end_stream) && self.inner.is_end_stream()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, T, ReqBody, ResBody> ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: CacheStore,
+{
+    /// Waits for the response to a concurrent request missing the same URI,
+    /// or lets other requests wait for the response to this one.
+    fn join_flight(&self, miss: &mut Miss) -> Option<oneshot::Receiver<CachedResponse>> {
+        let mut flights = self.flights.lock().unwrap();
+        if let Some(waiters) = flights.get_mut(&miss.key) {
+            // Requests wanting a response from the origin do not wait for one
+            // that may have been requested earlier.
+            if miss.cc.no_cache {
+                return None;
+            }
+            let (tx, rx) = oneshot::channel();
+            waiters.push(tx);
+            return Some(rx);
+        }
+
+        flights.insert(miss.key.clone(), Vec::new());
+        miss.flight = Some(Flight {
+            key: miss.key.clone(),
+            done: false,
+            flights: self.flights.clone(),
+        });
+        None
+    }
+
+    /// Returns whether to revalidate a stored response rather than fetching
+    /// a new one, leaving conditional requests of the client alone.
+    fn can_revalidate(&self, cached: &CachedResponse, request: &Request<ReqBody>) -> bool {
+        self.config.private
+            && policy::has_validators(&cached.headers)
+            && !policy::is_conditional(request.headers())
+    }
+
+    /// Handles the response to a request that missed the cache, returning it
+    /// unless it is to be stored.
+    fn fetched(&mut self, response: Response<ResBody>, miss: Miss) -> Option<Response<ResBody>> {
+        let (parts, body) = response.into_parts();
+        match miss.storable(&parts, &self.config) {
+            Some((initial_age, lifetime)) => {
+                self.state = State::Buffering {
+                    parts,
+                    body,
+                    buf: BytesMut::new(),
+                    miss,
+                    initial_age,
+                    lifetime,
+                };
+                None
+            }
+            None => Some(Response::from_parts(parts, body)),
+        }
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Future for ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: From<ResBody::Error>,
+    T: CacheStore,
+    ResBody: Body + From<Bytes>,
+{
+    type Item = Response<CacheBody<ResBody>>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Looking {
+                    mut lookup,
+                    mut service,
+                    mut request,
+                    key,
+                    cc,
+                } => {
+                    let variants = match lookup.poll() {
+                        Ok(Async::Ready(variants)) => variants,
+                        Ok(Async::NotReady) => {
+                            self.state = State::Looking {
+                                lookup,
+                                service,
+                                request,
+                                key,
+                                cc,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                        Err(_) => Vec::new(),
+                    };
+
+                    let head = request.method() == Method::HEAD;
+                    let (matching, others): (Vec<_>, Vec<_>) = variants
+                        .into_iter()
+                        .partition(|variant| variant.matches(request.headers()));
+                    match matching.into_iter().next() {
+                        Some(ref cached) if !cc.no_cache && cached.is_fresh_for(&cc) => {
+                            let response = cached.to_response(head);
+                            return Ok(Async::Ready(response.map(CacheBody::new)));
+                        }
+                        _ if head => self.state = State::Called(service.call(request)),
+                        cached => {
+                            let mut miss = Miss::new(key, request.headers().clone(), cc);
+                            miss.variants = others
+                                .into_iter()
+                                .filter(CachedResponse::is_fresh)
+                                .collect();
+                            self.state = match self.join_flight(&mut miss) {
+                                Some(rx) => State::Waiting {
+                                    rx,
+                                    delay: self
+                                        .config
+                                        .coalesce_timeout
+                                        .map(|timeout| Delay::new(Instant::now() + timeout)),
+                                    service,
+                                    request,
+                                    miss,
+                                },
+                                None => match cached
+                                    .filter(|cached| self.can_revalidate(cached, &request))
+                                {
+                                    Some(cached) => {
+                                        cached.add_validators(request.headers_mut());
+                                        State::Revalidating {
+                                            future: service.call(request),
+                                            miss,
+                                            cached,
+                                        }
+                                    }
+                                    None => State::Fetching {
+                                        future: service.call(request),
+                                        miss,
+                                    },
+                                },
+                            };
+                        }
+                    }
+                }
+                State::Waiting {
+                    mut rx,
+                    mut delay,
+                    mut service,
+                    request,
+                    miss,
+                } => {
+                    let timed_out = match delay {
+                        Some(ref mut delay) => match delay.poll() {
+                            Ok(Async::NotReady) => false,
+                            // Timer errors end the wait too.
+                            _ => true,
+                        },
+                        None => false,
+                    };
+                    match rx.poll() {
+                        Ok(Async::Ready(cached))
+                            if cached.matches(request.headers())
+                                && cached.is_fresh_for(&miss.cc) =>
+                        {
+                            let response = cached.to_response(false);
+                            return Ok(Async::Ready(response.map(CacheBody::new)));
+                        }
+                        Ok(Async::NotReady) if !timed_out => {
+                            self.state = State::Waiting {
+                                rx,
+                                delay,
+                                service,
+                                request,
+                                miss,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                        _ => {}
+                    }
+                    self.state = State::Fetching {
+                        future: service.call(request),
+                        miss,
+                    };
+                }
+                State::Fetching { mut future, miss } => {
+                    let response = match future.poll()? {
+                        Async::Ready(response) => response,
+                        Async::NotReady => {
+                            self.state = State::Fetching { future, miss };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    if let Some(response) = self.fetched(response, miss) {
+                        return Ok(Async::Ready(response.map(CacheBody::new)));
+                    }
+                }
+                State::Revalidating {
+                    mut future,
+                    mut miss,
+                    cached,
+                } => {
+                    let response = match future.poll()? {
+                        Async::Ready(response) => response,
+                        Async::NotReady => {
+                            self.state = State::Revalidating {
+                                future,
+                                miss,
+                                cached,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    if response.status() != StatusCode::NOT_MODIFIED {
+                        if let Some(response) = self.fetched(response, miss) {
+                            return Ok(Async::Ready(response.map(CacheBody::new)));
+                        }
+                        continue;
+                    }
+
+                    let cached = cached.revalidated(response.headers(), miss.requested_at);
+                    if let Some(flight) = miss.flight.take() {
+                        flight.complete(&cached);
+                    }
+                    let response = cached.to_response(false);
+                    let key = miss.key.clone();
+                    let (variants, ttl) = miss.into_variants(cached, &self.config);
+                    self.state = State::Writing {
+                        write: self.store.put(key, variants, ttl),
+                        response: Some(response),
+                    };
+                }
+                State::Buffering {
+                    parts,
+                    mut body,
+                    mut buf,
+                    mut miss,
+                    initial_age,
+                    lifetime,
+                } => match body.poll_data()? {
+                    Async::Ready(Some(mut data)) => {
+                        while data.has_remaining() {
+                            let n = {
+                                let bytes = data.bytes();
+                                buf.extend_from_slice(bytes);
+                                bytes.len()
+                            };
+                            data.advance(n);
+                        }
+                        if buf.len() > self.config.max_entry_size {
+                            // Dropping the miss releases the waiting requests.
+                            let body = CacheBody {
+                                prefix: Some(ResBody::from(buf.freeze())),
+                                inner: body,
+                            };
+                            return Ok(Async::Ready(Response::from_parts(parts, body)));
+                        }
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf,
+                            miss,
+                            initial_age,
+                            lifetime,
+                        };
+                    }
+                    Async::Ready(None) => {
+                        let body = buf.freeze();
+                        let mut request_headers = HeaderMap::new();
+                        for name in policy::vary(&parts.headers).unwrap_or_default() {
+                            for value in miss.headers.get_all(&name) {
+                                request_headers.append(name.clone(), value.clone());
+                            }
+                        }
+                        let cached = CachedResponse {
+                            status: parts.status,
+                            version: parts.version,
+                            headers: parts.headers.clone(),
+                            body: body.clone(),
+                            request_headers,
+                            stored_at: SystemTime::now(),
+                            initial_age,
+                            lifetime,
+                        };
+                        if let Some(flight) = miss.flight.take() {
+                            flight.complete(&cached);
+                        }
+                        let key = miss.key.clone();
+                        let (variants, ttl) = miss.into_variants(cached, &self.config);
+                        self.state = State::Writing {
+                            write: self.store.put(key, variants, ttl),
+                            response: Some(Response::from_parts(parts, ResBody::from(body))),
+                        };
+                    }
+                    Async::NotReady => {
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf,
+                            miss,
+                            initial_age,
+                            lifetime,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Invalidating { mut future, key } => {
+                    let response = match future.poll()? {
+                        Async::Ready(response) => response,
+                        Async::NotReady => {
+                            self.state = State::Invalidating { future, key };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    let status = response.status();
+                    if !status.is_success() && !status.is_redirection() {
+                        return Ok(Async::Ready(response.map(CacheBody::new)));
+                    }
+                    self.state = State::Writing {
+                        write: self.store.invalidate(&key),
+                        response: Some(response),
+                    };
+                }
+                State::Writing {
+                    mut write,
+                    mut response,
+                } => {
+                    // Failing to write leaves the cache as it was.
+                    if let Ok(Async::NotReady) = write.poll() {
+                        self.state = State::Writing { write, response };
+                        return Ok(Async::NotReady);
+                    }
+                    let response = response.take().expect("polled after completion");
+                    return Ok(Async::Ready(response.map(CacheBody::new)));
+                }
+                State::Called(mut future) => match future.poll()? {
+                    Async::Ready(response) => {
+                        return Ok(Async::Ready(response.map(CacheBody::new)));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Called(future);
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, T, ReqBody, ResBody> fmt::Debug for ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: CacheStore,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Looking { .. } => "Looking",
+            State::Waiting { .. } => "Waiting",
+            State::Fetching { .. } => "Fetching",
+            State::Revalidating { .. } => "Revalidating",
+            State::Buffering { .. } => "Buffering",
+            State::Invalidating { .. } => "Invalidating",
+            State::Writing { .. } => "Writing",
+            State::Called(_) => "Called",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
@@ -0,0 +1,317 @@
+use http::header::{HeaderMap, HeaderName, AGE, AUTHORIZATION, CACHE_CONTROL, DATE, ETAG, EXPIRES};
+use http::header::{IF_MATCH, IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, IF_UNMODIFIED_SINCE};
+use http::header::{LAST_MODIFIED, SET_COOKIE, VARY};
+use http::StatusCode;
+use std::time::{Duration, SystemTime};
+
+/// The longest heuristic freshness lifetime.
+const MAX_HEURISTIC_LIFETIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The directives of `Cache-Control` headers relevant to caching.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct CacheControl {
+    pub(crate) no_store: bool,
+    pub(crate) no_cache: bool,
+    pub(crate) private: bool,
+    pub(crate) public: bool,
+    pub(crate) must_revalidate: bool,
+    pub(crate) max_age: Option<Duration>,
+    pub(crate) s_maxage: Option<Duration>,
+    pub(crate) min_fresh: Option<Duration>,
+}
+
+impl CacheControl {
+    pub(crate) fn parse(headers: &HeaderMap) -> Self {
+        let mut cc = CacheControl::default();
+        let values = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok());
+        for directive in values.flat_map(|value| value.split(',')) {
+            let mut directive = directive.splitn(2, '=');
+            let name = directive.next().unwrap_or("").trim().to_ascii_lowercase();
+            // Invalid delta-seconds make the response stale, as recommended
+            // by RFC 7234.
+            let mut secs = || {
+                let secs = directive
+                    .next()
+                    .and_then(|arg| arg.trim().trim_matches('"').parse().ok());
+                Duration::from_secs(secs.unwrap_or(0))
+            };
+            // `no-cache` and `private` with field names are applied to the
+            // whole response.
+            match &*name {
+                "no-store" => cc.no_store = true,
+                "no-cache" => cc.no_cache = true,
+                "private" => cc.private = true,
+                "public" => cc.public = true,
+                "must-revalidate" | "proxy-revalidate" => cc.must_revalidate = true,
+                "max-age" => cc.max_age = Some(secs()),
+                "s-maxage" => cc.s_maxage = Some(secs()),
+                "min-fresh" => cc.min_fresh = Some(secs()),
+                _ => {}
+            }
+        }
+        cc
+    }
+}
+
+/// Returns whether a shared or private cache may store a response to a
+/// request.
+pub(crate) fn is_storable(
+    req_headers: &HeaderMap,
+    req_cc: &CacheControl,
+    status: StatusCode,
+    res_headers: &HeaderMap,
+    res_cc: &CacheControl,
+    shared: bool,
+) -> bool {
+    // Responses to authorized requests are only shared when they say so.
+    let shareable = !req_headers.contains_key(AUTHORIZATION)
+        || res_cc.public
+        || res_cc.must_revalidate
+        || res_cc.s_maxage.is_some();
+    // Shared caches do not revalidate, so `no-cache` responses could never be
+    // served from them. Cookies belong to the client they were set for.
+    // `Vary: *` responses can never be served.
+    let shared_allowed =
+        !res_cc.no_cache && !res_cc.private && !res_headers.contains_key(SET_COOKIE) && shareable;
+    !req_cc.no_store
+        && !res_cc.no_store
+        && (!shared || shared_allowed)
+        && is_understood(status)
+        && vary(res_headers).is_some()
+}
+
+/// Returns the names of the request headers listed by the `Vary` headers,
+/// or `None` for `Vary: *`.
+pub(crate) fn vary(headers: &HeaderMap) -> Option<Vec<HeaderName>> {
+    let mut names = Vec::new();
+    let values = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    for name in values.flat_map(|value| value.split(',')) {
+        let name = name.trim();
+        if name == "*" {
+            return None;
+        }
+        if let Ok(name) = HeaderName::from_bytes(name.as_bytes()) {
+            names.push(name);
+        }
+    }
+    Some(names)
+}
+
+/// Returns whether two requests have the same values for the header `name`,
+/// ignoring whitespace around commas.
+pub(crate) fn same_values(a: &HeaderMap, b: &HeaderMap, name: &HeaderName) -> bool {
+    fn values(headers: &HeaderMap, name: &HeaderName) -> Vec<String> {
+        headers
+            .get_all(name)
+            .iter()
+            .flat_map(|value| value.as_bytes().split(|&b| b == b','))
+            .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+    values(a, name) == values(b, name)
+}
+
+/// Returns how long a response stays fresh in a shared or private cache, if
+/// it can be told.
+pub(crate) fn freshness_lifetime(
+    status: StatusCode,
+    headers: &HeaderMap,
+    cc: &CacheControl,
+    shared: bool,
+    now: SystemTime,
+) -> Option<Duration> {
+    if cc.no_cache {
+        return Some(Duration::default());
+    }
+    let explicit = if shared {
+        cc.s_maxage.or(cc.max_age)
+    } else {
+        cc.max_age
+    };
+    if let Some(lifetime) = explicit {
+        return Some(lifetime);
+    }
+
+    let date = http_date(headers, &DATE).unwrap_or(now);
+    if headers.contains_key(EXPIRES) {
+        // Invalid dates, such as `0`, mean already expired.
+        let lifetime = http_date(headers, &EXPIRES)
+            .and_then(|expires| expires.duration_since(date).ok())
+            .unwrap_or_default();
+        return Some(lifetime);
+    }
+
+    if is_cacheable_by_default(status) {
+        let last_modified = http_date(headers, &LAST_MODIFIED)?;
+        let since = date.duration_since(last_modified).ok()?;
+        return Some((since / 10).min(MAX_HEURISTIC_LIFETIME));
+    }
+    None
+}
+
+/// Returns the age of a response when received at `now`, the request having
+/// been sent at `requested_at`.
+pub(crate) fn initial_age(
+    headers: &HeaderMap,
+    requested_at: SystemTime,
+    now: SystemTime,
+) -> Duration {
+    let apparent_age = http_date(headers, &DATE)
+        .and_then(|date| now.duration_since(date).ok())
+        .unwrap_or_default();
+    let age = headers
+        .get(AGE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map_or_else(Duration::default, Duration::from_secs);
+    let delay = now.duration_since(requested_at).unwrap_or_default();
+    // An `Age` too large to add the delay to is stale either way.
+    apparent_age.max(age.checked_add(delay).unwrap_or(age))
+}
+
+/// Returns whether a response can be revalidated with a conditional request.
+pub(crate) fn has_validators(headers: &HeaderMap) -> bool {
+    headers.contains_key(ETAG) || headers.contains_key(LAST_MODIFIED)
+}
+
+/// Returns whether a request is conditional.
+pub(crate) fn is_conditional(headers: &HeaderMap) -> bool {
+    [
+        IF_MATCH,
+        IF_NONE_MATCH,
+        IF_MODIFIED_SINCE,
+        IF_UNMODIFIED_SINCE,
+        IF_RANGE,
+    ]
+    .iter()
+    .any(|name| headers.contains_key(name))
+}
+
+fn http_date(headers: &HeaderMap, name: &HeaderName) -> Option<SystemTime> {
+    let value = headers.get(name)?.to_str().ok()?;
+    httpdate::parse_http_date(value).ok()
+}
+
+/// Statuses that may be stored with heuristic freshness.
+fn is_cacheable_by_default(status: StatusCode) -> bool {
+    match status.as_u16() {
+        200 | 203 | 204 | 300 | 301 | 404 | 405 | 410 | 414 | 501 => true,
+        _ => false,
+    }
+}
+
+/// Statuses that may be stored with explicit freshness. Partial content is
+/// not supported.
+fn is_understood(status: StatusCode) -> bool {
+    match status.as_u16() {
+        302 | 307 | 308 => true,
+        _ => is_cacheable_by_default(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+    use std::time::UNIX_EPOCH;
+
+    fn headers(pairs: &[(HeaderName, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.clone(), HeaderValue::from_static(value)))
+            .collect()
+    }
+
+    #[test]
+    fn parses_cache_control() {
+        let cc = CacheControl::parse(&headers(&[
+            (CACHE_CONTROL, "public, Max-Age=60"),
+            (CACHE_CONTROL, "s-maxage=\"120\", no-cache=\"set-cookie\""),
+        ]));
+        assert!(cc.public && cc.no_cache);
+        assert_eq!(cc.max_age, Some(Duration::from_secs(60)));
+        assert_eq!(cc.s_maxage, Some(Duration::from_secs(120)));
+
+        let cc = CacheControl::parse(&headers(&[(CACHE_CONTROL, "max-age=soon")]));
+        assert_eq!(cc.max_age, Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn matches_variants() {
+        let vary = headers(&[(VARY, "Accept-Encoding, accept-language")]);
+        assert_eq!(
+            super::vary(&vary),
+            Some(vec![
+                http::header::ACCEPT_ENCODING,
+                http::header::ACCEPT_LANGUAGE
+            ])
+        );
+        assert_eq!(super::vary(&headers(&[(VARY, "*")])), None);
+
+        let name = http::header::ACCEPT_ENCODING;
+        let a = headers(&[(name.clone(), "gzip,br")]);
+        let b = headers(&[(name.clone(), "gzip"), (name.clone(), " br")]);
+        assert!(same_values(&a, &b, &name));
+        assert!(!same_values(&a, &HeaderMap::new(), &name));
+        assert!(same_values(&HeaderMap::new(), &HeaderMap::new(), &name));
+    }
+
+    #[test]
+    fn computes_freshness() {
+        let now = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let lifetime = |pairs: &[(HeaderName, &'static str)]| {
+            let headers = headers(pairs);
+            let cc = CacheControl::parse(&headers);
+            freshness_lifetime(StatusCode::OK, &headers, &cc, true, now)
+        };
+
+        let date = (DATE, "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            lifetime(&[date.clone(), (EXPIRES, "Sun, 06 Nov 1994 08:59:37 GMT")]),
+            Some(Duration::from_secs(600))
+        );
+        assert_eq!(
+            lifetime(&[(CACHE_CONTROL, "max-age=5"), (EXPIRES, "0")]),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(lifetime(&[(EXPIRES, "0")]), Some(Duration::from_secs(0)));
+        assert_eq!(
+            lifetime(&[date, (LAST_MODIFIED, "Sun, 06 Nov 1994 08:32:57 GMT")]),
+            Some(Duration::from_secs(100))
+        );
+        assert_eq!(lifetime(&[]), None);
+
+        let headers = headers(&[(CACHE_CONTROL, "max-age=5, s-maxage=10")]);
+        let cc = CacheControl::parse(&headers);
+        let private = freshness_lifetime(StatusCode::OK, &headers, &cc, false, now);
+        assert_eq!(private, Some(Duration::from_secs(5)));
+    }
+
+    #[test]
+    fn computes_initial_age() {
+        let requested_at = UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let now = requested_at + Duration::from_secs(2);
+
+        let age = initial_age(&headers(&[(AGE, "10")]), requested_at, now);
+        assert_eq!(age, Duration::from_secs(12));
+
+        let date = headers(&[(DATE, "Sun, 06 Nov 1994 08:49:17 GMT")]);
+        assert_eq!(
+            initial_age(&date, requested_at, now),
+            Duration::from_secs(22)
+        );
+
+        let age = headers(&[(AGE, "18446744073709551615")]);
+        assert_eq!(
+            initial_age(&age, requested_at, now),
+            Duration::from_secs(std::u64::MAX)
+        );
+    }
+}
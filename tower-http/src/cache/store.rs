@@ -0,0 +1,284 @@
+use bytes::Bytes;
+use futures::future::{self, FutureResult};
+use futures::Future;
+use http::header::HeaderMap;
+use http::{StatusCode, Version};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Storage for cached responses.
+///
+/// Implement this trait to keep responses elsewhere than in memory, e.g. on
+/// disk or in Redis. Each key holds the variants of a response, selected by
+/// the request headers named by its `Vary` header. Stores may drop entries
+/// at any time, and should drop them once their time to live has elapsed.
+pub trait CacheStore {
+    /// Errors produced by the store.
+    type Error;
+
+    /// Future returned by `get`.
+    type GetFuture: Future<Item = Vec<CachedResponse>, Error = Self::Error>;
+
+    /// Future returned by `put` and `invalidate`.
+    type WriteFuture: Future<Item = (), Error = Self::Error>;
+
+    /// Get the variants stored with `key`, if any.
+    fn get(&self, key: &str) -> Self::GetFuture;
+
+    /// Store `variants` with `key` for `ttl`, replacing the previous ones.
+    fn put(&self, key: String, variants: Vec<CachedResponse>, ttl: Duration) -> Self::WriteFuture;
+
+    /// Remove the variants stored with `key`.
+    fn invalidate(&self, key: &str) -> Self::WriteFuture;
+}
+
+/// A response stored in a `CacheStore`.
+#[derive(Debug, Clone)]
+pub struct CachedResponse {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The HTTP version of the response.
+    pub version: Version,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The body of the response.
+    pub body: Bytes,
+    /// The request headers named by the `Vary` header of the response, which
+    /// select this variant.
+    pub request_headers: HeaderMap,
+    /// When the response was stored.
+    pub stored_at: SystemTime,
+    /// The age of the response when stored.
+    pub initial_age: Duration,
+    /// How long the response stays fresh, since it was generated.
+    pub lifetime: Duration,
+}
+
+/// An in-memory `CacheStore`, evicting the least recently used responses.
+///
+/// Clones share the same responses.
+#[derive(Clone)]
+pub struct MemoryStore {
+    lru: Arc<Mutex<Lru>>,
+}
+
+struct Lru {
+    capacity: usize,
+    entries: HashMap<String, LruEntry>,
+    /// Keys by the tick of their last use.
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+struct LruEntry {
+    variants: Vec<CachedResponse>,
+    expires: Instant,
+    used: u64,
+}
+
+// ===== impl CachedResponse =====
+
+impl CachedResponse {
+    /// Returns the current age of the response.
+    pub fn age(&self) -> Duration {
+        let resident = SystemTime::now()
+            .duration_since(self.stored_at)
+            .unwrap_or_default();
+        self.initial_age
+            .checked_add(resident)
+            .unwrap_or(self.initial_age)
+    }
+
+    /// Returns whether the response is still fresh.
+    pub fn is_fresh(&self) -> bool {
+        self.age() < self.lifetime
+    }
+}
+
+// ===== impl MemoryStore =====
+
+impl MemoryStore {
+    /// Create a new `MemoryStore` holding the responses of up to `capacity`
+    /// keys.
+    pub fn new(capacity: usize) -> Self {
+        MemoryStore {
+            lru: Arc::new(Mutex::new(Lru {
+                capacity,
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                tick: 0,
+            })),
+        }
+    }
+
+    /// Returns the number of keys in the store, including expired ones not
+    /// evicted yet.
+    pub fn len(&self) -> usize {
+        self.lru.lock().unwrap().entries.len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl CacheStore for MemoryStore {
+    type Error = std::convert::Infallible;
+    type GetFuture = FutureResult<Vec<CachedResponse>, Self::Error>;
+    type WriteFuture = FutureResult<(), Self::Error>;
+
+    fn get(&self, key: &str) -> Self::GetFuture {
+        future::ok(self.lru.lock().unwrap().get(key, Instant::now()))
+    }
+
+    fn put(&self, key: String, variants: Vec<CachedResponse>, ttl: Duration) -> Self::WriteFuture {
+        let mut lru = self.lru.lock().unwrap();
+        // Entries outliving any representable instant are not stored, but
+        // still replace the previous ones.
+        match Instant::now().checked_add(ttl) {
+            Some(expires) => lru.put(key, variants, expires),
+            None => lru.remove(&key),
+        }
+        future::ok(())
+    }
+
+    fn invalidate(&self, key: &str) -> Self::WriteFuture {
+        self.lru.lock().unwrap().remove(key);
+        future::ok(())
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let lru = self.lru.lock().unwrap();
+        f.debug_struct("MemoryStore")
+            .field("capacity", &lru.capacity)
+            .field("len", &lru.entries.len())
+            .finish()
+    }
+}
+
+// ===== impl Lru =====
+
+impl Lru {
+    fn get(&mut self, key: &str, now: Instant) -> Vec<CachedResponse> {
+        let (expires, used) = match self.entries.get(key) {
+            Some(entry) => (entry.expires, entry.used),
+            None => return Vec::new(),
+        };
+        if expires <= now {
+            self.remove(key);
+            return Vec::new();
+        }
+
+        self.tick += 1;
+        self.recency.remove(&used);
+        self.recency.insert(self.tick, key.to_owned());
+        let entry = self.entries.get_mut(key).unwrap();
+        entry.used = self.tick;
+        entry.variants.clone()
+    }
+
+    fn put(&mut self, key: String, variants: Vec<CachedResponse>, expires: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let oldest = match self.recency.keys().next() {
+                Some(&used) => self.recency.remove(&used).unwrap(),
+                None => break,
+            };
+            self.entries.remove(&oldest);
+        }
+
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        let entry = LruEntry {
+            variants,
+            expires,
+            used: self.tick,
+        };
+        self.entries.insert(key, entry);
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response() -> CachedResponse {
+        CachedResponse {
+            status: StatusCode::OK,
+            version: Version::HTTP_11,
+            headers: HeaderMap::new(),
+            body: Bytes::new(),
+            request_headers: HeaderMap::new(),
+            stored_at: SystemTime::now(),
+            initial_age: Duration::from_secs(0),
+            lifetime: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let store = MemoryStore::new(2);
+        let ttl = Duration::from_secs(60);
+        store
+            .put("a".to_owned(), vec![response()], ttl)
+            .wait()
+            .unwrap();
+        store
+            .put("b".to_owned(), vec![response()], ttl)
+            .wait()
+            .unwrap();
+        assert!(store.get("a").wait().unwrap().len() == 1);
+
+        store
+            .put("c".to_owned(), vec![response()], ttl)
+            .wait()
+            .unwrap();
+        assert!(store.get("a").wait().unwrap().len() == 1);
+        assert!(store.get("b").wait().unwrap().is_empty());
+        assert!(store.get("c").wait().unwrap().len() == 1);
+        assert_eq!(store.len(), 2);
+    }
+
+    #[test]
+    fn expires_entries() {
+        let store = MemoryStore::new(2);
+        store
+            .put("a".to_owned(), vec![response()], Duration::from_secs(0))
+            .wait()
+            .unwrap();
+        assert!(store.get("a").wait().unwrap().is_empty());
+        assert!(store.is_empty());
+
+        store
+            .put("a".to_owned(), vec![response()], Duration::from_secs(60))
+            .wait()
+            .unwrap();
+        store.invalidate("a").wait().unwrap();
+        assert!(store.get("a").wait().unwrap().is_empty());
+
+        store
+            .put("a".to_owned(), vec![response()], Duration::from_secs(60))
+            .wait()
+            .unwrap();
+        let forever = Duration::from_secs(std::u64::MAX);
+        store
+            .put("a".to_owned(), vec![response()], forever)
+            .wait()
+            .unwrap();
+        assert!(store.is_empty());
+    }
+}
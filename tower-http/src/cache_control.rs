@@ -0,0 +1,311 @@
+//! Middleware that sets `Cache-Control` headers by policy.
+//!
+//! `SetCacheControl` applies the first of a list of rules whose condition
+//! matches a response, on the request path, the response status or its
+//! content type:
+//!
+//! ```
+//! use tower_http::cache_control::{Builder, Condition};
+//!
+//! let builder = Builder::new()
+//!     .rule(Condition::path_prefix("/assets/"), "max-age=31536000, immutable")
+//!     .rule(Condition::content_type("text/html"), "no-cache")
+//!     .rule(Condition::statuses(400..=599), "no-store");
+//! ```
+//!
+//! Responses already carrying a `Cache-Control` header are left untouched,
+//! so individual handlers can still override the stack-wide policy. An
+//! `Expires` header matching `max-age` can be added for HTTP/1.0 caches.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE, EXPIRES};
+use http::{HttpTryFrom, Request, Response, StatusCode};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tower_service::Service;
+
+/// Sets `Cache-Control` headers on responses according to rules.
+#[derive(Debug, Clone)]
+pub struct SetCacheControl<S> {
+    inner: S,
+    policy: Arc<Policy>,
+}
+
+/// A condition on a response, and the request it answers.
+#[derive(Debug, Clone)]
+pub struct Condition {
+    kind: Kind,
+}
+
+/// Configure a `SetCacheControl` instance.
+#[derive(Debug, Default)]
+pub struct Builder {
+    rules: Vec<(Condition, HeaderValue)>,
+    expires: bool,
+    invalid: bool,
+}
+
+/// Errors that can happen when building a `SetCacheControl`.
+#[derive(Debug)]
+pub struct BuilderError {
+    _p: (),
+}
+
+/// Response future for `SetCacheControl`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    policy: Arc<Policy>,
+    path: String,
+}
+
+#[derive(Debug)]
+struct Policy {
+    rules: Vec<(Condition, HeaderValue)>,
+    expires: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    PathPrefix(String),
+    PathSuffix(String),
+    ContentType(String),
+    Statuses(RangeInclusive<u16>),
+    All(Vec<Condition>),
+}
+
+// ===== impl SetCacheControl =====
+
+impl<S> SetCacheControl<S> {
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetCacheControl<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            path: req.uri().path().to_owned(),
+            inner: self.inner.call(req),
+            policy: self.policy.clone(),
+        }
+    }
+}
+
+// ===== impl Condition =====
+
+impl Condition {
+    /// Matches requests whose path starts with `prefix`.
+    pub fn path_prefix<T: Into<String>>(prefix: T) -> Self {
+        Condition {
+            kind: Kind::PathPrefix(prefix.into()),
+        }
+    }
+
+    /// Matches requests whose path ends with `suffix`, such as `.js`.
+    pub fn path_suffix<T: Into<String>>(suffix: T) -> Self {
+        Condition {
+            kind: Kind::PathSuffix(suffix.into()),
+        }
+    }
+
+    /// Matches responses of the media type `media_type`, such as
+    /// `text/html`, ignoring parameters. `image/*` matches all image types.
+    pub fn content_type<T: Into<String>>(media_type: T) -> Self {
+        Condition {
+            kind: Kind::ContentType(media_type.into().to_ascii_lowercase()),
+        }
+    }
+
+    /// Matches responses whose status is within `range`.
+    pub fn statuses(range: RangeInclusive<u16>) -> Self {
+        Condition {
+            kind: Kind::Statuses(range),
+        }
+    }
+
+    /// Matches responses with the status `status`.
+    pub fn status(status: StatusCode) -> Self {
+        Self::statuses(status.as_u16()..=status.as_u16())
+    }
+
+    /// Matches when both `self` and `other` match.
+    pub fn and(self, other: Condition) -> Self {
+        let mut conditions = match self.kind {
+            Kind::All(conditions) => conditions,
+            kind => vec![Condition { kind }],
+        };
+        conditions.push(other);
+        Condition {
+            kind: Kind::All(conditions),
+        }
+    }
+
+    pub(crate) fn matches(
+        &self,
+        path: &str,
+        status: StatusCode,
+        content_type: Option<&str>,
+    ) -> bool {
+        match self.kind {
+            Kind::PathPrefix(ref prefix) => path.starts_with(&**prefix),
+            Kind::PathSuffix(ref suffix) => path.ends_with(&**suffix),
+            Kind::ContentType(ref pattern) => content_type.map_or(false, |content_type| {
+                let media_type = content_type
+                    .split(';')
+                    .next()
+                    .unwrap_or("")
+                    .trim()
+                    .to_ascii_lowercase();
+                if pattern.ends_with("/*") {
+                    media_type.starts_with(&pattern[..pattern.len() - 1])
+                } else {
+                    media_type == *pattern
+                }
+            }),
+            Kind::Statuses(ref range) => range.contains(&status.as_u16()),
+            Kind::All(ref conditions) => conditions
+                .iter()
+                .all(|condition| condition.matches(path, status, content_type)),
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Return a new builder without rules.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set `Cache-Control` to `value` on responses matching `condition`,
+    /// unless an earlier rule matches.
+    pub fn rule<V>(mut self, condition: Condition, value: V) -> Self
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        match HeaderValue::try_from(value) {
+            Ok(value) => self.rules.push((condition, value)),
+            Err(_) => self.invalid = true,
+        }
+        self
+    }
+
+    /// Also set `Expires` on responses given a `max-age`, unless they carry
+    /// one already.
+    pub fn expires(mut self, expires: bool) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    /// Build the `SetCacheControl` from the provided rules.
+    pub fn build<S>(self, inner: S) -> Result<SetCacheControl<S>, BuilderError> {
+        if self.invalid {
+            return Err(BuilderError { _p: () });
+        }
+
+        Ok(SetCacheControl {
+            inner,
+            policy: Arc::new(Policy {
+                rules: self.rules,
+                expires: self.expires,
+            }),
+        })
+    }
+}
+
+// ===== impl BuilderError =====
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid cache control value")
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        if res.headers().contains_key(CACHE_CONTROL) {
+            return Ok(Async::Ready(res));
+        }
+
+        let value = {
+            let content_type = res
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok());
+            self.policy
+                .rules
+                .iter()
+                .find(|(condition, _)| condition.matches(&self.path, res.status(), content_type))
+                .map(|(_, value)| value.clone())
+        };
+        let value = match value {
+            Some(value) => value,
+            None => return Ok(Async::Ready(res)),
+        };
+
+        if self.policy.expires && !res.headers().contains_key(EXPIRES) {
+            if let Some(max_age) = max_age(&value) {
+                let expires = httpdate::fmt_http_date(SystemTime::now() + max_age);
+                let expires = HeaderValue::from_str(&expires).expect("HTTP dates are valid");
+                res.headers_mut().insert(EXPIRES, expires);
+            }
+        }
+        res.headers_mut().insert(CACHE_CONTROL, value);
+
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Returns the `max-age` of a `Cache-Control` value.
+fn max_age(value: &HeaderValue) -> Option<Duration> {
+    value.to_str().ok()?.split(',').find_map(|directive| {
+        let mut directive = directive.splitn(2, '=');
+        if directive.next()?.trim().eq_ignore_ascii_case("max-age") {
+            directive
+                .next()?
+                .trim()
+                .parse()
+                .ok()
+                .map(Duration::from_secs)
+        } else {
+            None
+        }
+    })
+}
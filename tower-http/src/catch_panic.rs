@@ -0,0 +1,248 @@
+//! Convert panics into `500 Internal Server Error` responses.
+//!
+//! `CatchPanic` catches panics raised by the inner service's `call`, by its
+//! response future, and while polling the response body. Panics before the
+//! response head is available are turned into a response by a
+//! `ResponseForPanic` responder, which receives the panic payload; the
+//! default responds with an empty `500`. Once the head has been sent, a
+//! panic in the body can only be reported as a `PanicError::Panicked` body
+//! error.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::HeaderMap;
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Catches panics of the inner service and responds with `500`.
+#[derive(Debug, Clone)]
+pub struct CatchPanic<S, T = DefaultResponseForPanic> {
+    inner: S,
+    responder: Arc<T>,
+}
+
+/// Builds the response sent when the inner service panics.
+pub trait ResponseForPanic<B> {
+    /// Build a response from the panic payload.
+    fn response_for_panic(&self, payload: Box<dyn Any + Send>) -> Response<B>;
+}
+
+/// Responds with an empty `500 Internal Server Error`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultResponseForPanic {
+    _p: (),
+}
+
+/// A body that catches panics raised while polling it.
+#[derive(Debug)]
+pub struct CatchPanicBody<B> {
+    inner: B,
+}
+
+/// Error returned by `CatchPanicBody`.
+#[derive(Debug)]
+pub enum PanicError<E> {
+    /// Polling the body panicked.
+    Panicked(Box<dyn Any + Send>),
+    /// The inner body failed.
+    Body(E),
+}
+
+/// Response future for `CatchPanic`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, T, B> {
+    state: State<F, B>,
+    responder: Arc<T>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F),
+    Panicked(Option<Response<B>>),
+}
+
+// ===== impl CatchPanic =====
+
+impl<S> CatchPanic<S> {
+    /// Create a new `CatchPanic` responding with an empty `500`.
+    pub fn new(inner: S) -> Self {
+        Self::with_responder(inner, DefaultResponseForPanic::default())
+    }
+}
+
+impl<S, T> CatchPanic<S, T> {
+    /// Create a new `CatchPanic` building responses with `responder`.
+    pub fn with_responder(inner: S, responder: T) -> Self {
+        CatchPanic {
+            inner,
+            responder: Arc::new(responder),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for CatchPanic<S, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    T: ResponseForPanic<ResBody>,
+{
+    type Response = Response<CatchPanicBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, T, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let inner = &mut self.inner;
+        let state = match panic::catch_unwind(AssertUnwindSafe(|| inner.call(req))) {
+            Ok(future) => State::Called(future),
+            Err(payload) => State::Panicked(Some(self.responder.response_for_panic(payload))),
+        };
+
+        ResponseFuture {
+            state,
+            responder: self.responder.clone(),
+        }
+    }
+}
+
+// ===== impl DefaultResponseForPanic =====
+
+impl<B: Default> ResponseForPanic<B> for DefaultResponseForPanic {
+    fn response_for_panic(&self, _payload: Box<dyn Any + Send>) -> Response<B> {
+        let mut res = Response::new(B::default());
+        *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        res
+    }
+}
+
+impl<F, B> ResponseForPanic<B> for F
+where
+    F: Fn(Box<dyn Any + Send>) -> Response<B>,
+{
+    fn response_for_panic(&self, payload: Box<dyn Any + Send>) -> Response<B> {
+        self(payload)
+    }
+}
+
+// ===== impl CatchPanicBody =====
+
+impl<B> CatchPanicBody<B> {
+    /// Wrap `body`, catching panics raised while polling it.
+    pub fn new(body: B) -> Self {
+        CatchPanicBody { inner: body }
+    }
+
+    /// Consumes `self`, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for CatchPanicBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = PanicError<B::Error>;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll_data())) {
+            Ok(result) => result.map_err(PanicError::Body),
+            Err(payload) => Err(PanicError::Panicked(payload)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        let inner = &mut self.inner;
+        match panic::catch_unwind(AssertUnwindSafe(|| inner.poll_trailers())) {
+            Ok(result) => result.map_err(PanicError::Body),
+            Err(payload) => Err(PanicError::Panicked(payload)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl PanicError =====
+
+impl<E: fmt::Display> fmt::Display for PanicError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PanicError::Panicked(ref payload) => match panic_message(&**payload) {
+                Some(msg) => write!(f, "body panicked: {}", msg),
+                None => f.write_str("body panicked"),
+            },
+            PanicError::Body(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for PanicError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PanicError::Panicked(_) => None,
+            PanicError::Body(ref e) => Some(e),
+        }
+    }
+}
+
+/// Returns the message of a panic payload raised by `panic!`, if any.
+pub fn panic_message(payload: &(dyn Any + Send)) -> Option<&str> {
+    if let Some(s) = payload.downcast_ref::<&'static str>() {
+        Some(s)
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        Some(s)
+    } else {
+        None
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, T, B> Future for ResponseFuture<F, T, B>
+where
+    F: Future<Item = Response<B>>,
+    T: ResponseForPanic<B>,
+{
+    type Item = Response<CatchPanicBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = match self.state {
+            State::Called(ref mut future) => {
+                match panic::catch_unwind(AssertUnwindSafe(|| future.poll())) {
+                    Ok(result) => try_ready!(result),
+                    Err(payload) => self.responder.response_for_panic(payload),
+                }
+            }
+            State::Panicked(ref mut res) => res.take().expect("polled after completion"),
+        };
+
+        Ok(Async::Ready(response.map(CatchPanicBody::new)))
+    }
+}
@@ -0,0 +1,510 @@
+//! A circuit breaker driven by response classification.
+//!
+//! `CircuitBreaker` records whether each response is a failure: errors and
+//! the responses classified as failures by a `ClassifyResponse`, by default
+//! `5xx` responses. The circuit opens when too many consecutive
+//! requests fail, or when the failure rate over a window of recent requests
+//! exceeds a threshold. While open, requests are answered without calling
+//! the inner service, with an empty `503 Service Unavailable` by default.
+//!
+//! After the open duration, the circuit is half-open: a limited number of
+//! trial requests are let through. The circuit closes once they all
+//! succeed, and opens again on the first failure.
+
+use crate::classify::{ClassifyResponse, FailureClass, ServerErrorsAsFailures, SharedClassifier};
+use futures::{Async, Future, Poll};
+use http::{HeaderMap, Request, Response, StatusCode};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tower_service::Service;
+
+/// Stops calling the inner service while it is failing.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker<S, R = DefaultOpenResponse> {
+    inner: S,
+    shared: Arc<Shared>,
+    responder: Arc<R>,
+}
+
+/// Configure a `CircuitBreaker`.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+/// Builds the response sent while the circuit is open.
+pub trait OpenResponse<B> {
+    /// Build the response.
+    fn open_response(&self) -> Response<B>;
+}
+
+/// Responds with an empty `503 Service Unavailable`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultOpenResponse {
+    _p: (),
+}
+
+/// The state of a circuit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Requests are passed to the inner service.
+    Closed,
+    /// Requests are rejected.
+    Open,
+    /// Trial requests are passed to the inner service.
+    HalfOpen,
+}
+
+/// Reports the state of a `CircuitBreaker` and its clones.
+#[derive(Debug, Clone)]
+pub struct CircuitHandle {
+    shared: Arc<Shared>,
+}
+
+/// Response future for `CircuitBreaker`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F, Outcome),
+    Rejected(Option<Response<B>>),
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    consecutive_failures: usize,
+    window: usize,
+    min_requests: usize,
+    failure_rate: f64,
+    open_duration: Duration,
+    half_open_trials: usize,
+    classifier: SharedClassifier,
+}
+
+#[derive(Debug)]
+struct Shared {
+    config: Config,
+    state: Mutex<Breaker>,
+}
+
+#[derive(Debug)]
+enum Breaker {
+    Closed {
+        consecutive_failures: usize,
+        recent: VecDeque<bool>,
+        generation: usize,
+    },
+    Open {
+        until: Instant,
+        generation: usize,
+    },
+    HalfOpen {
+        in_flight: usize,
+        successes: usize,
+        generation: usize,
+    },
+}
+
+/// Records the outcome of a request, or releases its trial slot if dropped
+/// before completion.
+#[derive(Debug)]
+struct Outcome {
+    shared: Arc<Shared>,
+    /// The half-open period this request is a trial of, if any.
+    trial: Option<usize>,
+    recorded: bool,
+}
+
+// ===== impl CircuitBreaker =====
+
+impl<S> CircuitBreaker<S> {
+    /// Create a new `CircuitBreaker` with the default settings.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+}
+
+impl<S, R> CircuitBreaker<S, R> {
+    /// Returns a handle reporting the state of the circuit.
+    pub fn handle(&self) -> CircuitHandle {
+        CircuitHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, R, ReqBody, ResBody> Service<Request<ReqBody>> for CircuitBreaker<S, R>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    R: OpenResponse<ResBody>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match self.shared.admit(Instant::now()) {
+            Some(trial) => {
+                let outcome = Outcome {
+                    shared: self.shared.clone(),
+                    trial,
+                    recorded: false,
+                };
+                State::Called(self.inner.call(req), outcome)
+            }
+            None => State::Rejected(Some(self.responder.open_response())),
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                consecutive_failures: 5,
+                window: 100,
+                min_requests: 20,
+                failure_rate: 0.5,
+                open_duration: Duration::from_secs(30),
+                half_open_trials: 1,
+                classifier: SharedClassifier::new(ServerErrorsAsFailures::new()),
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder`.
+    ///
+    /// By default, the circuit opens after 5 consecutive failures or when
+    /// half of the last 100 requests failed, with at least 20 requests
+    /// recorded. It stays open for 30 seconds, then lets 1 trial request
+    /// through.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Open the circuit after `n` consecutive failures.
+    pub fn consecutive_failures(mut self, n: usize) -> Self {
+        self.config.consecutive_failures = n;
+        self
+    }
+
+    /// Open the circuit when the ratio of failures among the last `window`
+    /// requests reaches `rate`, once at least `min_requests` have been
+    /// recorded.
+    pub fn failure_rate(mut self, rate: f64, window: usize, min_requests: usize) -> Self {
+        self.config.failure_rate = rate;
+        self.config.window = window;
+        self.config.min_requests = min_requests;
+        self
+    }
+
+    /// Keep the circuit open for `duration` before trying again.
+    pub fn open_duration(mut self, duration: Duration) -> Self {
+        self.config.open_duration = duration;
+        self
+    }
+
+    /// Let `n` trial requests through while half-open.
+    pub fn half_open_trials(mut self, n: usize) -> Self {
+        self.config.half_open_trials = n.max(1);
+        self
+    }
+
+    /// Set which response statuses count as failures.
+    ///
+    /// Defaults to `5xx` statuses. Errors always count as failures.
+    pub fn failure_statuses(self, is_failure: fn(StatusCode) -> bool) -> Self {
+        self.classifier(FailureStatuses(is_failure))
+    }
+
+    /// Set the classifier deciding which responses count as failures.
+    ///
+    /// Defaults to `ServerErrorsAsFailures`. Errors always count as
+    /// failures.
+    pub fn classifier<C>(mut self, classifier: C) -> Self
+    where
+        C: ClassifyResponse + Send + Sync + 'static,
+    {
+        self.config.classifier = SharedClassifier::new(classifier);
+        self
+    }
+
+    /// Build a `CircuitBreaker` answering with an empty `503` while open.
+    pub fn build<S>(self, inner: S) -> CircuitBreaker<S> {
+        self.build_with_response(inner, DefaultOpenResponse::default())
+    }
+
+    /// Build a `CircuitBreaker` answering with responses built by
+    /// `responder` while open.
+    pub fn build_with_response<S, R>(self, inner: S, responder: R) -> CircuitBreaker<S, R> {
+        CircuitBreaker {
+            inner,
+            shared: Arc::new(Shared {
+                config: self.config,
+                state: Mutex::new(Breaker::closed(0)),
+            }),
+            responder: Arc::new(responder),
+        }
+    }
+}
+
+struct FailureStatuses(fn(StatusCode) -> bool);
+
+impl ClassifyResponse for FailureStatuses {
+    fn classify_response(&self, status: StatusCode, _: &HeaderMap) -> Result<(), FailureClass> {
+        if (self.0)(status) {
+            Err(FailureClass::StatusCode(status))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// ===== impl OpenResponse =====
+
+impl<B: Default> OpenResponse<B> for DefaultOpenResponse {
+    fn open_response(&self) -> Response<B> {
+        let mut res = Response::new(B::default());
+        *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        res
+    }
+}
+
+impl<F, B> OpenResponse<B> for F
+where
+    F: Fn() -> Response<B>,
+{
+    fn open_response(&self) -> Response<B> {
+        self()
+    }
+}
+
+// ===== impl CircuitHandle =====
+
+impl CircuitHandle {
+    /// Returns the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        let mut breaker = self.shared.state.lock().unwrap();
+        breaker.poll_open(Instant::now());
+        match *breaker {
+            Breaker::Closed { .. } => CircuitState::Closed,
+            Breaker::Open { .. } => CircuitState::Open,
+            Breaker::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+}
+
+// ===== impl Breaker =====
+
+impl Breaker {
+    fn closed(generation: usize) -> Self {
+        Breaker::Closed {
+            consecutive_failures: 0,
+            recent: VecDeque::new(),
+            generation,
+        }
+    }
+
+    /// Moves an open circuit whose open duration has passed to half-open.
+    fn poll_open(&mut self, now: Instant) {
+        if let Breaker::Open { until, generation } = *self {
+            if now >= until {
+                *self = Breaker::HalfOpen {
+                    in_flight: 0,
+                    successes: 0,
+                    generation: generation.wrapping_add(1),
+                };
+            }
+        }
+    }
+
+    /// The number of the last half-open period.
+    fn generation(&self) -> usize {
+        match *self {
+            Breaker::Closed { generation, .. }
+            | Breaker::Open { generation, .. }
+            | Breaker::HalfOpen { generation, .. } => generation,
+        }
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    /// Returns whether a request may be sent, and if it is a trial, the
+    /// half-open period it belongs to.
+    fn admit(&self, now: Instant) -> Option<Option<usize>> {
+        let mut breaker = self.state.lock().unwrap();
+        breaker.poll_open(now);
+        match *breaker {
+            Breaker::Closed { .. } => Some(None),
+            Breaker::Open { .. } => None,
+            Breaker::HalfOpen {
+                ref mut in_flight,
+                successes,
+                generation,
+            } => {
+                if *in_flight + successes < self.config.half_open_trials {
+                    *in_flight += 1;
+                    Some(Some(generation))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn record(&self, failure: bool, trial: Option<usize>) {
+        let config = &self.config;
+        let mut breaker = self.state.lock().unwrap();
+        let open = Breaker::Open {
+            until: Instant::now() + config.open_duration,
+            generation: breaker.generation(),
+        };
+
+        match *breaker {
+            Breaker::Closed {
+                ref mut consecutive_failures,
+                ref mut recent,
+                ..
+            } => {
+                if trial.is_some() {
+                    // A trial admitted before the circuit closed.
+                    return;
+                }
+                *consecutive_failures = if failure {
+                    *consecutive_failures + 1
+                } else {
+                    0
+                };
+                recent.push_back(failure);
+                if recent.len() > config.window {
+                    recent.pop_front();
+                }
+
+                let failures = recent.iter().filter(|&&failure| failure).count();
+                let rate_exceeded = recent.len() >= config.min_requests
+                    && failures as f64 >= config.failure_rate * recent.len() as f64;
+                if *consecutive_failures < config.consecutive_failures && !rate_exceeded {
+                    return;
+                }
+            }
+            Breaker::Open { .. } => return,
+            Breaker::HalfOpen {
+                ref mut in_flight,
+                ref mut successes,
+                generation,
+            } => {
+                if trial != Some(generation) {
+                    // Not a trial, or a trial of an earlier half-open period.
+                    return;
+                }
+                *in_flight -= 1;
+                if !failure {
+                    *successes += 1;
+                    if *successes >= config.half_open_trials {
+                        *breaker = Breaker::closed(generation);
+                    }
+                    return;
+                }
+            }
+        }
+
+        *breaker = open;
+    }
+
+    fn release_trial(&self, trial: usize) {
+        let mut breaker = self.state.lock().unwrap();
+        if let Breaker::HalfOpen {
+            ref mut in_flight,
+            generation,
+            ..
+        } = *breaker
+        {
+            if generation == trial {
+                *in_flight -= 1;
+            }
+        }
+    }
+}
+
+// ===== impl Outcome =====
+
+impl Outcome {
+    fn record(&mut self, failure: bool) {
+        self.recorded = true;
+        self.shared.record(failure, self.trial);
+    }
+}
+
+impl Drop for Outcome {
+    fn drop(&mut self) {
+        if let Some(trial) = self.trial {
+            if !self.recorded {
+                self.shared.release_trial(trial);
+            }
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Called(ref mut future, ref mut outcome) => match future.poll() {
+                Ok(Async::Ready(response)) => {
+                    let classifier = &outcome.shared.config.classifier;
+                    let failure = classifier
+                        .classify_response(response.status(), response.headers())
+                        .is_err();
+                    outcome.record(failure);
+                    Ok(Async::Ready(response))
+                }
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(e) => {
+                    outcome.record(true);
+                    Err(e)
+                }
+            },
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Classify responses as successes or failures.
+//!
+//! Middleware reporting or reacting to failures, such as `Trace`,
+//! `CircuitBreaker` and `RetryPolicy`, take a `ClassifyResponse` so that a
+//! stack can share one definition of a failure. Errors returned by the
+//! inner service or the response body are always failures.
+
+use http::{HeaderMap, StatusCode};
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+
+/// Decides whether a response is a failure.
+pub trait ClassifyResponse {
+    /// Classify a response from its status and headers.
+    fn classify_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> Result<(), FailureClass>;
+
+    /// Classify an error of the inner service or the response body.
+    fn classify_error(&self, error: &dyn fmt::Display) -> FailureClass {
+        FailureClass::Error(error.to_string())
+    }
+}
+
+/// The reason a request failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureClass {
+    /// The response has a failure status.
+    StatusCode(StatusCode),
+    /// The inner service or the response body failed, with the error
+    /// message.
+    Error(String),
+}
+
+/// Classifies `5xx` responses as failures.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ServerErrorsAsFailures {
+    _p: (),
+}
+
+/// Classifies responses whose status is in a range as failures.
+#[derive(Debug, Clone)]
+pub struct StatusInRangeAsFailures {
+    range: RangeInclusive<u16>,
+}
+
+/// Never classifies a response as a failure. Errors are still failures.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NeverClassifyAsFailure {
+    _p: (),
+}
+
+/// A classifier shared by the clones of a middleware.
+#[derive(Clone)]
+pub(crate) struct SharedClassifier(Arc<dyn ClassifyResponse + Send + Sync>);
+
+// ===== impl FailureClass =====
+
+impl fmt::Display for FailureClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FailureClass::StatusCode(status) => write!(f, "status code {}", status.as_u16()),
+            FailureClass::Error(ref message) => f.write_str(message),
+        }
+    }
+}
+
+// ===== impl ServerErrorsAsFailures =====
+
+impl ServerErrorsAsFailures {
+    /// Create a new `ServerErrorsAsFailures`.
+    pub fn new() -> Self {
+        ServerErrorsAsFailures::default()
+    }
+}
+
+impl ClassifyResponse for ServerErrorsAsFailures {
+    fn classify_response(&self, status: StatusCode, _: &HeaderMap) -> Result<(), FailureClass> {
+        if status.is_server_error() {
+            Err(FailureClass::StatusCode(status))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// ===== impl StatusInRangeAsFailures =====
+
+impl StatusInRangeAsFailures {
+    /// Create a new `StatusInRangeAsFailures` classifying statuses in
+    /// `range` as failures.
+    pub fn new(range: RangeInclusive<u16>) -> Self {
+        StatusInRangeAsFailures { range }
+    }
+
+    /// Classify `4xx` and `5xx` responses as failures.
+    pub fn client_and_server_errors() -> Self {
+        StatusInRangeAsFailures::new(400..=599)
+    }
+}
+
+impl ClassifyResponse for StatusInRangeAsFailures {
+    fn classify_response(&self, status: StatusCode, _: &HeaderMap) -> Result<(), FailureClass> {
+        let code = status.as_u16();
+        if *self.range.start() <= code && code <= *self.range.end() {
+            Err(FailureClass::StatusCode(status))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+// ===== impl NeverClassifyAsFailure =====
+
+impl NeverClassifyAsFailure {
+    /// Create a new `NeverClassifyAsFailure`.
+    pub fn new() -> Self {
+        NeverClassifyAsFailure::default()
+    }
+}
+
+impl ClassifyResponse for NeverClassifyAsFailure {
+    fn classify_response(&self, _: StatusCode, _: &HeaderMap) -> Result<(), FailureClass> {
+        Ok(())
+    }
+}
+
+impl<C> ClassifyResponse for Arc<C>
+where
+    C: ClassifyResponse + ?Sized,
+{
+    fn classify_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> Result<(), FailureClass> {
+        (**self).classify_response(status, headers)
+    }
+
+    fn classify_error(&self, error: &dyn fmt::Display) -> FailureClass {
+        (**self).classify_error(error)
+    }
+}
+
+// ===== impl SharedClassifier =====
+
+impl SharedClassifier {
+    pub(crate) fn new<C>(classifier: C) -> Self
+    where
+        C: ClassifyResponse + Send + Sync + 'static,
+    {
+        SharedClassifier(Arc::new(classifier))
+    }
+}
+
+impl ClassifyResponse for SharedClassifier {
+    fn classify_response(
+        &self,
+        status: StatusCode,
+        headers: &HeaderMap,
+    ) -> Result<(), FailureClass> {
+        self.0.classify_response(status, headers)
+    }
+
+    fn classify_error(&self, error: &dyn fmt::Display) -> FailureClass {
+        self.0.classify_error(error)
+    }
+}
+
+impl fmt::Debug for SharedClassifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedClassifier").finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_statuses() {
+        let headers = HeaderMap::new();
+        let server = ServerErrorsAsFailures::new();
+        assert!(server
+            .classify_response(StatusCode::NOT_FOUND, &headers)
+            .is_ok());
+        assert_eq!(
+            server.classify_response(StatusCode::BAD_GATEWAY, &headers),
+            Err(FailureClass::StatusCode(StatusCode::BAD_GATEWAY))
+        );
+
+        let range = StatusInRangeAsFailures::client_and_server_errors();
+        assert!(range.classify_response(StatusCode::OK, &headers).is_ok());
+        assert!(range
+            .classify_response(StatusCode::NOT_FOUND, &headers)
+            .is_err());
+
+        let never = NeverClassifyAsFailure::new();
+        assert!(never
+            .classify_response(StatusCode::INTERNAL_SERVER_ERROR, &headers)
+            .is_ok());
+        assert_eq!(
+            never.classify_error(&"refused"),
+            FailureClass::Error("refused".to_owned())
+        );
+    }
+}
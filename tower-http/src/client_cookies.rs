@@ -0,0 +1,359 @@
+//! Cookie jar middleware for HTTP clients.
+//!
+//! `ClientCookies` stores cookies set by responses in a `CookieStore` and
+//! attaches the matching ones to subsequent requests, following the storage
+//! and retrieval rules of RFC 6265: the `Domain`, `Path`, `Expires`,
+//! `Max-Age` and `Secure` attributes are honoured. Public suffixes are not
+//! checked, so a server may set cookies for a registrable parent domain of
+//! itself only as far as the domain-match rule allows.
+//!
+//! Requests should carry an absolute URI or a `Host` header; requests
+//! without a host neither receive nor store cookies. Stored cookies are
+//! appended to the `Cookie` header the request already carries, if any.
+
+use crate::scheme;
+use cookie::Cookie;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, COOKIE, HOST, SET_COOKIE};
+use http::{Request, Response};
+use std::cmp::Reverse;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower_service::Service;
+
+/// Stores cookies from responses and sends them with matching requests.
+#[derive(Debug, Clone)]
+pub struct ClientCookies<S> {
+    inner: S,
+    store: CookieStore,
+}
+
+/// A shared cookie jar.
+///
+/// Clones share the same cookies.
+#[derive(Debug, Clone, Default)]
+pub struct CookieStore {
+    cookies: Arc<Mutex<Vec<StoredCookie>>>,
+}
+
+#[derive(Debug, Clone)]
+struct StoredCookie {
+    name: String,
+    value: String,
+    domain: String,
+    host_only: bool,
+    path: String,
+    secure: bool,
+    expires: Option<SystemTime>,
+}
+
+/// The origin of a request, used to store cookies of its response.
+#[derive(Debug)]
+struct Origin {
+    host: String,
+    path: String,
+}
+
+/// Response future for `ClientCookies`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    store: CookieStore,
+    origin: Option<Origin>,
+}
+
+// ===== impl ClientCookies =====
+
+impl<S> ClientCookies<S> {
+    /// Create a new `ClientCookies` with an empty cookie store.
+    pub fn new(inner: S) -> Self {
+        Self::with_store(inner, CookieStore::new())
+    }
+
+    /// Create a new `ClientCookies` using `store`.
+    pub fn with_store(inner: S, store: CookieStore) -> Self {
+        ClientCookies { inner, store }
+    }
+
+    /// Returns the cookie store.
+    pub fn store(&self) -> &CookieStore {
+        &self.store
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ClientCookies<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let origin = request_host(&req).map(|host| Origin {
+            host,
+            path: req.uri().path().to_owned(),
+        });
+
+        if let Some(ref origin) = origin {
+            let secure = scheme::is_https(&req);
+            if let Some(cookies) = self.store.header_value(&origin.host, &origin.path, secure) {
+                // HTTP/1 allows a single `Cookie` header, so stored cookies
+                // are merged into the one the request already carries.
+                let mut value = Vec::new();
+                for existing in req.headers().get_all(COOKIE) {
+                    value.extend_from_slice(existing.as_bytes());
+                    value.extend_from_slice(b"; ");
+                }
+                value.extend_from_slice(cookies.as_bytes());
+                if let Ok(value) = HeaderValue::from_bytes(&value) {
+                    req.headers_mut().insert(COOKIE, value);
+                }
+            }
+        }
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            store: self.store.clone(),
+            origin,
+        }
+    }
+}
+
+// ===== impl CookieStore =====
+
+impl CookieStore {
+    /// Create a new, empty `CookieStore`.
+    pub fn new() -> Self {
+        CookieStore::default()
+    }
+
+    /// Returns the `name=value` pairs of the cookies that would be sent in
+    /// a request to `host` and `path`.
+    pub fn matching(&self, host: &str, path: &str, secure: bool) -> Vec<(String, String)> {
+        let now = SystemTime::now();
+        let host = host.to_ascii_lowercase();
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|cookie| !cookie.is_expired(now));
+
+        let mut matching: Vec<&StoredCookie> = cookies
+            .iter()
+            .filter(|cookie| cookie.matches(&host, path, secure))
+            .collect();
+        // Cookies with longer paths are listed first.
+        matching.sort_by_key(|cookie| Reverse(cookie.path.len()));
+
+        matching
+            .into_iter()
+            .map(|cookie| (cookie.name.clone(), cookie.value.clone()))
+            .collect()
+    }
+
+    /// Store a cookie received in a response from `host` for `path`.
+    ///
+    /// Cookies whose `Domain` does not match `host` are ignored.
+    pub fn store(&self, cookie: &Cookie, host: &str, path: &str) {
+        let host = host.to_ascii_lowercase();
+
+        let (domain, host_only) = match cookie.domain() {
+            Some(domain) if !domain.is_empty() => {
+                let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+                if !domain_match(&host, &domain) {
+                    return;
+                }
+                (domain, false)
+            }
+            _ => (host, true),
+        };
+
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_owned(),
+            _ => default_path(path),
+        };
+
+        // Expiries too far in the future to represent never expire.
+        let now = SystemTime::now();
+        let expires = if let Some(max_age) = cookie.max_age() {
+            let secs = max_age.num_seconds();
+            if secs <= 0 {
+                Some(UNIX_EPOCH)
+            } else {
+                now.checked_add(Duration::from_secs(secs as u64))
+            }
+        } else if let Some(tm) = cookie.expires() {
+            let secs = tm.to_timespec().sec;
+            if secs <= 0 {
+                Some(UNIX_EPOCH)
+            } else {
+                UNIX_EPOCH.checked_add(Duration::from_secs(secs as u64))
+            }
+        } else {
+            None
+        };
+
+        let stored = StoredCookie {
+            name: cookie.name().to_owned(),
+            value: cookie.value().to_owned(),
+            domain,
+            host_only,
+            path,
+            secure: cookie.secure().unwrap_or(false),
+            expires,
+        };
+
+        let mut cookies = self.cookies.lock().unwrap();
+        cookies.retain(|c| {
+            !(c.name == stored.name && c.domain == stored.domain && c.path == stored.path)
+        });
+        // A cookie with an expiry in the past only removes its predecessor.
+        if !stored.is_expired(now) {
+            cookies.push(stored);
+        }
+    }
+
+    /// Remove all cookies.
+    pub fn clear(&self) {
+        self.cookies.lock().unwrap().clear();
+    }
+
+    fn header_value(&self, host: &str, path: &str, secure: bool) -> Option<HeaderValue> {
+        let pairs = self.matching(host, path, secure);
+        if pairs.is_empty() {
+            return None;
+        }
+
+        let value = pairs
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        HeaderValue::from_str(&value).ok()
+    }
+}
+
+// ===== impl StoredCookie =====
+
+impl StoredCookie {
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.map_or(false, |expires| expires <= now)
+    }
+
+    fn matches(&self, host: &str, path: &str, secure: bool) -> bool {
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_match(host, &self.domain)
+        };
+        domain_ok && path_match(path, &self.path) && (secure || !self.secure)
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, ResBody> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<ResBody>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+
+        if let Some(ref origin) = self.origin {
+            for value in response.headers().get_all(SET_COOKIE).iter() {
+                let cookie = value
+                    .to_str()
+                    .ok()
+                    .and_then(|value| Cookie::parse(value).ok());
+                if let Some(cookie) = cookie {
+                    self.store.store(&cookie, &origin.host, &origin.path);
+                }
+            }
+        }
+
+        Ok(Async::Ready(response))
+    }
+}
+
+fn request_host<B>(req: &Request<B>) -> Option<String> {
+    if let Some(host) = req.uri().host() {
+        return Some(host.to_owned());
+    }
+
+    let host = req.headers().get(HOST)?.to_str().ok()?;
+    // Strip the port, taking care of bracketed IPv6 literals.
+    let host = if host.starts_with('[') {
+        &host[..host.find(']').map_or(host.len(), |i| i + 1)]
+    } else {
+        host.split(':').next().unwrap_or(host)
+    };
+    Some(host.to_owned())
+}
+
+/// The domain-match rule of RFC 6265, section 5.1.3.
+fn domain_match(host: &str, domain: &str) -> bool {
+    if host == domain {
+        return true;
+    }
+    host.ends_with(domain)
+        && host[..host.len() - domain.len()].ends_with('.')
+        && host.parse::<std::net::IpAddr>().is_err()
+}
+
+/// The path-match rule of RFC 6265, section 5.1.4.
+fn path_match(request_path: &str, cookie_path: &str) -> bool {
+    request_path == cookie_path
+        || (request_path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || request_path[cookie_path.len()..].starts_with('/')))
+}
+
+/// The default-path algorithm of RFC 6265, section 5.1.4.
+fn default_path(request_path: &str) -> String {
+    match request_path.rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(i) => request_path[..i].to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_domains() {
+        assert!(domain_match("example.com", "example.com"));
+        assert!(domain_match("api.example.com", "example.com"));
+        assert!(!domain_match("badexample.com", "example.com"));
+        assert!(!domain_match("example.com", "api.example.com"));
+    }
+
+    #[test]
+    fn matches_paths() {
+        assert!(path_match("/", "/"));
+        assert!(path_match("/api/users", "/api"));
+        assert!(path_match("/api/users", "/api/"));
+        assert!(!path_match("/apiary", "/api"));
+        assert_eq!(default_path("/api/users"), "/api");
+        assert_eq!(default_path("/users"), "/");
+    }
+}
@@ -0,0 +1,424 @@
+//! Limit the number of requests in flight.
+//!
+//! `ConcurrencyLimit` admits up to a fixed number of concurrent requests. A
+//! request counts as in flight until its response body has been read to the
+//! end or dropped, not merely until the response head is available. Requests
+//! over the limit wait in a bounded FIFO queue; once the queue is full they
+//! are answered with `503 Service Unavailable`.
+//!
+//! The inner service must be `Clone`, as queued requests call it once a slot
+//! becomes available. A `LimitHandle` reports the current number of
+//! requests in flight and in the queue, e.g. for metrics.
+
+use futures::task::{self, Task};
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Limits the number of requests in flight, responding `503` on overflow.
+#[derive(Debug, Clone)]
+pub struct ConcurrencyLimit<S> {
+    inner: S,
+    shared: Arc<Shared>,
+}
+
+/// Configure a `ConcurrencyLimit`.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    max_in_flight: usize,
+    max_queued: usize,
+    retry_after: Option<HeaderValue>,
+}
+
+/// Reports the load of a `ConcurrencyLimit` and its clones.
+#[derive(Debug, Clone)]
+pub struct LimitHandle {
+    shared: Arc<Shared>,
+}
+
+/// A response body that holds its request's slot until it completes.
+#[derive(Debug)]
+pub struct LimitBody<B> {
+    inner: B,
+    permit: Option<Permit>,
+}
+
+/// Response future for `ConcurrencyLimit`.
+pub struct ResponseFuture<S, B>
+where
+    S: Service<Request<B>>,
+{
+    state: State<S, B>,
+}
+
+enum State<S, B>
+where
+    S: Service<Request<B>>,
+{
+    Queued {
+        waiter: Waiter,
+        service: S,
+        request: Request<B>,
+    },
+    Called {
+        future: S::Future,
+        permit: Permit,
+    },
+    Rejected(Option<HeaderValue>),
+    Done,
+}
+
+#[derive(Debug)]
+struct Shared {
+    max_in_flight: usize,
+    max_queued: usize,
+    retry_after: Option<HeaderValue>,
+    state: Mutex<SemaphoreState>,
+}
+
+#[derive(Debug, Default)]
+struct SemaphoreState {
+    in_flight: usize,
+    queue: VecDeque<(u64, Option<Task>)>,
+    next_id: u64,
+}
+
+/// A slot of the limit, released when dropped.
+#[derive(Debug)]
+struct Permit {
+    shared: Arc<Shared>,
+}
+
+/// A queued request waiting for a slot.
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    shared: Arc<Shared>,
+}
+
+// ===== impl ConcurrencyLimit =====
+
+impl<S> ConcurrencyLimit<S> {
+    /// Create a new `ConcurrencyLimit` admitting `max` concurrent requests
+    /// without queueing.
+    pub fn new(inner: S, max: usize) -> Self {
+        Builder::new(max).build(inner)
+    }
+
+    /// Returns a handle reporting the number of requests in flight and
+    /// queued.
+    pub fn handle(&self) -> LimitHandle {
+        LimitHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ConcurrencyLimit<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    ResBody: Default,
+{
+    type Response = Response<LimitBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match Shared::acquire(&self.shared) {
+            Acquire::Permit(permit) => State::Called {
+                future: self.inner.call(req),
+                permit,
+            },
+            Acquire::Queued(waiter) => {
+                let clone = self.inner.clone();
+                State::Queued {
+                    waiter,
+                    service: mem::replace(&mut self.inner, clone),
+                    request: req,
+                }
+            }
+            Acquire::Full => State::Rejected(self.shared.retry_after.clone()),
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Create a new `Builder` admitting `max_in_flight` concurrent requests
+    /// without queueing.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max_in_flight` is zero.
+    pub fn new(max_in_flight: usize) -> Self {
+        assert!(max_in_flight > 0, "max_in_flight must be positive");
+        Builder {
+            max_in_flight,
+            max_queued: 0,
+            retry_after: None,
+        }
+    }
+
+    /// Queue up to `max_queued` requests while the limit is reached.
+    pub fn max_queued(mut self, max_queued: usize) -> Self {
+        self.max_queued = max_queued;
+        self
+    }
+
+    /// Set the `Retry-After` header sent with `503` responses, rounded down
+    /// to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    /// Build a `ConcurrencyLimit` wrapping `inner`.
+    pub fn build<S>(self, inner: S) -> ConcurrencyLimit<S> {
+        ConcurrencyLimit {
+            inner,
+            shared: Arc::new(Shared {
+                max_in_flight: self.max_in_flight,
+                max_queued: self.max_queued,
+                retry_after: self.retry_after,
+                state: Mutex::new(SemaphoreState::default()),
+            }),
+        }
+    }
+}
+
+// ===== impl LimitHandle =====
+
+impl LimitHandle {
+    /// Returns the number of requests in flight.
+    pub fn in_flight(&self) -> usize {
+        self.shared.state.lock().unwrap().in_flight
+    }
+
+    /// Returns the number of queued requests.
+    pub fn queued(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+}
+
+// ===== impl Shared =====
+
+enum Acquire {
+    Permit(Permit),
+    Queued(Waiter),
+    Full,
+}
+
+impl Shared {
+    fn acquire(this: &Arc<Shared>) -> Acquire {
+        let mut state = this.state.lock().unwrap();
+        if state.in_flight < this.max_in_flight && state.queue.is_empty() {
+            state.in_flight += 1;
+            Acquire::Permit(Permit {
+                shared: this.clone(),
+            })
+        } else if state.queue.len() < this.max_queued {
+            let id = state.next_id;
+            state.next_id += 1;
+            state.queue.push_back((id, None));
+            Acquire::Queued(Waiter {
+                id,
+                shared: this.clone(),
+            })
+        } else {
+            Acquire::Full
+        }
+    }
+
+    fn notify_front(state: &SemaphoreState) {
+        if let Some(&(_, Some(ref task))) = state.queue.front() {
+            task.notify();
+        }
+    }
+}
+
+impl Waiter {
+    fn poll_acquire(&mut self) -> Async<Permit> {
+        let mut state = self.shared.state.lock().unwrap();
+        let is_front = state.queue.front().map_or(false, |&(id, _)| id == self.id);
+        if is_front && state.in_flight < self.shared.max_in_flight {
+            state.queue.pop_front();
+            state.in_flight += 1;
+            // More than one slot may have been released.
+            Shared::notify_front(&state);
+            return Async::Ready(Permit {
+                shared: self.shared.clone(),
+            });
+        }
+
+        let id = self.id;
+        if let Some(entry) = state.queue.iter_mut().find(|entry| entry.0 == id) {
+            entry.1 = Some(task::current());
+        }
+        Async::NotReady
+    }
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        let id = self.id;
+        state.queue.retain(|entry| entry.0 != id);
+        Shared::notify_front(&state);
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.in_flight -= 1;
+        Shared::notify_front(&state);
+    }
+}
+
+// ===== impl LimitBody =====
+
+impl<B> LimitBody<B> {
+    /// Consumes `self`, returning the inner body.
+    ///
+    /// The request's slot is released.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for LimitBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let result = self.inner.poll_data();
+        match result {
+            Ok(Async::NotReady) | Ok(Async::Ready(Some(_))) => {}
+            _ => self.permit = None,
+        }
+        result
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        let result = self.inner.poll_trailers();
+        match result {
+            Ok(Async::NotReady) => {}
+            _ => self.permit = None,
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, ReqBody, ResBody> Future for ResponseFuture<S, ReqBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Item = Response<LimitBody<ResBody>>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Queued {
+                    mut waiter,
+                    mut service,
+                    request,
+                } => match waiter.poll_acquire() {
+                    Async::Ready(permit) => {
+                        self.state = State::Called {
+                            future: service.call(request),
+                            permit,
+                        };
+                    }
+                    Async::NotReady => {
+                        self.state = State::Queued {
+                            waiter,
+                            service,
+                            request,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Called { mut future, permit } => match future.poll()? {
+                    Async::Ready(response) => {
+                        return Ok(Async::Ready(response.map(|body| LimitBody {
+                            inner: body,
+                            permit: Some(permit),
+                        })));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Called { future, permit };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Rejected(retry_after) => {
+                    let mut res = Response::new(LimitBody {
+                        inner: ResBody::default(),
+                        permit: None,
+                    });
+                    *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+                    if let Some(retry_after) = retry_after {
+                        res.headers_mut().insert(RETRY_AFTER, retry_after);
+                    }
+                    return Ok(Async::Ready(res));
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for ResponseFuture<S, B>
+where
+    S: Service<Request<B>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Queued { .. } => "Queued",
+            State::Called { .. } => "Called",
+            State::Rejected(_) => "Rejected",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
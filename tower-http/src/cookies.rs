@@ -0,0 +1,316 @@
+//! Middleware that manages request and response cookies.
+//!
+//! `CookieManager` parses the `Cookie` headers of a request into a
+//! `CookieJar`, exposed to handlers through the `Cookies` request extension.
+//! Cookies that handlers add or remove are recorded as the jar's delta and
+//! written as `Set-Cookie` headers on the response.
+//!
+//! When configured with `Keys`, cookies can also be signed, so clients
+//! cannot tamper with them, or made private, so clients can neither tamper
+//! with nor read them. Keys can be rotated: cookies are always written with
+//! the primary key, and cookies still carrying a fallback key are accepted
+//! and transparently re-issued under the primary key.
+
+pub use cookie::Key;
+use cookie::{Cookie, CookieJar};
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, COOKIE, SET_COOKIE};
+use http::{Request, Response};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tower_service::Service;
+
+/// Parses request cookies and writes changed cookies to responses.
+#[derive(Debug, Clone)]
+pub struct CookieManager<S> {
+    inner: S,
+    keys: Option<Arc<Keys>>,
+}
+
+/// Handle to the cookies of the current request, inserted as a request
+/// extension by `CookieManager`.
+#[derive(Clone, Default)]
+pub struct Cookies {
+    jar: Arc<Mutex<CookieJar>>,
+    keys: Option<Arc<Keys>>,
+}
+
+/// Keys used to sign and encrypt cookies.
+#[derive(Clone)]
+pub struct Keys {
+    primary: Key,
+    fallbacks: Vec<Key>,
+}
+
+/// View of the signed cookies of a request.
+#[derive(Debug)]
+pub struct SignedCookies<'a> {
+    cookies: &'a Cookies,
+    keys: &'a Keys,
+}
+
+/// View of the private (encrypted) cookies of a request.
+#[derive(Debug)]
+pub struct PrivateCookies<'a> {
+    cookies: &'a Cookies,
+    keys: &'a Keys,
+}
+
+/// Response future for `CookieManager`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    cookies: Cookies,
+}
+
+// ===== impl CookieManager =====
+
+impl<S> CookieManager<S> {
+    /// Create a new `CookieManager`.
+    pub fn new(inner: S) -> Self {
+        CookieManager { inner, keys: None }
+    }
+
+    /// Create a new `CookieManager` supporting signed and private cookies
+    /// with the given keys.
+    pub fn with_keys(inner: S, keys: Keys) -> Self {
+        CookieManager {
+            inner,
+            keys: Some(Arc::new(keys)),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for CookieManager<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let mut cookies = Cookies::from_headers(req.headers());
+        cookies.keys = self.keys.clone();
+        req.extensions_mut().insert(cookies.clone());
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            cookies,
+        }
+    }
+}
+
+// ===== impl Cookies =====
+
+impl Cookies {
+    /// Parse the `Cookie` headers in `headers`.
+    ///
+    /// Malformed cookies are skipped.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut jar = CookieJar::new();
+
+        for value in headers.get_all(COOKIE).iter() {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            for cookie in value.split(';') {
+                if let Ok(cookie) = Cookie::parse(cookie.trim().to_owned()) {
+                    jar.add_original(cookie);
+                }
+            }
+        }
+
+        Cookies {
+            jar: Arc::new(Mutex::new(jar)),
+            keys: None,
+        }
+    }
+
+    /// Returns the cookie with the given name.
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        self.jar.lock().unwrap().get(name).cloned()
+    }
+
+    /// Add a cookie, sending it to the client.
+    pub fn add(&self, cookie: Cookie<'static>) {
+        self.jar.lock().unwrap().add(cookie);
+    }
+
+    /// Remove a cookie, instructing the client to delete it.
+    ///
+    /// The cookie's path and domain must match those it was set with.
+    pub fn remove(&self, cookie: Cookie<'static>) {
+        self.jar.lock().unwrap().remove(cookie);
+    }
+
+    /// Run `f` with exclusive access to the underlying `CookieJar`.
+    pub fn with_jar<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce(&mut CookieJar) -> T,
+    {
+        f(&mut self.jar.lock().unwrap())
+    }
+
+    /// Returns a view of the signed cookies.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `CookieManager` was not configured with keys.
+    pub fn signed(&self) -> SignedCookies<'_> {
+        SignedCookies {
+            cookies: self,
+            keys: self.keys.as_ref().expect("CookieManager has no keys"),
+        }
+    }
+
+    /// Returns a view of the private cookies.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the `CookieManager` was not configured with keys.
+    pub fn private(&self) -> PrivateCookies<'_> {
+        PrivateCookies {
+            cookies: self,
+            keys: self.keys.as_ref().expect("CookieManager has no keys"),
+        }
+    }
+
+    /// Append a `Set-Cookie` header for each changed cookie to `headers`.
+    pub fn write_delta(&self, headers: &mut HeaderMap) {
+        let jar = self.jar.lock().unwrap();
+        for cookie in jar.delta() {
+            if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                headers.append(SET_COOKIE, value);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Cookies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Cookies")
+            .field("jar", &self.jar)
+            .field("keys", &self.keys.as_ref().map(|_| "..."))
+            .finish()
+    }
+}
+
+// ===== impl Keys =====
+
+impl Keys {
+    /// Create a new `Keys` signing and encrypting with `primary`.
+    pub fn new(primary: Key) -> Self {
+        Keys {
+            primary,
+            fallbacks: Vec::new(),
+        }
+    }
+
+    /// Also accept cookies signed or encrypted with `key`, such as a key
+    /// that is being rotated out.
+    pub fn fallback(mut self, key: Key) -> Self {
+        self.fallbacks.push(key);
+        self
+    }
+}
+
+impl fmt::Debug for Keys {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Keys")
+            .field("fallbacks", &self.fallbacks.len())
+            .finish()
+    }
+}
+
+// ===== impl SignedCookies =====
+
+impl<'a> SignedCookies<'a> {
+    /// Returns the cookie with the given name if its signature is valid.
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        let mut jar = self.cookies.jar.lock().unwrap();
+
+        if let Some(cookie) = jar.signed(&self.keys.primary).get(name) {
+            return Some(cookie);
+        }
+
+        for key in &self.keys.fallbacks {
+            if let Some(cookie) = jar.signed(key).get(name) {
+                jar.signed(&self.keys.primary).add(cookie.clone());
+                return Some(cookie);
+            }
+        }
+
+        None
+    }
+
+    /// Add a cookie, signed with the primary key.
+    pub fn add(&self, cookie: Cookie<'static>) {
+        let mut jar = self.cookies.jar.lock().unwrap();
+        jar.signed(&self.keys.primary).add(cookie);
+    }
+}
+
+// ===== impl PrivateCookies =====
+
+impl<'a> PrivateCookies<'a> {
+    /// Returns the decrypted cookie with the given name if it is authentic.
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        let mut jar = self.cookies.jar.lock().unwrap();
+
+        if let Some(cookie) = jar.private(&self.keys.primary).get(name) {
+            return Some(cookie);
+        }
+
+        for key in &self.keys.fallbacks {
+            if let Some(cookie) = jar.private(key).get(name) {
+                jar.private(&self.keys.primary).add(cookie.clone());
+                return Some(cookie);
+            }
+        }
+
+        None
+    }
+
+    /// Add a cookie, encrypted with the primary key.
+    pub fn add(&self, cookie: Cookie<'static>) {
+        let mut jar = self.cookies.jar.lock().unwrap();
+        jar.private(&self.keys.primary).add(cookie);
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        self.cookies.write_delta(res.headers_mut());
+        Ok(Async::Ready(res))
+    }
+}
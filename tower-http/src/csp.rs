@@ -0,0 +1,439 @@
+//! Middleware that sets a `Content-Security-Policy` header.
+//!
+//! Policies are assembled with a typed `Builder` rather than by hand, and
+//! rendered once when built. Directives containing `Source::Nonce` are
+//! rendered per request with a fresh nonce, which is made available to
+//! handlers through the `Nonce` request extension so they can stamp it on
+//! inline `<script>` and `<style>` elements.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{
+    HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, CONTENT_SECURITY_POLICY_REPORT_ONLY,
+};
+use http::{Request, Response};
+use std::fmt;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Sets a `Content-Security-Policy` (or `-Report-Only`) header on responses.
+#[derive(Debug, Clone)]
+pub struct SetContentSecurityPolicy<S> {
+    inner: S,
+    policy: Arc<Policy>,
+}
+
+/// A rendered content security policy.
+#[derive(Debug)]
+pub struct Policy {
+    header: HeaderName,
+    directives: Vec<(&'static str, Vec<Source>)>,
+    /// Pre-rendered value, available when the policy has no nonce sources.
+    value: Option<HeaderValue>,
+}
+
+/// A source expression in a fetch directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// `'none'`
+    None,
+    /// `'self'`
+    SelfOrigin,
+    /// `'unsafe-inline'`
+    UnsafeInline,
+    /// `'unsafe-eval'`
+    UnsafeEval,
+    /// `'strict-dynamic'`
+    StrictDynamic,
+    /// `'nonce-…'`, using the nonce generated for the current request.
+    Nonce,
+    /// A host source such as `https://cdn.example.com` or `*.example.com`.
+    Host(String),
+    /// A scheme source such as `data:` or `https:`.
+    Scheme(String),
+    /// A hash source such as `sha256-…`.
+    Hash(String),
+}
+
+/// The nonce generated for a request, inserted as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nonce(String);
+
+/// Configure a `SetContentSecurityPolicy` instance.
+#[derive(Debug, Default)]
+pub struct Builder {
+    directives: Vec<(&'static str, Vec<Source>)>,
+    report_only: bool,
+    invalid: bool,
+}
+
+/// Errors that can happen when building a `SetContentSecurityPolicy`.
+#[derive(Debug)]
+pub struct BuilderError {
+    _p: (),
+}
+
+/// Response future for `SetContentSecurityPolicy`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    header: Option<(HeaderName, HeaderValue)>,
+}
+
+// ===== impl SetContentSecurityPolicy =====
+
+impl<S> SetContentSecurityPolicy<S> {
+    /// Create a new `SetContentSecurityPolicy` applying `policy`.
+    pub fn new(inner: S, policy: Arc<Policy>) -> Self {
+        SetContentSecurityPolicy { inner, policy }
+    }
+
+    /// Returns the policy applied by this middleware.
+    pub fn policy(&self) -> &Policy {
+        &self.policy
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetContentSecurityPolicy<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let value = match self.policy.value {
+            Some(ref value) => value.clone(),
+            None => {
+                let nonce = Nonce::generate();
+                let value = self.policy.render(Some(&nonce));
+                req.extensions_mut().insert(nonce);
+                value
+            }
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            header: Some((self.policy.header.clone(), value)),
+        }
+    }
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    /// Returns whether the policy is sent in report-only mode.
+    pub fn is_report_only(&self) -> bool {
+        self.header == CONTENT_SECURITY_POLICY_REPORT_ONLY
+    }
+
+    /// Returns whether the policy needs a nonce per request.
+    pub fn uses_nonce(&self) -> bool {
+        self.value.is_none()
+    }
+
+    fn render(&self, nonce: Option<&Nonce>) -> HeaderValue {
+        let mut value = String::new();
+
+        for (name, sources) in &self.directives {
+            if !value.is_empty() {
+                value.push_str("; ");
+            }
+            value.push_str(name);
+
+            for source in sources {
+                value.push(' ');
+                match *source {
+                    Source::None => value.push_str("'none'"),
+                    Source::SelfOrigin => value.push_str("'self'"),
+                    Source::UnsafeInline => value.push_str("'unsafe-inline'"),
+                    Source::UnsafeEval => value.push_str("'unsafe-eval'"),
+                    Source::StrictDynamic => value.push_str("'strict-dynamic'"),
+                    Source::Nonce => {
+                        let nonce = nonce.expect("nonce is generated for nonce policies");
+                        value.push_str("'nonce-");
+                        value.push_str(nonce.as_str());
+                        value.push('\'');
+                    }
+                    Source::Host(ref host) => value.push_str(host),
+                    Source::Scheme(ref scheme) => {
+                        value.push_str(scheme);
+                        if !scheme.ends_with(':') {
+                            value.push(':');
+                        }
+                    }
+                    Source::Hash(ref hash) => {
+                        value.push('\'');
+                        value.push_str(hash);
+                        value.push('\'');
+                    }
+                }
+            }
+        }
+
+        HeaderValue::from_str(&value).expect("validated CSP is a valid header value")
+    }
+}
+
+// ===== impl Nonce =====
+
+impl Nonce {
+    fn generate() -> Self {
+        let bytes: [u8; 16] = rand::random();
+        Nonce(base64::encode(&bytes))
+    }
+
+    /// Returns the base64 encoded nonce, to be used as a `nonce` attribute.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Nonce {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// ===== impl Builder =====
+
+macro_rules! directives {
+    ($($(#[$attr:meta])* $method:ident => $name:expr,)*) => {
+        $(
+            $(#[$attr])*
+            pub fn $method<I>(self, sources: I) -> Self
+            where
+                I: IntoIterator<Item = Source>,
+            {
+                self.directive($name, sources)
+            }
+        )*
+    };
+}
+
+impl Builder {
+    /// Return a new, empty builder.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    directives! {
+        /// Set the `default-src` directive.
+        default_src => "default-src",
+        /// Set the `script-src` directive.
+        script_src => "script-src",
+        /// Set the `style-src` directive.
+        style_src => "style-src",
+        /// Set the `img-src` directive.
+        img_src => "img-src",
+        /// Set the `connect-src` directive.
+        connect_src => "connect-src",
+        /// Set the `font-src` directive.
+        font_src => "font-src",
+        /// Set the `object-src` directive.
+        object_src => "object-src",
+        /// Set the `media-src` directive.
+        media_src => "media-src",
+        /// Set the `frame-src` directive.
+        frame_src => "frame-src",
+        /// Set the `worker-src` directive.
+        worker_src => "worker-src",
+        /// Set the `frame-ancestors` directive.
+        frame_ancestors => "frame-ancestors",
+        /// Set the `base-uri` directive.
+        base_uri => "base-uri",
+        /// Set the `form-action` directive.
+        form_action => "form-action",
+    }
+
+    /// Add the `upgrade-insecure-requests` directive.
+    pub fn upgrade_insecure_requests(self) -> Self {
+        self.directive("upgrade-insecure-requests", None)
+    }
+
+    /// Set the `report-uri` directive.
+    pub fn report_uri(mut self, uri: &str) -> Self {
+        if !is_valid_source(uri) {
+            self.invalid = true;
+        }
+        self.directive("report-uri", Some(Source::Host(uri.to_owned())))
+    }
+
+    /// Send the policy as `Content-Security-Policy-Report-Only`.
+    pub fn report_only(mut self, report_only: bool) -> Self {
+        self.report_only = report_only;
+        self
+    }
+
+    fn directive<I>(mut self, name: &'static str, sources: I) -> Self
+    where
+        I: IntoIterator<Item = Source>,
+    {
+        let sources: Vec<Source> = sources.into_iter().collect();
+
+        for source in &sources {
+            let valid = match *source {
+                Source::Host(ref s) | Source::Scheme(ref s) | Source::Hash(ref s) => {
+                    is_valid_source(s)
+                }
+                _ => true,
+            };
+            if !valid {
+                self.invalid = true;
+            }
+        }
+
+        match self.directives.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = sources,
+            None => self.directives.push((name, sources)),
+        }
+        self
+    }
+
+    /// Build the `Policy` from the provided settings.
+    pub fn build_policy(self) -> Result<Policy, BuilderError> {
+        if self.invalid || self.directives.is_empty() {
+            return Err(BuilderError { _p: () });
+        }
+
+        let header = if self.report_only {
+            CONTENT_SECURITY_POLICY_REPORT_ONLY
+        } else {
+            CONTENT_SECURITY_POLICY
+        };
+
+        let mut policy = Policy {
+            header,
+            directives: self.directives,
+            value: None,
+        };
+
+        let uses_nonce = policy
+            .directives
+            .iter()
+            .any(|(_, sources)| sources.contains(&Source::Nonce));
+        if !uses_nonce {
+            policy.value = Some(policy.render(None));
+        }
+
+        Ok(policy)
+    }
+
+    /// Build the `SetContentSecurityPolicy` from the provided settings.
+    pub fn build<S>(self, inner: S) -> Result<SetContentSecurityPolicy<S>, BuilderError> {
+        let policy = self.build_policy()?;
+        Ok(SetContentSecurityPolicy::new(inner, Arc::new(policy)))
+    }
+}
+
+// ===== impl BuilderError =====
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid content security policy")
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+
+        if let Some((name, value)) = self.header.take() {
+            if !res.headers().contains_key(&name) {
+                res.headers_mut().insert(name, value);
+            }
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Source expressions must not be able to terminate the directive or the
+/// header value they are embedded in.
+fn is_valid_source(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes()
+            .all(|b| b.is_ascii_graphic() && b != b';' && b != b',' && b != b'\'')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_directives() {
+        let policy = Builder::new()
+            .default_src(vec![Source::SelfOrigin])
+            .img_src(vec![
+                Source::SelfOrigin,
+                Source::Scheme("data".into()),
+                Source::Host("https://cdn.example.com".into()),
+            ])
+            .object_src(vec![Source::None])
+            .upgrade_insecure_requests()
+            .build_policy()
+            .unwrap();
+
+        assert!(!policy.uses_nonce());
+        assert_eq!(
+            policy.value.unwrap(),
+            "default-src 'self'; img-src 'self' data: https://cdn.example.com; \
+             object-src 'none'; upgrade-insecure-requests"
+        );
+    }
+
+    #[test]
+    fn renders_nonce() {
+        let policy = Builder::new()
+            .script_src(vec![Source::Nonce, Source::StrictDynamic])
+            .report_only(true)
+            .build_policy()
+            .unwrap();
+
+        assert!(policy.uses_nonce());
+        assert!(policy.is_report_only());
+
+        let nonce = Nonce("abc".into());
+        assert_eq!(
+            policy.render(Some(&nonce)),
+            "script-src 'nonce-abc' 'strict-dynamic'"
+        );
+    }
+
+    #[test]
+    fn rejects_injection() {
+        let res = Builder::new()
+            .script_src(vec![Source::Host("example.com; script-src *".into())])
+            .build_policy();
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,162 @@
+//! Middleware that adds a `Date` header to responses.
+//!
+//! RFC 7231 requires origin servers with a clock to send `Date`. Servers
+//! such as hyper's add it automatically; this middleware covers stacks that
+//! run on other transports.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, DATE};
+use http::{Request, Response};
+use std::cell::RefCell;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tower_service::Service;
+
+/// Adds a `Date` header to responses that do not already carry one.
+#[derive(Debug, Clone)]
+pub struct SetDate<S> {
+    inner: S,
+}
+
+/// Response future for `SetDate`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+}
+
+// ===== impl SetDate =====
+
+impl<S> SetDate<S> {
+    /// Create a new `SetDate`.
+    pub fn new(inner: S) -> Self {
+        SetDate { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetDate<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+
+        if !res.headers().contains_key(DATE) {
+            res.headers_mut().insert(DATE, now());
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Returns the current time formatted as an HTTP date.
+///
+/// The formatted value is cached per thread and re-rendered at most once
+/// per second.
+pub fn now() -> HeaderValue {
+    thread_local! {
+        static CACHE: RefCell<Option<(u64, HeaderValue)>> = RefCell::new(None);
+    }
+
+    let now = SystemTime::now();
+    let secs = now
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        match *cache {
+            Some((cached, ref value)) if cached == secs => value.clone(),
+            _ => {
+                let value = HeaderValue::from_str(&httpdate::fmt_http_date(now))
+                    .expect("HTTP dates are valid header values");
+                *cache = Some((secs, value.clone()));
+                value
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future;
+
+    fn respond(res: Response<()>) -> Response<()> {
+        let mut future = ResponseFuture {
+            inner: future::ok::<_, ()>(res),
+        };
+        match future.poll() {
+            Ok(Async::Ready(res)) => res,
+            _ => panic!("expected a response"),
+        }
+    }
+
+    #[test]
+    fn inserts_date() {
+        let res = respond(Response::new(()));
+        assert!(res.headers().contains_key(DATE));
+    }
+
+    #[test]
+    fn keeps_existing_date() {
+        let mut res = Response::new(());
+        res.headers_mut().insert(
+            DATE,
+            HeaderValue::from_static("Sun, 06 Nov 1994 08:49:37 GMT"),
+        );
+        let res = respond(res);
+        assert_eq!(res.headers()[DATE], "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(res.headers().get_all(DATE).iter().count(), 1);
+    }
+
+    #[test]
+    fn formats_imf_fixdate() {
+        let value = now();
+        let value = value.to_str().unwrap();
+        let date = httpdate::parse_http_date(value).unwrap();
+        // IMF-fixdate is the only format `fmt_http_date` produces, e.g.
+        // `Sun, 06 Nov 1994 08:49:37 GMT`.
+        assert_eq!(value, httpdate::fmt_http_date(date));
+        assert_eq!(value.len(), 29);
+        assert!(value.ends_with(" GMT"));
+    }
+}
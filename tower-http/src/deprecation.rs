@@ -0,0 +1,207 @@
+//! Middleware that marks responses as deprecated.
+//!
+//! Matching responses get a `Deprecation` header, and optionally a `Sunset`
+//! header announcing when the resource will stop working and a `Link` to
+//! documentation, so API clients can detect the lifecycle of the endpoints
+//! they use.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderName, HeaderValue, LINK};
+use http::{Request, Response};
+use std::fmt;
+use std::time::SystemTime;
+use tower_service::Service;
+
+/// Name of the `Deprecation` header.
+pub const DEPRECATION: &str = "deprecation";
+
+/// Name of the `Sunset` header.
+pub const SUNSET: &str = "sunset";
+
+/// Adds `Deprecation` and `Sunset` headers to responses to matching requests.
+#[derive(Clone)]
+pub struct Deprecation<S, P> {
+    inner: S,
+    predicate: P,
+    headers: Vec<(HeaderName, HeaderValue)>,
+}
+
+/// Configure a `Deprecation` instance.
+#[derive(Debug, Default)]
+pub struct Builder {
+    deprecated_at: Option<SystemTime>,
+    sunset: Option<SystemTime>,
+    links: Vec<(String, &'static str)>,
+}
+
+/// Errors that can happen when building a `Deprecation`.
+#[derive(Debug)]
+pub struct BuilderError {
+    _p: (),
+}
+
+/// Response future for `Deprecation`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    headers: Option<Vec<(HeaderName, HeaderValue)>>,
+}
+
+// ===== impl Deprecation =====
+
+impl<S, P> Deprecation<S, P> {
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, P, ReqBody, ResBody> Service<Request<ReqBody>> for Deprecation<S, P>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    P: Fn(&Request<ReqBody>) -> bool,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let headers = if (self.predicate)(&req) {
+            Some(self.headers.clone())
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            headers,
+        }
+    }
+}
+
+impl<S, P> fmt::Debug for Deprecation<S, P>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Deprecation")
+            .field("inner", &self.inner)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Return a new builder emitting `Deprecation: true`.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the date at which the resource was deprecated.
+    pub fn deprecated_at(mut self, at: SystemTime) -> Self {
+        self.deprecated_at = Some(at);
+        self
+    }
+
+    /// Set the date after which the resource will become unresponsive.
+    pub fn sunset(mut self, at: SystemTime) -> Self {
+        self.sunset = Some(at);
+        self
+    }
+
+    /// Link to documentation about the deprecation (`rel="deprecation"`).
+    pub fn deprecation_link(mut self, uri: &str) -> Self {
+        self.links.push((uri.to_owned(), "deprecation"));
+        self
+    }
+
+    /// Link to documentation about the sunset (`rel="sunset"`).
+    pub fn sunset_link(mut self, uri: &str) -> Self {
+        self.links.push((uri.to_owned(), "sunset"));
+        self
+    }
+
+    /// Build the `Deprecation` middleware, applied to requests for which
+    /// `predicate` returns `true`.
+    pub fn build<S, P>(self, inner: S, predicate: P) -> Result<Deprecation<S, P>, BuilderError> {
+        let mut headers = Vec::new();
+
+        let deprecation = match self.deprecated_at {
+            Some(at) => HeaderValue::from_str(&httpdate::fmt_http_date(at)),
+            None => Ok(HeaderValue::from_static("true")),
+        };
+        headers.push((
+            HeaderName::from_static(DEPRECATION),
+            deprecation.map_err(|_| BuilderError { _p: () })?,
+        ));
+
+        if let Some(at) = self.sunset {
+            let value = HeaderValue::from_str(&httpdate::fmt_http_date(at))
+                .map_err(|_| BuilderError { _p: () })?;
+            headers.push((HeaderName::from_static(SUNSET), value));
+        }
+
+        for (uri, rel) in self.links {
+            if uri.contains(|c| c == '<' || c == '>') {
+                return Err(BuilderError { _p: () });
+            }
+            let value = HeaderValue::from_str(&format!("<{}>; rel=\"{}\"", uri, rel))
+                .map_err(|_| BuilderError { _p: () })?;
+            headers.push((LINK, value));
+        }
+
+        Ok(Deprecation {
+            inner,
+            predicate,
+            headers,
+        })
+    }
+}
+
+// ===== impl BuilderError =====
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid deprecation header")
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+
+        if let Some(headers) = self.headers.take() {
+            for (name, value) in headers {
+                res.headers_mut().append(name, value);
+            }
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
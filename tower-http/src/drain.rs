@@ -0,0 +1,294 @@
+//! Graceful shutdown.
+//!
+//! Once `DrainHandle::drain` has been called, `Drain` fails readiness with a
+//! `Draining` error so no new requests are accepted, and adds `Connection:
+//! close` to the HTTP/1 responses of requests still in flight, other than
+//! upgrade responses as defined by the `upgrade` module. The future
+//! returned by `DrainHandle::drained` completes when the last of those
+//! responses has been sent, that is when its body has been read to the end
+//! or dropped.
+
+use crate::upgrade::is_upgrade_response;
+use futures::task::{self, Task};
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, CONNECTION};
+use http::{Request, Response, Version};
+use http_body::Body;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tower_service::Service;
+
+/// Stops accepting requests once draining has been signalled.
+#[derive(Debug, Clone)]
+pub struct Drain<S> {
+    inner: S,
+    shared: Arc<Shared>,
+}
+
+/// Signals draining and waits for in-flight requests.
+#[derive(Debug, Clone)]
+pub struct DrainHandle {
+    shared: Arc<Shared>,
+}
+
+/// Completes when draining has been signalled and no requests are in
+/// flight.
+#[derive(Debug)]
+pub struct Drained {
+    shared: Arc<Shared>,
+}
+
+/// Error returned by `Drain::poll_ready` while draining.
+#[derive(Debug)]
+pub struct Draining {
+    _p: (),
+}
+
+/// A response body that counts as in flight until it completes.
+#[derive(Debug)]
+pub struct DrainBody<B> {
+    inner: B,
+    guard: Option<InFlight>,
+}
+
+/// Response future for `Drain`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    guard: Option<InFlight>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    state: Mutex<DrainState>,
+}
+
+#[derive(Debug, Default)]
+struct DrainState {
+    draining: bool,
+    in_flight: usize,
+    waiters: Vec<Task>,
+}
+
+#[derive(Debug)]
+struct InFlight {
+    shared: Arc<Shared>,
+}
+
+// ===== impl Drain =====
+
+impl<S> Drain<S> {
+    /// Create a new `Drain` and the handle controlling it.
+    pub fn new(inner: S) -> (Self, DrainHandle) {
+        let shared = Arc::new(Shared::default());
+        let handle = DrainHandle {
+            shared: shared.clone(),
+        };
+        (Drain { inner, shared }, handle)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Drain<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: From<Draining>,
+{
+    type Response = Response<DrainBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        if self.shared.state.lock().unwrap().draining {
+            return Err(Draining { _p: () }.into());
+        }
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        self.shared.state.lock().unwrap().in_flight += 1;
+        let guard = InFlight {
+            shared: self.shared.clone(),
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            guard: Some(guard),
+        }
+    }
+}
+
+// ===== impl DrainHandle =====
+
+impl DrainHandle {
+    /// Signal draining.
+    pub fn drain(&self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.draining = true;
+        if state.in_flight == 0 {
+            for task in state.waiters.drain(..) {
+                task.notify();
+            }
+        }
+    }
+
+    /// Returns whether draining has been signalled.
+    pub fn is_draining(&self) -> bool {
+        self.shared.state.lock().unwrap().draining
+    }
+
+    /// Returns the number of requests in flight.
+    pub fn in_flight(&self) -> usize {
+        self.shared.state.lock().unwrap().in_flight
+    }
+
+    /// Returns a future completing once draining has been signalled and no
+    /// requests are in flight.
+    pub fn drained(&self) -> Drained {
+        Drained {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl Future for Drained {
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        let mut state = self.shared.state.lock().unwrap();
+        if state.draining && state.in_flight == 0 {
+            return Ok(Async::Ready(()));
+        }
+        if !state.waiters.iter().any(Task::will_notify_current) {
+            state.waiters.push(task::current());
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.in_flight -= 1;
+        if state.draining && state.in_flight == 0 {
+            for task in state.waiters.drain(..) {
+                task.notify();
+            }
+        }
+    }
+}
+
+// ===== impl Draining =====
+
+impl fmt::Display for Draining {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("service is draining")
+    }
+}
+
+impl Error for Draining {}
+
+// ===== impl DrainBody =====
+
+impl<B> DrainBody<B> {
+    /// Returns a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner body.
+    ///
+    /// The request no longer counts as in flight.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for DrainBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let result = self.inner.poll_data();
+        match result {
+            Ok(Async::NotReady) | Ok(Async::Ready(Some(_))) => {}
+            _ => self.guard = None,
+        }
+        result
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        let result = self.inner.poll_trailers();
+        match result {
+            Ok(Async::NotReady) => {}
+            _ => self.guard = None,
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = Response<DrainBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response = try_ready!(self.inner.poll());
+        let guard = self.guard.take().expect("polled after completion");
+
+        let draining = guard.shared.state.lock().unwrap().draining;
+        let http1 =
+            response.version() == Version::HTTP_10 || response.version() == Version::HTTP_11;
+        if draining && http1 && !is_upgrade_response(&response) {
+            let close = response
+                .headers()
+                .get_all(CONNECTION)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .flat_map(|value| value.split(','))
+                .any(|token| token.trim().eq_ignore_ascii_case("close"));
+            if !close {
+                response
+                    .headers_mut()
+                    .append(CONNECTION, HeaderValue::from_static("close"));
+            }
+        }
+
+        Ok(Async::Ready(response.map(|body| DrainBody {
+            inner: body,
+            guard: Some(guard),
+        })))
+    }
+}
@@ -0,0 +1,587 @@
+//! Dump requests and responses for debugging.
+//!
+//! `Dump` writes the head of each request and response, with a bounded
+//! prefix of their bodies, to a `LogWriter`. With the `password` field
+//! redacted through `Builder::redact_field`, a login request is dumped as:
+//!
+//! ```text
+//! > #1 POST /login?next=%2F HTTP/1.1
+//! > content-type: application/x-www-form-urlencoded
+//! > cookie: [redacted]
+//! >
+//! > user=alice&password=[redacted]
+//! ```
+//!
+//! Lines of requests start with `>` and lines of responses with `<`,
+//! followed by a number identifying the exchange. Control characters,
+//! including line breaks, are escaped, so that the body prefix is written
+//! on a single line and cannot forge records. A record is written once
+//! the body prefix has been read, or when the body ends or is dropped.
+//! The bodies of upgrade requests and responses, as defined by the `upgrade`
+//! module, carry the tunneled connection and are not dumped: their record
+//! is written as soon as the head is known.
+//!
+//! Sensitive data is redacted: the values of the headers in
+//! `sensitive_headers::DEFAULT_SENSITIVE_HEADERS` and of those marked as
+//! sensitive by default, as well as the values of configurable fields, in
+//! query strings, URL-encoded forms and top-level JSON strings. Dumping
+//! can be limited to a random sample of the requests, so that it can be left
+//! enabled at low rates in production.
+
+use crate::access_log::LogWriter;
+use crate::sensitive_headers::DEFAULT_SENSITIVE_HEADERS;
+use crate::upgrade::{is_upgrade_request, is_upgrade_response};
+use bytes::Buf;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, HeaderName, CONTENT_TYPE};
+use http::{Request, Response};
+use http_body::Body;
+use rand::Rng;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tower_service::Service;
+
+const REDACTED: &str = "[redacted]";
+
+/// Dumps requests and responses to a `LogWriter`.
+#[derive(Debug, Clone)]
+pub struct Dump<S, W> {
+    inner: S,
+    writer: Arc<W>,
+    config: Arc<Config>,
+    next_id: Arc<AtomicUsize>,
+}
+
+/// Configure a `Dump`.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+/// Response future for `Dump`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, W> {
+    inner: F,
+    writer: Arc<W>,
+    config: Arc<Config>,
+    id: Option<usize>,
+}
+
+/// A body whose prefix is dumped.
+#[derive(Debug)]
+pub struct DumpBody<B, W>
+where
+    W: LogWriter,
+{
+    inner: B,
+    capture: Option<Capture<W>>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    max_body: usize,
+    ratio: f64,
+    headers: Vec<HeaderName>,
+    fields: Vec<String>,
+}
+
+/// A record waiting for its body prefix.
+#[derive(Debug)]
+struct Capture<W>
+where
+    W: LogWriter,
+{
+    writer: Arc<W>,
+    config: Arc<Config>,
+    marker: char,
+    head: String,
+    kind: BodyKind,
+    prefix: Vec<u8>,
+    truncated: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyKind {
+    Form,
+    Json,
+    Other,
+}
+
+// ===== impl Dump =====
+
+impl<S, W> Dump<S, W> {
+    /// Create a new `Dump` writing every request and response, with the
+    /// first KiB of their bodies, to `writer`.
+    pub fn new(inner: S, writer: W) -> Self {
+        Builder::new().build(inner, writer)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, W, ReqBody, ResBody> Service<Request<ReqBody>> for Dump<S, W>
+where
+    S: Service<Request<DumpBody<ReqBody, W>>, Response = Response<ResBody>>,
+    W: LogWriter,
+{
+    type Response = Response<DumpBody<ResBody, W>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, W>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let sampled = self.config.ratio >= 1.0
+            || (self.config.ratio > 0.0 && rand::thread_rng().gen_bool(self.config.ratio));
+        if !sampled {
+            let req = req.map(|body| DumpBody {
+                inner: body,
+                capture: None,
+            });
+            return ResponseFuture {
+                inner: self.inner.call(req),
+                writer: self.writer.clone(),
+                config: self.config.clone(),
+                id: None,
+            };
+        }
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let target = req
+            .uri()
+            .path_and_query()
+            .map(|target| target.as_str())
+            // The authority form of `CONNECT` requests.
+            .or_else(|| {
+                req.uri()
+                    .authority_part()
+                    .map(|authority| authority.as_str())
+            })
+            .unwrap_or("/");
+        let target = self.config.redact_query(target);
+        let mut head = format!("> #{} {} {} {:?}", id, req.method(), target, req.version());
+        self.config.write_headers(&mut head, '>', req.headers());
+
+        let capture = if is_upgrade_request(&req) {
+            self.writer.write_line(&head);
+            None
+        } else {
+            Some(Capture::new(
+                self.writer.clone(),
+                self.config.clone(),
+                '>',
+                head,
+                req.headers(),
+            ))
+        };
+        let req = req.map(|body| DumpBody {
+            inner: body,
+            capture,
+        });
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            writer: self.writer.clone(),
+            config: self.config.clone(),
+            id: Some(id),
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                max_body: 1024,
+                ratio: 1.0,
+                headers: DEFAULT_SENSITIVE_HEADERS.to_vec(),
+                fields: Vec::new(),
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder` dumping every request and response, with the
+    /// first KiB of their bodies.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Dump at most `max` bytes of each body.
+    pub fn max_body(mut self, max: usize) -> Self {
+        self.config.max_body = max;
+        self
+    }
+
+    /// Only dump a `ratio` of the requests, between 0 and 1, and their
+    /// responses.
+    pub fn sample(mut self, ratio: f64) -> Self {
+        self.config.ratio = ratio.max(0.0).min(1.0);
+        self
+    }
+
+    /// Redact the values of the header `name`.
+    pub fn redact_header(mut self, name: HeaderName) -> Self {
+        self.config.headers.push(name);
+        self
+    }
+
+    /// Redact the values of the field `name` in query strings, URL-encoded
+    /// forms and top-level JSON strings.
+    pub fn redact_field<T: Into<String>>(mut self, name: T) -> Self {
+        self.config.fields.push(name.into());
+        self
+    }
+
+    /// Build a `Dump` writing to `writer`.
+    pub fn build<S, W>(self, inner: S, writer: W) -> Dump<S, W> {
+        Dump {
+            inner,
+            writer: Arc::new(writer),
+            config: Arc::new(self.config),
+            next_id: Arc::new(AtomicUsize::new(1)),
+        }
+    }
+}
+
+// ===== impl Config =====
+
+impl Config {
+    fn write_headers(&self, out: &mut String, marker: char, headers: &HeaderMap) {
+        for (name, value) in headers {
+            let value = if value.is_sensitive() || self.headers.contains(name) {
+                REDACTED.to_owned()
+            } else {
+                escape(&String::from_utf8_lossy(value.as_bytes()))
+            };
+            write!(out, "\n{} {}: {}", marker, name, value).unwrap();
+        }
+    }
+
+    fn is_redacted(&self, field: &str) -> bool {
+        self.fields.iter().any(|name| name == field)
+    }
+
+    fn redact_query(&self, target: &str) -> String {
+        match target.find('?') {
+            Some(i) => format!("{}?{}", &target[..i], self.redact_form(&target[i + 1..])),
+            None => target.to_owned(),
+        }
+    }
+
+    fn redact_form(&self, form: &str) -> String {
+        form.split('&')
+            .map(|pair| match pair.find('=') {
+                Some(i) if self.is_redacted(&pair[..i]) => format!("{}={}", &pair[..i], REDACTED),
+                _ => pair.to_owned(),
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    /// Redacts the string values of the redacted fields of a JSON object,
+    /// without parsing it, so that truncated documents are redacted too.
+    fn redact_json(&self, json: &str) -> String {
+        let mut out = String::with_capacity(json.len());
+        let mut rest = json;
+        let mut depth = 0;
+        while let Some(i) = rest.find(|c| "\"{}[]".contains(c)) {
+            let c = rest[i..].chars().next().unwrap();
+            out.push_str(&rest[..=i]);
+            rest = &rest[i + 1..];
+            match c {
+                '{' | '[' => depth += 1,
+                '}' | ']' => depth -= 1,
+                _ => {
+                    let (name, after) = split_string(rest);
+                    out.push_str(rest);
+                    let after = match after {
+                        Some(after) => after,
+                        None => return out,
+                    };
+                    out.truncate(out.len() - after.len());
+                    rest = after;
+
+                    // A redacted field with a string value.
+                    let value = after.trim_start();
+                    if depth != 1 || !value.starts_with(':') || !self.is_redacted(name) {
+                        continue;
+                    }
+                    let value = value[1..].trim_start();
+                    if !value.starts_with('"') {
+                        continue;
+                    }
+                    out.push_str(&after[..after.len() - value.len() + 1]);
+                    out.push_str(REDACTED);
+                    match split_string(&value[1..]).1 {
+                        Some(after) => {
+                            out.push('"');
+                            rest = after;
+                        }
+                        None => return out,
+                    }
+                }
+            }
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Splits the rest of a JSON string after its opening quote into its
+/// contents and what follows its closing quote, if any.
+fn split_string(s: &str) -> (&str, Option<&str>) {
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            '\\' if !escaped => escaped = true,
+            '"' if !escaped => return (&s[..i], Some(&s[i + 1..])),
+            _ => escaped = false,
+        }
+    }
+    (s, None)
+}
+
+/// Escapes control characters.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\r' => escaped.push_str("\\r"),
+            '\n' => escaped.push_str("\\n"),
+            c if c.is_control() => write!(escaped, "\\u{{{:x}}}", c as u32).unwrap(),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, W, B> Future for ResponseFuture<F, W>
+where
+    F: Future<Item = Response<B>>,
+    W: LogWriter,
+{
+    type Item = Response<DumpBody<B, W>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = match self.inner.poll() {
+            Ok(Async::Ready(response)) => response,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                if let Some(id) = self.id.take() {
+                    self.writer.write_line(&format!("< #{} failed", id));
+                }
+                return Err(e);
+            }
+        };
+
+        let capture = self.id.take().and_then(|id| {
+            let mut head = format!("< #{} {:?} {}", id, response.version(), response.status());
+            self.config
+                .write_headers(&mut head, '<', response.headers());
+            if is_upgrade_response(&response) {
+                self.writer.write_line(&head);
+                return None;
+            }
+            Some(Capture::new(
+                self.writer.clone(),
+                self.config.clone(),
+                '<',
+                head,
+                response.headers(),
+            ))
+        });
+
+        Ok(Async::Ready(response.map(|body| DumpBody {
+            inner: body,
+            capture,
+        })))
+    }
+}
+
+// ===== impl DumpBody =====
+
+impl<B, W> Body for DumpBody<B, W>
+where
+    B: Body,
+    W: LogWriter,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let result = self.inner.poll_data();
+        match result {
+            Ok(Async::Ready(Some(ref chunk))) => {
+                let full = match self.capture {
+                    Some(ref mut capture) => capture.push(chunk.bytes()),
+                    None => false,
+                };
+                if full {
+                    self.capture = None;
+                }
+            }
+            Ok(Async::NotReady) => {}
+            _ => self.capture = None,
+        }
+        result
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl Capture =====
+
+impl<W> Capture<W>
+where
+    W: LogWriter,
+{
+    fn new(
+        writer: Arc<W>,
+        config: Arc<Config>,
+        marker: char,
+        head: String,
+        headers: &HeaderMap,
+    ) -> Self {
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+        let kind = if content_type.starts_with("application/x-www-form-urlencoded") {
+            BodyKind::Form
+        } else if content_type.starts_with("application/json") || content_type.contains("+json") {
+            BodyKind::Json
+        } else {
+            BodyKind::Other
+        };
+
+        Capture {
+            writer,
+            config,
+            marker,
+            head,
+            kind,
+            prefix: Vec::new(),
+            truncated: false,
+        }
+    }
+
+    /// Adds a chunk to the prefix, returning whether the prefix is full.
+    fn push(&mut self, chunk: &[u8]) -> bool {
+        let room = self.config.max_body - self.prefix.len();
+        if chunk.len() > room {
+            self.prefix.extend_from_slice(&chunk[..room]);
+            self.truncated = true;
+            true
+        } else {
+            self.prefix.extend_from_slice(chunk);
+            false
+        }
+    }
+}
+
+impl<W> Drop for Capture<W>
+where
+    W: LogWriter,
+{
+    fn drop(&mut self) {
+        let mut record = self.head.clone();
+        if !self.prefix.is_empty() {
+            let body = String::from_utf8_lossy(&self.prefix);
+            let body = match self.kind {
+                BodyKind::Form => self.config.redact_form(&body),
+                BodyKind::Json => self.config.redact_json(&body),
+                BodyKind::Other => body.into_owned(),
+            };
+            write!(record, "\n{}", self.marker).unwrap();
+            write!(record, "\n{} {}", self.marker, escape(&body)).unwrap();
+        }
+        if self.truncated {
+            write!(
+                record,
+                "\n{} [truncated after {} bytes]",
+                self.marker, self.config.max_body
+            )
+            .unwrap();
+        }
+        self.writer.write_line(&record);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Builder::new()
+            .redact_field("password")
+            .redact_field("token")
+            .config
+    }
+
+    #[test]
+    fn redacts_forms() {
+        let config = config();
+        assert_eq!(
+            config.redact_form("user=alice&password=hunter2"),
+            "user=alice&password=[redacted]"
+        );
+        assert_eq!(config.redact_query("/a?token=x"), "/a?token=[redacted]");
+        assert_eq!(config.redact_query("/a"), "/a");
+    }
+
+    #[test]
+    fn redacts_json() {
+        let config = config();
+        assert_eq!(
+            config.redact_json(r#"{"user": "alice", "password": "hun\"ter2", "n": 1}"#),
+            r#"{"user": "alice", "password": "[redacted]", "n": 1}"#
+        );
+        // Only top-level fields are redacted.
+        assert_eq!(
+            config.redact_json(r#"{"a": {"token": "x"}, "b": "password"}"#),
+            r#"{"a": {"token": "x"}, "b": "password"}"#
+        );
+        // Truncated documents.
+        assert_eq!(
+            config.redact_json(r#"{"token": "abc"#),
+            r#"{"token": "[redacted]"#
+        );
+    }
+
+    #[test]
+    fn escapes_line_breaks() {
+        assert_eq!(
+            escape("ok\r\n< #2 HTTP/1.1 200 OK\t"),
+            "ok\\r\\n< #2 HTTP/1.1 200 OK\\u{9}"
+        );
+    }
+}
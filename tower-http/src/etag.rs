@@ -0,0 +1,465 @@
+//! Middleware that sets `ETag` headers and answers `If-None-Match`.
+//!
+//! `ETag` gives responses to `GET` and `HEAD` requests an entity tag, unless
+//! they carry one already, and replaces successful responses whose tag
+//! matches the request's `If-None-Match` header with `304 Not Modified`, so
+//! that polling clients only download representations that changed.
+//!
+//! Strong tags are a hash of the response body, which is buffered to be
+//! hashed; event streams and partial content are left alone. Weak tags are
+//! derived from the `Content-Length` and `Last-Modified` headers instead,
+//! without buffering, and are only set on responses carrying the latter.
+//! Responses to `HEAD` requests have no body to hash, so they only get weak
+//! tags. Upgrade requests, as defined by the `upgrade` module, are passed
+//! through.
+//!
+//! Bodies are buffered up to a maximum size, 1 MiB by default. As soon as
+//! their `Content-Length` or buffered body exceeds it, they get a weak tag,
+//! if any, instead, and the rest of the body is streamed after the part
+//! already buffered.
+
+use crate::upgrade::is_upgrade_request;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use http::header::{CACHE_CONTROL, CONTENT_LOCATION, DATE, EXPIRES, LAST_MODIFIED, VARY};
+use http::{response, Method, Request, Response, StatusCode};
+use http_body::Body;
+use sha2::{Digest, Sha256};
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Sets `ETag` headers and answers `If-None-Match` with `304 Not Modified`.
+#[derive(Debug, Clone)]
+pub struct ETag<S> {
+    inner: S,
+    strength: Strength,
+    max_buffer: usize,
+}
+
+/// How entity tags are computed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strength {
+    /// A hash of the buffered response body.
+    Strong,
+    /// Derived from the `Content-Length` and `Last-Modified` headers.
+    Weak,
+}
+
+/// Response body for `ETag`.
+///
+/// Yields the part of the body buffered before it was found too large to
+/// hash, if any, then the rest of the body.
+#[derive(Debug)]
+pub struct ETagBody<B> {
+    prefix: Option<B>,
+    inner: B,
+}
+
+/// Response future for `ETag`.
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+    /// The strength of the tag to set, if any.
+    strength: Option<Strength>,
+    max_buffer: usize,
+    /// The `If-None-Match` header values of the request.
+    if_none_match: Vec<HeaderValue>,
+}
+
+enum State<F, B> {
+    Called(F),
+    Tagging(F),
+    Buffering {
+        parts: response::Parts,
+        body: B,
+        buf: BytesMut,
+    },
+    Done,
+}
+
+// ===== impl ETag =====
+
+impl<S> ETag<S> {
+    /// Create a new `ETag` setting strong entity tags.
+    pub fn new(inner: S) -> Self {
+        Self::with_strength(inner, Strength::Strong)
+    }
+
+    /// Create a new `ETag` setting weak entity tags, without buffering.
+    pub fn weak(inner: S) -> Self {
+        Self::with_strength(inner, Strength::Weak)
+    }
+
+    /// Create a new `ETag` setting entity tags of the given strength.
+    pub fn with_strength(inner: S, strength: Strength) -> Self {
+        ETag {
+            inner,
+            strength,
+            max_buffer: 1024 * 1024,
+        }
+    }
+
+    /// Only hash response bodies of at most `max` bytes.
+    pub fn max_buffer(mut self, max: usize) -> Self {
+        self.max_buffer = max;
+        self
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ETag<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: From<ResBody::Error>,
+    ResBody: Body + From<Bytes>,
+{
+    type Response = Response<ETagBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let strength = match *req.method() {
+            Method::GET => Some(self.strength),
+            Method::HEAD if self.strength == Strength::Weak => Some(Strength::Weak),
+            _ => None,
+        };
+        let tagging = (req.method() == Method::GET || req.method() == Method::HEAD)
+            && !is_upgrade_request(&req);
+        let if_none_match = req
+            .headers()
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .cloned()
+            .collect();
+        let future = self.inner.call(req);
+        ResponseFuture {
+            state: if tagging {
+                State::Tagging(future)
+            } else {
+                State::Called(future)
+            },
+            strength,
+            max_buffer: self.max_buffer,
+            if_none_match,
+        }
+    }
+}
+
+/// Returns whether an entity tag list, such as an `If-None-Match` header,
+/// matches `etag`, comparing tags weakly or strongly.
+pub(crate) fn list_matches<'a, I>(values: I, etag: &str, weak: bool) -> bool
+where
+    I: IntoIterator<Item = &'a HeaderValue>,
+{
+    let etag = match parse_tag(etag) {
+        Some(etag) => etag,
+        None => return false,
+    };
+    values
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|candidate| {
+            let candidate = candidate.trim();
+            if candidate == "*" {
+                return true;
+            }
+            match parse_tag(candidate) {
+                Some(candidate) if weak => candidate.1 == etag.1,
+                Some(candidate) => !candidate.0 && !etag.0 && candidate.1 == etag.1,
+                None => false,
+            }
+        })
+}
+
+/// Splits an entity tag into whether it is weak and its opaque tag.
+fn parse_tag(etag: &str) -> Option<(bool, &str)> {
+    let (weak, tag) = if etag.starts_with("W/") {
+        (true, &etag[2..])
+    } else {
+        (false, etag)
+    };
+    if tag.len() >= 2 && tag.starts_with('"') && tag.ends_with('"') {
+        Some((weak, tag))
+    } else {
+        None
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> ResponseFuture<F, B>
+where
+    B: From<Bytes>,
+{
+    /// Sets the entity tag, returning the response or `304 Not Modified`.
+    fn finish(
+        &self,
+        mut parts: response::Parts,
+        body: ETagBody<B>,
+        etag: Option<HeaderValue>,
+    ) -> Response<ETagBody<B>> {
+        if let Some(etag) = etag {
+            parts.headers.insert(ETAG, etag);
+        }
+
+        let not_modified = parts.status == StatusCode::OK
+            && parts
+                .headers
+                .get(ETAG)
+                .and_then(|etag| etag.to_str().ok())
+                .map_or(false, |etag| list_matches(&self.if_none_match, etag, true));
+        if !not_modified {
+            return Response::from_parts(parts, body);
+        }
+
+        // Only keep the headers a `200 OK` response would have needed to
+        // update stored responses.
+        let mut headers = HeaderMap::new();
+        for name in &[
+            CACHE_CONTROL,
+            CONTENT_LOCATION,
+            DATE,
+            ETAG,
+            EXPIRES,
+            LAST_MODIFIED,
+            VARY,
+        ] {
+            for value in parts.headers.get_all(name) {
+                headers.append(name.clone(), value.clone());
+            }
+        }
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers = headers;
+        Response::from_parts(parts, ETagBody::new(B::from(Bytes::new())))
+    }
+}
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+    F::Error: From<B::Error>,
+    B: Body + From<Bytes>,
+{
+    type Item = Response<ETagBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Called(mut future) => match future.poll()? {
+                    Async::Ready(response) => {
+                        return Ok(Async::Ready(response.map(ETagBody::new)));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Called(future);
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Tagging(mut future) => {
+                    let response = match future.poll()? {
+                        Async::Ready(response) => response,
+                        Async::NotReady => {
+                            self.state = State::Tagging(future);
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    let (parts, body) = response.into_parts();
+                    let too_large = parts
+                        .headers
+                        .get(CONTENT_LENGTH)
+                        .and_then(|len| len.to_str().ok())
+                        .and_then(|len| len.parse::<u64>().ok())
+                        .map_or(false, |len| len > self.max_buffer as u64);
+                    let buffer = self.strength == Some(Strength::Strong)
+                        && parts.status.is_success()
+                        && parts.status != StatusCode::PARTIAL_CONTENT
+                        && !parts.headers.contains_key(ETAG)
+                        && !is_event_stream(&parts.headers);
+                    if buffer && !too_large {
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf: BytesMut::new(),
+                        };
+                        continue;
+                    }
+
+                    let etag = if self.strength == Some(Strength::Weak) || buffer {
+                        weak_etag(&parts.headers)
+                    } else {
+                        None
+                    };
+                    let body = ETagBody::new(body);
+                    return Ok(Async::Ready(self.finish(parts, body, etag)));
+                }
+                State::Buffering {
+                    parts,
+                    mut body,
+                    mut buf,
+                } => match body.poll_data()? {
+                    Async::Ready(Some(mut data)) => {
+                        while data.has_remaining() {
+                            let n = {
+                                let bytes = data.bytes();
+                                buf.extend_from_slice(bytes);
+                                bytes.len()
+                            };
+                            data.advance(n);
+                        }
+                        if buf.len() > self.max_buffer {
+                            let etag = weak_etag(&parts.headers);
+                            let body = ETagBody {
+                                prefix: Some(B::from(buf.freeze())),
+                                inner: body,
+                            };
+                            return Ok(Async::Ready(self.finish(parts, body, etag)));
+                        }
+                        self.state = State::Buffering { parts, body, buf };
+                    }
+                    Async::Ready(None) => {
+                        let body = buf.freeze();
+                        let etag = strong_etag(&body);
+                        let body = ETagBody::new(B::from(body));
+                        return Ok(Async::Ready(self.finish(parts, body, Some(etag))));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Buffering { parts, body, buf };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<F, B> fmt::Debug for ResponseFuture<F, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Called(_) => "Called",
+            State::Tagging(_) => "Tagging",
+            State::Buffering { .. } => "Buffering",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .field("strength", &self.strength)
+            .finish()
+    }
+}
+
+// ===== impl ETagBody =====
+
+impl<B> ETagBody<B> {
+    fn new(inner: B) -> Self {
+        ETagBody {
+            prefix: None,
+            inner,
+        }
+    }
+}
+
+impl<B> Body for ETagBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        if let Some(ref mut prefix) = self.prefix {
+            if let Some(data) = try_ready!(prefix.poll_data()) {
+                return Ok(Async::Ready(Some(data)));
+            }
+        }
+        self.prefix = None;
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.prefix.as_ref().map_or(true, Body::is_end_stream) && self.inner.is_end_stream()
+    }
+}
+
+fn is_event_stream(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map_or(false, |value| value.starts_with("text/event-stream"))
+}
+
+/// Returns a strong entity tag made of the first 128 bits of the SHA-256
+/// hash of `body`.
+pub(crate) fn strong_etag(body: &[u8]) -> HeaderValue {
+    let digest = Sha256::digest(body);
+    let tag = base64::encode_config(&digest[..16], base64::URL_SAFE_NO_PAD);
+    HeaderValue::from_str(&format!("\"{}\"", tag)).expect("base64 is a valid header value")
+}
+
+/// Returns a weak entity tag made of the `Content-Length` and
+/// `Last-Modified` headers.
+fn weak_etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    if headers.contains_key(ETAG) {
+        return None;
+    }
+    let last_modified = headers.get(LAST_MODIFIED)?.to_str().ok()?;
+    let last_modified = httpdate::parse_http_date(last_modified).ok()?;
+    let secs = last_modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .ok()?
+        .as_secs();
+    let len = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|len| len.to_str().ok())
+        .and_then(|len| len.parse::<u64>().ok());
+    let tag = match len {
+        Some(len) => format!("W/\"{:x}-{:x}\"", len, secs),
+        None => format!("W/\"{:x}\"", secs),
+    };
+    HeaderValue::from_str(&tag).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_lists() {
+        let values = [HeaderValue::from_static("\"a\", W/\"b\"")];
+        assert!(list_matches(&values, "\"a\"", true));
+        assert!(list_matches(&values, "\"b\"", true));
+        assert!(list_matches(&values, "W/\"a\"", true));
+        assert!(list_matches(&values, "\"a\"", false));
+        assert!(!list_matches(&values, "\"b\"", false));
+        assert!(!list_matches(&values, "W/\"a\"", false));
+        assert!(!list_matches(&values, "\"c\"", true));
+        assert!(list_matches(
+            &[HeaderValue::from_static("*")],
+            "\"c\"",
+            false
+        ));
+    }
+}
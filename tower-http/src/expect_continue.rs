@@ -0,0 +1,246 @@
+//! Support for `Expect: 100-continue`.
+//!
+//! A client sending `Expect: 100-continue` waits for an interim `100
+//! Continue` response before transmitting the request body. If the request
+//! is rejected before its body is read, the final response can be sent
+//! instead and the body is never transmitted.
+//!
+//! The convention is:
+//!
+//! - the server integration inserts a `Continue` request extension, which
+//!   sends the interim response on the connection;
+//! - `ExpectContinue` wraps the body of requests expecting `100-continue`
+//!   in a `ContinueBody`, which sends the interim response when the body is
+//!   first polled, and rejects other expectations with `417 Expectation
+//!   Failed`;
+//! - middleware rejecting requests before reading their body, such as
+//!   `auth::RequireAuthorization` or `body_limit::RequestBodyLimit`, simply
+//!   does not poll the body. Those that need to know whether the unread body
+//!   will be transmitted use `expects_continue`: if it returns `true`, the
+//!   connection can be kept alive.
+//!
+//! `ExpectContinue` should be placed outside of the rejecting middleware.
+
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, EXPECT};
+use http::{Request, Response, StatusCode, Version};
+use http_body::Body;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Sends the interim `100 Continue` response of a request.
+///
+/// Inserted as a request extension by the server integration. Clones share
+/// the same state, and the response is sent at most once.
+#[derive(Clone)]
+pub struct Continue {
+    send: Arc<dyn Fn() + Send + Sync>,
+    sent: Arc<AtomicBool>,
+}
+
+/// Sends `100 Continue` when the request body is first polled.
+#[derive(Debug, Clone)]
+pub struct ExpectContinue<S> {
+    inner: S,
+}
+
+/// A body sending `100 Continue` when first polled.
+#[derive(Debug)]
+pub struct ContinueBody<B> {
+    inner: B,
+    cont: Option<Continue>,
+}
+
+/// Response future for `ExpectContinue`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+/// Returns whether `req` expects `100 Continue` that has not been sent
+/// yet, so that rejecting it now prevents the body from being transmitted.
+pub fn expects_continue<B>(req: &Request<B>) -> bool {
+    let pending = req
+        .extensions()
+        .get::<Continue>()
+        .map_or(true, |cont| !cont.is_sent());
+    pending && expectation(req.version(), req.headers()) == Some(true)
+}
+
+/// Returns `Some(true)` for `100-continue`, `Some(false)` for other
+/// expectations and `None` without an `Expect` header.
+fn expectation(version: Version, headers: &HeaderMap) -> Option<bool> {
+    let mut values = headers.get_all(EXPECT).iter().peekable();
+    values.peek()?;
+
+    // Expectations are only defined for HTTP/1.1.
+    if version != Version::HTTP_11 {
+        return None;
+    }
+
+    let all_continue = values.all(|value| {
+        value.to_str().ok().map_or(false, |value| {
+            value.trim().eq_ignore_ascii_case("100-continue")
+        })
+    });
+    Some(all_continue)
+}
+
+// ===== impl Continue =====
+
+impl Continue {
+    /// Create a new `Continue` calling `send` to send the interim response.
+    pub fn new<F>(send: F) -> Self
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        Continue {
+            send: Arc::new(send),
+            sent: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Send the interim response, unless it has already been sent.
+    pub fn send(&self) {
+        if !self.sent.swap(true, Ordering::SeqCst) {
+            (self.send)();
+        }
+    }
+
+    /// Returns whether the interim response has been sent.
+    pub fn is_sent(&self) -> bool {
+        self.sent.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for Continue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Continue")
+            .field("sent", &self.is_sent())
+            .finish()
+    }
+}
+
+// ===== impl ExpectContinue =====
+
+impl<S> ExpectContinue<S> {
+    /// Create a new `ExpectContinue`.
+    pub fn new(inner: S) -> Self {
+        ExpectContinue { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ExpectContinue<S>
+where
+    S: Service<Request<ContinueBody<ReqBody>>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let cont = match expectation(req.version(), req.headers()) {
+            Some(true) => req.extensions().get::<Continue>().cloned(),
+            Some(false) => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::EXPECTATION_FAILED;
+                return ResponseFuture {
+                    state: State::Rejected(Some(res)),
+                };
+            }
+            None => None,
+        };
+
+        let req = req.map(|body| ContinueBody { inner: body, cont });
+        ResponseFuture {
+            state: State::Accepted(self.inner.call(req)),
+        }
+    }
+}
+
+// ===== impl ContinueBody =====
+
+impl<B> ContinueBody<B> {
+    /// Returns a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+}
+
+impl<B> Body for ContinueBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        if let Some(cont) = self.cont.take() {
+            cont.send();
+        }
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        if let Some(cont) = self.cont.take() {
+            cont.send();
+        }
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,493 @@
+//! Middleware that determines client information from proxy headers.
+//!
+//! Reverse proxies report the original client address, scheme and host in
+//! the `Forwarded` (RFC 7239) or `X-Forwarded-For`, `X-Forwarded-Proto` and
+//! `X-Forwarded-Host` headers. These headers are trivially spoofable, so they
+//! are only honoured when the request was received from a trusted proxy.
+//!
+//! The address of the immediate peer is read from a `SocketAddr` request
+//! extension, which transports are expected to insert. Without it no proxy
+//! is trusted.
+
+use crate::scheme;
+use http::header::{HeaderMap, FORWARDED, HOST};
+use http::uri::{Authority, Scheme};
+use http::{HttpTryFrom, Request};
+use std::fmt;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Name of the `X-Forwarded-For` header.
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Name of the `X-Forwarded-Proto` header.
+pub const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+/// Name of the `X-Forwarded-Host` header.
+pub const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// Information about the client that originated a request, inserted as a
+/// request extension by `SetClientInfo`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// The client address.
+    pub ip: Option<IpAddr>,
+    /// The scheme the client used.
+    pub proto: Option<Scheme>,
+    /// The host the client requested.
+    pub host: Option<Authority>,
+}
+
+/// Inserts a `ClientInfo` extension into requests.
+#[derive(Debug, Clone)]
+pub struct SetClientInfo<S> {
+    inner: S,
+    trusted: Arc<TrustedProxies>,
+}
+
+/// A set of address ranges whose proxy headers are trusted.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    ranges: Vec<Cidr>,
+}
+
+/// An IP address range in CIDR notation, such as `10.0.0.0/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cidr {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+/// Error returned when parsing an invalid `Cidr`.
+#[derive(Debug)]
+pub struct InvalidCidr {
+    _p: (),
+}
+
+/// A single element of a `Forwarded` header.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct Element {
+    for_: Option<IpAddr>,
+    proto: Option<Scheme>,
+    host: Option<Authority>,
+}
+
+// ===== impl SetClientInfo =====
+
+impl<S> SetClientInfo<S> {
+    /// Create a new `SetClientInfo` trusting the given proxies.
+    pub fn new(inner: S, trusted: TrustedProxies) -> Self {
+        SetClientInfo {
+            inner,
+            trusted: Arc::new(trusted),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for SetClientInfo<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let info = self.trusted.client_info(&req);
+        req.extensions_mut().insert(info);
+        self.inner.call(req)
+    }
+}
+
+// ===== impl TrustedProxies =====
+
+impl TrustedProxies {
+    /// Trust no proxies.
+    pub fn none() -> Self {
+        TrustedProxies::default()
+    }
+
+    /// Trust the given address ranges.
+    pub fn new<I>(ranges: I) -> Self
+    where
+        I: IntoIterator<Item = Cidr>,
+    {
+        TrustedProxies {
+            ranges: ranges.into_iter().collect(),
+        }
+    }
+
+    /// Trust loopback and private network addresses.
+    pub fn private_networks() -> Self {
+        let ranges = [
+            "127.0.0.0/8",
+            "10.0.0.0/8",
+            "172.16.0.0/12",
+            "192.168.0.0/16",
+            "::1/128",
+            "fc00::/7",
+        ];
+        Self::new(ranges.iter().map(|r| r.parse().unwrap()))
+    }
+
+    /// Returns whether `addr` is a trusted proxy.
+    pub fn is_trusted(&self, addr: IpAddr) -> bool {
+        self.ranges.iter().any(|range| range.contains(addr))
+    }
+
+    /// Determine the `ClientInfo` of `req`.
+    pub fn client_info<B>(&self, req: &Request<B>) -> ClientInfo {
+        let peer = req.extensions().get::<SocketAddr>().map(|addr| addr.ip());
+
+        let mut info = ClientInfo {
+            ip: peer,
+            proto: scheme::request_scheme(req).cloned(),
+            host: req
+                .uri()
+                .authority_part()
+                .cloned()
+                .or_else(|| header_authority(req.headers(), HOST.as_str())),
+        };
+
+        match peer {
+            Some(peer) if self.is_trusted(peer) => {}
+            _ => return info,
+        }
+
+        let forwarded = req.headers().contains_key(FORWARDED);
+        let elements = if forwarded {
+            parse_forwarded(req.headers())
+        } else {
+            parse_x_forwarded_for(req.headers())
+        };
+
+        // Walk the chain from the nearest hop, skipping trusted proxies. The
+        // first untrusted hop is the client; if every hop is trusted, the
+        // farthest one is.
+        let mut client = None;
+        for element in elements.iter().rev() {
+            client = Some(element);
+            match element.for_ {
+                Some(addr) if self.is_trusted(addr) => continue,
+                _ => break,
+            }
+        }
+
+        if let Some(client) = client {
+            if client.for_.is_some() {
+                info.ip = client.for_;
+            }
+            if client.proto.is_some() {
+                info.proto = client.proto.clone();
+            }
+            if client.host.is_some() {
+                info.host = client.host.clone();
+            }
+        }
+
+        if !forwarded {
+            // Proxies append to these headers rather than replacing them, so
+            // only the last element, set by the nearest proxy, which is
+            // trusted at this point, is used; the others may be the client's.
+            let proto = last_element(req.headers(), X_FORWARDED_PROTO)
+                .and_then(|value| Scheme::try_from(value).ok());
+            if proto.is_some() {
+                info.proto = proto;
+            }
+            let host = last_element(req.headers(), X_FORWARDED_HOST)
+                .and_then(|value| Authority::try_from(value).ok());
+            if host.is_some() {
+                info.host = host;
+            }
+        }
+
+        info
+    }
+}
+
+// ===== impl Cidr =====
+
+impl Cidr {
+    /// Create a new range from an address and a prefix length.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, InvalidCidr> {
+        let max = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        if prefix > max {
+            return Err(InvalidCidr { _p: () });
+        }
+        Ok(Cidr { addr, prefix })
+    }
+
+    /// Returns whether `addr` is within this range.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, addr) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::max_value()
+                    .checked_shl(32 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::max_value()
+                    .checked_shl(128 - u32::from(self.prefix))
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            }
+            (IpAddr::V4(_), IpAddr::V6(addr)) => match addr.to_ipv4() {
+                // IPv4-mapped addresses, as reported by dual-stack sockets.
+                Some(v4) if addr.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => {
+                    self.contains(IpAddr::V4(v4))
+                }
+                _ => false,
+            },
+            (IpAddr::V6(_), IpAddr::V4(_)) => false,
+        }
+    }
+}
+
+impl FromStr for Cidr {
+    type Err = InvalidCidr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(2, '/');
+        let addr: IpAddr = parts
+            .next()
+            .unwrap()
+            .parse()
+            .map_err(|_| InvalidCidr { _p: () })?;
+        let prefix = match parts.next() {
+            Some(prefix) => prefix.parse().map_err(|_| InvalidCidr { _p: () })?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Cidr::new(addr, prefix)
+    }
+}
+
+impl From<IpAddr> for Cidr {
+    fn from(addr: IpAddr) -> Self {
+        let prefix = if addr.is_ipv4() { 32 } else { 128 };
+        Cidr { addr, prefix }
+    }
+}
+
+// ===== impl InvalidCidr =====
+
+impl fmt::Display for InvalidCidr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid CIDR address range")
+    }
+}
+
+impl std::error::Error for InvalidCidr {}
+
+// ===== parsing =====
+
+fn header_authority(headers: &HeaderMap, name: &str) -> Option<Authority> {
+    let value = headers.get(name)?.to_str().ok()?;
+    let value = value.split(',').next()?.trim();
+    Authority::try_from(value).ok()
+}
+
+/// Returns the last element of the comma-separated list in the headers
+/// `name`.
+fn last_element<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    let value = headers.get_all(name).iter().next_back()?.to_str().ok()?;
+    value.rsplit(',').next().map(str::trim)
+}
+
+fn parse_forwarded(headers: &HeaderMap) -> Vec<Element> {
+    let mut elements = Vec::new();
+
+    for value in headers.get_all(FORWARDED).iter() {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+
+        for element in value.split(',') {
+            let mut parsed = Element::default();
+
+            for pair in element.split(';') {
+                let mut pair = pair.splitn(2, '=');
+                let key = pair.next().unwrap().trim();
+                let value = match pair.next() {
+                    Some(value) => unquote(value.trim()),
+                    None => continue,
+                };
+
+                if key.eq_ignore_ascii_case("for") {
+                    parsed.for_ = parse_node(value);
+                } else if key.eq_ignore_ascii_case("proto") {
+                    parsed.proto = Scheme::try_from(value).ok();
+                } else if key.eq_ignore_ascii_case("host") {
+                    parsed.host = Authority::try_from(value).ok();
+                }
+            }
+
+            elements.push(parsed);
+        }
+    }
+
+    elements
+}
+
+fn parse_x_forwarded_for(headers: &HeaderMap) -> Vec<Element> {
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|node| Element {
+            for_: parse_node(node.trim()),
+            ..Element::default()
+        })
+        .collect()
+}
+
+fn unquote(value: &str) -> &str {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        &value[1..value.len() - 1]
+    } else {
+        value
+    }
+}
+
+/// Parses a node identifier: an IP address, optionally bracketed (IPv6) and
+/// followed by a port. Obfuscated and `unknown` identifiers yield `None`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    if node.starts_with('[') {
+        let end = node.find(']')?;
+        return node[1..end].parse().ok();
+    }
+
+    if let Ok(addr) = node.parse() {
+        return Some(addr);
+    }
+
+    node.parse::<SocketAddr>().ok().map(|addr| addr.ip())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    fn request(peer: &str, headers: &[(&'static str, &'static str)]) -> Request<()> {
+        let mut req = Request::get("/").header(HOST, "internal").body(()).unwrap();
+        for (name, value) in headers {
+            req.headers_mut()
+                .append(*name, HeaderValue::from_static(value));
+        }
+        req.extensions_mut()
+            .insert(peer.parse::<SocketAddr>().unwrap());
+        req
+    }
+
+    #[test]
+    fn cidr_contains() {
+        let range: Cidr = "10.1.0.0/16".parse().unwrap();
+        assert!(range.contains("10.1.2.3".parse().unwrap()));
+        assert!(!range.contains("10.2.0.1".parse().unwrap()));
+        assert!(range.contains("::ffff:10.1.0.1".parse().unwrap()));
+
+        let range: Cidr = "fc00::/7".parse().unwrap();
+        assert!(range.contains("fd12::1".parse().unwrap()));
+        assert!(!range.contains("2001:db8::1".parse().unwrap()));
+
+        assert!("10.0.0.0/33".parse::<Cidr>().is_err());
+    }
+
+    #[test]
+    fn ignores_headers_from_untrusted_peer() {
+        let trusted = TrustedProxies::private_networks();
+        let req = request(
+            "203.0.113.7:1234",
+            &[
+                ("x-forwarded-for", "198.51.100.1"),
+                ("x-forwarded-proto", "https"),
+            ],
+        );
+
+        let info = trusted.client_info(&req);
+        assert_eq!(info.ip, Some("203.0.113.7".parse().unwrap()));
+        assert_eq!(info.proto, None);
+        assert_eq!(info.host.unwrap(), "internal");
+    }
+
+    #[test]
+    fn parses_x_forwarded_from_trusted_peer() {
+        let trusted = TrustedProxies::private_networks();
+        let req = request(
+            "10.0.0.2:1234",
+            &[
+                ("x-forwarded-for", "198.51.100.1, 203.0.113.9, 10.0.0.1"),
+                ("x-forwarded-proto", "https"),
+                ("x-forwarded-host", "example.com"),
+            ],
+        );
+
+        let info = trusted.client_info(&req);
+        assert_eq!(info.ip, Some("203.0.113.9".parse().unwrap()));
+        assert_eq!(info.proto, Some(Scheme::HTTPS));
+        assert_eq!(info.host.unwrap(), "example.com");
+    }
+
+    #[test]
+    fn uses_x_forwarded_elements_of_the_nearest_proxy() {
+        let trusted = TrustedProxies::private_networks();
+        let req = request(
+            "10.0.0.2:1234",
+            &[
+                ("x-forwarded-for", "198.51.100.1"),
+                ("x-forwarded-proto", "https, http"),
+                ("x-forwarded-host", "evil.example"),
+                ("x-forwarded-host", "example.com"),
+            ],
+        );
+
+        let info = trusted.client_info(&req);
+        assert_eq!(info.proto, Some(Scheme::HTTP));
+        assert_eq!(info.host.unwrap(), "example.com");
+    }
+
+    #[test]
+    fn parses_forwarded_from_trusted_peer() {
+        let trusted = TrustedProxies::private_networks();
+        let req = request(
+            "10.0.0.2:1234",
+            &[(
+                "forwarded",
+                "for=\"[2001:db8::1]:4711\";proto=https;host=example.com, for=10.0.0.1",
+            )],
+        );
+
+        let info = trusted.client_info(&req);
+        assert_eq!(info.ip, Some("2001:db8::1".parse().unwrap()));
+        assert_eq!(info.proto, Some(Scheme::HTTPS));
+        assert_eq!(info.host.unwrap(), "example.com");
+    }
+}
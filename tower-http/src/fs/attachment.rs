@@ -0,0 +1,166 @@
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, CONTENT_DISPOSITION};
+use http::{Request, Response};
+use std::fmt::Write;
+use tower_service::Service;
+
+/// Serves the responses of the inner service as attachments, to be
+/// downloaded under a file name.
+#[derive(Debug, Clone)]
+pub struct Attachment<S> {
+    inner: S,
+    disposition: HeaderValue,
+}
+
+/// Response future for `Attachment`.
+#[derive(Debug)]
+pub struct AttachmentFuture<F> {
+    inner: F,
+    disposition: HeaderValue,
+}
+
+impl<S> Attachment<S> {
+    /// Create a new `Attachment` offering the responses of `inner` to be
+    /// saved as `filename`.
+    ///
+    /// Path separators in `filename` are replaced, so that clients are not
+    /// told to save files outside their download directory.
+    pub fn new(inner: S, filename: &str) -> Self {
+        Attachment {
+            inner,
+            disposition: content_disposition(filename),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Attachment<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = AttachmentFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        AttachmentFuture {
+            inner: self.inner.call(req),
+            disposition: self.disposition.clone(),
+        }
+    }
+}
+
+impl<F, B> Future for AttachmentFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        if res.status().is_success() {
+            res.headers_mut()
+                .insert(CONTENT_DISPOSITION, self.disposition.clone());
+        }
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Returns a `Content-Disposition` value offering a response to be saved as
+/// `filename`, following RFC 6266.
+///
+/// Names which are not plain ASCII are sent in a `filename*` parameter,
+/// encoded as in RFC 5987, along with an ASCII approximation in `filename`
+/// for older clients.
+pub fn content_disposition(filename: &str) -> HeaderValue {
+    let filename: String = filename
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            '"' | '\\' => '_',
+            ' '..='~' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut value = format!("attachment; filename=\"{}\"", fallback);
+    if fallback != filename {
+        value.push_str("; filename*=UTF-8''");
+        for &b in filename.as_bytes() {
+            if is_attr_char(b) {
+                value.push(b as char);
+            } else {
+                write!(value, "%{:02X}", b).unwrap();
+            }
+        }
+    }
+    HeaderValue::from_str(&value).expect("generated a valid header value")
+}
+
+/// Returns whether `b` may appear unencoded in an RFC 5987 value.
+fn is_attr_char(b: u8) -> bool {
+    match b {
+        b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => true,
+        b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_file_names() {
+        assert_eq!(
+            content_disposition("report.pdf"),
+            "attachment; filename=\"report.pdf\""
+        );
+        assert_eq!(
+            content_disposition("my report.pdf"),
+            "attachment; filename=\"my report.pdf\""
+        );
+        assert_eq!(
+            content_disposition("say \"hi\".txt"),
+            "attachment; filename=\"say _hi_.txt\"; filename*=UTF-8''say%20%22hi%22.txt"
+        );
+        assert_eq!(
+            content_disposition("€ rates.txt"),
+            "attachment; filename=\"_ rates.txt\"; filename*=UTF-8''%E2%82%AC%20rates.txt"
+        );
+        assert_eq!(
+            content_disposition("../etc/passwd"),
+            "attachment; filename=\".._etc_passwd\""
+        );
+        assert_eq!(
+            content_disposition("a\r\nb"),
+            "attachment; filename=\"a__b\""
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use bytes::Bytes;
+use futures::{try_ready, Async, Poll};
+use http::HeaderMap;
+use http_body::Body;
+use std::collections::VecDeque;
+use std::io::{self, Cursor, SeekFrom};
+use tokio_fs::File;
+use tokio_io::AsyncRead;
+
+/// The size of the chunks files are read in by default.
+pub(crate) const DEFAULT_CHUNK_SIZE: usize = 8 * 1024;
+
+/// The body of responses of the file services, streaming a file.
+#[derive(Debug)]
+pub struct FileBody {
+    file: Option<File>,
+    parts: VecDeque<Part>,
+    buf: Vec<u8>,
+    chunk_size: usize,
+}
+
+/// A step of streaming a body.
+#[derive(Debug)]
+pub(crate) enum Part {
+    /// Send bytes as they are.
+    Bytes(Bytes),
+    /// Move to an offset in the file.
+    Seek(u64),
+    /// Send a number of bytes from the file.
+    Read(u64),
+}
+
+impl FileBody {
+    /// Create a new `FileBody` streaming `parts` of `file`.
+    pub(crate) fn new(file: File, parts: VecDeque<Part>) -> Self {
+        FileBody {
+            file: Some(file),
+            parts,
+            buf: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Create a new `FileBody` holding `bytes`, generated in memory.
+    pub(crate) fn from_bytes(bytes: Bytes) -> Self {
+        let mut parts = VecDeque::new();
+        parts.push_back(Part::Bytes(bytes));
+        FileBody::from_parts(parts)
+    }
+
+    /// Create a new `FileBody` sending `parts`, which must all be bytes.
+    pub(crate) fn from_parts(parts: VecDeque<Part>) -> Self {
+        FileBody {
+            file: None,
+            parts,
+            buf: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Read the file in chunks of at most `size` bytes.
+    pub(crate) fn set_chunk_size(&mut self, size: usize) {
+        self.chunk_size = size;
+    }
+
+    /// Create a new, empty `FileBody`.
+    pub fn empty() -> Self {
+        FileBody {
+            file: None,
+            parts: VecDeque::new(),
+            buf: Vec::new(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+}
+
+impl From<Bytes> for FileBody {
+    fn from(bytes: Bytes) -> Self {
+        FileBody::from_bytes(bytes)
+    }
+}
+
+impl Default for FileBody {
+    fn default() -> Self {
+        FileBody::empty()
+    }
+}
+
+impl Body for FileBody {
+    type Data = Cursor<Bytes>;
+    type Error = io::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        loop {
+            match self.parts.front_mut() {
+                Some(Part::Read(remaining)) if *remaining > 0 => {
+                    let len = if *remaining < self.chunk_size as u64 {
+                        *remaining as usize
+                    } else {
+                        self.chunk_size
+                    };
+                    self.buf.resize(len, 0);
+                    let file = self.file.as_mut().expect("file parts without a file");
+                    let n = try_ready!(file.poll_read(&mut self.buf));
+                    if n == 0 {
+                        // The file was truncated while being sent.
+                        self.parts.clear();
+                        self.file = None;
+                        return Err(io::ErrorKind::UnexpectedEof.into());
+                    }
+                    *remaining -= n as u64;
+                    let data = Bytes::from(&self.buf[..n]);
+                    return Ok(Async::Ready(Some(Cursor::new(data))));
+                }
+                Some(_) => {}
+                None => {
+                    self.file = None;
+                    return Ok(Async::Ready(None));
+                }
+            }
+
+            match self.parts.pop_front() {
+                Some(Part::Bytes(bytes)) => return Ok(Async::Ready(Some(Cursor::new(bytes)))),
+                Some(Part::Seek(offset)) => {
+                    let file = self.file.as_mut().expect("file parts without a file");
+                    if let Async::NotReady = file.poll_seek(SeekFrom::Start(offset))? {
+                        self.parts.push_front(Part::Seek(offset));
+                        return Ok(Async::NotReady);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, io::Error> {
+        Ok(Async::Ready(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.parts.is_empty()
+    }
+}
@@ -0,0 +1,83 @@
+use http::header::{HeaderMap, ACCEPT_ENCODING};
+
+/// A content coding of precompressed files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    /// Returns the name of the coding, as in `Content-Encoding`.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// Returns the extension of files precompressed with the coding.
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// Returns whether the `Accept-Encoding` header in `headers` accepts
+/// `encoding`.
+pub(crate) fn accepts(headers: &HeaderMap, encoding: Encoding) -> bool {
+    let mut wildcard = false;
+    let items = headers
+        .get_all(ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    for item in items {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or("").trim();
+        let accepted = params
+            .filter_map(|param| {
+                let mut param = param.splitn(2, '=');
+                match (param.next(), param.next()) {
+                    (Some(name), Some(q)) if name.trim().eq_ignore_ascii_case("q") => {
+                        q.trim().parse::<f32>().ok()
+                    }
+                    _ => None,
+                }
+            })
+            .next_back()
+            .map_or(true, |q| q > 0.0);
+        if coding.eq_ignore_ascii_case(encoding.name()) {
+            return accepted;
+        }
+        if coding == "*" {
+            wildcard = accepted;
+        }
+    }
+    wildcard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    fn accepts(value: &'static str, encoding: Encoding) -> bool {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static(value));
+        super::accepts(&headers, encoding)
+    }
+
+    #[test]
+    fn parses_accept_encoding() {
+        assert!(accepts("gzip, deflate, br", Encoding::Brotli));
+        assert!(accepts("gzip;q=0.5", Encoding::Gzip));
+        assert!(!accepts("gzip;q=0", Encoding::Gzip));
+        assert!(!accepts("deflate", Encoding::Gzip));
+        assert!(accepts("*", Encoding::Gzip));
+        assert!(!accepts("*, gzip;q=0", Encoding::Gzip));
+        assert!(!accepts("", Encoding::Brotli));
+    }
+}
@@ -0,0 +1,220 @@
+use super::FileBody;
+use bytes::Bytes;
+use http::header::{HeaderMap, HeaderValue, ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, VARY};
+use http::Response;
+use percent_encoding::{utf8_percent_encode, PATH_SEGMENT_ENCODE_SET};
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+use std::{fs, io};
+
+/// The format of directory listings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Format {
+    Html,
+    Json,
+}
+
+/// A directory to list, should its index file be missing.
+#[derive(Debug)]
+pub(crate) struct Listing {
+    /// The path of the directory.
+    pub(crate) dir: PathBuf,
+    /// The path the directory was requested at, ending with a slash.
+    pub(crate) path: String,
+    pub(crate) format: Format,
+    /// Whether to list entries whose name starts with a dot.
+    pub(crate) dotfiles: bool,
+}
+
+struct Entry {
+    name: String,
+    dir: bool,
+    len: u64,
+    modified: Option<u64>,
+}
+
+/// Picks the format of a listing from the `Accept` header in `headers`.
+pub(crate) fn negotiate(headers: &HeaderMap) -> Format {
+    let json = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|item| {
+            let media_type = item.split(';').next().unwrap_or("").trim();
+            media_type.eq_ignore_ascii_case("application/json")
+        });
+    if json {
+        Format::Json
+    } else {
+        Format::Html
+    }
+}
+
+/// Lists a directory.
+///
+/// This blocks on reading the directory.
+pub(crate) fn render(listing: &Listing) -> io::Result<Response<FileBody>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(&listing.dir)? {
+        let entry = entry?;
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(_) => continue,
+        };
+        if name.starts_with('.') && !listing.dotfiles {
+            continue;
+        }
+        // Follow symbolic links, skipping broken ones.
+        let metadata = match fs::metadata(entry.path()) {
+            Ok(metadata) => metadata,
+            Err(_) => continue,
+        };
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map(|modified| modified.as_secs());
+        entries.push(Entry {
+            name,
+            dir: metadata.is_dir(),
+            len: metadata.len(),
+            modified,
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let (body, content_type) = match listing.format {
+        Format::Html => (html(&entries, &listing.path), "text/html; charset=utf-8"),
+        Format::Json => (json(&entries), "application/json"),
+    };
+    let mut res = Response::new(FileBody::from_bytes(Bytes::from(body.as_bytes())));
+    let headers = res.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(body.len()));
+    headers.insert(VARY, HeaderValue::from_static("accept"));
+    Ok(res)
+}
+
+fn html(entries: &[Entry], path: &str) -> String {
+    let title = format!("Index of {}", escape_html(path));
+    let mut html = String::new();
+    html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+    let _ = writeln!(html, "<title>{}</title>\n</head>\n<body>", title);
+    let _ = writeln!(html, "<h1>{}</h1>\n<ul>", title);
+    if path != "/" {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for entry in entries {
+        let slash = if entry.dir { "/" } else { "" };
+        let href = utf8_percent_encode(&entry.name, PATH_SEGMENT_ENCODE_SET).to_string();
+        let _ = writeln!(
+            html,
+            "<li><a href=\"./{}{}\">{}{}</a></li>",
+            escape_html(&href),
+            slash,
+            escape_html(&entry.name),
+            slash,
+        );
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+    html
+}
+
+fn json(entries: &[Entry]) -> String {
+    let mut json = String::from("[");
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str("{\"name\":");
+        escape_json(&mut json, &entry.name);
+        if entry.dir {
+            json.push_str(",\"type\":\"directory\"");
+        } else {
+            let _ = write!(json, ",\"type\":\"file\",\"size\":{}", entry.len);
+        }
+        if let Some(modified) = entry.modified {
+            let _ = write!(json, ",\"modified\":{}", modified);
+        }
+        json.push('}');
+    }
+    json.push(']');
+    json
+}
+
+fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn escape_json(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry {
+                name: "a<b>%.txt".to_owned(),
+                dir: false,
+                len: 3,
+                modified: Some(60),
+            },
+            Entry {
+                name: "sub dir".to_owned(),
+                dir: true,
+                len: 0,
+                modified: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn renders_html() {
+        let html = html(&entries(), "/a/");
+        assert!(html.contains("<h1>Index of /a/</h1>"));
+        assert!(html.contains("<li><a href=\"../\">../</a></li>"));
+        assert!(html.contains("<a href=\"./a%3Cb%3E%25.txt\">a&lt;b&gt;%.txt</a>"));
+        assert!(html.contains("<a href=\"./sub%20dir/\">sub dir/</a>"));
+    }
+
+    #[test]
+    fn renders_json() {
+        let mut entries = entries();
+        entries[0].name = "a\"b".to_owned();
+        assert_eq!(
+            json(&entries),
+            "[{\"name\":\"a\\\"b\",\"type\":\"file\",\"size\":3,\"modified\":60},\
+             {\"name\":\"sub dir\",\"type\":\"directory\"}]"
+        );
+    }
+}
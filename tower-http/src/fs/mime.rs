@@ -0,0 +1,127 @@
+use http::header::HeaderValue;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Media types by file extension, sorted by extension.
+const TYPES: &[(&str, &str)] = &[
+    ("avif", "image/avif"),
+    ("bmp", "image/bmp"),
+    ("css", "text/css; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("eot", "application/vnd.ms-fontobject"),
+    ("gif", "image/gif"),
+    ("gz", "application/gzip"),
+    ("htm", "text/html; charset=utf-8"),
+    ("html", "text/html; charset=utf-8"),
+    ("ico", "image/x-icon"),
+    ("jpeg", "image/jpeg"),
+    ("jpg", "image/jpeg"),
+    ("js", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("mp3", "audio/mpeg"),
+    ("mp4", "video/mp4"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("ogv", "video/ogg"),
+    ("otf", "font/otf"),
+    ("pdf", "application/pdf"),
+    ("png", "image/png"),
+    ("svg", "image/svg+xml"),
+    ("tar", "application/x-tar"),
+    ("ttf", "font/ttf"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("wasm", "application/wasm"),
+    ("wav", "audio/wav"),
+    ("webm", "video/webm"),
+    ("webmanifest", "application/manifest+json"),
+    ("webp", "image/webp"),
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("xml", "text/xml; charset=utf-8"),
+    ("zip", "application/zip"),
+];
+
+/// Guesses the media type of files from their extension.
+#[derive(Debug, Clone)]
+pub(crate) struct MimeTypes {
+    /// Media types by lowercase extension, taking precedence over the table.
+    pub(crate) overrides: HashMap<String, HeaderValue>,
+    /// The media type of files with an unknown extension.
+    pub(crate) default: Option<HeaderValue>,
+}
+
+impl Default for MimeTypes {
+    fn default() -> Self {
+        MimeTypes {
+            overrides: HashMap::new(),
+            default: Some(HeaderValue::from_static("application/octet-stream")),
+        }
+    }
+}
+
+impl MimeTypes {
+    /// Guesses the media type of the file at `path`.
+    pub(crate) fn guess(&self, path: &Path) -> Option<HeaderValue> {
+        let ext = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(str::to_ascii_lowercase);
+        let guessed = ext.and_then(|ext| match self.overrides.get(&ext) {
+            Some(value) => Some(value.clone()),
+            None => lookup(&ext).map(HeaderValue::from_static),
+        });
+        guessed.or_else(|| self.default.clone())
+    }
+}
+
+/// Looks up the media type of files with the lowercase extension `ext` in
+/// the built-in table.
+fn lookup(ext: &str) -> Option<&'static str> {
+    TYPES
+        .binary_search_by(|&(e, _)| e.cmp(ext))
+        .ok()
+        .map(|i| TYPES[i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted() {
+        assert!(TYPES.windows(2).all(|w| w[0].0 < w[1].0));
+    }
+
+    #[test]
+    fn guesses_by_extension() {
+        let mut types = MimeTypes {
+            overrides: HashMap::new(),
+            default: None,
+        };
+        let guess = |types: &MimeTypes, path| types.guess(Path::new(path));
+        assert_eq!(
+            guess(&types, "a/b.HTML").unwrap(),
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(
+            guess(&types, "app.js").unwrap(),
+            "text/javascript; charset=utf-8"
+        );
+        assert_eq!(guess(&types, "archive.tar.unknown"), None);
+        assert_eq!(guess(&types, "Makefile"), None);
+
+        types.overrides.insert(
+            "js".to_owned(),
+            HeaderValue::from_static("application/javascript"),
+        );
+        types.default = MimeTypes::default().default;
+        assert_eq!(guess(&types, "app.JS").unwrap(), "application/javascript");
+        assert_eq!(
+            guess(&types, "Makefile").unwrap(),
+            "application/octet-stream"
+        );
+    }
+}
@@ -0,0 +1,764 @@
+//! Serve files from the filesystem.
+//!
+//! `ServeFile` is a leaf service answering every request with the contents
+//! of a single file. The `Content-Type` of the response is guessed from the
+//! extension of the file with a built-in table, defaulting to
+//! `application/octet-stream`, and its `Content-Length` is taken from the
+//! file's metadata. `ServeDir` can override the media type of extensions and
+//! the default. Bodies are streamed as `FileBody`, reading the file in chunks
+//! of 8 KiB by default without loading it into memory or blocking the
+//! reactor.
+//!
+//! `ServeDir` serves the files under a directory, mapping the
+//! percent-decoded path of each request to a path under the directory.
+//! Requests whose path could escape the directory or name another file on
+//! some platform are answered with `403 Forbidden`: paths with `..`
+//! segments, backslashes, colons, control characters, segments ending with
+//! a dot or a space, or Windows device names such as `CON`. Dotfiles are
+//! refused as well unless enabled. Symbolic links under the directory are followed by
+//! default; they can be followed only if they point inside the directory,
+//! or refused, with `403 Forbidden`.
+//!
+//! Requests for directories are redirected to the same path with a trailing
+//! slash, which is answered with the `index.html` file of the directory, or
+//! a listing of its contents if enabled. Requests missing files are passed
+//! to a fallback service, such as a `ServeFile` serving the `index.html` of
+//! a single-page application, answering with `404 Not Found` by default.
+//!
+//! `ServeDir` can serve precompressed files, such as `app.js.br` and
+//! `app.js.gz`, in place of `app.js` to clients accepting their coding, with
+//! a `Content-Encoding` header. Responses then carry a
+//! `Vary: Accept-Encoding` header.
+//!
+//! `ServeEmbedded` serves assets held in memory, such as files embedded in
+//! the binary with `include_bytes!`, by path. Assets can have precompressed
+//! contents, and their entity tag is a hash of their contents unless given.
+//!
+//! `ServeArchive` serves the files of a tar archive as if it were a
+//! directory, reading them from the archive as they are stored uncompressed.
+//! The archive is indexed once, when the service is created.
+//!
+//! `Attachment` wraps any of them, or another service, to offer successful
+//! responses to be saved under a file name, with a `Content-Disposition`
+//! header. Names which are not plain ASCII are encoded as RFC 6266 requires.
+//!
+//! All services support range requests: a single range is answered with
+//! `206 Partial Content` and a `Content-Range` header, several ranges with a
+//! `multipart/byteranges` body, and ranges lying outside the file with
+//! `416 Range Not Satisfiable`.
+//!
+//! Responses carry a `Last-Modified` header and an `ETag` derived from the
+//! size and modification time of the file. Requests whose `If-None-Match`
+//! or `If-Modified-Since` header shows the client has the current file are
+//! answered with `304 Not Modified`. An `If-Range` date or entity tag lets
+//! ranges be served only if the file is unchanged. `ServeDir` can also set
+//! `Cache-Control` on responses with files, by rules on their path and media
+//! type, e.g. to let clients cache hashed assets forever while revalidating
+//! mutable content.
+//!
+//! Only `GET` and `HEAD` requests are served; other methods are answered
+//! with `405 Method Not Allowed`. `HEAD` requests are answered with the
+//! headers a `GET` request would get, computed from the metadata of the
+//! file without opening it, and an empty body. Missing files are answered
+//! with `404 Not Found`, and files that cannot be read with
+//! `403 Forbidden`; other I/O errors are returned as the service's error.
+//!
+//! Files are accessed through `tokio-fs`, so the services must run on the
+//! Tokio thread pool runtime.
+
+mod attachment;
+mod body;
+mod encoding;
+mod listing;
+mod mime;
+mod path;
+mod range;
+mod serve_archive;
+mod serve_dir;
+mod serve_embedded;
+mod serve_file;
+mod symlink;
+
+pub use self::attachment::{content_disposition, Attachment, AttachmentFuture};
+pub use self::body::FileBody;
+pub use self::serve_archive::ServeArchive;
+pub use self::serve_dir::{Builder, NotFound, ServeDir, ServeDirFuture};
+pub use self::serve_embedded::{Asset, ServeEmbedded};
+pub use self::serve_file::ServeFile;
+pub use self::symlink::SymlinkPolicy;
+
+use self::body::{Part, DEFAULT_CHUNK_SIZE};
+use self::encoding::Encoding;
+use self::listing::Listing;
+use self::range::{ByteRange, Ranges};
+use self::serve_archive::Entry;
+use crate::{etag, vary};
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, ALLOW, LOCATION};
+use http::header::{
+    CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG,
+};
+use http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use http::{Method, Response, StatusCode};
+use std::collections::VecDeque;
+use std::fs::Metadata;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+use std::{fmt, io, mem};
+use tokio_fs::file::{MetadataFuture, OpenFuture};
+use tokio_fs::File;
+use tokio_threadpool::blocking;
+
+/// Response future for `ServeFile` and `ServeEmbedded`.
+pub struct ResponseFuture {
+    state: State,
+    content_type: Option<HeaderValue>,
+    conditions: Conditions,
+    /// The coding of the file being opened.
+    encoding: Option<Encoding>,
+    /// The files to open should the current one be missing.
+    fallbacks: VecDeque<(PathBuf, Option<Encoding>)>,
+    /// Whether the response depends on `Accept-Encoding`.
+    vary: bool,
+    /// Where to redirect to should the file be a directory.
+    redirect: Option<HeaderValue>,
+    /// The directory to list should the files be missing.
+    listing: Option<Listing>,
+    /// The size of the chunks the file is read in.
+    chunk_size: usize,
+    /// The root the files are served from, and how to treat links under it.
+    symlinks: Option<(PathBuf, SymlinkPolicy)>,
+    /// The `Cache-Control` of responses with the file.
+    cache_control: Option<HeaderValue>,
+    /// Whether to answer with headers only, without opening the file.
+    head: bool,
+    /// The entry to serve should the file be an archive.
+    entry: Option<Entry>,
+}
+
+/// The headers of a request making the response depend on the file.
+#[derive(Debug, Default)]
+struct Conditions {
+    range: Option<HeaderValue>,
+    if_range: Option<HeaderValue>,
+    if_none_match: Vec<HeaderValue>,
+    if_modified_since: Option<HeaderValue>,
+}
+
+/// The validators of a file.
+struct Validators {
+    etag: Option<String>,
+    modified: Option<u64>,
+}
+
+/// Where the body of a response comes from.
+enum Source {
+    File(File),
+    /// The part of a file starting at an offset.
+    Section(File, u64),
+    Bytes(Bytes),
+    /// No body, as for `HEAD` requests.
+    Empty,
+}
+
+enum State {
+    Embedded(Bytes, String),
+    Checking(PathBuf),
+    Opening(OpenFuture<PathBuf>),
+    Stat(tokio_fs::MetadataFuture<PathBuf>),
+    Reading(MetadataFuture),
+    Listing(Listing),
+    Ready(Response<FileBody>),
+    Done,
+}
+
+// ===== impl ResponseFuture =====
+
+impl ResponseFuture {
+    /// Serve the file at `path` in response to a request with `headers`.
+    pub(crate) fn open(
+        path: PathBuf,
+        content_type: Option<HeaderValue>,
+        headers: &HeaderMap,
+    ) -> Self {
+        let mut candidates = VecDeque::new();
+        candidates.push_back((path, None));
+        Self::open_any(candidates, content_type, headers, false)
+    }
+
+    /// Serve the first of `candidates` that exists, with its coding, in
+    /// response to a request with `headers`.
+    pub(crate) fn open_any(
+        mut candidates: VecDeque<(PathBuf, Option<Encoding>)>,
+        content_type: Option<HeaderValue>,
+        headers: &HeaderMap,
+        vary: bool,
+    ) -> Self {
+        let (path, encoding) = candidates.pop_front().expect("no file to open");
+        let mut future = Self::with_state(State::Checking(path));
+        future.content_type = content_type;
+        future.conditions = Conditions::new(headers);
+        future.encoding = encoding;
+        future.fallbacks = candidates;
+        future.vary = vary;
+        future
+    }
+
+    /// Serve `bytes`, with its coding and entity tag `etag`, in response to
+    /// a request with `headers`.
+    pub(crate) fn embedded(
+        bytes: Bytes,
+        etag: String,
+        content_type: Option<HeaderValue>,
+        encoding: Option<Encoding>,
+        headers: &HeaderMap,
+        vary: bool,
+    ) -> Self {
+        let mut future = Self::with_state(State::Embedded(bytes, etag));
+        future.content_type = content_type;
+        future.conditions = Conditions::new(headers);
+        future.encoding = encoding;
+        future.vary = vary;
+        future
+    }
+
+    /// Redirect to `location` should the file be a directory.
+    pub(crate) fn redirect_directory(mut self, location: HeaderValue) -> Self {
+        self.redirect = Some(location);
+        self
+    }
+
+    /// Answer with `listing` should the files be missing.
+    pub(crate) fn list_directory(mut self, listing: Listing) -> Self {
+        self.listing = Some(listing);
+        self
+    }
+
+    /// Read the file in chunks of at most `size` bytes.
+    pub(crate) fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size;
+        self
+    }
+
+    /// Check that the files, under `root`, may be served according to
+    /// `policy` before opening them.
+    pub(crate) fn symlinks(mut self, root: PathBuf, policy: SymlinkPolicy) -> Self {
+        if policy != SymlinkPolicy::Follow {
+            self.symlinks = Some((root, policy));
+        }
+        self
+    }
+
+    /// Set `Cache-Control` to `value` on responses with the file, or
+    /// telling the client its copy is current.
+    pub(crate) fn cache_control(mut self, value: Option<HeaderValue>) -> Self {
+        self.cache_control = value;
+        self
+    }
+
+    /// Answer with headers only, taken from the metadata of the file
+    /// without opening it, as suits `HEAD` requests.
+    pub(crate) fn head(mut self, head: bool) -> Self {
+        self.head = head;
+        self
+    }
+
+    /// Serve `entry` of the file, an archive, rather than the whole file.
+    pub(crate) fn entry(mut self, entry: Entry) -> Self {
+        self.entry = Some(entry);
+        self
+    }
+
+    /// Answer with an empty response with `status`.
+    pub(crate) fn status(status: StatusCode) -> Self {
+        Self::ready(empty(status))
+    }
+
+    /// Answer requests with methods other than `GET` and `HEAD` with
+    /// `405 Method Not Allowed`.
+    pub(crate) fn check_method(method: &Method) -> Option<Self> {
+        if *method == Method::GET || *method == Method::HEAD {
+            return None;
+        }
+        let mut res = empty(StatusCode::METHOD_NOT_ALLOWED);
+        res.headers_mut()
+            .insert(ALLOW, HeaderValue::from_static("GET, HEAD"));
+        Some(Self::ready(res))
+    }
+
+    fn ready(res: Response<FileBody>) -> Self {
+        Self::with_state(State::Ready(res))
+    }
+
+    fn with_state(state: State) -> Self {
+        ResponseFuture {
+            state,
+            content_type: None,
+            conditions: Conditions::default(),
+            encoding: None,
+            fallbacks: VecDeque::new(),
+            vary: false,
+            redirect: None,
+            listing: None,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            symlinks: None,
+            cache_control: None,
+            head: false,
+            entry: None,
+        }
+    }
+}
+
+fn empty(status: StatusCode) -> Response<FileBody> {
+    let mut res = Response::new(FileBody::empty());
+    *res.status_mut() = status;
+    res
+}
+
+/// Turns errors caused by the request into responses.
+fn error_response(e: io::Error) -> Result<Response<FileBody>, io::Error> {
+    match e.kind() {
+        _ if is_not_found(&e) => Ok(empty(StatusCode::NOT_FOUND)),
+        io::ErrorKind::PermissionDenied => Ok(empty(StatusCode::FORBIDDEN)),
+        _ => Err(e),
+    }
+}
+
+/// Returns whether `e` shows a file is missing, including when one of its
+/// parents is not a directory.
+fn is_not_found(e: &io::Error) -> bool {
+    // `ENOTDIR` has the same value on Linux, macOS and the BSDs.
+    const ENOTDIR: i32 = 20;
+    e.kind() == io::ErrorKind::NotFound || (cfg!(unix) && e.raw_os_error() == Some(ENOTDIR))
+}
+
+impl ResponseFuture {
+    /// Moves on to the next file, or the listing, should the current one be
+    /// missing, or answers with the response to `e`.
+    fn open_failed(&mut self, e: io::Error) -> Result<Option<Response<FileBody>>, io::Error> {
+        if is_not_found(&e) {
+            if let Some((path, encoding)) = self.fallbacks.pop_front() {
+                self.state = State::Checking(path);
+                self.encoding = encoding;
+                return Ok(None);
+            }
+            if let Some(listing) = self.listing.take() {
+                self.state = State::Listing(listing);
+                return Ok(None);
+            }
+        }
+        error_response(e).map(Some)
+    }
+
+    /// Answers with the file opened, or with headers only if `file` is
+    /// `None`.
+    fn finish_file(&mut self, file: Option<File>, metadata: &Metadata) -> Response<FileBody> {
+        if metadata.is_dir() {
+            return match self.redirect.take() {
+                Some(location) => {
+                    let mut res = empty(StatusCode::PERMANENT_REDIRECT);
+                    res.headers_mut().insert(LOCATION, location);
+                    res
+                }
+                None => empty(StatusCode::NOT_FOUND),
+            };
+        }
+        if let Some(entry) = self.entry.take() {
+            let source = file.map_or(Source::Empty, |file| Source::Section(file, entry.offset));
+            let validators = Validators::from_parts(entry.len, entry.modified);
+            return self.finish(source, entry.len, &validators);
+        }
+        let source = file.map_or(Source::Empty, Source::File);
+        self.finish(source, metadata.len(), &Validators::new(metadata))
+    }
+
+    /// Answers with `len` bytes from `source`, whose validators are
+    /// `validators`.
+    fn finish(&mut self, source: Source, len: u64, validators: &Validators) -> Response<FileBody> {
+        let mut res = self.respond(source, len, validators);
+        if let Some(encoding) = self.encoding {
+            let encoding = HeaderValue::from_static(encoding.name());
+            res.headers_mut().insert(CONTENT_ENCODING, encoding);
+        }
+        if self.vary {
+            vary::append_vary(res.headers_mut(), &[ACCEPT_ENCODING]);
+        }
+        res
+    }
+
+    fn respond(&mut self, source: Source, len: u64, validators: &Validators) -> Response<FileBody> {
+        if self.conditions.not_modified(validators) {
+            let mut res = empty(StatusCode::NOT_MODIFIED);
+            validators.set_headers(res.headers_mut());
+            if let Some(value) = self.cache_control.take() {
+                res.headers_mut().insert(CACHE_CONTROL, value);
+            }
+            return res;
+        }
+
+        let ranges = match self.conditions.range {
+            Some(ref range) if self.conditions.if_range_matches(validators) => {
+                range::parse(range, len)
+            }
+            _ => Ranges::Ignored,
+        };
+
+        let content_type = self.content_type.take();
+        let mut res = match ranges {
+            Ranges::Ignored => {
+                let mut parts = VecDeque::new();
+                parts.push_back(Part::Read(len));
+                let mut res = Response::new(body(source, parts));
+                res.headers_mut()
+                    .insert(CONTENT_LENGTH, HeaderValue::from(len));
+                if let Some(content_type) = content_type {
+                    res.headers_mut().insert(CONTENT_TYPE, content_type);
+                }
+                res
+            }
+            Ranges::Unsatisfiable => {
+                let mut res = empty(StatusCode::RANGE_NOT_SATISFIABLE);
+                let content_range = format!("bytes */{}", len);
+                res.headers_mut()
+                    .insert(CONTENT_RANGE, header_value(content_range));
+                res
+            }
+            Ranges::Satisfiable(ref ranges) if ranges.len() == 1 => {
+                let range = ranges[0];
+                let mut parts = VecDeque::new();
+                parts.push_back(Part::Seek(range.start));
+                parts.push_back(Part::Read(range.len()));
+                let mut res = Response::new(body(source, parts));
+                *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+                let headers = res.headers_mut();
+                headers.insert(CONTENT_LENGTH, HeaderValue::from(range.len()));
+                headers.insert(CONTENT_RANGE, header_value(content_range(range, len)));
+                if let Some(content_type) = content_type {
+                    headers.insert(CONTENT_TYPE, content_type);
+                }
+                res
+            }
+            Ranges::Satisfiable(ranges) => multipart(source, &ranges, len, content_type),
+        };
+        res.body_mut().set_chunk_size(self.chunk_size);
+        res.headers_mut()
+            .insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        validators.set_headers(res.headers_mut());
+        if res.status() != StatusCode::RANGE_NOT_SATISFIABLE {
+            if let Some(value) = self.cache_control.take() {
+                res.headers_mut().insert(CACHE_CONTROL, value);
+            }
+        }
+        res
+    }
+}
+
+// ===== impl Conditions =====
+
+impl Conditions {
+    fn new(headers: &HeaderMap) -> Self {
+        Conditions {
+            range: headers.get(RANGE).cloned(),
+            if_range: headers.get(IF_RANGE).cloned(),
+            if_none_match: headers.get_all(IF_NONE_MATCH).iter().cloned().collect(),
+            if_modified_since: headers.get(IF_MODIFIED_SINCE).cloned(),
+        }
+    }
+
+    /// Evaluates `If-None-Match`, or `If-Modified-Since` in its absence.
+    fn not_modified(&self, validators: &Validators) -> bool {
+        if !self.if_none_match.is_empty() {
+            return validators.etag.as_ref().map_or(false, |etag| {
+                etag::list_matches(&self.if_none_match, etag, true)
+            });
+        }
+        match (parse_date(&self.if_modified_since), validators.modified) {
+            (Some(since), Some(modified)) => modified <= since,
+            _ => false,
+        }
+    }
+
+    /// Evaluates `If-Range`, which only lets ranges be served if the file is
+    /// unchanged.
+    fn if_range_matches(&self, validators: &Validators) -> bool {
+        let value = match self.if_range {
+            Some(ref value) => value,
+            None => return true,
+        };
+        if value.as_bytes().starts_with(b"\"") || value.as_bytes().starts_with(b"W/") {
+            return validators
+                .etag
+                .as_ref()
+                .map_or(false, |etag| etag::list_matches(Some(value), etag, false));
+        }
+        match (parse_date(&self.if_range), validators.modified) {
+            (Some(date), Some(modified)) => date == modified,
+            _ => false,
+        }
+    }
+}
+
+/// Parses an HTTP date header, in seconds since the epoch.
+fn parse_date(value: &Option<HeaderValue>) -> Option<u64> {
+    let date = value.as_ref()?.to_str().ok()?;
+    let date = httpdate::parse_http_date(date).ok()?;
+    date.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|date| date.as_secs())
+}
+
+// ===== impl Validators =====
+
+impl Validators {
+    /// Derives validators from the size and modification time of a file.
+    fn new(metadata: &Metadata) -> Self {
+        Self::from_parts(metadata.len(), modified_secs(metadata))
+    }
+
+    /// Derives validators from a size and a modification time, in seconds
+    /// since the epoch.
+    fn from_parts(len: u64, modified: Option<u64>) -> Self {
+        Validators {
+            etag: modified.map(|modified| format!("\"{:x}-{:x}\"", len, modified)),
+            modified,
+        }
+    }
+
+    fn set_headers(&self, headers: &mut HeaderMap) {
+        if let Some(ref etag) = self.etag {
+            headers.insert(ETAG, header_value(etag.clone()));
+        }
+        if let Some(modified) = self.modified {
+            let modified = UNIX_EPOCH + Duration::from_secs(modified);
+            let modified = httpdate::fmt_http_date(modified);
+            headers.insert(LAST_MODIFIED, header_value(modified));
+        }
+    }
+}
+
+/// Returns the modification time of a file, in seconds since the epoch.
+fn modified_secs(metadata: &Metadata) -> Option<u64> {
+    let modified = metadata.modified().ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|modified| modified.as_secs())
+}
+
+fn content_range(range: ByteRange, len: u64) -> String {
+    format!("bytes {}-{}/{}", range.start, range.end, len)
+}
+
+fn header_value(value: String) -> HeaderValue {
+    HeaderValue::from_shared(Bytes::from(value)).expect("generated a valid header value")
+}
+
+/// Streams `parts` of `source`.
+fn body(source: Source, parts: VecDeque<Part>) -> FileBody {
+    match source {
+        Source::File(file) => FileBody::new(file, parts),
+        Source::Section(file, offset) => {
+            let mut parts: VecDeque<_> = parts
+                .into_iter()
+                .map(|part| match part {
+                    Part::Seek(start) => Part::Seek(offset + start),
+                    part => part,
+                })
+                .collect();
+            parts.push_front(Part::Seek(offset));
+            FileBody::new(file, parts)
+        }
+        Source::Bytes(bytes) => {
+            let mut offset = 0;
+            let parts = parts
+                .into_iter()
+                .filter_map(|part| match part {
+                    Part::Seek(start) => {
+                        offset = start as usize;
+                        None
+                    }
+                    Part::Read(len) => {
+                        let start = offset;
+                        offset += len as usize;
+                        Some(Part::Bytes(bytes.slice(start, offset)))
+                    }
+                    part => Some(part),
+                })
+                .collect();
+            FileBody::from_parts(parts)
+        }
+        Source::Empty => FileBody::empty(),
+    }
+}
+
+/// Builds a `multipart/byteranges` response holding `ranges` of `file`.
+fn multipart(
+    source: Source,
+    ranges: &[ByteRange],
+    len: u64,
+    content_type: Option<HeaderValue>,
+) -> Response<FileBody> {
+    let boundary = format!("{:016x}", rand::random::<u64>());
+    let mut parts = VecDeque::new();
+    let mut content_length = 0;
+    for (i, &range) in ranges.iter().enumerate() {
+        let mut head = String::new();
+        if i > 0 {
+            head.push_str("\r\n");
+        }
+        head.push_str("--");
+        head.push_str(&boundary);
+        head.push_str("\r\n");
+        if let Some(content_type) = content_type.as_ref().and_then(|v| v.to_str().ok()) {
+            head.push_str("Content-Type: ");
+            head.push_str(content_type);
+            head.push_str("\r\n");
+        }
+        head.push_str("Content-Range: ");
+        head.push_str(&content_range(range, len));
+        head.push_str("\r\n\r\n");
+
+        content_length += head.len() as u64 + range.len();
+        parts.push_back(Part::Bytes(Bytes::from(head)));
+        parts.push_back(Part::Seek(range.start));
+        parts.push_back(Part::Read(range.len()));
+    }
+    let tail = format!("\r\n--{}--\r\n", boundary);
+    content_length += tail.len() as u64;
+    parts.push_back(Part::Bytes(Bytes::from(tail)));
+
+    let mut res = Response::new(body(source, parts));
+    *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+    let content_type = format!("multipart/byteranges; boundary={}", boundary);
+    res.headers_mut()
+        .insert(CONTENT_TYPE, header_value(content_type));
+    res.headers_mut()
+        .insert(CONTENT_LENGTH, HeaderValue::from(content_length));
+    res
+}
+
+impl Future for ResponseFuture {
+    type Item = Response<FileBody>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Embedded(bytes, etag) => {
+                    let len = bytes.len() as u64;
+                    let validators = Validators {
+                        etag: Some(etag),
+                        modified: None,
+                    };
+                    let source = if self.head {
+                        Source::Empty
+                    } else {
+                        Source::Bytes(bytes)
+                    };
+                    return Ok(Async::Ready(self.finish(source, len, &validators)));
+                }
+                State::Checking(path) => {
+                    let checked = match self.symlinks {
+                        Some((ref root, policy)) => {
+                            match blocking(|| symlink::check(root, &path, policy)) {
+                                Ok(Async::Ready(checked)) => checked,
+                                Ok(Async::NotReady) => {
+                                    self.state = State::Checking(path);
+                                    return Ok(Async::NotReady);
+                                }
+                                Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+                            }
+                        }
+                        None => Ok(()),
+                    };
+                    match checked {
+                        Ok(()) if self.head => self.state = State::Stat(tokio_fs::metadata(path)),
+                        Ok(()) => self.state = State::Opening(File::open(path)),
+                        Err(e) => {
+                            if let Some(res) = self.open_failed(e)? {
+                                return Ok(Async::Ready(res));
+                            }
+                        }
+                    }
+                }
+                State::Opening(mut future) => match future.poll() {
+                    Ok(Async::Ready(file)) => self.state = State::Reading(file.metadata()),
+                    Ok(Async::NotReady) => {
+                        self.state = State::Opening(future);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(e) => {
+                        if let Some(res) = self.open_failed(e)? {
+                            return Ok(Async::Ready(res));
+                        }
+                    }
+                },
+                State::Stat(mut future) => match future.poll() {
+                    Ok(Async::Ready(metadata)) => {
+                        return Ok(Async::Ready(self.finish_file(None, &metadata)));
+                    }
+                    Ok(Async::NotReady) => {
+                        self.state = State::Stat(future);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(e) => {
+                        if let Some(res) = self.open_failed(e)? {
+                            return Ok(Async::Ready(res));
+                        }
+                    }
+                },
+                State::Reading(mut future) => {
+                    let (file, metadata) = match future.poll() {
+                        Ok(Async::Ready(ready)) => ready,
+                        Ok(Async::NotReady) => {
+                            self.state = State::Reading(future);
+                            return Ok(Async::NotReady);
+                        }
+                        Err(e) => return error_response(e).map(Async::Ready),
+                    };
+                    return Ok(Async::Ready(self.finish_file(Some(file), &metadata)));
+                }
+                State::Listing(listing) => match blocking(|| listing::render(&listing)) {
+                    Ok(Async::Ready(Ok(res))) => return Ok(Async::Ready(res)),
+                    Ok(Async::Ready(Err(e))) => return error_response(e).map(Async::Ready),
+                    Ok(Async::NotReady) => {
+                        self.state = State::Listing(listing);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(e) => return Err(io::Error::new(io::ErrorKind::Other, e)),
+                },
+                State::Ready(res) => return Ok(Async::Ready(res)),
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl fmt::Debug for ResponseFuture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Embedded(..) => "Embedded",
+            State::Checking(_) => "Checking",
+            State::Opening(_) => "Opening",
+            State::Stat(_) => "Stat",
+            State::Reading(_) => "Reading",
+            State::Listing(_) => "Listing",
+            State::Ready(_) => "Ready",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .field("content_type", &self.content_type)
+            .field("conditions", &self.conditions)
+            .field("encoding", &self.encoding)
+            .field("vary", &self.vary)
+            .field("redirect", &self.redirect)
+            .field("listing", &self.listing)
+            .field("symlinks", &self.symlinks)
+            .field("cache_control", &self.cache_control)
+            .field("head", &self.head)
+            .field("entry", &self.entry)
+            .finish()
+    }
+}
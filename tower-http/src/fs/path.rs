@@ -0,0 +1,127 @@
+use percent_encoding::percent_decode;
+use std::path::{Path, PathBuf};
+
+/// Device names Windows reserves in every directory, whatever the extension.
+const RESERVED: &[&str] = &[
+    "AUX", "CON", "NUL", "PRN", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Maps the path of a request to a path under `root`, or `None` if it is
+/// unsafe to serve.
+///
+/// The path is percent-decoded before being split into segments, so that
+/// encoded slashes cannot smuggle in `..` segments. Segments which could
+/// escape `root` or name another file than they seem to on some platform
+/// are refused, as are segments starting with a dot unless `dotfiles` is
+/// set.
+pub(crate) fn resolve(root: &Path, path: &str, dotfiles: bool) -> Option<PathBuf> {
+    let path = percent_decode(path.as_bytes()).decode_utf8().ok()?;
+    let mut resolved = root.to_owned();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            _ if !is_safe(segment) => return None,
+            _ if segment.starts_with('.') && !dotfiles => return None,
+            _ => resolved.push(segment),
+        }
+    }
+    Some(resolved)
+}
+
+/// Returns whether a path segment names a file inside its directory on
+/// every platform.
+fn is_safe(segment: &str) -> bool {
+    if segment == ".." {
+        return false;
+    }
+    // Backslashes separate segments, and colons name drives and alternate
+    // data streams, on Windows.
+    if segment
+        .chars()
+        .any(|c| c.is_control() || c == '\\' || c == ':')
+    {
+        return false;
+    }
+    // Windows drops trailing dots and spaces, so that `.. ` is `..`.
+    if segment.ends_with('.') || segment.ends_with(' ') {
+        return false;
+    }
+    let stem = segment.split('.').next().unwrap_or("").trim_end();
+    !RESERVED.iter().any(|name| stem.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(path: &str) -> Option<PathBuf> {
+        super::resolve(Path::new("/srv"), path, false)
+    }
+
+    #[test]
+    fn resolves_under_root() {
+        assert_eq!(resolve("/"), Some("/srv".into()));
+        assert_eq!(resolve("/a/./b%20c.txt"), Some("/srv/a/b c.txt".into()));
+        assert_eq!(resolve("//a//"), Some("/srv/a".into()));
+        assert_eq!(resolve("/%E2%9C%93.txt"), Some("/srv/\u{2713}.txt".into()));
+        assert_eq!(resolve("/a..b/c"), Some("/srv/a..b/c".into()));
+        assert_eq!(resolve("/console.txt"), Some("/srv/console.txt".into()));
+        // Encodings are only decoded once.
+        assert_eq!(resolve("/%252e%252e"), Some("/srv/%2e%2e".into()));
+    }
+
+    #[test]
+    fn refuses_malicious_paths() {
+        let paths = [
+            // Traversal.
+            "/..",
+            "/../etc/passwd",
+            "/a/../../etc/passwd",
+            "/%2e%2e/etc/passwd",
+            "/%2E%2E%2Fetc%2Fpasswd",
+            "/a%2f..%2f..%2fetc",
+            "/a/..%2F..",
+            // Windows separators, drives and streams.
+            "/..\\etc",
+            "/a%5c..%5cb",
+            "/C:/Windows/win.ini",
+            "/c%3a%5cwindows",
+            "/index.html::$DATA",
+            "/file.txt:stream",
+            // Windows trailing dots and spaces.
+            "/.. ",
+            "/...",
+            "/%2e%2e%20/etc",
+            "/index.html.",
+            "/index.html%20",
+            // Windows device names.
+            "/CON",
+            "/a/nul.txt",
+            "/Com1.log",
+            "/lpt9",
+            "/aux .txt",
+            // Control characters and invalid UTF-8.
+            "/a%00b",
+            "/index.html%00.txt",
+            "/a%0ab",
+            "/%ff",
+        ];
+        for path in paths.iter() {
+            assert_eq!(resolve(path), None, "{} was not refused", path);
+        }
+    }
+
+    #[test]
+    fn refuses_dotfiles_unless_allowed() {
+        let root = Path::new("/srv");
+        assert_eq!(resolve("/.env"), None);
+        assert_eq!(resolve("/.git/config"), None);
+        assert_eq!(resolve("/a/%2Ehtpasswd"), None);
+        assert_eq!(
+            super::resolve(root, "/.well-known/a", true),
+            Some("/srv/.well-known/a".into())
+        );
+        assert_eq!(super::resolve(root, "/../a", true), None);
+    }
+}
@@ -0,0 +1,136 @@
+use http::header::HeaderValue;
+
+/// The maximum number of ranges served per request.
+///
+/// Requests with more ranges are answered with the whole file, as many
+/// small ranges make for a large response.
+const MAX_RANGES: usize = 16;
+
+/// A satisfiable range of bytes, both ends inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ByteRange {
+    pub(crate) start: u64,
+    pub(crate) end: u64,
+}
+
+/// The outcome of parsing a `Range` header.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Ranges {
+    /// The header is to be ignored, serving the whole file.
+    Ignored,
+    /// None of the ranges overlap the file.
+    Unsatisfiable,
+    /// The ranges to serve, in the order requested.
+    Satisfiable(Vec<ByteRange>),
+}
+
+impl ByteRange {
+    pub(crate) fn len(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+/// Parses a `Range` header for a file of `len` bytes.
+pub(crate) fn parse(value: &HeaderValue, len: u64) -> Ranges {
+    let value = match value.to_str() {
+        Ok(value) => value.trim(),
+        Err(_) => return Ranges::Ignored,
+    };
+    if value.len() < 6 || !value[..6].eq_ignore_ascii_case("bytes=") {
+        return Ranges::Ignored;
+    }
+
+    let mut ranges = Vec::new();
+    for spec in value[6..]
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+    {
+        let dash = match spec.find('-') {
+            Some(dash) => dash,
+            None => return Ranges::Ignored,
+        };
+        let (first, last) = (spec[..dash].trim(), spec[dash + 1..].trim());
+        let range = if first.is_empty() {
+            // A suffix range, for the last bytes of the file.
+            let suffix = match last.parse::<u64>() {
+                Ok(suffix) => suffix,
+                Err(_) => return Ranges::Ignored,
+            };
+            if suffix == 0 || len == 0 {
+                continue;
+            }
+            ByteRange {
+                start: len - suffix.min(len),
+                end: len - 1,
+            }
+        } else {
+            let start = match first.parse::<u64>() {
+                Ok(start) => start,
+                Err(_) => return Ranges::Ignored,
+            };
+            let end = if last.is_empty() {
+                u64::max_value()
+            } else {
+                match last.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return Ranges::Ignored,
+                }
+            };
+            if start >= len {
+                continue;
+            }
+            ByteRange {
+                start,
+                end: end.min(len - 1),
+            }
+        };
+        ranges.push(range);
+        if ranges.len() > MAX_RANGES {
+            return Ranges::Ignored;
+        }
+    }
+
+    if ranges.is_empty() {
+        Ranges::Unsatisfiable
+    } else {
+        Ranges::Satisfiable(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(value: &'static str, len: u64) -> Ranges {
+        super::parse(&HeaderValue::from_static(value), len)
+    }
+
+    fn ranges(ranges: &[(u64, u64)]) -> Ranges {
+        let ranges = ranges
+            .iter()
+            .map(|&(start, end)| ByteRange { start, end })
+            .collect();
+        Ranges::Satisfiable(ranges)
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse("bytes=0-4", 10), ranges(&[(0, 4)]));
+        assert_eq!(parse("bytes=5-", 10), ranges(&[(5, 9)]));
+        assert_eq!(parse("bytes=-3", 10), ranges(&[(7, 9)]));
+        assert_eq!(parse("bytes=-30", 10), ranges(&[(0, 9)]));
+        assert_eq!(parse("bytes=8-20, 0-0", 10), ranges(&[(8, 9), (0, 0)]));
+        assert_eq!(parse("bytes=10-, 0-1", 10), ranges(&[(0, 1)]));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_and_invalid_ranges() {
+        assert_eq!(parse("bytes=10-20", 10), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-0", 10), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=0-", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=5-4", 10), Ranges::Ignored);
+        assert_eq!(parse("bytes=a-b", 10), Ranges::Ignored);
+        assert_eq!(parse("items=0-4", 10), Ranges::Ignored);
+    }
+}
@@ -0,0 +1,258 @@
+use super::mime::MimeTypes;
+use super::{FileBody, ResponseFuture};
+use futures::{Async, Poll};
+use http::{Method, Request, Response, StatusCode};
+use percent_encoding::percent_decode;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// The size of the blocks of tar archives.
+const BLOCK: u64 = 512;
+
+/// The longest GNU long name accepted.
+const MAX_LONG_NAME: u64 = 64 * 1024;
+
+/// Serves the files of a tar archive.
+#[derive(Debug, Clone)]
+pub struct ServeArchive {
+    inner: Arc<Inner>,
+}
+
+/// A file stored in an archive.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Entry {
+    /// The offset of the contents of the file in the archive.
+    pub(crate) offset: u64,
+    pub(crate) len: u64,
+    /// The modification time of the file, in seconds since the epoch.
+    pub(crate) modified: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+struct Inner {
+    path: PathBuf,
+    entries: HashMap<String, Entry>,
+    mime_types: MimeTypes,
+    index_file: String,
+}
+
+// ===== impl ServeArchive =====
+
+impl ServeArchive {
+    /// Create a new `ServeArchive` serving the files of the tar archive at
+    /// `path`, such that `/css/app.css` is the file stored as
+    /// `css/app.css`.
+    ///
+    /// The archive is indexed synchronously, so this is best done at
+    /// startup. The archive must not change while it is being served.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let entries = index(BufReader::new(File::open(&path)?))?;
+        Ok(ServeArchive {
+            inner: Arc::new(Inner {
+                path,
+                entries,
+                mime_types: MimeTypes::default(),
+                index_file: "index.html".to_owned(),
+            }),
+        })
+    }
+
+    /// Serve the file named `name` in response to requests for directories.
+    ///
+    /// Defaults to `index.html`.
+    pub fn index_file<N: Into<String>>(mut self, name: N) -> Self {
+        Arc::make_mut(&mut self.inner).index_file = name.into();
+        self
+    }
+
+    /// Returns the path of the served archive.
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+}
+
+impl<B> Service<Request<B>> for ServeArchive {
+    type Response = Response<FileBody>;
+    type Error = io::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if let Some(future) = ResponseFuture::check_method(req.method()) {
+            return future;
+        }
+        let mut path = percent_decode(req.uri().path().as_bytes())
+            .decode_utf8_lossy()
+            .into_owned();
+        if path.ends_with('/') {
+            path.push_str(&self.inner.index_file);
+        }
+        let entry = match self.inner.entries.get(&path) {
+            Some(&entry) => entry,
+            None => return ResponseFuture::status(StatusCode::NOT_FOUND),
+        };
+
+        let content_type = self.inner.mime_types.guess(Path::new(&path));
+        ResponseFuture::open(self.inner.path.clone(), content_type, req.headers())
+            .entry(entry)
+            .head(req.method() == Method::HEAD)
+    }
+}
+
+/// Lists the regular files of a tar archive, by their path.
+///
+/// Both POSIX ustar and GNU archives are read, including GNU long names.
+/// Files whose name has `..` segments are left out.
+fn index<R: Read + Seek>(mut archive: R) -> io::Result<HashMap<String, Entry>> {
+    let mut entries = HashMap::new();
+    let mut header = [0; BLOCK as usize];
+    let mut offset = 0;
+    let mut long_name = None;
+    loop {
+        match archive.read_exact(&mut header) {
+            Ok(()) => {}
+            // Some archives lack the final zero blocks.
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        if header.iter().all(|&b| b == 0) {
+            break;
+        }
+        if number(&header[148..156])? != checksum(&header) {
+            return Err(invalid("invalid tar header checksum"));
+        }
+        let len = number(&header[124..136])?;
+        offset += BLOCK;
+
+        match header[156] {
+            b'0' | b'\0' | b'7' => {
+                let name = long_name.take().unwrap_or_else(|| name(&header));
+                if let Some(path) = normalize(&name) {
+                    let entry = Entry {
+                        offset,
+                        len,
+                        modified: number(&header[136..148]).ok(),
+                    };
+                    entries.insert(path, entry);
+                }
+            }
+            b'L' => {
+                if len > MAX_LONG_NAME {
+                    return Err(invalid("tar long name too long"));
+                }
+                let mut name = vec![0; len as usize];
+                archive.read_exact(&mut name)?;
+                long_name = Some(c_str(&name));
+            }
+            _ => long_name = None,
+        }
+
+        offset += (len + BLOCK - 1) / BLOCK * BLOCK;
+        archive.seek(SeekFrom::Start(offset))?;
+    }
+    Ok(entries)
+}
+
+/// Returns the name of the file described by `header`.
+fn name(header: &[u8]) -> String {
+    let name = c_str(&header[..100]);
+    // POSIX archives split long names in a prefix and a name.
+    if &header[257..263] == b"ustar\0" {
+        let prefix = c_str(&header[345..500]);
+        if !prefix.is_empty() {
+            return format!("{}/{}", prefix, name);
+        }
+    }
+    name
+}
+
+/// Turns a name stored in an archive into the path it is served at.
+fn normalize(name: &str) -> Option<String> {
+    let mut path = String::new();
+    for segment in name.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            segment => {
+                path.push('/');
+                path.push_str(segment);
+            }
+        }
+    }
+    if path.is_empty() {
+        None
+    } else {
+        Some(path)
+    }
+}
+
+/// Parses a numeric field, in octal or in GNU base-256.
+fn number(field: &[u8]) -> io::Result<u64> {
+    if field[0] & 0x80 != 0 {
+        let mut value: u64 = u64::from(field[0] & 0x7f);
+        for &b in &field[1..] {
+            value = value
+                .checked_mul(256)
+                .map(|value| value + u64::from(b))
+                .ok_or_else(|| invalid("tar number too large"))?;
+        }
+        return Ok(value);
+    }
+    let field = c_str(field);
+    let field = field.trim_matches(' ');
+    if field.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(field, 8).map_err(|_| invalid("invalid tar number"))
+}
+
+/// Computes the checksum of a header, counting its checksum field as
+/// spaces.
+fn checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b })
+        .map(u64::from)
+        .sum()
+}
+
+/// Reads a string ended by a NUL byte or the end of `bytes`.
+fn c_str(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(number(b"0000644\0").unwrap(), 0o644);
+        assert_eq!(number(b"00000000016 ").unwrap(), 14);
+        assert_eq!(number(b"\0\0\0\0").unwrap(), 0);
+        assert_eq!(number(&[0x80, 0, 0, 1, 0]).unwrap(), 256);
+        assert!(number(b"0000009\0").is_err());
+    }
+
+    #[test]
+    fn normalizes_names() {
+        assert_eq!(normalize("./css/app.css"), Some("/css/app.css".into()));
+        assert_eq!(normalize("index.html"), Some("/index.html".into()));
+        assert_eq!(normalize("a/../../etc/passwd"), None);
+        assert_eq!(normalize("./"), None);
+    }
+}
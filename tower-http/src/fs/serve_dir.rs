@@ -0,0 +1,464 @@
+use super::body::DEFAULT_CHUNK_SIZE;
+use super::encoding::{self, Encoding};
+use super::listing::{self, Listing};
+use super::mime::MimeTypes;
+use super::path;
+use super::symlink::SymlinkPolicy;
+use super::{FileBody, ResponseFuture};
+use crate::cache_control::Condition;
+use futures::future::{self, FutureResult};
+use futures::{Async, Future, Poll};
+use http::header::HeaderValue;
+use http::{Method, Request, Response, StatusCode};
+use percent_encoding::percent_decode;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, io, mem};
+use tower_service::Service;
+
+/// Serves the files under a directory.
+#[derive(Debug, Clone)]
+pub struct ServeDir<F = NotFound> {
+    config: Arc<Config>,
+    fallback: F,
+}
+
+/// The default fallback of `ServeDir`, answering with `404 Not Found`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotFound {
+    _p: (),
+}
+
+/// Configure a `ServeDir`.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    precompressed: Vec<Encoding>,
+    mime_types: MimeTypes,
+    index_file: String,
+    listing: bool,
+    dotfiles: bool,
+    symlinks: SymlinkPolicy,
+    chunk_size: usize,
+    cache_control: Vec<(Condition, HeaderValue)>,
+}
+
+/// Response future for `ServeDir`.
+pub struct ServeDirFuture<F, B>
+where
+    F: Service<Request<B>>,
+{
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+struct Config {
+    root: PathBuf,
+    /// The codings of precompressed files, by order of preference.
+    precompressed: Vec<Encoding>,
+    mime_types: MimeTypes,
+    index_file: String,
+    listing: bool,
+    dotfiles: bool,
+    symlinks: SymlinkPolicy,
+    chunk_size: usize,
+    cache_control: Vec<(Condition, HeaderValue)>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<F, B>
+where
+    F: Service<Request<B>>,
+{
+    Serving {
+        future: ResponseFuture,
+        fallback: F,
+        request: Request<B>,
+    },
+    Fallback(F::Future),
+    Done,
+}
+
+// ===== impl ServeDir =====
+
+impl ServeDir {
+    /// Create a new `ServeDir` serving the files under `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        Builder::new().build(root)
+    }
+}
+
+impl<F> ServeDir<F> {
+    /// Create a new `ServeDir` serving the files under `root`, calling
+    /// `fallback` for requests missing them.
+    pub fn with_fallback<P: AsRef<Path>>(root: P, fallback: F) -> Self {
+        Builder::new().build_with_fallback(root, fallback)
+    }
+
+    /// Returns the directory files are served from.
+    pub fn root(&self) -> &Path {
+        &self.config.root
+    }
+
+    /// Returns a reference to the fallback service.
+    pub fn get_ref(&self) -> &F {
+        &self.fallback
+    }
+
+    /// Returns a mutable reference to the fallback service.
+    pub fn get_mut(&mut self) -> &mut F {
+        &mut self.fallback
+    }
+
+    /// Consumes `self`, returning the fallback service.
+    pub fn into_inner(self) -> F {
+        self.fallback
+    }
+
+    /// Serve the file requested by `req`.
+    fn serve<B>(&self, req: &Request<B>) -> ResponseFuture {
+        if let Some(future) = ResponseFuture::check_method(req.method()) {
+            return future;
+        }
+        let uri_path = req.uri().path();
+        let path = match path::resolve(&self.config.root, uri_path, self.config.dotfiles) {
+            Some(path) => path,
+            None => return ResponseFuture::status(StatusCode::FORBIDDEN),
+        };
+
+        if !uri_path.ends_with('/') {
+            // Leading slashes would make the location relative to the
+            // scheme, redirecting to another host, as would backslashes for
+            // some browsers.
+            let trimmed = uri_path.trim_start_matches(|c| c == '/' || c == '\\');
+            let mut location = format!("/{}/", trimmed);
+            if let Some(query) = req.uri().query() {
+                location.push('?');
+                location.push_str(query);
+            }
+            let future = self.open(path.clone(), req);
+            return match HeaderValue::from_str(&location) {
+                Ok(location) => future.redirect_directory(location),
+                Err(_) => future,
+            };
+        }
+
+        let future = self.open(path.join(&self.config.index_file), req);
+        if !self.config.listing {
+            return future;
+        }
+        let listing = Listing {
+            dir: path,
+            path: percent_decode(uri_path.as_bytes())
+                .decode_utf8_lossy()
+                .into_owned(),
+            format: listing::negotiate(req.headers()),
+            dotfiles: self.config.dotfiles,
+        };
+        future.list_directory(listing)
+    }
+
+    /// Serve the file at `path`, or a precompressed version of it.
+    fn open<B>(&self, path: PathBuf, req: &Request<B>) -> ResponseFuture {
+        let content_type = self.config.mime_types.guess(&path);
+        let cache_control = {
+            let content_type = content_type.as_ref().and_then(|v| v.to_str().ok());
+            self.config
+                .cache_control
+                .iter()
+                .find(|(condition, _)| {
+                    condition.matches(req.uri().path(), StatusCode::OK, content_type)
+                })
+                .map(|(_, value)| value.clone())
+        };
+
+        let mut candidates = VecDeque::new();
+        for &encoding in &self.config.precompressed {
+            if encoding::accepts(req.headers(), encoding) {
+                let mut sidecar = path.clone().into_os_string();
+                sidecar.push(".");
+                sidecar.push(encoding.extension());
+                candidates.push_back((sidecar.into(), Some(encoding)));
+            }
+        }
+        candidates.push_back((path, None));
+        let vary = !self.config.precompressed.is_empty();
+        ResponseFuture::open_any(candidates, content_type, req.headers(), vary)
+            .chunk_size(self.config.chunk_size)
+            .symlinks(self.config.root.clone(), self.config.symlinks)
+            .cache_control(cache_control)
+            .head(req.method() == Method::HEAD)
+    }
+}
+
+impl<F, B> Service<Request<B>> for ServeDir<F>
+where
+    F: Service<Request<B>, Response = Response<FileBody>, Error = io::Error> + Clone,
+{
+    type Response = Response<FileBody>;
+    type Error = io::Error;
+    type Future = ServeDirFuture<F, B>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.fallback.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let future = self.serve(&req);
+        let clone = self.fallback.clone();
+        ServeDirFuture {
+            state: State::Serving {
+                future,
+                fallback: mem::replace(&mut self.fallback, clone),
+                request: req,
+            },
+        }
+    }
+}
+
+// ===== impl NotFound =====
+
+impl<B> Service<Request<B>> for NotFound {
+    type Response = Response<FileBody>;
+    type Error = io::Error;
+    type Future = FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _: Request<B>) -> Self::Future {
+        let mut res = Response::new(FileBody::empty());
+        *res.status_mut() = StatusCode::NOT_FOUND;
+        future::ok(res)
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            precompressed: Vec::new(),
+            mime_types: MimeTypes::default(),
+            index_file: "index.html".to_owned(),
+            listing: false,
+            dotfiles: false,
+            symlinks: SymlinkPolicy::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            cache_control: Vec::new(),
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder` serving files as they are, guessing their
+    /// media type from the built-in table.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Serve `foo.js.gz` in place of `foo.js`, when present, to clients
+    /// accepting gzip.
+    pub fn precompressed_gzip(self, enable: bool) -> Self {
+        self.precompressed(Encoding::Gzip, enable)
+    }
+
+    /// Serve `foo.js.br` in place of `foo.js`, when present, to clients
+    /// accepting Brotli.
+    ///
+    /// Brotli files are preferred over gzip files.
+    pub fn precompressed_br(self, enable: bool) -> Self {
+        self.precompressed(Encoding::Brotli, enable)
+    }
+
+    fn precompressed(mut self, encoding: Encoding, enable: bool) -> Self {
+        self.precompressed.retain(|&e| e != encoding);
+        if enable {
+            self.precompressed.push(encoding);
+            // Brotli compresses better.
+            self.precompressed.sort_by_key(|&e| e != Encoding::Brotli);
+        }
+        self
+    }
+
+    /// Serve files with `extension`, such as `"js"`, as `content_type`,
+    /// overriding the built-in table.
+    pub fn content_type<E: Into<String>>(
+        mut self,
+        extension: E,
+        content_type: HeaderValue,
+    ) -> Self {
+        let extension = extension.into().to_ascii_lowercase();
+        self.mime_types.overrides.insert(extension, content_type);
+        self
+    }
+
+    /// Serve files with an unknown extension as `content_type`, or without
+    /// a `Content-Type` if `None`.
+    ///
+    /// Defaults to `application/octet-stream`.
+    pub fn default_content_type(mut self, content_type: Option<HeaderValue>) -> Self {
+        self.mime_types.default = content_type;
+        self
+    }
+
+    /// Serve the file named `name` in response to requests for directories.
+    ///
+    /// Defaults to `index.html`.
+    pub fn index_file<N: Into<String>>(mut self, name: N) -> Self {
+        self.index_file = name.into();
+        self
+    }
+
+    /// List the contents of directories without an index file, as HTML, or
+    /// as JSON to clients accepting `application/json`.
+    pub fn directory_listing(mut self, enable: bool) -> Self {
+        self.listing = enable;
+        self
+    }
+
+    /// Serve files and directories whose name starts with a dot, such as
+    /// `.well-known`.
+    ///
+    /// Requests for them are otherwise answered with `403 Forbidden`, and
+    /// they are left out of listings, as they often hold secrets such as
+    /// `.env` or `.git/config`. Defaults to `false`.
+    pub fn dotfiles(mut self, enable: bool) -> Self {
+        self.dotfiles = enable;
+        self
+    }
+
+    /// Set how symbolic links under the root are treated.
+    ///
+    /// Defaults to `SymlinkPolicy::Follow`.
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> Self {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Set `Cache-Control` to `value` on responses with files matching
+    /// `condition`, unless an earlier rule matches.
+    ///
+    /// Conditions are evaluated on the path of the request and the media
+    /// type of the file, as for a `200 OK` response. Listings and responses
+    /// without a file are left without `Cache-Control`.
+    pub fn cache_control(mut self, condition: Condition, value: HeaderValue) -> Self {
+        self.cache_control.push((condition, value));
+        self
+    }
+
+    /// Let clients cache files matching `condition` for a year without
+    /// revalidating them, as suits assets whose name includes a hash of
+    /// their contents.
+    pub fn cache_immutable(self, condition: Condition) -> Self {
+        let value = HeaderValue::from_static("public, max-age=31536000, immutable");
+        self.cache_control(condition, value)
+    }
+
+    /// Make clients revalidate files matching `condition`, with their
+    /// `ETag` or `Last-Modified`, before each use, as suits mutable content.
+    pub fn cache_revalidate(self, condition: Condition) -> Self {
+        self.cache_control(condition, HeaderValue::from_static("no-cache"))
+    }
+
+    /// Read files in chunks of at most `size` bytes, bounding the memory
+    /// used by each response.
+    ///
+    /// Defaults to 8 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "chunk size must be positive");
+        self.chunk_size = size;
+        self
+    }
+
+    /// Build a `ServeDir` serving the files under `root`.
+    pub fn build<P: AsRef<Path>>(self, root: P) -> ServeDir {
+        self.build_with_fallback(root, NotFound::default())
+    }
+
+    /// Build a `ServeDir` serving the files under `root`, calling `fallback`
+    /// for requests missing them.
+    pub fn build_with_fallback<P: AsRef<Path>, F>(self, root: P, fallback: F) -> ServeDir<F> {
+        ServeDir {
+            config: Arc::new(Config {
+                root: root.as_ref().to_owned(),
+                precompressed: self.precompressed,
+                mime_types: self.mime_types,
+                index_file: self.index_file,
+                listing: self.listing,
+                dotfiles: self.dotfiles,
+                symlinks: self.symlinks,
+                chunk_size: self.chunk_size,
+                cache_control: self.cache_control,
+            }),
+            fallback,
+        }
+    }
+}
+
+// ===== impl ServeDirFuture =====
+
+impl<F, B> Future for ServeDirFuture<F, B>
+where
+    F: Service<Request<B>, Response = Response<FileBody>, Error = io::Error>,
+{
+    type Item = Response<FileBody>;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Serving {
+                    mut future,
+                    mut fallback,
+                    request,
+                } => {
+                    let res = match future.poll()? {
+                        Async::Ready(res) => res,
+                        Async::NotReady => {
+                            self.state = State::Serving {
+                                future,
+                                fallback,
+                                request,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    if res.status() != StatusCode::NOT_FOUND {
+                        return Ok(Async::Ready(res));
+                    }
+                    self.state = State::Fallback(fallback.call(request));
+                }
+                State::Fallback(mut future) => {
+                    let result = future.poll();
+                    if let Ok(Async::NotReady) = result {
+                        self.state = State::Fallback(future);
+                    }
+                    return result;
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<F, B> fmt::Debug for ServeDirFuture<F, B>
+where
+    F: Service<Request<B>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Serving { .. } => "Serving",
+            State::Fallback(_) => "Fallback",
+            State::Done => "Done",
+        };
+        f.debug_struct("ServeDirFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
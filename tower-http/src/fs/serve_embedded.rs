@@ -0,0 +1,215 @@
+use super::encoding::{self, Encoding};
+use super::mime::MimeTypes;
+use super::{FileBody, ResponseFuture};
+use crate::etag::strong_etag;
+use bytes::Bytes;
+use futures::{Async, Poll};
+use http::header::HeaderValue;
+use http::{Method, Request, Response, StatusCode};
+use percent_encoding::percent_decode;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Serves assets held in memory, such as files embedded in the binary with
+/// `include_bytes!`.
+#[derive(Debug, Clone)]
+pub struct ServeEmbedded {
+    inner: Arc<Inner>,
+}
+
+/// An asset served by `ServeEmbedded`.
+#[derive(Debug, Clone)]
+pub struct Asset {
+    content_type: Option<HeaderValue>,
+    /// The contents of the asset, by order of preference, ending with the
+    /// uncompressed ones.
+    variants: Vec<Variant>,
+}
+
+#[derive(Debug, Clone)]
+struct Inner {
+    assets: HashMap<String, Asset>,
+    index_file: String,
+}
+
+#[derive(Debug, Clone)]
+struct Variant {
+    encoding: Option<Encoding>,
+    bytes: Bytes,
+    etag: String,
+}
+
+// ===== impl ServeEmbedded =====
+
+impl ServeEmbedded {
+    /// Create a new `ServeEmbedded` without assets.
+    pub fn new() -> Self {
+        ServeEmbedded {
+            inner: Arc::new(Inner {
+                assets: HashMap::new(),
+                index_file: "index.html".to_owned(),
+            }),
+        }
+    }
+
+    /// Serve `bytes` at `path`, such as `/app.js`, guessing their media type
+    /// from the extension of `path`.
+    pub fn insert<P, B>(self, path: P, bytes: B) -> Self
+    where
+        P: Into<String>,
+        B: Into<Bytes>,
+    {
+        let path = path.into();
+        let content_type = MimeTypes::default().guess(Path::new(&path));
+        self.insert_asset(path, Asset::new(bytes, content_type))
+    }
+
+    /// Serve `asset` at `path`, such as `/app.js`.
+    pub fn insert_asset<P: Into<String>>(mut self, path: P, asset: Asset) -> Self {
+        Arc::make_mut(&mut self.inner)
+            .assets
+            .insert(path.into(), asset);
+        self
+    }
+
+    /// Serve the asset named `name` under a path in response to requests for
+    /// the path with a trailing slash.
+    ///
+    /// Defaults to `index.html`.
+    pub fn index_file<N: Into<String>>(mut self, name: N) -> Self {
+        Arc::make_mut(&mut self.inner).index_file = name.into();
+        self
+    }
+
+    fn get(&self, path: &str) -> Option<&Asset> {
+        if path.ends_with('/') {
+            let path = format!("{}{}", path, self.inner.index_file);
+            self.inner.assets.get(&path)
+        } else {
+            self.inner.assets.get(path)
+        }
+    }
+}
+
+impl Default for ServeEmbedded {
+    fn default() -> Self {
+        ServeEmbedded::new()
+    }
+}
+
+impl<B> Service<Request<B>> for ServeEmbedded {
+    type Response = Response<FileBody>;
+    type Error = io::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if let Some(future) = ResponseFuture::check_method(req.method()) {
+            return future;
+        }
+        let path = percent_decode(req.uri().path().as_bytes()).decode_utf8_lossy();
+        let asset = match self.get(&path) {
+            Some(asset) => asset,
+            None => return ResponseFuture::status(StatusCode::NOT_FOUND),
+        };
+
+        let variant = asset
+            .variants
+            .iter()
+            .find(|variant| {
+                variant
+                    .encoding
+                    .map_or(true, |encoding| encoding::accepts(req.headers(), encoding))
+            })
+            .expect("assets have uncompressed contents");
+        ResponseFuture::embedded(
+            variant.bytes.clone(),
+            variant.etag.clone(),
+            asset.content_type.clone(),
+            variant.encoding,
+            req.headers(),
+            asset.variants.len() > 1,
+        )
+        .head(req.method() == Method::HEAD)
+    }
+}
+
+// ===== impl Asset =====
+
+impl Asset {
+    /// Create a new `Asset` holding `bytes`, served as `content_type`.
+    ///
+    /// Its entity tag is derived from a hash of `bytes`.
+    pub fn new<B: Into<Bytes>>(bytes: B, content_type: Option<HeaderValue>) -> Self {
+        Asset {
+            content_type,
+            variants: vec![Variant::new(None, bytes.into())],
+        }
+    }
+
+    /// Use `etag`, such as `"\"v1\""`, as the entity tag of the asset, as
+    /// computed ahead of time by a build script.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `etag` is not a valid header value.
+    pub fn etag<T: Into<String>>(mut self, etag: T) -> Self {
+        let etag = etag.into();
+        assert!(
+            HeaderValue::from_str(&etag).is_ok(),
+            "invalid entity tag: {:?}",
+            etag
+        );
+        self.variants
+            .last_mut()
+            .expect("assets have uncompressed contents")
+            .etag = etag;
+        self
+    }
+
+    /// Serve `bytes`, the asset compressed with gzip, to clients accepting
+    /// gzip.
+    pub fn precompressed_gzip<B: Into<Bytes>>(self, bytes: B) -> Self {
+        self.precompressed(Encoding::Gzip, bytes.into())
+    }
+
+    /// Serve `bytes`, the asset compressed with Brotli, to clients accepting
+    /// Brotli.
+    ///
+    /// Brotli contents are preferred over gzip contents.
+    pub fn precompressed_br<B: Into<Bytes>>(self, bytes: B) -> Self {
+        self.precompressed(Encoding::Brotli, bytes.into())
+    }
+
+    fn precompressed(mut self, encoding: Encoding, bytes: Bytes) -> Self {
+        self.variants
+            .retain(|variant| variant.encoding != Some(encoding));
+        self.variants.push(Variant::new(Some(encoding), bytes));
+        self.variants.sort_by_key(|variant| match variant.encoding {
+            Some(Encoding::Brotli) => 0,
+            Some(Encoding::Gzip) => 1,
+            None => 2,
+        });
+        self
+    }
+}
+
+impl Variant {
+    fn new(encoding: Option<Encoding>, bytes: Bytes) -> Self {
+        let etag = strong_etag(&bytes)
+            .to_str()
+            .expect("generated a valid entity tag")
+            .to_owned();
+        Variant {
+            encoding,
+            bytes,
+            etag,
+        }
+    }
+}
@@ -0,0 +1,83 @@
+use super::body::DEFAULT_CHUNK_SIZE;
+use super::mime::MimeTypes;
+use super::{FileBody, ResponseFuture};
+use futures::{Async, Poll};
+use http::header::HeaderValue;
+use http::{Method, Request, Response};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Serves a single file.
+#[derive(Debug, Clone)]
+pub struct ServeFile {
+    path: Arc<PathBuf>,
+    content_type: Option<HeaderValue>,
+    chunk_size: usize,
+}
+
+impl ServeFile {
+    /// Create a new `ServeFile` serving the file at `path`, guessing its
+    /// media type from its extension.
+    ///
+    /// Files with an unknown extension are served as
+    /// `application/octet-stream`.
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        ServeFile {
+            content_type: MimeTypes::default().guess(path),
+            path: Arc::new(path.to_owned()),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Create a new `ServeFile` serving the file at `path` as
+    /// `content_type`.
+    pub fn with_content_type<P: AsRef<Path>>(path: P, content_type: HeaderValue) -> Self {
+        ServeFile {
+            path: Arc::new(path.as_ref().to_owned()),
+            content_type: Some(content_type),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    /// Read the file in chunks of at most `size` bytes, bounding the memory
+    /// used by each response.
+    ///
+    /// Defaults to 8 KiB.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is zero.
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        assert!(size > 0, "chunk size must be positive");
+        self.chunk_size = size;
+        self
+    }
+
+    /// Returns the path of the served file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl<B> Service<Request<B>> for ServeFile {
+    type Response = Response<FileBody>;
+    type Error = io::Error;
+    type Future = ResponseFuture;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if let Some(future) = ResponseFuture::check_method(req.method()) {
+            return future;
+        }
+        let path = PathBuf::clone(&self.path);
+        ResponseFuture::open(path, self.content_type.clone(), req.headers())
+            .chunk_size(self.chunk_size)
+            .head(req.method() == Method::HEAD)
+    }
+}
@@ -0,0 +1,56 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How `ServeDir` treats symbolic links under its root.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Follow every symbolic link, wherever it points.
+    Follow,
+    /// Follow symbolic links pointing inside the root, refusing the others.
+    FollowWithinRoot,
+    /// Refuse every path going through a symbolic link.
+    Refuse,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        SymlinkPolicy::Follow
+    }
+}
+
+/// Checks that `path`, under `root`, may be served according to `policy`.
+///
+/// Fails with `PermissionDenied` if it goes through a refused link, and with
+/// `NotFound` if some part of it is missing. The root itself may be a link.
+pub(crate) fn check(root: &Path, path: &Path, policy: SymlinkPolicy) -> io::Result<()> {
+    if policy == SymlinkPolicy::Follow {
+        return Ok(());
+    }
+    let relative = path.strip_prefix(root).map_err(|_| forbidden())?;
+    let canonical_root = match policy {
+        SymlinkPolicy::FollowWithinRoot => Some(fs::canonicalize(root)?),
+        _ => None,
+    };
+
+    let mut current = root.to_owned();
+    for component in relative.components() {
+        current.push(component);
+        if !fs::symlink_metadata(&current)?.file_type().is_symlink() {
+            continue;
+        }
+        match canonical_root {
+            Some(ref canonical_root) => {
+                if !fs::canonicalize(&current)?.starts_with(canonical_root) {
+                    return Err(forbidden());
+                }
+            }
+            None => return Err(forbidden()),
+        }
+    }
+    Ok(())
+}
+
+fn forbidden() -> io::Error {
+    io::ErrorKind::PermissionDenied.into()
+}
@@ -0,0 +1,194 @@
+//! Middleware that turns errors into responses.
+//!
+//! Transports only know what to do with responses, so the errors of a
+//! stack must be converted into responses somewhere. `HandleError` passes
+//! the errors of the inner service, from `poll_ready` as well as from its
+//! response futures, to an `ErrorHandler` which builds the response to
+//! send. Handlers may answer immediately or asynchronously, and usually
+//! have `std::convert::Infallible` as their error type, which then becomes
+//! the error type of the stack.
+//!
+//! So that readiness errors can be answered too, the inner service is
+//! cloned for each request and driven to readiness by the response future;
+//! the inner service must then be `Clone`, and `HandleError` itself is
+//! always ready.
+
+use futures::{Async, Future, IntoFuture, Poll};
+use http::Request;
+use std::sync::Arc;
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Turns the errors of the inner service into responses.
+#[derive(Debug, Clone)]
+pub struct HandleError<S, H> {
+    inner: S,
+    handler: Arc<H>,
+}
+
+/// Builds responses from errors, possibly asynchronously.
+///
+/// This is implemented by closures returning an `IntoFuture`, so that
+/// handlers answering immediately can return a `Result`.
+pub trait ErrorHandler<E> {
+    /// The response built from errors.
+    type Response;
+
+    /// The error returned when no response can be built.
+    type Error;
+
+    /// Resolves to the response.
+    type Future: Future<Item = Self::Response, Error = Self::Error>;
+
+    /// Build the response to `error`.
+    fn handle_error(&self, error: E) -> Self::Future;
+}
+
+/// Response future for `HandleError`.
+pub struct ResponseFuture<S, H, B>
+where
+    S: Service<Request<B>>,
+    H: ErrorHandler<S::Error>,
+{
+    state: State<S, S::Future, H::Future, B>,
+    handler: Arc<H>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<S, F, H, B> {
+    Ready { service: S, request: Request<B> },
+    Called(F),
+    Handling(H),
+    Done,
+}
+
+// ===== impl HandleError =====
+
+impl<S, H> HandleError<S, H> {
+    /// Create a new `HandleError` turning errors into responses with
+    /// `handler`.
+    pub fn new(inner: S, handler: H) -> Self {
+        HandleError {
+            inner,
+            handler: Arc::new(handler),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, H, ReqBody> Service<Request<ReqBody>> for HandleError<S, H>
+where
+    S: Service<Request<ReqBody>> + Clone,
+    H: ErrorHandler<S::Error, Response = S::Response>,
+{
+    type Response = S::Response;
+    type Error = H::Error;
+    type Future = ResponseFuture<S, H, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            state: State::Ready {
+                service: self.inner.clone(),
+                request: req,
+            },
+            handler: self.handler.clone(),
+        }
+    }
+}
+
+// ===== impl ErrorHandler =====
+
+impl<F, E, R> ErrorHandler<E> for F
+where
+    F: Fn(E) -> R,
+    R: IntoFuture,
+{
+    type Response = R::Item;
+    type Error = R::Error;
+    type Future = R::Future;
+
+    fn handle_error(&self, error: E) -> Self::Future {
+        self(error).into_future()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, H, B> Future for ResponseFuture<S, H, B>
+where
+    S: Service<Request<B>>,
+    H: ErrorHandler<S::Error, Response = S::Response>,
+{
+    type Item = S::Response;
+    type Error = H::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Ready {
+                    mut service,
+                    request,
+                } => match service.poll_ready() {
+                    Ok(Async::Ready(())) => self.state = State::Called(service.call(request)),
+                    Ok(Async::NotReady) => {
+                        self.state = State::Ready { service, request };
+                        return Ok(Async::NotReady);
+                    }
+                    Err(e) => self.state = State::Handling(self.handler.handle_error(e)),
+                },
+                State::Called(mut future) => match future.poll() {
+                    Ok(Async::Ready(res)) => return Ok(Async::Ready(res)),
+                    Ok(Async::NotReady) => {
+                        self.state = State::Called(future);
+                        return Ok(Async::NotReady);
+                    }
+                    Err(e) => self.state = State::Handling(self.handler.handle_error(e)),
+                },
+                State::Handling(mut future) => {
+                    let poll = future.poll();
+                    if let Ok(Async::NotReady) = poll {
+                        self.state = State::Handling(future);
+                    }
+                    return poll;
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, H, B> fmt::Debug for ResponseFuture<S, H, B>
+where
+    S: Service<Request<B>>,
+    H: ErrorHandler<S::Error>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Ready { .. } => "Ready",
+            State::Called(_) => "Called",
+            State::Handling(_) => "Handling",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
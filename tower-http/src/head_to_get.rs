@@ -0,0 +1,135 @@
+//! Middleware that answers `HEAD` requests with the inner service's `GET`
+//! responses.
+//!
+//! `HeadToGet` turns `HEAD` requests into `GET` requests before calling the
+//! inner service, then drops the body of the response, keeping its status
+//! and headers, `Content-Length` included. Handlers then only need to
+//! implement `GET`, at the price of producing bodies that are never sent.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::HeaderMap;
+use http::{Method, Request, Response};
+use http_body::Body;
+use tower_service::Service;
+
+/// Answers `HEAD` requests with the headers of `GET` responses.
+#[derive(Debug, Clone)]
+pub struct HeadToGet<S> {
+    inner: S,
+}
+
+/// Response future for `HeadToGet`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    head: bool,
+}
+
+/// Response body for `HeadToGet`, empty in response to `HEAD` requests.
+#[derive(Debug)]
+pub struct HeadBody<B> {
+    inner: Option<B>,
+}
+
+// ===== impl HeadToGet =====
+
+impl<S> HeadToGet<S> {
+    /// Create a new `HeadToGet`.
+    pub fn new(inner: S) -> Self {
+        HeadToGet { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for HeadToGet<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = Response<HeadBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let head = req.method() == Method::HEAD;
+        if head {
+            *req.method_mut() = Method::GET;
+        }
+        ResponseFuture {
+            inner: self.inner.call(req),
+            head,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = Response<HeadBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = try_ready!(self.inner.poll());
+        let head = self.head;
+        Ok(Async::Ready(res.map(|body| HeadBody {
+            // Dropping the body lets it release its resources early.
+            inner: if head { None } else { Some(body) },
+        })))
+    }
+}
+
+// ===== impl HeadBody =====
+
+impl<B> HeadBody<B> {
+    /// Returns the body of the inner response, or `None` if it was dropped
+    /// in response to a `HEAD` request.
+    pub fn into_inner(self) -> Option<B> {
+        self.inner
+    }
+}
+
+impl<B> Body for HeadBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self.inner {
+            Some(ref mut inner) => inner.poll_data(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match self.inner {
+            Some(ref mut inner) => inner.poll_trailers(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.as_ref().map_or(true, Body::is_end_stream)
+    }
+}
@@ -0,0 +1,219 @@
+//! Limit the size and number of request headers.
+//!
+//! `HeaderLimit` answers requests whose headers exceed any of the
+//! configured limits with `431 Request Header Fields Too Large`, without
+//! calling the inner service:
+//!
+//! - the length of a single header value;
+//! - the total size of the headers, counting each name, value and the
+//!   4 bytes of `: ` and CRLF per header, as in HTTP/1.1;
+//! - the number of headers.
+
+use futures::{Async, Future, Poll};
+use http::header::HeaderMap;
+use http::{Request, Response, StatusCode};
+use tower_service::Service;
+
+/// Rejects requests with oversized headers with `431`.
+#[derive(Debug, Clone)]
+pub struct HeaderLimit<S> {
+    inner: S,
+    limits: Limits,
+}
+
+/// Configure a `HeaderLimit`.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    limits: Limits,
+}
+
+/// Response future for `HeaderLimit`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    max_value_len: usize,
+    max_total_len: usize,
+    max_count: usize,
+}
+
+// ===== impl HeaderLimit =====
+
+impl<S> HeaderLimit<S> {
+    /// Create a new `HeaderLimit` with the default limits.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for HeaderLimit<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = if self.limits.exceeded_by(req.headers()) {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE;
+            State::Rejected(Some(res))
+        } else {
+            State::Accepted(self.inner.call(req))
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            limits: Limits {
+                max_value_len: 8 * 1024,
+                max_total_len: 32 * 1024,
+                max_count: 100,
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Create a new `Builder`.
+    ///
+    /// By default, header values may be 8 KiB long, headers may total
+    /// 32 KiB, and there may be 100 headers.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the maximum length of a header value.
+    pub fn max_value_len(mut self, len: usize) -> Self {
+        self.limits.max_value_len = len;
+        self
+    }
+
+    /// Set the maximum total size of the headers.
+    pub fn max_total_len(mut self, len: usize) -> Self {
+        self.limits.max_total_len = len;
+        self
+    }
+
+    /// Set the maximum number of headers.
+    pub fn max_count(mut self, count: usize) -> Self {
+        self.limits.max_count = count;
+        self
+    }
+
+    /// Build a `HeaderLimit` wrapping `inner`.
+    pub fn build<S>(self, inner: S) -> HeaderLimit<S> {
+        HeaderLimit {
+            inner,
+            limits: self.limits,
+        }
+    }
+}
+
+// ===== impl Limits =====
+
+impl Limits {
+    fn exceeded_by(&self, headers: &HeaderMap) -> bool {
+        if headers.len() > self.max_count {
+            return true;
+        }
+
+        let mut total = 0;
+        for (name, value) in headers {
+            if value.len() > self.max_value_len {
+                return true;
+            }
+            total += name.as_str().len() + value.len() + 4;
+        }
+        total > self.max_total_len
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    fn limits() -> Limits {
+        Builder::new()
+            .max_value_len(10)
+            .max_total_len(40)
+            .max_count(2)
+            .limits
+    }
+
+    #[test]
+    fn checks_limits() {
+        let mut headers = HeaderMap::new();
+        headers.insert("a", HeaderValue::from_static("0123456789"));
+        assert!(!limits().exceeded_by(&headers));
+
+        headers.insert("a", HeaderValue::from_static("0123456789a"));
+        assert!(limits().exceeded_by(&headers));
+
+        headers.insert("a", HeaderValue::from_static("0123456789"));
+        headers.insert("b", HeaderValue::from_static("0123456789"));
+        assert!(!limits().exceeded_by(&headers));
+        headers.insert("cccccccccc", HeaderValue::from_static("0"));
+        assert!(limits().exceeded_by(&headers));
+
+        headers.remove("cccccccccc");
+        headers.append("b", HeaderValue::from_static("0123456789"));
+        assert!(limits().exceeded_by(&headers));
+    }
+}
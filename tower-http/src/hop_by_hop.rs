@@ -0,0 +1,191 @@
+//! Middleware that strips hop-by-hop headers from proxied messages.
+//!
+//! RFC 7230 §6.1 requires intermediaries to remove the headers listed in
+//! `Connection`, as well as `Connection` itself and the other headers that
+//! only describe the current connection, before forwarding a message.
+//!
+//! Upgrade requests and `101 Switching Protocols` responses, as defined by
+//! the `upgrade` module, keep their `Upgrade` header along with
+//! `Connection: upgrade`, so that the handshake reaches the other end.
+
+use crate::upgrade::{is_upgrade_request, is_upgrade_response};
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http::{Request, Response};
+use tower_service::Service;
+
+/// Hop-by-hop headers that are always removed.
+///
+/// `Keep-Alive` and `Proxy-Connection` are removed as well, but have no
+/// constants in `http`.
+pub const HOP_BY_HOP_HEADERS: [HeaderName; 7] = [
+    header::CONNECTION,
+    header::PROXY_AUTHENTICATE,
+    header::PROXY_AUTHORIZATION,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+];
+
+/// Removes hop-by-hop headers from requests and responses.
+#[derive(Debug, Clone)]
+pub struct StripHopByHopHeaders<S> {
+    inner: S,
+}
+
+/// Response future for `StripHopByHopHeaders`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+}
+
+// ===== impl StripHopByHopHeaders =====
+
+impl<S> StripHopByHopHeaders<S> {
+    /// Create a new `StripHopByHopHeaders`.
+    pub fn new(inner: S) -> Self {
+        StripHopByHopHeaders { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for StripHopByHopHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        if is_upgrade_request(&req) {
+            strip_except_upgrade(req.headers_mut());
+        } else {
+            strip_hop_by_hop(req.headers_mut());
+        }
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        if is_upgrade_response(&res) {
+            strip_except_upgrade(res.headers_mut());
+        } else {
+            strip_hop_by_hop(res.headers_mut());
+        }
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Removes hop-by-hop headers, including those nominated by `Connection`,
+/// from `headers`.
+pub fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let nominated: Vec<HeaderName> = headers
+        .get_all(header::CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|token| HeaderName::from_bytes(token.trim().as_bytes()).ok())
+        .collect();
+
+    for name in nominated {
+        headers.remove(&name);
+    }
+
+    for name in HOP_BY_HOP_HEADERS.iter() {
+        headers.remove(name);
+    }
+
+    headers.remove("keep-alive");
+    headers.remove("proxy-connection");
+}
+
+/// Removes hop-by-hop headers from `headers`, but keeps `Upgrade`, nominated
+/// by `Connection: upgrade`.
+fn strip_except_upgrade(headers: &mut HeaderMap) {
+    let upgrade: Vec<HeaderValue> = headers.get_all(header::UPGRADE).iter().cloned().collect();
+    strip_hop_by_hop(headers);
+    if upgrade.is_empty() {
+        return;
+    }
+    headers.insert(header::CONNECTION, HeaderValue::from_static("upgrade"));
+    for value in upgrade {
+        headers.append(header::UPGRADE, value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::CONTENT_TYPE;
+
+    #[test]
+    fn removes_nominated_and_standard_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("close, x-custom"),
+        );
+        headers.insert("x-custom", HeaderValue::from_static("1"));
+        headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+        headers.insert(
+            header::TRANSFER_ENCODING,
+            HeaderValue::from_static("chunked"),
+        );
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+
+        strip_hop_by_hop(&mut headers);
+
+        assert_eq!(headers.len(), 1);
+        assert!(headers.contains_key(CONTENT_TYPE));
+    }
+
+    #[test]
+    fn keeps_upgrade_handshakes() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::CONNECTION,
+            HeaderValue::from_static("keep-alive, Upgrade"),
+        );
+        headers.insert("keep-alive", HeaderValue::from_static("timeout=5"));
+        headers.insert(header::UPGRADE, HeaderValue::from_static("websocket"));
+
+        strip_except_upgrade(&mut headers);
+
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[header::CONNECTION], "upgrade");
+        assert_eq!(headers[header::UPGRADE], "websocket");
+    }
+}
@@ -0,0 +1,167 @@
+//! Middleware that sets the `Strict-Transport-Security` header.
+//!
+//! The header is only meaningful over TLS and browsers ignore it on cleartext
+//! responses, so it is only attached to requests that `scheme::is_https`
+//! identifies as secure.
+
+use crate::scheme;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, STRICT_TRANSPORT_SECURITY};
+use http::{Request, Response};
+use std::time::Duration;
+use tower_service::Service;
+
+/// Adds `Strict-Transport-Security` to responses to HTTPS requests.
+#[derive(Debug, Clone)]
+pub struct StrictTransportSecurity<S> {
+    inner: S,
+    value: HeaderValue,
+}
+
+/// Configure a `StrictTransportSecurity` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    max_age: Duration,
+    include_subdomains: bool,
+    preload: bool,
+}
+
+/// Response future for `StrictTransportSecurity`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    value: Option<HeaderValue>,
+}
+
+// ===== impl StrictTransportSecurity =====
+
+impl<S> StrictTransportSecurity<S> {
+    /// Create a new `StrictTransportSecurity` with the given `max-age`.
+    pub fn new(inner: S, max_age: Duration) -> Self {
+        Builder::new().max_age(max_age).build(inner)
+    }
+
+    /// Returns the header value set by this middleware.
+    pub fn header_value(&self) -> &HeaderValue {
+        &self.value
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for StrictTransportSecurity<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let value = if scheme::is_https(&req) {
+            Some(self.value.clone())
+        } else {
+            None
+        };
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            value,
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            // One year, the minimum accepted by the preload list.
+            max_age: Duration::from_secs(31_536_000),
+            include_subdomains: false,
+            preload: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder with a `max-age` of one year.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the `max-age` directive.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Set whether to add the `includeSubDomains` directive.
+    pub fn include_subdomains(mut self, include_subdomains: bool) -> Self {
+        self.include_subdomains = include_subdomains;
+        self
+    }
+
+    /// Set whether to add the `preload` directive.
+    pub fn preload(mut self, preload: bool) -> Self {
+        self.preload = preload;
+        self
+    }
+
+    /// Render the configured directives into a header value.
+    pub fn to_header_value(&self) -> HeaderValue {
+        let mut value = format!("max-age={}", self.max_age.as_secs());
+        if self.include_subdomains {
+            value.push_str("; includeSubDomains");
+        }
+        if self.preload {
+            value.push_str("; preload");
+        }
+        HeaderValue::from_str(&value).expect("HSTS directives are valid header values")
+    }
+
+    /// Build the `StrictTransportSecurity` from the provided settings.
+    pub fn build<S>(self, inner: S) -> StrictTransportSecurity<S> {
+        StrictTransportSecurity {
+            inner,
+            value: self.to_header_value(),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+
+        if let Some(value) = self.value.take() {
+            res.headers_mut().insert(STRICT_TRANSPORT_SECURITY, value);
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
@@ -0,0 +1,249 @@
+//! Middleware that redirects cleartext requests to HTTPS.
+//!
+//! `RedirectHttps` answers requests that were not received over HTTPS with
+//! a redirect to the same URL with the `https` scheme, and passes HTTPS
+//! requests to the inner service. The scheme and host of a request are
+//! taken from a `ClientInfo` extension, as inserted by
+//! `forwarded::SetClientInfo`, or else determined with the configured
+//! `TrustedProxies`, so that `X-Forwarded-Proto` and `Forwarded` headers are
+//! only honoured from trusted proxies. Transports that terminate TLS
+//! themselves must record the scheme as described in the `scheme` module,
+//! or every request is redirected.
+//!
+//! Requests under `/.well-known/acme-challenge/` are passed through by
+//! default, so that certificates can be obtained over HTTP. Requests
+//! without a host are answered with `400 Bad Request`.
+
+use crate::forwarded::{ClientInfo, TrustedProxies};
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, LOCATION};
+use http::uri::Scheme;
+use http::{Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// The path prefix of ACME HTTP challenges.
+const ACME_CHALLENGE: &str = "/.well-known/acme-challenge/";
+
+/// Redirects cleartext requests to HTTPS.
+#[derive(Debug, Clone)]
+pub struct RedirectHttps<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure a `RedirectHttps` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+/// Response future for `RedirectHttps`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    trusted: TrustedProxies,
+    status: StatusCode,
+    port: Option<u16>,
+    excluded: Vec<String>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F),
+    Redirect(Option<Response<B>>),
+}
+
+// ===== impl RedirectHttps =====
+
+impl<S> RedirectHttps<S> {
+    /// Create a new `RedirectHttps` trusting no proxy and redirecting with
+    /// `308 Permanent Redirect`.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RedirectHttps<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let path = req.uri().path();
+        let excluded = self
+            .config
+            .excluded
+            .iter()
+            .any(|prefix| path.starts_with(&**prefix));
+        let res = if excluded {
+            None
+        } else {
+            self.config.redirect(&req)
+        };
+
+        let state = match res {
+            Some(res) => State::Redirect(Some(res)),
+            None => State::Called(self.inner.call(req)),
+        };
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl Config =====
+
+impl Config {
+    /// Returns the response to `req`, unless it was received over HTTPS.
+    fn redirect<B, ResBody: Default>(&self, req: &Request<B>) -> Option<Response<ResBody>> {
+        let computed;
+        let info = match req.extensions().get::<ClientInfo>() {
+            Some(info) => info,
+            None => {
+                computed = self.trusted.client_info(req);
+                &computed
+            }
+        };
+        if info.proto == Some(Scheme::HTTPS) {
+            return None;
+        }
+
+        let mut res = Response::new(ResBody::default());
+        let location = info.host.as_ref().and_then(|host| {
+            let mut location = format!("https://{}", host.host());
+            if let Some(port) = self.port {
+                location.push_str(&format!(":{}", port));
+            }
+            match req.uri().path_and_query() {
+                Some(path_and_query) => location.push_str(path_and_query.as_str()),
+                None => location.push('/'),
+            }
+            HeaderValue::from_str(&location).ok()
+        });
+        match location {
+            Some(location) => {
+                *res.status_mut() = self.status;
+                res.headers_mut().insert(LOCATION, location);
+            }
+            None => *res.status_mut() = StatusCode::BAD_REQUEST,
+        }
+        Some(res)
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                trusted: TrustedProxies::none(),
+                status: StatusCode::PERMANENT_REDIRECT,
+                port: None,
+                excluded: vec![ACME_CHALLENGE.to_owned()],
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder trusting no proxy, redirecting with
+    /// `308 Permanent Redirect` and excluding ACME challenges.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Honour the proxy headers of requests received from `trusted`.
+    pub fn trusted_proxies(mut self, trusted: TrustedProxies) -> Self {
+        self.config.trusted = trusted;
+        self
+    }
+
+    /// Redirect with `301 Moved Permanently` rather than
+    /// `308 Permanent Redirect`.
+    ///
+    /// Clients may then turn other requests than `GET` into `GET` requests,
+    /// but some very old clients only understand `301`.
+    pub fn moved_permanently(mut self, enable: bool) -> Self {
+        self.config.status = if enable {
+            StatusCode::MOVED_PERMANENTLY
+        } else {
+            StatusCode::PERMANENT_REDIRECT
+        };
+        self
+    }
+
+    /// Redirect to `port` rather than the default HTTPS port.
+    pub fn https_port(mut self, port: u16) -> Self {
+        self.config.port = if port == 443 { None } else { Some(port) };
+        self
+    }
+
+    /// Pass requests whose path starts with `prefix` to the inner service
+    /// without redirecting them.
+    pub fn exclude_prefix<T: Into<String>>(mut self, prefix: T) -> Self {
+        self.config.excluded.push(prefix.into());
+        self
+    }
+
+    /// Redirect ACME challenges as well.
+    pub fn redirect_acme_challenges(mut self) -> Self {
+        self.config
+            .excluded
+            .retain(|prefix| prefix != ACME_CHALLENGE);
+        self
+    }
+
+    /// Build the `RedirectHttps` from the provided settings.
+    pub fn build<S>(self, inner: S) -> RedirectHttps<S> {
+        RedirectHttps {
+            inner,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Called(ref mut future) => future.poll(),
+            State::Redirect(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,536 @@
+//! Replay responses to retried requests carrying an `Idempotency-Key`.
+//!
+//! `Idempotency` stores the response to each unsafe request, such as `POST`,
+//! carrying an `Idempotency-Key` header, and answers retries with the same
+//! key with the stored response, without calling the inner service, so that
+//! clients can safely retry requests which must not be processed twice, such
+//! as payments. Replayed responses carry an `Idempotent-Replayed: true`
+//! header.
+//!
+//! Requests are fingerprinted by their method, target and body, so that keys
+//! are not reused for different requests: a request reusing a key with a
+//! different payload is answered with `422 Unprocessable Entity`, and a
+//! retry arriving while the first request is still processed with
+//! `409 Conflict`. Keys must be at most 255 characters long; requests with
+//! invalid keys are answered with `400 Bad Request`. Responses with a server
+//! error status, and requests failing, release their key, so that they can
+//! be retried.
+//!
+//! Keys are chosen by clients, so they are scoped by a principal, such as
+//! the authenticated user, read from each request by the
+//! `rate_limit::KeyExtractor` given when creating the middleware: a retry is
+//! only answered with the stored response if it comes from the same
+//! principal, and requests without a principal are passed through.
+//!
+//! Keys and responses live in an `IdempotencyStore`. `MemoryStore` keeps
+//! them in memory; multi-instance deployments can implement the trait on
+//! top of a shared store. As looking up the store is asynchronous, the inner
+//! service must be `Clone`, and store errors are converted into the inner
+//! service's error type.
+//!
+//! Request and response bodies are buffered, to be fingerprinted and
+//! stored; `BodyLimit` can bound the size of request bodies. Upgrade
+//! requests, as defined by the `upgrade` module, are passed through without
+//! being buffered.
+
+mod store;
+
+pub use self::store::{Begin, IdempotencyStore, MemoryStore, StoredResponse};
+
+use crate::rate_limit::KeyExtractor;
+use crate::upgrade::is_upgrade_request;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::{Async, Future, Poll};
+use http::header::{HeaderName, HeaderValue};
+use http::{request, response, Method, Request, Response, StatusCode};
+use http_body::Body;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// The `Idempotency-Key` header name.
+pub const IDEMPOTENCY_KEY: &str = "idempotency-key";
+
+/// The `Idempotent-Replayed` header name.
+pub const IDEMPOTENT_REPLAYED: &str = "idempotent-replayed";
+
+/// The maximum length of idempotency keys.
+const MAX_KEY_LEN: usize = 255;
+
+/// Replays the stored response to requests retried with the same
+/// `Idempotency-Key`.
+#[derive(Debug, Clone)]
+pub struct Idempotency<S, P, T = MemoryStore> {
+    inner: S,
+    store: T,
+    principal: Arc<P>,
+    config: Arc<Config>,
+}
+
+/// Configure an `Idempotency`.
+#[derive(Debug, Clone)]
+pub struct Builder<P> {
+    config: Config,
+    principal: P,
+}
+
+/// Response future for `Idempotency`.
+pub struct ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: IdempotencyStore,
+{
+    state: State<S, T, ReqBody, ResBody>,
+    store: T,
+    config: Arc<Config>,
+    key: String,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    ttl: Duration,
+    lock_timeout: Duration,
+}
+
+enum State<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: IdempotencyStore,
+{
+    Reading {
+        parts: request::Parts,
+        body: ReqBody,
+        buf: BytesMut,
+        service: S,
+    },
+    Beginning {
+        begin: T::BeginFuture,
+        service: S,
+        request: Request<ReqBody>,
+        fingerprint: String,
+    },
+    Processing {
+        future: S::Future,
+        fingerprint: String,
+    },
+    Buffering {
+        parts: response::Parts,
+        body: ResBody,
+        buf: BytesMut,
+        fingerprint: String,
+    },
+    Writing {
+        write: T::WriteFuture,
+        result: Option<Result<Response<ResBody>, S::Error>>,
+    },
+    Rejected(StatusCode),
+    Called(S::Future),
+    Done,
+}
+
+// ===== impl Idempotency =====
+
+impl<S, P> Idempotency<S, P> {
+    /// Create a new `Idempotency` scoping keys by the principal `principal`
+    /// returns for each request, and keeping them in memory for 24 hours.
+    pub fn new(inner: S, principal: P) -> Self {
+        Builder::new(principal).build(inner)
+    }
+}
+
+impl<S, P, T> Idempotency<S, P, T> {
+    /// Create a new `Idempotency` scoping keys by the principal `principal`
+    /// returns for each request, and keeping them in `store` for 24 hours.
+    pub fn with_store(inner: S, principal: P, store: T) -> Self {
+        Builder::new(principal).build_with_store(inner, store)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, P, T, ReqBody, ResBody> Service<Request<ReqBody>> for Idempotency<S, P, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    S::Error: From<T::Error> + From<ReqBody::Error> + From<ResBody::Error>,
+    T: IdempotencyStore + Clone,
+    P: KeyExtractor<ReqBody>,
+    ReqBody: Body + From<Bytes>,
+    ResBody: Body + From<Bytes>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, T, ReqBody, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let key = req
+            .headers()
+            .get(IDEMPOTENCY_KEY)
+            .filter(|_| !is_safe(req.method()) && !is_upgrade_request(&req))
+            .map(|key| key.to_str().ok().map(str::to_owned));
+        let (state, key) = match key {
+            Some(Some(ref key)) if !key.is_empty() && key.len() <= MAX_KEY_LEN => {
+                match self.principal.extract(&req) {
+                    Some(principal) => {
+                        let clone = self.inner.clone();
+                        let (parts, body) = req.into_parts();
+                        let state = State::Reading {
+                            parts,
+                            body,
+                            buf: BytesMut::new(),
+                            service: mem::replace(&mut self.inner, clone),
+                        };
+                        (state, scoped_key(&principal, key))
+                    }
+                    None => (State::Called(self.inner.call(req)), String::new()),
+                }
+            }
+            Some(_) => (State::Rejected(StatusCode::BAD_REQUEST), String::new()),
+            None => (State::Called(self.inner.call(req)), String::new()),
+        };
+
+        ResponseFuture {
+            state,
+            store: self.store.clone(),
+            config: self.config.clone(),
+            key,
+        }
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    *method == Method::GET
+        || *method == Method::HEAD
+        || *method == Method::OPTIONS
+        || *method == Method::TRACE
+}
+
+/// Returns the key under which the key of a principal is stored, prefixed
+/// with the length of the principal so that distinct pairs never collide.
+fn scoped_key(principal: &str, key: &str) -> String {
+    format!("{}:{}:{}", principal.len(), principal, key)
+}
+
+/// Fingerprints a request by its method, target and body.
+fn fingerprint(parts: &request::Parts, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(parts.method.as_str());
+    hasher.input(b" ");
+    hasher.input(parts.uri.to_string());
+    hasher.input(b"\n");
+    hasher.input(body);
+    base64::encode_config(&hasher.result(), base64::URL_SAFE_NO_PAD)
+}
+
+fn extend<D: Buf>(buf: &mut BytesMut, mut data: D) {
+    while data.has_remaining() {
+        let n = {
+            let bytes = data.bytes();
+            buf.extend_from_slice(bytes);
+            bytes.len()
+        };
+        data.advance(n);
+    }
+}
+
+// ===== impl Builder =====
+
+impl<P> Builder<P> {
+    /// Create a new `Builder` scoping keys by the principal `principal`
+    /// returns for each request, and keeping them for 24 hours.
+    ///
+    /// Requests for which it returns `None` are passed through.
+    pub fn new(principal: P) -> Self {
+        Builder {
+            config: Config {
+                ttl: Duration::from_secs(24 * 60 * 60),
+                lock_timeout: Duration::from_secs(60),
+            },
+            principal,
+        }
+    }
+
+    /// Keep keys and their responses for `ttl`.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.config.ttl = ttl;
+        self
+    }
+
+    /// Reserve keys for at most `timeout` while their request is processed.
+    ///
+    /// This bounds how long a key stays unusable should an instance stop
+    /// while processing its request. Defaults to a minute.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Self {
+        self.config.lock_timeout = timeout;
+        self
+    }
+
+    /// Build an `Idempotency` wrapping `inner`, keeping keys in memory.
+    pub fn build<S>(self, inner: S) -> Idempotency<S, P> {
+        self.build_with_store(inner, MemoryStore::new())
+    }
+
+    /// Build an `Idempotency` wrapping `inner`, keeping keys in `store`.
+    pub fn build_with_store<S, T>(self, inner: S, store: T) -> Idempotency<S, P, T> {
+        Idempotency {
+            inner,
+            store,
+            principal: Arc::new(self.principal),
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, T, ReqBody, ResBody> Future for ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: From<T::Error> + From<ReqBody::Error> + From<ResBody::Error>,
+    T: IdempotencyStore,
+    ReqBody: Body + From<Bytes>,
+    ResBody: Body + From<Bytes>,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Reading {
+                    parts,
+                    mut body,
+                    mut buf,
+                    service,
+                } => match body.poll_data()? {
+                    Async::Ready(Some(data)) => {
+                        extend(&mut buf, data);
+                        self.state = State::Reading {
+                            parts,
+                            body,
+                            buf,
+                            service,
+                        };
+                    }
+                    Async::Ready(None) => {
+                        let body = buf.freeze();
+                        let fingerprint = fingerprint(&parts, &body);
+                        let begin = self.store.begin(
+                            self.key.clone(),
+                            fingerprint.clone(),
+                            self.config.lock_timeout,
+                        );
+                        self.state = State::Beginning {
+                            begin,
+                            service,
+                            request: Request::from_parts(parts, ReqBody::from(body)),
+                            fingerprint,
+                        };
+                    }
+                    Async::NotReady => {
+                        self.state = State::Reading {
+                            parts,
+                            body,
+                            buf,
+                            service,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Beginning {
+                    mut begin,
+                    mut service,
+                    request,
+                    fingerprint,
+                } => {
+                    let begin = match begin.poll()? {
+                        Async::Ready(begin) => begin,
+                        Async::NotReady => {
+                            self.state = State::Beginning {
+                                begin,
+                                service,
+                                request,
+                                fingerprint,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    match begin {
+                        Begin::Started => {
+                            self.state = State::Processing {
+                                future: service.call(request),
+                                fingerprint,
+                            };
+                        }
+                        Begin::InProgress => self.state = State::Rejected(StatusCode::CONFLICT),
+                        Begin::Mismatch => {
+                            self.state = State::Rejected(StatusCode::UNPROCESSABLE_ENTITY)
+                        }
+                        Begin::Completed(stored) => return Ok(Async::Ready(replay(stored))),
+                    }
+                }
+                State::Processing {
+                    mut future,
+                    fingerprint,
+                } => {
+                    let response = match future.poll() {
+                        Ok(Async::Ready(response)) => response,
+                        Ok(Async::NotReady) => {
+                            self.state = State::Processing {
+                                future,
+                                fingerprint,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                        Err(e) => {
+                            self.state = State::Writing {
+                                write: self.store.release(&self.key),
+                                result: Some(Err(e)),
+                            };
+                            continue;
+                        }
+                    };
+                    if response.status().is_server_error() {
+                        self.state = State::Writing {
+                            write: self.store.release(&self.key),
+                            result: Some(Ok(response)),
+                        };
+                        continue;
+                    }
+                    let (parts, body) = response.into_parts();
+                    self.state = State::Buffering {
+                        parts,
+                        body,
+                        buf: BytesMut::new(),
+                        fingerprint,
+                    };
+                }
+                State::Buffering {
+                    parts,
+                    mut body,
+                    mut buf,
+                    fingerprint,
+                } => {
+                    let data = match body.poll_data() {
+                        Ok(Async::Ready(data)) => data,
+                        Ok(Async::NotReady) => {
+                            self.state = State::Buffering {
+                                parts,
+                                body,
+                                buf,
+                                fingerprint,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                        Err(e) => {
+                            self.state = State::Writing {
+                                write: self.store.release(&self.key),
+                                result: Some(Err(e.into())),
+                            };
+                            continue;
+                        }
+                    };
+                    if let Some(data) = data {
+                        extend(&mut buf, data);
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf,
+                            fingerprint,
+                        };
+                        continue;
+                    }
+
+                    let body = buf.freeze();
+                    let stored = StoredResponse {
+                        fingerprint,
+                        status: parts.status,
+                        version: parts.version,
+                        headers: parts.headers.clone(),
+                        body: body.clone(),
+                    };
+                    let key = mem::replace(&mut self.key, String::new());
+                    self.state = State::Writing {
+                        write: self.store.complete(key, stored, self.config.ttl),
+                        result: Some(Ok(Response::from_parts(parts, ResBody::from(body)))),
+                    };
+                }
+                State::Writing { mut write, result } => {
+                    // The response is sent even if the store failed.
+                    if let Ok(Async::NotReady) = write.poll() {
+                        self.state = State::Writing { write, result };
+                        return Ok(Async::NotReady);
+                    }
+                    let result = result.expect("polled after completion");
+                    return result.map(Async::Ready);
+                }
+                State::Rejected(status) => {
+                    let mut res = Response::new(ResBody::from(Bytes::new()));
+                    *res.status_mut() = status;
+                    return Ok(Async::Ready(res));
+                }
+                State::Called(mut future) => {
+                    let result = future.poll();
+                    if let Ok(Async::NotReady) = result {
+                        self.state = State::Called(future);
+                    }
+                    return result;
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+fn replay<B: From<Bytes>>(stored: StoredResponse) -> Response<B> {
+    let mut res = Response::new(B::from(stored.body));
+    *res.status_mut() = stored.status;
+    *res.version_mut() = stored.version;
+    *res.headers_mut() = stored.headers;
+    res.headers_mut().insert(
+        HeaderName::from_static(IDEMPOTENT_REPLAYED),
+        HeaderValue::from_static("true"),
+    );
+    res
+}
+
+impl<S, T, ReqBody, ResBody> fmt::Debug for ResponseFuture<S, T, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>>,
+    T: IdempotencyStore,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Reading { .. } => "Reading",
+            State::Beginning { .. } => "Beginning",
+            State::Processing { .. } => "Processing",
+            State::Buffering { .. } => "Buffering",
+            State::Writing { .. } => "Writing",
+            State::Rejected(_) => "Rejected",
+            State::Called(_) => "Called",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .field("key", &self.key)
+            .finish()
+    }
+}
@@ -0,0 +1,204 @@
+use bytes::Bytes;
+use futures::future::{self, FutureResult};
+use futures::Future;
+use http::header::HeaderMap;
+use http::{StatusCode, Version};
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Storage for idempotency keys and the responses to their requests.
+///
+/// Implement this trait to share keys between instances, e.g. in Redis.
+/// `begin` must reserve keys atomically, so that a single request is
+/// processed per key.
+pub trait IdempotencyStore {
+    /// Errors produced by the store.
+    type Error;
+
+    /// Future returned by `begin`.
+    type BeginFuture: Future<Item = Begin, Error = Self::Error>;
+
+    /// Future returned by `complete` and `release`.
+    type WriteFuture: Future<Item = (), Error = Self::Error>;
+
+    /// Look up `key`, reserving it for `ttl` for the request with
+    /// `fingerprint` if it is unused.
+    fn begin(&self, key: String, fingerprint: String, ttl: Duration) -> Self::BeginFuture;
+
+    /// Store `response` with the reserved `key` for `ttl`.
+    fn complete(&self, key: String, response: StoredResponse, ttl: Duration) -> Self::WriteFuture;
+
+    /// Release the reservation of `key`, so that the request can be retried.
+    fn release(&self, key: &str) -> Self::WriteFuture;
+}
+
+/// The outcome of looking up an idempotency key.
+#[derive(Debug, Clone)]
+pub enum Begin {
+    /// The key was unused, and is now reserved for the request.
+    Started,
+    /// A request with the same key and payload is being processed.
+    InProgress,
+    /// The key was used for a request with a different payload.
+    Mismatch,
+    /// The request was already processed, with this response.
+    Completed(StoredResponse),
+}
+
+/// A response stored in an `IdempotencyStore`.
+#[derive(Debug, Clone)]
+pub struct StoredResponse {
+    /// The fingerprint of the request the response is to.
+    pub fingerprint: String,
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The HTTP version of the response.
+    pub version: Version,
+    /// The headers of the response.
+    pub headers: HeaderMap,
+    /// The body of the response.
+    pub body: Bytes,
+}
+
+/// An in-memory `IdempotencyStore`.
+///
+/// Clones share the same keys.
+#[derive(Clone, Default)]
+pub struct MemoryStore {
+    entries: Arc<Mutex<Entries>>,
+}
+
+#[derive(Default)]
+struct Entries {
+    map: HashMap<String, Entry>,
+    writes: u64,
+}
+
+struct Entry {
+    state: EntryState,
+    expires: Instant,
+}
+
+enum EntryState {
+    Pending(String),
+    Done(StoredResponse),
+}
+
+impl MemoryStore {
+    /// Create a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Look up `key` at `now`, reserving it until `expires` if unused.
+    pub fn begin_at(
+        &self,
+        key: String,
+        fingerprint: String,
+        now: Instant,
+        expires: Instant,
+    ) -> Begin {
+        let mut entries = self.entries.lock().unwrap();
+
+        // Drop expired keys now and then so the map does not grow with every
+        // key ever seen.
+        entries.writes += 1;
+        if entries.writes % 1024 == 0 {
+            entries.map.retain(|_, entry| entry.expires > now);
+        }
+
+        if let Some(entry) = entries.map.get(&key) {
+            if entry.expires > now {
+                return match entry.state {
+                    EntryState::Pending(ref f) if *f == fingerprint => Begin::InProgress,
+                    EntryState::Done(ref res) if res.fingerprint == fingerprint => {
+                        Begin::Completed(res.clone())
+                    }
+                    _ => Begin::Mismatch,
+                };
+            }
+        }
+
+        let entry = Entry {
+            state: EntryState::Pending(fingerprint),
+            expires,
+        };
+        entries.map.insert(key, entry);
+        Begin::Started
+    }
+
+    /// Returns the number of keys, including expired ones not dropped yet.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    /// Returns `true` if the store holds no keys.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl IdempotencyStore for MemoryStore {
+    type Error = std::convert::Infallible;
+    type BeginFuture = FutureResult<Begin, Self::Error>;
+    type WriteFuture = FutureResult<(), Self::Error>;
+
+    fn begin(&self, key: String, fingerprint: String, ttl: Duration) -> Self::BeginFuture {
+        let now = Instant::now();
+        future::ok(self.begin_at(key, fingerprint, now, now + ttl))
+    }
+
+    fn complete(&self, key: String, response: StoredResponse, ttl: Duration) -> Self::WriteFuture {
+        let entry = Entry {
+            state: EntryState::Done(response),
+            expires: Instant::now() + ttl,
+        };
+        self.entries.lock().unwrap().map.insert(key, entry);
+        future::ok(())
+    }
+
+    fn release(&self, key: &str) -> Self::WriteFuture {
+        self.entries.lock().unwrap().map.remove(key);
+        future::ok(())
+    }
+}
+
+impl fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MemoryStore")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserves_keys() {
+        let store = MemoryStore::new();
+        let now = Instant::now();
+        let later = now + Duration::from_secs(60);
+        let begin = |fingerprint: &str, now| {
+            store.begin_at("k".to_owned(), fingerprint.to_owned(), now, later)
+        };
+
+        assert_eq!(name(begin("a", now)), "Started");
+        assert_eq!(name(begin("a", now)), "InProgress");
+        assert_eq!(name(begin("b", now)), "Mismatch");
+        // Expired reservations are replaced.
+        assert_eq!(name(begin("b", later)), "Started");
+    }
+
+    fn name(begin: Begin) -> &'static str {
+        match begin {
+            Begin::Started => "Started",
+            Begin::InProgress => "InProgress",
+            Begin::Mismatch => "Mismatch",
+            Begin::Completed(_) => "Completed",
+        }
+    }
+}
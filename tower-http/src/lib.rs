@@ -1,3 +1,76 @@
+#![doc(html_root_url = "https://docs.rs/tower-http/0.1.0")]
+#![deny(missing_docs, missing_debug_implementations, unreachable_pub)]
+#![cfg_attr(test, deny(warnings))]
+
+//! Tower middleware and utilities for HTTP clients and servers.
+
+pub mod access_log;
+pub mod allowed_hosts;
+pub mod api_version;
+pub mod auth;
+pub mod baggage;
+pub mod body_limit;
+pub mod cache;
+pub mod cache_control;
+pub mod catch_panic;
+pub mod circuit_breaker;
+pub mod classify;
+pub mod client_cookies;
+pub mod concurrency_limit;
+pub mod cookies;
+pub mod csp;
+pub mod date;
+pub mod deprecation;
+pub mod drain;
+pub mod dump;
+pub mod etag;
+pub mod expect_continue;
+pub mod forwarded;
+pub mod fs;
+pub mod handle_error;
+pub mod head_to_get;
+pub mod header_limit;
+pub mod hop_by_hop;
+pub mod hsts;
+pub mod https_redirect;
+pub mod idempotency;
+pub mod load_shed;
+pub mod map;
+pub mod method_filter;
+pub mod method_override;
+pub mod metrics;
+pub mod mirror;
+pub mod negotiate;
+pub mod normalize_path;
+pub mod normalize_query;
+pub mod options;
+pub mod precondition;
+pub mod problem;
+pub mod rate_limit;
+pub mod redirect;
+pub mod require_content_type;
+pub mod retry;
+pub mod scheme;
+pub mod security_headers;
+pub mod sensitive_headers;
+pub mod server_header;
+pub mod server_timing;
+pub mod session;
+pub mod set_forwarded;
+pub mod signed_url;
+pub mod signing;
+pub mod singleflight;
+pub mod smuggling_guard;
+pub mod throttle;
+pub mod timeout;
+pub mod trace;
+pub mod trace_context;
+pub mod upgrade;
+pub mod user_agent;
+pub mod vary;
+
+mod util;
+
 pub use http_body::Body;
 pub use tower_http_util::body::BodyExt;
 pub use tower_http_util::connection::HttpMakeConnection;
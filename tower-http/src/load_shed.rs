@@ -0,0 +1,195 @@
+//! Shed load with `503 Service Unavailable`.
+//!
+//! `LoadShed` always reports itself ready. When the inner service is not
+//! ready, or the number of requests in flight has reached the configured
+//! threshold, requests are answered immediately with `503 Service
+//! Unavailable` and an optional `Retry-After` header instead of waiting for
+//! capacity, so backpressure does not propagate to the accept loop.
+
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, RETRY_AFTER};
+use http::{Request, Response, StatusCode};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tower_service::Service;
+
+/// Answers requests with `503` when the inner service is overloaded.
+#[derive(Debug)]
+pub struct LoadShed<S> {
+    inner: S,
+    retry_after: Option<HeaderValue>,
+    max_in_flight: Option<usize>,
+    in_flight: Arc<AtomicUsize>,
+    ready: bool,
+}
+
+/// Configure a `LoadShed`.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    retry_after: Option<HeaderValue>,
+    max_in_flight: Option<usize>,
+}
+
+/// Response future for `LoadShed`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted { future: F, _in_flight: InFlight },
+    Shed(Option<Response<B>>),
+}
+
+/// Decrements the in-flight count when dropped.
+#[derive(Debug)]
+struct InFlight(Arc<AtomicUsize>);
+
+// ===== impl LoadShed =====
+
+impl<S> LoadShed<S> {
+    /// Create a new `LoadShed` shedding requests while the inner service is
+    /// not ready.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns the number of requests in flight.
+    ///
+    /// The count is shared with clones of this service.
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: Clone> Clone for LoadShed<S> {
+    fn clone(&self) -> Self {
+        LoadShed {
+            inner: self.inner.clone(),
+            retry_after: self.retry_after.clone(),
+            max_in_flight: self.max_in_flight,
+            in_flight: self.in_flight.clone(),
+            // The clone has not been polled for readiness.
+            ready: false,
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for LoadShed<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.ready = self.inner.poll_ready()?.is_ready();
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let ready = self.ready;
+        self.ready = false;
+
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let guard = InFlight(self.in_flight.clone());
+        let overloaded = self.max_in_flight.map_or(false, |max| in_flight >= max);
+
+        let state = if ready && !overloaded {
+            State::Accepted {
+                future: self.inner.call(req),
+                _in_flight: guard,
+            }
+        } else {
+            drop(guard);
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            if let Some(ref retry_after) = self.retry_after {
+                res.headers_mut().insert(RETRY_AFTER, retry_after.clone());
+            }
+            State::Shed(Some(res))
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Create a new `Builder` without `Retry-After` or in-flight threshold.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the `Retry-After` header sent with `503` responses, rounded down
+    /// to whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = Some(HeaderValue::from(retry_after.as_secs()));
+        self
+    }
+
+    /// Shed requests once `max` requests are in flight, even if the inner
+    /// service is ready.
+    pub fn max_in_flight(mut self, max: usize) -> Self {
+        self.max_in_flight = Some(max);
+        self
+    }
+
+    /// Build a `LoadShed` wrapping `inner`.
+    pub fn build<S>(self, inner: S) -> LoadShed<S> {
+        LoadShed {
+            inner,
+            retry_after: self.retry_after,
+            max_in_flight: self.max_in_flight,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            ready: false,
+        }
+    }
+}
+
+// ===== impl InFlight =====
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted { ref mut future, .. } => future.poll(),
+            State::Shed(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! Middleware applying functions to requests and responses.
+//!
+//! These cover the long tail of small transformations which do not deserve
+//! a middleware of their own:
+//!
+//! - `MapRequest` applies a function to each request before calling the
+//!   inner service;
+//! - `MapResponse` applies a function to each response of the inner
+//!   service;
+//! - `MapRequestBody` and `MapResponseBody` only replace the bodies of
+//!   requests and responses, such as to wrap every response body in a
+//!   metrics or limiting body, and may change the body types.
+//!
+//! Each middleware comes with a `Layer`, for use in service builders.
+
+mod request;
+mod request_body;
+mod response;
+mod response_body;
+
+pub use self::request::{MapRequest, MapRequestLayer};
+pub use self::request_body::{MapRequestBody, MapRequestBodyLayer};
+pub use self::response::{MapResponse, MapResponseFuture, MapResponseLayer};
+pub use self::response_body::{MapResponseBody, MapResponseBodyFuture, MapResponseBodyLayer};
@@ -0,0 +1,77 @@
+use futures::Poll;
+use http::Request;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Applies a function to each request before calling the inner service.
+#[derive(Debug, Clone)]
+pub struct MapRequest<S, F> {
+    inner: S,
+    f: F,
+}
+
+/// Applies `MapRequest` to services.
+#[derive(Debug, Clone)]
+pub struct MapRequestLayer<F> {
+    f: F,
+}
+
+// ===== impl MapRequest =====
+
+impl<S, F> MapRequest<S, F> {
+    /// Create a new `MapRequest` applying `f` to requests.
+    pub fn new(inner: S, f: F) -> Self {
+        MapRequest { inner, f }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, ReqBody, NewReqBody> Service<Request<ReqBody>> for MapRequest<S, F>
+where
+    S: Service<Request<NewReqBody>>,
+    F: FnMut(Request<ReqBody>) -> Request<NewReqBody>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let req = (self.f)(req);
+        self.inner.call(req)
+    }
+}
+
+// ===== impl MapRequestLayer =====
+
+impl<F> MapRequestLayer<F> {
+    /// Create a new `MapRequestLayer` applying `f` to requests.
+    pub fn new(f: F) -> Self {
+        MapRequestLayer { f }
+    }
+}
+
+impl<S, F: Clone> Layer<S> for MapRequestLayer<F> {
+    type Service = MapRequest<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapRequest::new(inner, self.f.clone())
+    }
+}
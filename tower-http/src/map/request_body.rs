@@ -0,0 +1,78 @@
+use futures::Poll;
+use http::Request;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Applies a function to the body of each request before calling the inner
+/// service.
+#[derive(Debug, Clone)]
+pub struct MapRequestBody<S, F> {
+    inner: S,
+    f: F,
+}
+
+/// Applies `MapRequestBody` to services.
+#[derive(Debug, Clone)]
+pub struct MapRequestBodyLayer<F> {
+    f: F,
+}
+
+// ===== impl MapRequestBody =====
+
+impl<S, F> MapRequestBody<S, F> {
+    /// Create a new `MapRequestBody` applying `f` to request bodies.
+    pub fn new(inner: S, f: F) -> Self {
+        MapRequestBody { inner, f }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, ReqBody, NewReqBody> Service<Request<ReqBody>> for MapRequestBody<S, F>
+where
+    S: Service<Request<NewReqBody>>,
+    F: FnMut(ReqBody) -> NewReqBody,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let req = req.map(&mut self.f);
+        self.inner.call(req)
+    }
+}
+
+// ===== impl MapRequestBodyLayer =====
+
+impl<F> MapRequestBodyLayer<F> {
+    /// Create a new `MapRequestBodyLayer` applying `f` to request bodies.
+    pub fn new(f: F) -> Self {
+        MapRequestBodyLayer { f }
+    }
+}
+
+impl<S, F: Clone> Layer<S> for MapRequestBodyLayer<F> {
+    type Service = MapRequestBody<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapRequestBody::new(inner, self.f.clone())
+    }
+}
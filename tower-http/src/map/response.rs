@@ -0,0 +1,109 @@
+use futures::{try_ready, Async, Future, Poll};
+use http::{Request, Response};
+use std::sync::Arc;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Applies a function to each response of the inner service.
+#[derive(Debug, Clone)]
+pub struct MapResponse<S, F> {
+    inner: S,
+    f: Arc<F>,
+}
+
+/// Applies `MapResponse` to services.
+#[derive(Debug, Clone)]
+pub struct MapResponseLayer<F> {
+    f: Arc<F>,
+}
+
+/// Response future for `MapResponse`.
+#[derive(Debug)]
+pub struct MapResponseFuture<T, F> {
+    inner: T,
+    f: Arc<F>,
+}
+
+// ===== impl MapResponse =====
+
+impl<S, F> MapResponse<S, F> {
+    /// Create a new `MapResponse` applying `f` to responses.
+    pub fn new(inner: S, f: F) -> Self {
+        MapResponse {
+            inner,
+            f: Arc::new(f),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, ReqBody, ResBody, NewResBody> Service<Request<ReqBody>> for MapResponse<S, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    F: Fn(Response<ResBody>) -> Response<NewResBody>,
+{
+    type Response = Response<NewResBody>;
+    type Error = S::Error;
+    type Future = MapResponseFuture<S::Future, F>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        MapResponseFuture {
+            inner: self.inner.call(req),
+            f: self.f.clone(),
+        }
+    }
+}
+
+// ===== impl MapResponseLayer =====
+
+impl<F> MapResponseLayer<F> {
+    /// Create a new `MapResponseLayer` applying `f` to responses.
+    pub fn new(f: F) -> Self {
+        MapResponseLayer { f: Arc::new(f) }
+    }
+}
+
+impl<S, F> Layer<S> for MapResponseLayer<F> {
+    type Service = MapResponse<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapResponse {
+            inner,
+            f: self.f.clone(),
+        }
+    }
+}
+
+// ===== impl MapResponseFuture =====
+
+impl<T, F, B, NewB> Future for MapResponseFuture<T, F>
+where
+    T: Future<Item = Response<B>>,
+    F: Fn(Response<B>) -> Response<NewB>,
+{
+    type Item = Response<NewB>;
+    type Error = T::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = try_ready!(self.inner.poll());
+        Ok(Async::Ready((self.f)(res)))
+    }
+}
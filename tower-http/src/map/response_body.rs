@@ -0,0 +1,109 @@
+use futures::{try_ready, Async, Future, Poll};
+use http::{Request, Response};
+use std::sync::Arc;
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Applies a function to the body of each response of the inner service.
+#[derive(Debug, Clone)]
+pub struct MapResponseBody<S, F> {
+    inner: S,
+    f: Arc<F>,
+}
+
+/// Applies `MapResponseBody` to services.
+#[derive(Debug, Clone)]
+pub struct MapResponseBodyLayer<F> {
+    f: Arc<F>,
+}
+
+/// Response future for `MapResponseBody`.
+#[derive(Debug)]
+pub struct MapResponseBodyFuture<T, F> {
+    inner: T,
+    f: Arc<F>,
+}
+
+// ===== impl MapResponseBody =====
+
+impl<S, F> MapResponseBody<S, F> {
+    /// Create a new `MapResponseBody` applying `f` to response bodies.
+    pub fn new(inner: S, f: F) -> Self {
+        MapResponseBody {
+            inner,
+            f: Arc::new(f),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, F, ReqBody, ResBody, NewResBody> Service<Request<ReqBody>> for MapResponseBody<S, F>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    F: Fn(ResBody) -> NewResBody,
+{
+    type Response = Response<NewResBody>;
+    type Error = S::Error;
+    type Future = MapResponseBodyFuture<S::Future, F>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        MapResponseBodyFuture {
+            inner: self.inner.call(req),
+            f: self.f.clone(),
+        }
+    }
+}
+
+// ===== impl MapResponseBodyLayer =====
+
+impl<F> MapResponseBodyLayer<F> {
+    /// Create a new `MapResponseBodyLayer` applying `f` to response bodies.
+    pub fn new(f: F) -> Self {
+        MapResponseBodyLayer { f: Arc::new(f) }
+    }
+}
+
+impl<S, F> Layer<S> for MapResponseBodyLayer<F> {
+    type Service = MapResponseBody<S, F>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MapResponseBody {
+            inner,
+            f: self.f.clone(),
+        }
+    }
+}
+
+// ===== impl MapResponseBodyFuture =====
+
+impl<T, F, B, NewB> Future for MapResponseBodyFuture<T, F>
+where
+    T: Future<Item = Response<B>>,
+    F: Fn(B) -> NewB,
+{
+    type Item = Response<NewB>;
+    type Error = T::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = try_ready!(self.inner.poll());
+        Ok(Async::Ready(res.map(&*self.f)))
+    }
+}
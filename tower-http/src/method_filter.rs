@@ -0,0 +1,178 @@
+//! Middleware that restricts the methods reaching a service.
+//!
+//! `MethodFilter` passes requests using one of the allowed methods to the
+//! inner service, and answers other requests with
+//! `405 Method Not Allowed` and an `Allow` header listing the allowed
+//! methods. Servers which should rather report unlisted methods as not
+//! implemented at all can answer them with `501 Not Implemented` instead.
+//!
+//! `HEAD` is not implied by `GET`: allow it explicitly if the inner service
+//! answers it.
+
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, ALLOW};
+use http::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Restricts the methods of requests reaching the inner service.
+#[derive(Debug, Clone)]
+pub struct MethodFilter<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure a `MethodFilter` instance.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    methods: Vec<Method>,
+    not_implemented: bool,
+}
+
+/// Response future for `MethodFilter`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+struct Config {
+    methods: Vec<Method>,
+    allow: HeaderValue,
+    not_implemented: bool,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+// ===== impl MethodFilter =====
+
+impl<S> MethodFilter<S> {
+    /// Create a new `MethodFilter` allowing the methods in `methods`.
+    pub fn new<I>(inner: S, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        methods
+            .into_iter()
+            .fold(Builder::new(), Builder::allow)
+            .build(inner)
+    }
+
+    /// Returns the allowed methods.
+    pub fn allowed_methods(&self) -> &[Method] {
+        &self.config.methods
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MethodFilter<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        if self.config.methods.contains(req.method()) {
+            return ResponseFuture {
+                state: State::Accepted(self.inner.call(req)),
+            };
+        }
+
+        let mut res = Response::new(ResBody::default());
+        if self.config.not_implemented {
+            *res.status_mut() = StatusCode::NOT_IMPLEMENTED;
+        } else {
+            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            res.headers_mut().insert(ALLOW, self.config.allow.clone());
+        }
+        ResponseFuture {
+            state: State::Rejected(Some(res)),
+        }
+    }
+}
+
+/// Returns the value of an `Allow` header listing `methods`.
+pub(crate) fn allow_header(methods: &[Method]) -> HeaderValue {
+    let methods: Vec<&str> = methods.iter().map(Method::as_str).collect();
+    HeaderValue::from_str(&methods.join(", ")).expect("methods are valid header values")
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Return a new builder allowing no method.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Allow requests using `method`.
+    pub fn allow(mut self, method: Method) -> Self {
+        if !self.methods.contains(&method) {
+            self.methods.push(method);
+        }
+        self
+    }
+
+    /// Answer requests using other methods with `501 Not Implemented`
+    /// rather than `405 Method Not Allowed`.
+    pub fn not_implemented(mut self, enable: bool) -> Self {
+        self.not_implemented = enable;
+        self
+    }
+
+    /// Build the `MethodFilter` from the provided settings.
+    pub fn build<S>(self, inner: S) -> MethodFilter<S> {
+        MethodFilter {
+            inner,
+            config: Arc::new(Config {
+                allow: allow_header(&self.methods),
+                methods: self.methods,
+                not_implemented: self.not_implemented,
+            }),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Middleware that overrides the method of `POST` requests.
+//!
+//! Some clients and gateways can only issue `GET` and `POST`. Such clients
+//! send a `POST` with the intended method in an `X-HTTP-Method-Override`
+//! header (or, optionally, a `_method` query parameter), and this middleware
+//! rewrites the request method before it reaches the inner service.
+
+use http::header::HeaderName;
+use http::{Method, Request};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Name of the `X-HTTP-Method-Override` header.
+pub const X_HTTP_METHOD_OVERRIDE: &str = "x-http-method-override";
+
+/// Rewrites the method of `POST` requests carrying an override.
+#[derive(Debug, Clone)]
+pub struct MethodOverride<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure a `MethodOverride` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    allowed: Vec<Method>,
+    header: Option<HeaderName>,
+    query_param: Option<String>,
+}
+
+// ===== impl MethodOverride =====
+
+impl<S> MethodOverride<S> {
+    /// Create a new `MethodOverride` with the default settings.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for MethodOverride<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if req.method() == Method::POST {
+            if let Some(method) = self.config.override_method(&req) {
+                *req.method_mut() = method;
+            }
+        }
+
+        if let Some(ref header) = self.config.header {
+            req.headers_mut().remove(header);
+        }
+
+        self.inner.call(req)
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                allowed: vec![Method::PUT, Method::PATCH, Method::DELETE],
+                header: Some(HeaderName::from_static(X_HTTP_METHOD_OVERRIDE)),
+                query_param: None,
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder reading `X-HTTP-Method-Override` and allowing
+    /// `PUT`, `PATCH` and `DELETE`.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the methods requests may be overridden to.
+    pub fn allowed_methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.config.allowed = methods.into_iter().collect();
+        self
+    }
+
+    /// Set the header to read the override from, or `None` to ignore headers.
+    pub fn header(mut self, header: Option<HeaderName>) -> Self {
+        self.config.header = header;
+        self
+    }
+
+    /// Set a query parameter to read the override from, such as `_method`.
+    pub fn query_param(mut self, name: &str) -> Self {
+        self.config.query_param = Some(name.to_owned());
+        self
+    }
+
+    /// Build the `MethodOverride` from the provided settings.
+    pub fn build<S>(self, inner: S) -> MethodOverride<S> {
+        MethodOverride {
+            inner,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+// ===== impl Config =====
+
+impl Config {
+    fn override_method<B>(&self, req: &Request<B>) -> Option<Method> {
+        let from_header = self
+            .header
+            .as_ref()
+            .and_then(|name| req.headers().get(name))
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+
+        let from_query = || {
+            let name = self.query_param.as_ref()?;
+            req.uri()
+                .query()?
+                .split('&')
+                .filter_map(|pair| {
+                    let mut pair = pair.splitn(2, '=');
+                    match (pair.next(), pair.next()) {
+                        (Some(key), Some(value)) if key == name.as_str() => Some(value.to_owned()),
+                        _ => None,
+                    }
+                })
+                .next()
+        };
+
+        let method = from_header.or_else(from_query)?;
+        let method = Method::from_bytes(method.trim().to_ascii_uppercase().as_bytes()).ok()?;
+
+        if self.allowed.contains(&method) {
+            Some(method)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,219 @@
+use super::{Labels, MakeLabels, MetricsSink, NoLabels};
+use super::{REQUEST_BODY_CHUNKS, REQUEST_BODY_SIZE, RESPONSE_BODY_CHUNKS, RESPONSE_BODY_SIZE};
+use bytes::Buf;
+use futures::{try_ready, Async, Future, Poll};
+use http::{HeaderMap, Request, Response};
+use http_body::Body;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Records the number of bytes and chunks of request and response bodies.
+///
+/// The sizes are recorded when a body ends, fails or is dropped, so a body
+/// that is not read to the end is recorded with the part that was read.
+/// The request sizes are labelled with the request labels of a
+/// `MakeLabels`, and the response sizes with its response labels as well.
+/// There are no labels by default.
+#[derive(Debug, Clone)]
+pub struct RecordBodySizes<S, M, L = NoLabels> {
+    inner: S,
+    sink: Arc<M>,
+    make_labels: Arc<L>,
+}
+
+/// A body whose size is recorded by `RecordBodySizes`.
+#[derive(Debug)]
+pub struct SizedBody<B, M>
+where
+    M: MetricsSink,
+{
+    inner: B,
+    sizes: Option<Sizes<M>>,
+}
+
+/// Response future for `RecordBodySizes`.
+#[derive(Debug)]
+pub struct BodySizeFuture<F, M, L, B> {
+    inner: F,
+    sink: Arc<M>,
+    make_labels: Arc<L>,
+    labels: Option<Labels>,
+    _marker: PhantomData<fn(B)>,
+}
+
+#[derive(Debug)]
+struct Sizes<M>
+where
+    M: MetricsSink,
+{
+    sink: Arc<M>,
+    labels: Labels,
+    names: (&'static str, &'static str),
+    bytes: u64,
+    chunks: u64,
+}
+
+// ===== impl RecordBodySizes =====
+
+impl<S, M> RecordBodySizes<S, M> {
+    /// Create a new `RecordBodySizes` reporting to `sink`.
+    pub fn new(inner: S, sink: M) -> Self {
+        Self::with_labels(inner, sink, NoLabels::new())
+    }
+}
+
+impl<S, M, L> RecordBodySizes<S, M, L> {
+    /// Create a new `RecordBodySizes` reporting to `sink`, with labels
+    /// derived by `make_labels`.
+    pub fn with_labels(inner: S, sink: M, make_labels: L) -> Self {
+        RecordBodySizes {
+            inner,
+            sink: Arc::new(sink),
+            make_labels: Arc::new(make_labels),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, M, L, ReqBody, ResBody> Service<Request<ReqBody>> for RecordBodySizes<S, M, L>
+where
+    S: Service<Request<SizedBody<ReqBody, M>>, Response = Response<ResBody>>,
+    M: MetricsSink,
+    L: MakeLabels<ReqBody>,
+{
+    type Response = Response<SizedBody<ResBody, M>>;
+    type Error = S::Error;
+    type Future = BodySizeFuture<S::Future, M, L, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let labels = self.make_labels.request_labels(&req);
+        let sizes = Sizes::new(
+            self.sink.clone(),
+            labels.clone(),
+            (REQUEST_BODY_SIZE, REQUEST_BODY_CHUNKS),
+        );
+        let req = req.map(|body| SizedBody {
+            inner: body,
+            sizes: Some(sizes),
+        });
+
+        BodySizeFuture {
+            inner: self.inner.call(req),
+            sink: self.sink.clone(),
+            make_labels: self.make_labels.clone(),
+            labels: Some(labels),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// ===== impl BodySizeFuture =====
+
+impl<F, M, L, B, ResBody> Future for BodySizeFuture<F, M, L, B>
+where
+    F: Future<Item = Response<ResBody>>,
+    M: MetricsSink,
+    L: MakeLabels<B>,
+{
+    type Item = Response<SizedBody<ResBody, M>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+
+        let mut labels = self.labels.take().expect("polled after completion");
+        self.make_labels
+            .response_labels(&mut labels, Some(response.status()));
+        let sizes = Sizes::new(
+            self.sink.clone(),
+            labels,
+            (RESPONSE_BODY_SIZE, RESPONSE_BODY_CHUNKS),
+        );
+
+        Ok(Async::Ready(response.map(|body| SizedBody {
+            inner: body,
+            sizes: Some(sizes),
+        })))
+    }
+}
+
+// ===== impl SizedBody =====
+
+impl<B, M> Body for SizedBody<B, M>
+where
+    B: Body,
+    M: MetricsSink,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let result = self.inner.poll_data();
+        match result {
+            Ok(Async::Ready(Some(ref chunk))) => {
+                if let Some(ref mut sizes) = self.sizes {
+                    sizes.bytes += chunk.remaining() as u64;
+                    sizes.chunks += 1;
+                }
+            }
+            Ok(Async::NotReady) => {}
+            _ => self.sizes = None,
+        }
+        result
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl Sizes =====
+
+impl<M> Sizes<M>
+where
+    M: MetricsSink,
+{
+    fn new(sink: Arc<M>, labels: Labels, names: (&'static str, &'static str)) -> Self {
+        Sizes {
+            sink,
+            labels,
+            names,
+            bytes: 0,
+            chunks: 0,
+        }
+    }
+}
+
+impl<M> Drop for Sizes<M>
+where
+    M: MetricsSink,
+{
+    fn drop(&mut self) {
+        let (bytes, chunks) = self.names;
+        self.sink.record_size(bytes, &self.labels, self.bytes);
+        self.sink.record_size(chunks, &self.labels, self.chunks);
+    }
+}
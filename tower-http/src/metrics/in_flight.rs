@@ -0,0 +1,176 @@
+use super::{Labels, MakeLabels, MetricsSink, NoLabels, REQUESTS_IN_FLIGHT};
+use futures::{try_ready, Async, Future, Poll};
+use http::{HeaderMap, Request, Response};
+use http_body::Body;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Maintains a gauge of the requests in flight.
+///
+/// A request is in flight from the call to the inner service until its
+/// response body has ended, failed or been dropped. The gauge is labelled
+/// with the request labels of a `MakeLabels`, none by default.
+#[derive(Debug, Clone)]
+pub struct InFlightRequests<S, M, L = NoLabels> {
+    inner: S,
+    sink: Arc<M>,
+    make_labels: Arc<L>,
+}
+
+/// Response future for `InFlightRequests`.
+#[derive(Debug)]
+pub struct InFlightFuture<F, M>
+where
+    M: MetricsSink,
+{
+    inner: F,
+    guard: Option<Guard<M>>,
+}
+
+/// A response body counting as in flight until it completes.
+#[derive(Debug)]
+pub struct InFlightBody<B, M>
+where
+    M: MetricsSink,
+{
+    inner: B,
+    guard: Option<Guard<M>>,
+}
+
+#[derive(Debug)]
+struct Guard<M>
+where
+    M: MetricsSink,
+{
+    sink: Arc<M>,
+    labels: Labels,
+}
+
+// ===== impl InFlightRequests =====
+
+impl<S, M> InFlightRequests<S, M> {
+    /// Create a new `InFlightRequests` reporting to `sink`.
+    pub fn new(inner: S, sink: M) -> Self {
+        Self::with_labels(inner, sink, NoLabels::new())
+    }
+}
+
+impl<S, M, L> InFlightRequests<S, M, L> {
+    /// Create a new `InFlightRequests` reporting to `sink`, with labels
+    /// derived by `make_labels`.
+    pub fn with_labels(inner: S, sink: M, make_labels: L) -> Self {
+        InFlightRequests {
+            inner,
+            sink: Arc::new(sink),
+            make_labels: Arc::new(make_labels),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, M, L, ReqBody, ResBody> Service<Request<ReqBody>> for InFlightRequests<S, M, L>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    M: MetricsSink,
+    L: MakeLabels<ReqBody>,
+{
+    type Response = Response<InFlightBody<ResBody, M>>;
+    type Error = S::Error;
+    type Future = InFlightFuture<S::Future, M>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let labels = self.make_labels.request_labels(&req);
+        self.sink.increment(REQUESTS_IN_FLIGHT, &labels);
+        let guard = Guard {
+            sink: self.sink.clone(),
+            labels,
+        };
+
+        InFlightFuture {
+            inner: self.inner.call(req),
+            guard: Some(guard),
+        }
+    }
+}
+
+// ===== impl InFlightFuture =====
+
+impl<F, M, B> Future for InFlightFuture<F, M>
+where
+    F: Future<Item = Response<B>>,
+    M: MetricsSink,
+{
+    type Item = Response<InFlightBody<B, M>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+        let guard = self.guard.take().expect("polled after completion");
+        Ok(Async::Ready(response.map(|body| InFlightBody {
+            inner: body,
+            guard: Some(guard),
+        })))
+    }
+}
+
+// ===== impl InFlightBody =====
+
+impl<B, M> Body for InFlightBody<B, M>
+where
+    B: Body,
+    M: MetricsSink,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let result = self.inner.poll_data();
+        match result {
+            Ok(Async::NotReady) | Ok(Async::Ready(Some(_))) => {}
+            _ => self.guard = None,
+        }
+        result
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        let result = self.inner.poll_trailers();
+        match result {
+            Ok(Async::NotReady) => {}
+            _ => self.guard = None,
+        }
+        result
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl Guard =====
+
+impl<M> Drop for Guard<M>
+where
+    M: MetricsSink,
+{
+    fn drop(&mut self) {
+        self.sink.decrement(REQUESTS_IN_FLIGHT, &self.labels);
+    }
+}
@@ -0,0 +1,171 @@
+use super::Labels;
+use http::{Method, Request, StatusCode};
+
+/// Derives the labels of the metrics of a request.
+pub trait MakeLabels<B> {
+    /// Returns the labels derived from the request.
+    fn request_labels(&self, request: &Request<B>) -> Labels;
+
+    /// Adds the labels derived from the response status, or from the
+    /// failure of the call when `status` is `None`.
+    ///
+    /// Adds a `status` label holding the class of the status, such as
+    /// `2xx`, or `error` when the call failed, by default.
+    fn response_labels(&self, labels: &mut Labels, status: Option<StatusCode>) {
+        labels.push("status", status_class(status));
+    }
+}
+
+/// Labels metrics with the method and the status class, and optionally the
+/// normalized path.
+///
+/// Clients choose the methods and paths they request, so to bound the
+/// number of series, methods other than the standard ones are labelled
+/// `other`, and paths are left out unless enabled with `with_paths`. To
+/// label metrics by route, pass a closure building the labels from the
+/// route template matched by the router instead.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultLabels {
+    paths: bool,
+}
+
+/// Labels metrics with nothing.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoLabels {
+    _p: (),
+}
+
+// ===== impl DefaultLabels =====
+
+impl DefaultLabels {
+    /// Create a new `DefaultLabels`.
+    pub fn new() -> Self {
+        DefaultLabels::default()
+    }
+
+    /// Also label metrics with the normalized path.
+    ///
+    /// Path segments looking like identifiers, that is numbers, or long
+    /// enough strings of hexadecimal digits and dashes such as UUIDs, are
+    /// replaced with `:id`. Other segments are kept, so every distinct path
+    /// requested makes new series: only enable this behind a router
+    /// rejecting unknown paths.
+    pub fn with_paths(mut self) -> Self {
+        self.paths = true;
+        self
+    }
+}
+
+impl<B> MakeLabels<B> for DefaultLabels {
+    fn request_labels(&self, request: &Request<B>) -> Labels {
+        let labels = Labels::new().with("method", method_label(request.method()));
+        if self.paths {
+            labels.with("path", normalize_path(request.uri().path()))
+        } else {
+            labels
+        }
+    }
+}
+
+// ===== impl NoLabels =====
+
+impl NoLabels {
+    /// Create a new `NoLabels`.
+    pub fn new() -> Self {
+        NoLabels::default()
+    }
+}
+
+impl<B> MakeLabels<B> for NoLabels {
+    fn request_labels(&self, _: &Request<B>) -> Labels {
+        Labels::new()
+    }
+
+    fn response_labels(&self, _: &mut Labels, _: Option<StatusCode>) {}
+}
+
+impl<B, F> MakeLabels<B> for F
+where
+    F: Fn(&Request<B>) -> Labels,
+{
+    fn request_labels(&self, request: &Request<B>) -> Labels {
+        self(request)
+    }
+}
+
+fn status_class(status: Option<StatusCode>) -> &'static str {
+    match status.map(|status| status.as_u16() / 100) {
+        Some(1) => "1xx",
+        Some(2) => "2xx",
+        Some(3) => "3xx",
+        Some(4) => "4xx",
+        Some(5) => "5xx",
+        Some(_) => "unknown",
+        None => "error",
+    }
+}
+
+fn method_label(method: &Method) -> &str {
+    match *method {
+        Method::GET
+        | Method::HEAD
+        | Method::POST
+        | Method::PUT
+        | Method::DELETE
+        | Method::CONNECT
+        | Method::OPTIONS
+        | Method::TRACE
+        | Method::PATCH => method.as_str(),
+        _ => "other",
+    }
+}
+
+fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| if is_id(segment) { ":id" } else { segment })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn is_id(segment: &str) -> bool {
+    let numeric = !segment.is_empty() && segment.bytes().all(|b| b.is_ascii_digit());
+    let hex = segment.len() >= 16
+        && segment.bytes().any(|b| b.is_ascii_digit())
+        && segment.bytes().all(|b| b.is_ascii_hexdigit() || b == b'-');
+    numeric || hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_identifiers() {
+        assert_eq!(normalize_path("/users/42/posts"), "/users/:id/posts");
+        assert_eq!(
+            normalize_path("/orders/6f1c2e9a-53d4-4b8e-9c1a-2f4d8e7b3a10"),
+            "/orders/:id"
+        );
+        assert_eq!(normalize_path("/v2/feed/"), "/v2/feed/");
+        assert_eq!(normalize_path("/"), "/");
+    }
+
+    #[test]
+    fn bounds_method_labels() {
+        assert_eq!(method_label(&Method::PATCH), "PATCH");
+        let custom = Method::from_bytes(b"PURGE").unwrap();
+        assert_eq!(method_label(&custom), "other");
+    }
+
+    #[test]
+    fn labels_status_class() {
+        let mut labels = Labels::new();
+        MakeLabels::<()>::response_labels(&DefaultLabels::new(), &mut labels, None);
+        assert_eq!(labels.get("status"), Some("error"));
+
+        let mut labels = Labels::new();
+        let status = Some(StatusCode::NOT_FOUND);
+        MakeLabels::<()>::response_labels(&DefaultLabels::new(), &mut labels, status);
+        assert_eq!(labels.get("status"), Some("4xx"));
+    }
+}
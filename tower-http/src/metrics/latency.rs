@@ -0,0 +1,119 @@
+use super::{Labels, MakeLabels, MetricsSink, NoLabels, REQUEST_DURATION};
+use futures::{Async, Future, Poll};
+use http::{Request, Response};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::time::Instant;
+use tower_service::Service;
+
+/// Records the time from receiving a request to its response headers being
+/// ready.
+///
+/// Failed calls are recorded too. The durations are labelled by a
+/// `MakeLabels`, with no labels by default.
+#[derive(Debug, Clone)]
+pub struct RecordLatency<S, M, L = NoLabels> {
+    inner: S,
+    sink: Arc<M>,
+    make_labels: Arc<L>,
+}
+
+/// Response future for `RecordLatency`.
+#[derive(Debug)]
+pub struct LatencyFuture<F, M, L, B> {
+    inner: F,
+    sink: Arc<M>,
+    make_labels: Arc<L>,
+    labels: Option<Labels>,
+    start: Instant,
+    _marker: PhantomData<fn(B)>,
+}
+
+// ===== impl RecordLatency =====
+
+impl<S, M> RecordLatency<S, M> {
+    /// Create a new `RecordLatency` reporting to `sink`.
+    pub fn new(inner: S, sink: M) -> Self {
+        Self::with_labels(inner, sink, NoLabels::new())
+    }
+}
+
+impl<S, M, L> RecordLatency<S, M, L> {
+    /// Create a new `RecordLatency` reporting to `sink`, with labels
+    /// derived by `make_labels`.
+    pub fn with_labels(inner: S, sink: M, make_labels: L) -> Self {
+        RecordLatency {
+            inner,
+            sink: Arc::new(sink),
+            make_labels: Arc::new(make_labels),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, M, L, ReqBody, ResBody> Service<Request<ReqBody>> for RecordLatency<S, M, L>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    M: MetricsSink,
+    L: MakeLabels<ReqBody>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = LatencyFuture<S::Future, M, L, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        LatencyFuture {
+            start: Instant::now(),
+            labels: Some(self.make_labels.request_labels(&req)),
+            inner: self.inner.call(req),
+            sink: self.sink.clone(),
+            make_labels: self.make_labels.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+// ===== impl LatencyFuture =====
+
+impl<F, M, L, B, ResBody> Future for LatencyFuture<F, M, L, B>
+where
+    F: Future<Item = Response<ResBody>>,
+    M: MetricsSink,
+    L: MakeLabels<B>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let result = self.inner.poll();
+        let status = match result {
+            Ok(Async::Ready(ref response)) => Some(response.status()),
+            Ok(Async::NotReady) => return result,
+            Err(_) => None,
+        };
+
+        let mut labels = self.labels.take().expect("polled after completion");
+        self.make_labels.response_labels(&mut labels, status);
+        self.sink
+            .record_duration(REQUEST_DURATION, &labels, self.start.elapsed());
+        result
+    }
+}
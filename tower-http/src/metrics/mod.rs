@@ -0,0 +1,154 @@
+//! Report request metrics to a pluggable sink.
+//!
+//! The middleware in this module report through a `MetricsSink`, a small
+//! trait implemented on top of the metrics backend in use, be it
+//! Prometheus, statsd or a custom system:
+//!
+//! - `InFlightRequests` maintains a gauge of the requests being processed,
+//!   until their response body has been sent;
+//! - `RecordLatency` records the time from receiving a request to its
+//!   response headers being ready;
+//! - `CountRequests` counts requests;
+//! - `RecordBodySizes` records the number of bytes and chunks of request
+//!   and response bodies.
+//!
+//! Metrics are identified by a name and a set of `Labels`, derived from the
+//! request and the response status by a `MakeLabels`. `DefaultLabels`
+//! provides the method and the status class, and optionally the normalized
+//! path.
+//!
+//! With the `prometheus` feature, the `prometheus` module provides a sink
+//! keeping metrics in memory and a service exposing them to Prometheus.
+
+mod body_size;
+mod count;
+mod in_flight;
+mod labels;
+mod latency;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+
+pub use self::body_size::{BodySizeFuture, RecordBodySizes, SizedBody};
+pub use self::count::{CountFuture, CountRequests};
+pub use self::in_flight::{InFlightBody, InFlightFuture, InFlightRequests};
+pub use self::labels::{DefaultLabels, MakeLabels, NoLabels};
+pub use self::latency::{LatencyFuture, RecordLatency};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The name of the gauge of requests in flight.
+pub const REQUESTS_IN_FLIGHT: &str = "http_requests_in_flight";
+
+/// The name of the request duration metric.
+pub const REQUEST_DURATION: &str = "http_request_duration_seconds";
+
+/// The name of the request counter.
+pub const REQUESTS_TOTAL: &str = "http_requests_total";
+
+/// The name of the request body size metric.
+pub const REQUEST_BODY_SIZE: &str = "http_request_body_size_bytes";
+
+/// The name of the request body chunk count metric.
+pub const REQUEST_BODY_CHUNKS: &str = "http_request_body_chunks";
+
+/// The name of the response body size metric.
+pub const RESPONSE_BODY_SIZE: &str = "http_response_body_size_bytes";
+
+/// The name of the response body chunk count metric.
+pub const RESPONSE_BODY_CHUNKS: &str = "http_response_body_chunks";
+
+/// Receives metrics from the middleware in this module.
+pub trait MetricsSink {
+    /// Increment the gauge `name`.
+    fn increment(&self, name: &'static str, labels: &Labels);
+
+    /// Decrement the gauge `name`.
+    fn decrement(&self, name: &'static str, labels: &Labels);
+
+    /// Record an observation of the duration metric `name`.
+    fn record_duration(&self, name: &'static str, labels: &Labels, duration: Duration);
+
+    /// Increment the counter `name`.
+    fn count(&self, name: &'static str, labels: &Labels);
+
+    /// Record an observation of the size metric `name`, such as a number of
+    /// bytes.
+    fn record_size(&self, name: &'static str, labels: &Labels, size: u64);
+}
+
+/// Labels distinguishing series of a metric.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Labels {
+    pairs: Vec<(&'static str, String)>,
+}
+
+// ===== impl MetricsSink =====
+
+impl<M> MetricsSink for Arc<M>
+where
+    M: MetricsSink + ?Sized,
+{
+    fn increment(&self, name: &'static str, labels: &Labels) {
+        (**self).increment(name, labels)
+    }
+
+    fn decrement(&self, name: &'static str, labels: &Labels) {
+        (**self).decrement(name, labels)
+    }
+
+    fn record_duration(&self, name: &'static str, labels: &Labels, duration: Duration) {
+        (**self).record_duration(name, labels, duration)
+    }
+
+    fn count(&self, name: &'static str, labels: &Labels) {
+        (**self).count(name, labels)
+    }
+
+    fn record_size(&self, name: &'static str, labels: &Labels, size: u64) {
+        (**self).record_size(name, labels, size)
+    }
+}
+
+// ===== impl Labels =====
+
+impl Labels {
+    /// Create an empty set of labels.
+    pub fn new() -> Self {
+        Labels::default()
+    }
+
+    /// Add the label `name` with `value`.
+    pub fn with<T: Into<String>>(mut self, name: &'static str, value: T) -> Self {
+        self.push(name, value);
+        self
+    }
+
+    /// Add the label `name` with `value`.
+    pub fn push<T: Into<String>>(&mut self, name: &'static str, value: T) {
+        self.pairs.push((name, value.into()));
+    }
+
+    /// Returns the value of the label `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|&&(n, _)| n == name)
+            .map(|(_, value)| &**value)
+    }
+
+    /// Returns an iterator over the labels, in the order they were added.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, &str)> + '_ {
+        self.pairs.iter().map(|&(name, ref value)| (name, &**value))
+    }
+
+    /// Returns the number of labels.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Returns whether there are no labels.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+}
@@ -0,0 +1,394 @@
+//! Expose metrics in the Prometheus text format.
+//!
+//! `Registry` is a `MetricsSink` keeping metrics in memory: gauges,
+//! counters, and histograms for durations and sizes. `ServeMetrics` answers
+//! requests to a path, `/metrics` by default, with the contents of a
+//! registry rendered in the Prometheus text exposition format, and passes
+//! other requests to the inner service.
+//!
+//! This module requires the `prometheus` feature.
+
+use super::{Labels, MetricsSink};
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, ALLOW, CONTENT_TYPE};
+use http::{Method, Request, Response, StatusCode};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower_service::Service;
+
+const DURATION_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const SIZE_BUCKETS: &[f64] = &[
+    1.0,
+    4.0,
+    16.0,
+    64.0,
+    256.0,
+    1024.0,
+    4096.0,
+    16384.0,
+    65536.0,
+    262_144.0,
+    1_048_576.0,
+    4_194_304.0,
+    16_777_216.0,
+];
+
+/// The content type of the Prometheus text format.
+const TEXT_FORMAT: &str = "text/plain; version=0.0.4";
+
+/// Keeps metrics in memory for rendering in the Prometheus text format.
+///
+/// Clones share the metrics, so a clone can be given to each middleware and
+/// to `ServeMetrics`.
+#[derive(Debug, Clone)]
+pub struct Registry {
+    shared: Arc<Shared>,
+}
+
+/// Serves the metrics of a `Registry` at a path.
+#[derive(Debug, Clone)]
+pub struct ServeMetrics<S> {
+    inner: S,
+    registry: Registry,
+    path: String,
+}
+
+/// Response future for `ServeMetrics`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F),
+    Served(Option<Response<B>>),
+}
+
+#[derive(Debug)]
+struct Shared {
+    duration_buckets: Vec<f64>,
+    size_buckets: Vec<f64>,
+    metrics: Mutex<Metrics>,
+}
+
+type Key = (&'static str, Labels);
+
+#[derive(Debug, Default)]
+struct Metrics {
+    gauges: BTreeMap<Key, i64>,
+    counters: BTreeMap<Key, u64>,
+    histograms: BTreeMap<Key, Histogram>,
+}
+
+#[derive(Debug)]
+struct Histogram {
+    /// The number of observations in each bucket, not cumulative.
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+// ===== impl Registry =====
+
+impl Registry {
+    /// Create a new `Registry` with the default histogram buckets.
+    ///
+    /// Durations are bucketed from 5 milliseconds to 10 seconds, and sizes
+    /// by powers of 4 from 1 to 16 MiB.
+    pub fn new() -> Self {
+        Self::with_buckets(DURATION_BUCKETS.to_vec(), SIZE_BUCKETS.to_vec())
+    }
+
+    /// Create a new `Registry` with the given upper bounds of the histogram
+    /// buckets, in seconds for durations.
+    pub fn with_buckets(mut duration_buckets: Vec<f64>, mut size_buckets: Vec<f64>) -> Self {
+        for buckets in &mut [&mut duration_buckets, &mut size_buckets] {
+            buckets.sort_by(|a, b| a.partial_cmp(b).expect("bucket bound is NaN"));
+        }
+        Registry {
+            shared: Arc::new(Shared {
+                duration_buckets,
+                size_buckets,
+                metrics: Mutex::new(Metrics::default()),
+            }),
+        }
+    }
+
+    /// Render the metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let metrics = self.shared.metrics.lock().unwrap();
+        let mut out = String::new();
+
+        let mut family = None;
+        for (&(name, ref labels), value) in &metrics.gauges {
+            type_line(&mut out, &mut family, name, "gauge");
+            sample(&mut out, name, "", labels, None, value);
+        }
+        for (&(name, ref labels), value) in &metrics.counters {
+            type_line(&mut out, &mut family, name, "counter");
+            sample(&mut out, name, "", labels, None, value);
+        }
+        for (&(name, ref labels), histogram) in &metrics.histograms {
+            type_line(&mut out, &mut family, name, "histogram");
+            let bounds = self.buckets_of(name);
+            let mut cumulative = 0;
+            for (bound, count) in bounds.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let le = bound.to_string();
+                sample(&mut out, name, "_bucket", labels, Some(&le), cumulative);
+            }
+            let count = histogram.count;
+            sample(&mut out, name, "_bucket", labels, Some("+Inf"), count);
+            sample(&mut out, name, "_sum", labels, None, histogram.sum);
+            sample(&mut out, name, "_count", labels, None, count);
+        }
+
+        out
+    }
+
+    fn buckets_of(&self, name: &str) -> &[f64] {
+        if name.ends_with("_seconds") {
+            &self.shared.duration_buckets
+        } else {
+            &self.shared.size_buckets
+        }
+    }
+
+    fn observe(&self, name: &'static str, labels: &Labels, value: f64) {
+        let bounds = self.buckets_of(name);
+        let mut metrics = self.shared.metrics.lock().unwrap();
+        let histogram = metrics
+            .histograms
+            .entry((name, labels.clone()))
+            .or_insert_with(|| Histogram {
+                buckets: vec![0; bounds.len()],
+                sum: 0.0,
+                count: 0,
+            });
+        if let Some(i) = bounds.iter().position(|&bound| value <= bound) {
+            histogram.buckets[i] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+
+    fn add_gauge(&self, name: &'static str, labels: &Labels, delta: i64) {
+        let mut metrics = self.shared.metrics.lock().unwrap();
+        *metrics.gauges.entry((name, labels.clone())).or_insert(0) += delta;
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+impl MetricsSink for Registry {
+    fn increment(&self, name: &'static str, labels: &Labels) {
+        self.add_gauge(name, labels, 1);
+    }
+
+    fn decrement(&self, name: &'static str, labels: &Labels) {
+        self.add_gauge(name, labels, -1);
+    }
+
+    fn record_duration(&self, name: &'static str, labels: &Labels, duration: Duration) {
+        let secs = duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) * 1e-9;
+        self.observe(name, labels, secs);
+    }
+
+    fn count(&self, name: &'static str, labels: &Labels) {
+        let mut metrics = self.shared.metrics.lock().unwrap();
+        *metrics.counters.entry((name, labels.clone())).or_insert(0) += 1;
+    }
+
+    fn record_size(&self, name: &'static str, labels: &Labels, size: u64) {
+        self.observe(name, labels, size as f64);
+    }
+}
+
+/// Writes the `# TYPE` line of a metric family unless it was just written.
+fn type_line(out: &mut String, family: &mut Option<&'static str>, name: &'static str, kind: &str) {
+    if *family != Some(name) {
+        let _ = writeln!(out, "# TYPE {} {}", name, kind);
+        *family = Some(name);
+    }
+}
+
+fn sample<T: std::fmt::Display>(
+    out: &mut String,
+    name: &str,
+    suffix: &str,
+    labels: &Labels,
+    le: Option<&str>,
+    value: T,
+) {
+    out.push_str(name);
+    out.push_str(suffix);
+
+    let le = le.map(|le| ("le", le));
+    let mut pairs = labels.iter().chain(le).peekable();
+    if pairs.peek().is_some() {
+        out.push('{');
+        for (i, (name, value)) in pairs.enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            out.push_str(name);
+            out.push_str("=\"");
+            for c in value.chars() {
+                match c {
+                    '\\' => out.push_str("\\\\"),
+                    '"' => out.push_str("\\\""),
+                    '\n' => out.push_str("\\n"),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+        }
+        out.push('}');
+    }
+
+    let _ = writeln!(out, " {}", value);
+}
+
+// ===== impl ServeMetrics =====
+
+impl<S> ServeMetrics<S> {
+    /// Create a new `ServeMetrics` serving the metrics of `registry` at
+    /// `/metrics`.
+    pub fn new(inner: S, registry: Registry) -> Self {
+        Self::with_path(inner, registry, "/metrics")
+    }
+
+    /// Create a new `ServeMetrics` serving the metrics of `registry` at
+    /// `path`.
+    pub fn with_path<P: Into<String>>(inner: S, registry: Registry, path: P) -> Self {
+        ServeMetrics {
+            inner,
+            registry,
+            path: path.into(),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn serve<B>(&self, method: &Method) -> Response<B>
+    where
+        B: From<Bytes>,
+    {
+        let mut res = if method == Method::GET {
+            Response::new(B::from(Bytes::from(self.registry.render())))
+        } else {
+            Response::new(B::from(Bytes::new()))
+        };
+
+        if method == Method::GET || method == Method::HEAD {
+            res.headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static(TEXT_FORMAT));
+        } else {
+            *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
+            res.headers_mut()
+                .insert(ALLOW, HeaderValue::from_static("GET, HEAD"));
+        }
+        res
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ServeMetrics<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: From<Bytes>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = if req.uri().path() == self.path {
+            State::Served(Some(self.serve(req.method())))
+        } else {
+            State::Called(self.inner.call(req))
+        };
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Called(ref mut future) => future.poll(),
+            State::Served(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_text_format() {
+        let registry = Registry::with_buckets(vec![0.1, 1.0], vec![10.0]);
+        let labels = Labels::new().with("path", "/a\"b");
+        registry.increment("in_flight", &Labels::new());
+        registry.count("requests_total", &labels);
+        registry.count("requests_total", &labels);
+        registry.record_duration("duration_seconds", &labels, Duration::from_millis(500));
+        registry.record_size("size_bytes", &Labels::new(), 20);
+
+        assert_eq!(
+            registry.render(),
+            "# TYPE in_flight gauge\n\
+             in_flight 1\n\
+             # TYPE requests_total counter\n\
+             requests_total{path=\"/a\\\"b\"} 2\n\
+             # TYPE duration_seconds histogram\n\
+             duration_seconds_bucket{path=\"/a\\\"b\",le=\"0.1\"} 0\n\
+             duration_seconds_bucket{path=\"/a\\\"b\",le=\"1\"} 1\n\
+             duration_seconds_bucket{path=\"/a\\\"b\",le=\"+Inf\"} 1\n\
+             duration_seconds_sum{path=\"/a\\\"b\"} 0.5\n\
+             duration_seconds_count{path=\"/a\\\"b\"} 1\n\
+             # TYPE size_bytes histogram\n\
+             size_bytes_bucket{le=\"10\"} 0\n\
+             size_bytes_bucket{le=\"+Inf\"} 1\n\
+             size_bytes_sum 20\n\
+             size_bytes_count 1\n"
+        );
+    }
+}
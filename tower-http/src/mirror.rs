@@ -0,0 +1,126 @@
+//! Mirror a share of the traffic to a shadow service.
+//!
+//! `Mirror` passes every request to the inner service and, for a
+//! configurable fraction of them, sends a copy to a shadow service, so that a
+//! new backend can be tested with real traffic before cutting over. The
+//! shadow responses and errors are discarded, and the shadow calls run on an
+//! executor so that they never delay the primary responses.
+//!
+//! Requests are copied with their method, URI, version, headers and body,
+//! but not their extensions. Only requests whose body is `Clone` can be
+//! mirrored; streaming bodies can be buffered into a `retry::Buffered` body
+//! beforehand. A request is not mirrored when the shadow service is not
+//! ready, nor when it is an upgrade or `CONNECT` request, as defined by the
+//! `upgrade` module, whose body carries a connection that cannot be copied.
+
+use crate::upgrade::is_upgrade_request;
+use futures::future::Executor;
+use futures::{Async, Future, Poll};
+use http::Request;
+use rand::Rng;
+use tower_service::Service;
+
+/// Sends a copy of a share of the requests to a shadow service.
+#[derive(Debug, Clone)]
+pub struct Mirror<S, T, E> {
+    inner: S,
+    shadow: T,
+    executor: E,
+    ratio: f64,
+}
+
+/// A shadow call, discarding its outcome.
+#[derive(Debug)]
+pub struct ShadowFuture<F> {
+    inner: F,
+}
+
+// ===== impl Mirror =====
+
+impl<S, T, E> Mirror<S, T, E> {
+    /// Create a new `Mirror` sending a copy of a `ratio` of the requests,
+    /// between 0 and 1, to `shadow`, and running the shadow calls on
+    /// `executor`.
+    pub fn new(inner: S, shadow: T, executor: E, ratio: f64) -> Self {
+        Mirror {
+            inner,
+            shadow,
+            executor,
+            ratio: ratio.max(0.0).min(1.0),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    /// Returns a reference to the shadow service.
+    pub fn shadow(&self) -> &T {
+        &self.shadow
+    }
+}
+
+impl<S, T, E, B> Service<Request<B>> for Mirror<S, T, E>
+where
+    S: Service<Request<B>>,
+    T: Service<Request<B>>,
+    E: Executor<ShadowFuture<T::Future>>,
+    B: Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        if self.ratio > 0.0 && !is_upgrade_request(&req) && rand::thread_rng().gen_bool(self.ratio)
+        {
+            if let Ok(Async::Ready(())) = self.shadow.poll_ready() {
+                let future = self.shadow.call(copy_request(&req));
+                // The shadow call is dropped if the executor is shut down.
+                let _ = self.executor.execute(ShadowFuture { inner: future });
+            }
+        }
+        self.inner.call(req)
+    }
+}
+
+fn copy_request<B: Clone>(req: &Request<B>) -> Request<B> {
+    let mut copy = Request::new(req.body().clone());
+    *copy.method_mut() = req.method().clone();
+    *copy.uri_mut() = req.uri().clone();
+    *copy.version_mut() = req.version();
+    *copy.headers_mut() = req.headers().clone();
+    copy
+}
+
+// ===== impl ShadowFuture =====
+
+impl<F> Future for ShadowFuture<F>
+where
+    F: Future,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<(), ()> {
+        match self.inner.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(_)) | Err(_) => Ok(Async::Ready(())),
+        }
+    }
+}
@@ -0,0 +1,244 @@
+//! Middleware that negotiates the media type of responses.
+//!
+//! `Negotiate` chooses, among the media types the inner service can
+//! produce, the one the client prefers according to the `Accept` header of
+//! the request, and stores it in a `Negotiated` request extension for the
+//! inner service to honour. Requests accepting none of the producible types
+//! are answered with `406 Not Acceptable`. Responses get `Vary: Accept`, as
+//! their contents depend on that header.
+//!
+//! Each producible type takes the quality of the most specific range
+//! matching it, `text/html` before `text/*` before `*/*`; ties are broken
+//! by the order in which the types were configured. Requests without a
+//! valid `Accept` get the first type.
+
+use crate::util::MediaType;
+use crate::vary;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderValue, ACCEPT};
+use http::{Request, Response, StatusCode};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Chooses the media type of responses according to `Accept`.
+#[derive(Debug, Clone)]
+pub struct Negotiate<S> {
+    inner: S,
+    produces: Arc<Vec<Producible>>,
+}
+
+/// The media type chosen for the response, stored as a request extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Negotiated {
+    /// The chosen media type, as configured, fit for `Content-Type`.
+    pub media_type: HeaderValue,
+}
+
+/// Response future for `Negotiate`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+struct Producible {
+    media_type: MediaType,
+    value: HeaderValue,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F),
+    NotAcceptable(Option<Response<B>>),
+}
+
+// ===== impl Negotiate =====
+
+impl<S> Negotiate<S> {
+    /// Create a new `Negotiate` choosing among `media_types`, from the most
+    /// to the least preferred by the server.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a media type is invalid or is a range.
+    pub fn new<I>(inner: S, media_types: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        let produces = media_types
+            .into_iter()
+            .map(|media_type| {
+                let media_type = media_type.as_ref();
+                let parsed = MediaType::parse(media_type)
+                    .filter(|parsed| !parsed.essence.contains('*'))
+                    .expect("invalid media type");
+                Producible {
+                    media_type: parsed,
+                    value: HeaderValue::from_str(media_type).expect("invalid media type"),
+                }
+            })
+            .collect();
+        Negotiate {
+            inner,
+            produces: Arc::new(produces),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Negotiate<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let state = match choose(&self.produces, req.headers()) {
+            Some(producible) => {
+                req.extensions_mut().insert(Negotiated {
+                    media_type: producible.value.clone(),
+                });
+                State::Called(self.inner.call(req))
+            }
+            None => {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::NOT_ACCEPTABLE;
+                State::NotAcceptable(Some(res))
+            }
+        };
+        ResponseFuture { state }
+    }
+}
+
+/// Returns the producible type preferred by the `Accept` header of
+/// `headers`.
+fn choose<'a>(produces: &'a [Producible], headers: &HeaderMap) -> Option<&'a Producible> {
+    let ranges: Vec<(MediaType, u16)> = headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|range| {
+            let range = MediaType::parse(range)?;
+            let quality = range.param("q").map_or(Some(1000), parse_quality)?;
+            Some((range, quality))
+        })
+        .collect();
+    // Requests without a valid `Accept` accept anything.
+    if ranges.is_empty() {
+        return produces.first();
+    }
+
+    let mut best: Option<(&Producible, u16)> = None;
+    for producible in produces {
+        let quality = ranges
+            .iter()
+            .filter(|&(range, _)| matches(range, &producible.media_type))
+            .max_by_key(|&(range, _)| specificity(range))
+            .map_or(0, |&(_, quality)| quality);
+        if quality > best.map_or(0, |(_, best)| best) {
+            best = Some((producible, quality));
+        }
+    }
+    best.map(|(producible, _)| producible)
+}
+
+/// Returns whether `range` includes `media_type`, with all its parameters
+/// other than `q`.
+fn matches(range: &MediaType, media_type: &MediaType) -> bool {
+    media_type.is_within(&range.essence)
+        && range
+            .params
+            .iter()
+            .filter(|&(name, _)| name != "q")
+            .all(|(name, value)| {
+                media_type
+                    .param(name)
+                    .map_or(false, |param| param.eq_ignore_ascii_case(value))
+            })
+}
+
+/// Ranks ranges from `*/*` to specific types with parameters.
+fn specificity(range: &MediaType) -> usize {
+    if range.essence == "*/*" {
+        0
+    } else if range.essence.ends_with("/*") {
+        1
+    } else {
+        2 + range.params.iter().filter(|&(name, _)| name != "q").count()
+    }
+}
+
+/// Parses a quality value into thousandths.
+fn parse_quality(q: &str) -> Option<u16> {
+    let mut parts = q.splitn(2, '.');
+    let int = parts.next()?;
+    let frac = parts.next().unwrap_or("");
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = format!("{:0<3}", frac).parse::<u16>().ok()?;
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = match self.state {
+            State::Called(ref mut future) => try_ready!(future.poll()),
+            State::NotAcceptable(ref mut res) => res.take().expect("polled after completion"),
+        };
+        vary::append_vary(res.headers_mut(), &[ACCEPT]);
+        Ok(Async::Ready(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_qualities() {
+        assert_eq!(parse_quality("1"), Some(1000));
+        assert_eq!(parse_quality("1.000"), Some(1000));
+        assert_eq!(parse_quality("0.5"), Some(500));
+        assert_eq!(parse_quality("0.05"), Some(50));
+        assert_eq!(parse_quality("0"), Some(0));
+        assert_eq!(parse_quality("1.5"), None);
+        assert_eq!(parse_quality("0.1234"), None);
+        assert_eq!(parse_quality("x"), None);
+    }
+}
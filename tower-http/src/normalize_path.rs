@@ -0,0 +1,242 @@
+//! Middleware that normalizes the path of requests.
+//!
+//! `NormalizePath` collapses runs of slashes in request paths, so that
+//! `//api///users` reaches the inner service as `/api/users`, and can append
+//! or trim trailing slashes. By default requests are rewritten in place;
+//! in redirect mode, requests whose path is not normal are instead answered
+//! with `308 Permanent Redirect` to the normal path, keeping the query, so
+//! that clients and caches learn a single URL for each resource.
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, LOCATION};
+use http::uri::{PathAndQuery, Uri};
+use http::{Request, Response, StatusCode};
+use tower_service::Service;
+
+/// Normalizes the path of requests.
+#[derive(Debug, Clone)]
+pub struct NormalizePath<S> {
+    inner: S,
+    trailing_slash: TrailingSlash,
+    redirect: bool,
+}
+
+/// Configure a `NormalizePath` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    trailing_slash: TrailingSlash,
+    redirect: bool,
+}
+
+/// What to do with trailing slashes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// Leave trailing slashes as they are.
+    Keep,
+    /// Add a trailing slash to paths lacking one.
+    Append,
+    /// Remove trailing slashes, except from the root path.
+    Trim,
+}
+
+/// Response future for `NormalizePath`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F),
+    Redirect(Option<Response<B>>),
+}
+
+// ===== impl NormalizePath =====
+
+impl<S> NormalizePath<S> {
+    /// Create a new `NormalizePath` collapsing slashes and rewriting
+    /// requests in place.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for NormalizePath<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let path = normalize(req.uri().path(), self.trailing_slash);
+        if path == req.uri().path() {
+            return ResponseFuture {
+                state: State::Called(self.inner.call(req)),
+            };
+        }
+
+        let mut path_and_query = path;
+        if let Some(query) = req.uri().query() {
+            path_and_query.push('?');
+            path_and_query.push_str(query);
+        }
+
+        if self.redirect {
+            // Some browsers read backslashes as slashes, which would make a
+            // location such as `/\evil.com` relative to the scheme.
+            let location = match path_and_query.find('?') {
+                Some(i) => path_and_query[..i].replace('\\', "%5C") + &path_and_query[i..],
+                None => path_and_query.replace('\\', "%5C"),
+            };
+            if let Ok(location) = HeaderValue::from_str(&location) {
+                let mut res = Response::new(ResBody::default());
+                *res.status_mut() = StatusCode::PERMANENT_REDIRECT;
+                res.headers_mut().insert(LOCATION, location);
+                return ResponseFuture {
+                    state: State::Redirect(Some(res)),
+                };
+            }
+        } else if let Some(uri) = with_path_and_query(req.uri(), path_and_query) {
+            *req.uri_mut() = uri;
+        }
+
+        ResponseFuture {
+            state: State::Called(self.inner.call(req)),
+        }
+    }
+}
+
+/// Collapses runs of slashes in `path` and applies `trailing_slash`.
+fn normalize(path: &str, trailing_slash: TrailingSlash) -> String {
+    let mut normal = String::with_capacity(path.len() + 1);
+    for c in path.chars() {
+        if c != '/' || !normal.ends_with('/') {
+            normal.push(c);
+        }
+    }
+    match trailing_slash {
+        TrailingSlash::Keep => {}
+        TrailingSlash::Append => {
+            if !normal.ends_with('/') {
+                normal.push('/');
+            }
+        }
+        TrailingSlash::Trim => {
+            if normal.len() > 1 && normal.ends_with('/') {
+                normal.pop();
+            }
+        }
+    }
+    normal
+}
+
+/// Returns `uri` with its path and query replaced.
+fn with_path_and_query(uri: &Uri, path_and_query: String) -> Option<Uri> {
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::from_shared(Bytes::from(path_and_query)).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            trailing_slash: TrailingSlash::Keep,
+            redirect: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder collapsing slashes and rewriting requests in
+    /// place.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set what to do with trailing slashes.
+    ///
+    /// Defaults to `TrailingSlash::Keep`.
+    pub fn trailing_slash(mut self, trailing_slash: TrailingSlash) -> Self {
+        self.trailing_slash = trailing_slash;
+        self
+    }
+
+    /// Answer requests whose path is not normal with
+    /// `308 Permanent Redirect` to the normal path, rather than rewriting
+    /// them.
+    pub fn redirect(mut self, redirect: bool) -> Self {
+        self.redirect = redirect;
+        self
+    }
+
+    /// Build the `NormalizePath` from the provided settings.
+    pub fn build<S>(self, inner: S) -> NormalizePath<S> {
+        NormalizePath {
+            inner,
+            trailing_slash: self.trailing_slash,
+            redirect: self.redirect,
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Called(ref mut future) => future.poll(),
+            State::Redirect(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_paths() {
+        use self::TrailingSlash::*;
+
+        assert_eq!(normalize("/", Keep), "/");
+        assert_eq!(normalize("//a///b//", Keep), "/a/b/");
+        assert_eq!(normalize("/a//b", Append), "/a/b/");
+        assert_eq!(normalize("/a/b/", Append), "/a/b/");
+        assert_eq!(normalize("/a//b//", Trim), "/a/b");
+        assert_eq!(normalize("//", Trim), "/");
+        assert_eq!(normalize("/", Append), "/");
+    }
+}
@@ -0,0 +1,256 @@
+//! Middleware that canonicalizes the query string of requests.
+//!
+//! The same resource is often requested with query strings differing only
+//! in the order of their parameters, in tracking parameters such as
+//! `utm_source` added by links, or in the spelling of empty values.
+//! `NormalizeQuery` rewrites the query of requests before they reach the
+//! inner service, so that caches and routing see a single form:
+//!
+//! - parameters are sorted by name, keeping the order of repeated names;
+//! - configured parameters, such as `utm_*`, are removed;
+//! - empty values, as in `a=`, are written as `a`, or removed altogether;
+//! - empty pairs, as in `a&&b`, are removed.
+//!
+//! The original query is kept in an `OriginalQuery` request extension.
+
+use crate::util::with_query;
+use futures::Poll;
+use http::Request;
+use percent_encoding::percent_decode;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Canonicalizes the query string of requests.
+#[derive(Debug, Clone)]
+pub struct NormalizeQuery<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure a `NormalizeQuery` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+/// What to do with parameters whose value is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmptyValues {
+    /// Leave `a=` and `a` as they are.
+    Keep,
+    /// Write `a=` as `a`.
+    Collapse,
+    /// Remove `a=` and `a`.
+    Remove,
+}
+
+/// The query of a request before it was normalized, stored as a request
+/// extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginalQuery(Option<String>);
+
+#[derive(Debug, Clone)]
+struct Config {
+    sort: bool,
+    strip: Vec<String>,
+    empty_values: EmptyValues,
+}
+
+// ===== impl NormalizeQuery =====
+
+impl<S> NormalizeQuery<S> {
+    /// Create a new `NormalizeQuery` sorting parameters and collapsing
+    /// empty values.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for NormalizeQuery<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let original = req.uri().query().map(str::to_owned);
+        if let Some(ref query) = original {
+            let query = self.config.normalize(query);
+            if let Some(uri) = with_query(req.uri(), &query) {
+                *req.uri_mut() = uri;
+            }
+        }
+        req.extensions_mut().insert(OriginalQuery(original));
+        self.inner.call(req)
+    }
+}
+
+// ===== impl Config =====
+
+impl Config {
+    fn normalize(&self, query: &str) -> String {
+        let mut pairs: Vec<(&str, Option<&str>)> = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let mut pair = pair.splitn(2, '=');
+                (pair.next().unwrap_or(""), pair.next())
+            })
+            .filter(|&(name, _)| !self.strips(name))
+            .filter_map(|(name, value)| match (self.empty_values, value) {
+                (EmptyValues::Keep, _) => Some((name, value)),
+                (_, Some(value)) if !value.is_empty() => Some((name, Some(value))),
+                (EmptyValues::Collapse, _) => Some((name, None)),
+                (EmptyValues::Remove, _) => None,
+            })
+            .collect();
+        if self.sort {
+            // Sorting is stable, keeping repeated names in order.
+            pairs.sort_by_key(|&(name, _)| name);
+        }
+
+        let mut normal = String::with_capacity(query.len());
+        for (name, value) in pairs {
+            if !normal.is_empty() {
+                normal.push('&');
+            }
+            normal.push_str(name);
+            if let Some(value) = value {
+                normal.push('=');
+                normal.push_str(value);
+            }
+        }
+        normal
+    }
+
+    /// Returns whether the parameter `name` must be removed.
+    fn strips(&self, name: &str) -> bool {
+        let name = percent_decode(name.as_bytes()).decode_utf8_lossy();
+        self.strip.iter().any(|pattern| {
+            if pattern.ends_with('*') {
+                name.starts_with(&pattern[..pattern.len() - 1])
+            } else {
+                name == pattern.as_str()
+            }
+        })
+    }
+}
+
+// ===== impl OriginalQuery =====
+
+impl OriginalQuery {
+    /// Returns the original query, or `None` if the request had none.
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_ref().map(|query| query.as_str())
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                sort: true,
+                strip: Vec::new(),
+                empty_values: EmptyValues::Collapse,
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder sorting parameters and collapsing empty
+    /// values.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Sort the parameters by name.
+    ///
+    /// Defaults to `true`.
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.config.sort = sort;
+        self
+    }
+
+    /// Remove the parameter `name`, or, if `name` ends with `*` as in
+    /// `utm_*`, the parameters starting with the rest of `name`.
+    pub fn strip<T: Into<String>>(mut self, name: T) -> Self {
+        self.config.strip.push(name.into());
+        self
+    }
+
+    /// Remove common tracking parameters: `utm_*`, `fbclid`, `gclid`,
+    /// `dclid`, `msclkid`, `mc_cid` and `mc_eid`.
+    pub fn strip_tracking(self) -> Self {
+        [
+            "utm_*", "fbclid", "gclid", "dclid", "msclkid", "mc_cid", "mc_eid",
+        ]
+        .iter()
+        .fold(self, |builder, &name| builder.strip(name))
+    }
+
+    /// Set what to do with parameters whose value is empty.
+    ///
+    /// Defaults to `EmptyValues::Collapse`.
+    pub fn empty_values(mut self, empty_values: EmptyValues) -> Self {
+        self.config.empty_values = empty_values;
+        self
+    }
+
+    /// Build the `NormalizeQuery` from the provided settings.
+    pub fn build<S>(self, inner: S) -> NormalizeQuery<S> {
+        NormalizeQuery {
+            inner,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalizes_queries() {
+        let config = Builder::new().strip_tracking().config;
+        assert_eq!(config.normalize("b=2&a=1&b=1"), "a=1&b=2&b=1");
+        assert_eq!(config.normalize("a=&&b"), "a&b");
+        assert_eq!(
+            config.normalize("utm_source=x&q=rust&utm%5Fmedium=y"),
+            "q=rust"
+        );
+        assert_eq!(config.normalize("gclid=abc"), "");
+
+        let config = Builder::new()
+            .sort(false)
+            .empty_values(EmptyValues::Remove)
+            .config;
+        assert_eq!(config.normalize("b=2&a=&c&a=1"), "b=2&a=1");
+
+        let config = Builder::new().empty_values(EmptyValues::Keep).config;
+        assert_eq!(config.normalize("b=&a"), "a&b=");
+    }
+}
@@ -0,0 +1,125 @@
+//! Middleware that answers `OPTIONS` requests.
+//!
+//! `Options` answers `OPTIONS` requests, including the server-wide
+//! `OPTIONS *`, with `204 No Content` and an `Allow` header listing the
+//! methods allowed for the resource, so that handlers do not have to
+//! expect them. Other requests are passed to the inner service.
+//!
+//! The allowed methods are either configured, or taken from a
+//! `MethodFilter` wrapped with `Options::for_filter`. `OPTIONS` itself is
+//! always listed. CORS preflight requests are `OPTIONS` requests as well,
+//! so CORS middleware must be placed outside of `Options`.
+
+use crate::method_filter::{self, MethodFilter};
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, ALLOW};
+use http::{Method, Request, Response, StatusCode};
+use tower_service::Service;
+
+/// Answers `OPTIONS` requests with the allowed methods.
+#[derive(Debug, Clone)]
+pub struct Options<S> {
+    inner: S,
+    allow: HeaderValue,
+}
+
+/// Response future for `Options`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Called(F),
+    Answered(Option<Response<B>>),
+}
+
+// ===== impl Options =====
+
+impl<S> Options<S> {
+    /// Create a new `Options` listing the methods in `methods`.
+    pub fn new<I>(inner: S, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        let mut allowed = Vec::new();
+        for method in methods.into_iter().chain(Some(Method::OPTIONS)) {
+            if !allowed.contains(&method) {
+                allowed.push(method);
+            }
+        }
+        Options {
+            inner,
+            allow: method_filter::allow_header(&allowed),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S> Options<MethodFilter<S>> {
+    /// Create a new `Options` listing the methods allowed by `filter`.
+    pub fn for_filter(filter: MethodFilter<S>) -> Self {
+        let methods = filter.allowed_methods().to_vec();
+        Options::new(filter, methods)
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Options<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = if req.method() == Method::OPTIONS {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::NO_CONTENT;
+            res.headers_mut().insert(ALLOW, self.allow.clone());
+            State::Answered(Some(res))
+        } else {
+            State::Called(self.inner.call(req))
+        };
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Called(ref mut future) => future.poll(),
+            State::Answered(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
@@ -0,0 +1,299 @@
+//! Enforce `If-Match` and `If-Unmodified-Since` on state-changing requests.
+//!
+//! `CheckPreconditions` evaluates the preconditions of requests with unsafe
+//! methods, such as `PUT` and `DELETE`, against the validators of the
+//! current representation of the target resource, supplied by a
+//! `CurrentValidators` implementation. Requests whose preconditions fail are
+//! answered with `412 Precondition Failed`, carrying the current `ETag` and
+//! `Last-Modified`, without calling the inner service. This makes lost
+//! updates impossible for clients sending back the tag they read, i.e.
+//! optimistic concurrency control.
+//!
+//! `If-Match` is evaluated with strong comparison; `If-Unmodified-Since` is
+//! only evaluated without `If-Match`, as RFC 7232 requires. Requests without
+//! preconditions are passed through unless preconditions are required, in
+//! which case they are answered with `428 Precondition Required`.
+//!
+//! As the validators are looked up asynchronously, the inner service must be
+//! `Clone`.
+
+use crate::etag;
+use futures::{Async, Future, IntoFuture, Poll};
+use http::header::{HeaderValue, ETAG, IF_MATCH, IF_UNMODIFIED_SINCE, LAST_MODIFIED};
+use http::{Method, Request, Response, StatusCode};
+use std::time::SystemTime;
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Answers requests whose preconditions fail with `412 Precondition Failed`.
+#[derive(Debug, Clone)]
+pub struct CheckPreconditions<S, V> {
+    inner: S,
+    validators: V,
+    require: bool,
+}
+
+/// The validators of the current representation of a resource.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// The entity tag, quotes included, such as `"v1"`.
+    pub etag: Option<String>,
+    /// The time of the last modification.
+    pub last_modified: Option<SystemTime>,
+}
+
+/// Looks up the validators of the current representation of the resource
+/// targeted by a request.
+pub trait CurrentValidators<B> {
+    /// Errors produced when looking up validators.
+    type Error;
+
+    /// Resolves to the validators, or `None` if the resource has no current
+    /// representation.
+    type Future: Future<Item = Option<Validators>, Error = Self::Error>;
+
+    /// Look up the validators for `req`.
+    fn current(&mut self, req: &Request<B>) -> Self::Future;
+}
+
+/// Response future for `CheckPreconditions`.
+pub struct ResponseFuture<S, V, B>
+where
+    S: Service<Request<B>>,
+    V: CurrentValidators<B>,
+{
+    state: State<S, V::Future, S::Future, B>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<S, V, F, B> {
+    Checking {
+        check: V,
+        service: S,
+        request: Request<B>,
+    },
+    Called(F),
+    Done,
+}
+
+// ===== impl CheckPreconditions =====
+
+impl<S, V> CheckPreconditions<S, V> {
+    /// Create a new `CheckPreconditions` looking up validators with
+    /// `validators`.
+    pub fn new(inner: S, validators: V) -> Self {
+        CheckPreconditions {
+            inner,
+            validators,
+            require: false,
+        }
+    }
+
+    /// Answer state-changing requests without preconditions with
+    /// `428 Precondition Required`.
+    pub fn require(mut self, require: bool) -> Self {
+        self.require = require;
+        self
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, V, ReqBody, ResBody> Service<Request<ReqBody>> for CheckPreconditions<S, V>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    S::Error: From<V::Error>,
+    V: CurrentValidators<ReqBody>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, V, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let conditional =
+            req.headers().contains_key(IF_MATCH) || req.headers().contains_key(IF_UNMODIFIED_SINCE);
+        let state = if is_safe(req.method()) || (!conditional && !self.require) {
+            State::Called(self.inner.call(req))
+        } else {
+            let clone = self.inner.clone();
+            State::Checking {
+                check: self.validators.current(&req),
+                service: mem::replace(&mut self.inner, clone),
+                request: req,
+            }
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+fn is_safe(method: &Method) -> bool {
+    *method == Method::GET
+        || *method == Method::HEAD
+        || *method == Method::OPTIONS
+        || *method == Method::TRACE
+}
+
+/// Evaluates the preconditions of `req`, returning the status to answer
+/// with if they fail.
+fn evaluate<B>(req: &Request<B>, current: Option<&Validators>) -> Option<StatusCode> {
+    let headers = req.headers();
+    if headers.contains_key(IF_MATCH) {
+        let matches = current.map_or(false, |current| {
+            let any = headers
+                .get_all(IF_MATCH)
+                .iter()
+                .any(|value| value.as_bytes() == b"*");
+            any || current.etag.as_ref().map_or(false, |etag| {
+                etag::list_matches(headers.get_all(IF_MATCH), etag, false)
+            })
+        });
+        return if matches {
+            None
+        } else {
+            Some(StatusCode::PRECONDITION_FAILED)
+        };
+    }
+
+    if let Some(value) = headers.get(IF_UNMODIFIED_SINCE) {
+        // Invalid dates are ignored.
+        let since = value
+            .to_str()
+            .ok()
+            .and_then(|value| httpdate::parse_http_date(value).ok());
+        let last_modified = current.and_then(|current| current.last_modified);
+        if let (Some(since), Some(last_modified)) = (since, last_modified) {
+            if last_modified > since {
+                return Some(StatusCode::PRECONDITION_FAILED);
+            }
+        }
+        return None;
+    }
+
+    Some(StatusCode::PRECONDITION_REQUIRED)
+}
+
+// ===== impl CurrentValidators =====
+
+impl<F, Fut, B> CurrentValidators<B> for F
+where
+    F: FnMut(&Request<B>) -> Fut,
+    Fut: IntoFuture<Item = Option<Validators>>,
+{
+    type Error = Fut::Error;
+    type Future = Fut::Future;
+
+    fn current(&mut self, req: &Request<B>) -> Self::Future {
+        self(req).into_future()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, V, ReqBody, ResBody> Future for ResponseFuture<S, V, ReqBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: From<V::Error>,
+    V: CurrentValidators<ReqBody>,
+    ResBody: Default,
+{
+    type Item = S::Response;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Checking {
+                    mut check,
+                    mut service,
+                    request,
+                } => {
+                    let current = match check.poll()? {
+                        Async::Ready(current) => current,
+                        Async::NotReady => {
+                            self.state = State::Checking {
+                                check,
+                                service,
+                                request,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+
+                    let status = match evaluate(&request, current.as_ref()) {
+                        Some(status) => status,
+                        None => {
+                            self.state = State::Called(service.call(request));
+                            continue;
+                        }
+                    };
+                    let mut res = Response::new(ResBody::default());
+                    *res.status_mut() = status;
+                    if let Some(current) = current {
+                        set_validators(&mut res, &current);
+                    }
+                    return Ok(Async::Ready(res));
+                }
+                State::Called(mut future) => {
+                    let result = future.poll();
+                    if let Ok(Async::NotReady) = result {
+                        self.state = State::Called(future);
+                    }
+                    return result;
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+fn set_validators<B>(res: &mut Response<B>, current: &Validators) {
+    let etag = current
+        .etag
+        .as_ref()
+        .and_then(|etag| HeaderValue::from_str(etag).ok());
+    if let Some(etag) = etag {
+        res.headers_mut().insert(ETAG, etag);
+    }
+    if let Some(last_modified) = current.last_modified {
+        let last_modified = httpdate::fmt_http_date(last_modified);
+        let last_modified =
+            HeaderValue::from_str(&last_modified).expect("HTTP dates are valid header values");
+        res.headers_mut().insert(LAST_MODIFIED, last_modified);
+    }
+}
+
+impl<S, V, B> fmt::Debug for ResponseFuture<S, V, B>
+where
+    S: Service<Request<B>>,
+    V: CurrentValidators<B>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Checking { .. } => "Checking",
+            State::Called(_) => "Called",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
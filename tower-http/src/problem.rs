@@ -0,0 +1,312 @@
+//! Middleware that reports errors as RFC 7807 problem details.
+//!
+//! `ProblemJson` turns the errors of the inner service, and the bodiless
+//! error responses produced by rejecting middleware such as
+//! `RequireContentType` or `AllowedHosts`, into
+//! `application/problem+json` responses, so that API clients get
+//! consistent, machine-readable errors. A `MapProblem` implementation
+//! decides which `Problem` describes each error; it can inspect the
+//! extensions of rejections, such as `api_version::UnsupportedVersion`, to
+//! give details. The default only reports the status, and never exposes
+//! error messages.
+//!
+//! Error responses which already have a body or a `Content-Type` are left
+//! alone.
+
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use http::response::Parts;
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::fmt::Write;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// The media type of problem details.
+pub const APPLICATION_PROBLEM_JSON: &str = "application/problem+json";
+
+/// Reports errors as `application/problem+json` responses.
+#[derive(Debug, Clone)]
+pub struct ProblemJson<S, M = DefaultMapProblem> {
+    inner: S,
+    mapper: Arc<M>,
+}
+
+/// A problem details object, as defined by RFC 7807.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    status: StatusCode,
+    type_uri: Option<String>,
+    title: Option<String>,
+    detail: Option<String>,
+    instance: Option<String>,
+}
+
+/// Describes errors and rejections as problems.
+pub trait MapProblem<E> {
+    /// Describe an error of the inner service, or return `None` to let it
+    /// propagate.
+    ///
+    /// Defaults to a `500 Internal Server Error` problem without details.
+    fn map_error(&self, error: &E) -> Option<Problem> {
+        let _ = error;
+        Some(Problem::new(StatusCode::INTERNAL_SERVER_ERROR))
+    }
+
+    /// Describe a bodiless response, or return `None` to leave it alone.
+    ///
+    /// Defaults to a problem of the same status for client and server
+    /// errors.
+    fn map_response(&self, parts: &Parts) -> Option<Problem> {
+        if parts.status.is_client_error() || parts.status.is_server_error() {
+            Some(Problem::new(parts.status))
+        } else {
+            None
+        }
+    }
+}
+
+/// Reports the status of errors only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultMapProblem {
+    _p: (),
+}
+
+/// Response future for `ProblemJson`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, M> {
+    inner: F,
+    mapper: Arc<M>,
+}
+
+// ===== impl ProblemJson =====
+
+impl<S> ProblemJson<S> {
+    /// Create a new `ProblemJson` only reporting the status of errors.
+    pub fn new(inner: S) -> Self {
+        Self::with_mapper(inner, DefaultMapProblem::default())
+    }
+}
+
+impl<S, M> ProblemJson<S, M> {
+    /// Create a new `ProblemJson` describing errors with `mapper`.
+    pub fn with_mapper(inner: S, mapper: M) -> Self {
+        ProblemJson {
+            inner,
+            mapper: Arc::new(mapper),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, M, ReqBody, ResBody> Service<Request<ReqBody>> for ProblemJson<S, M>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    M: MapProblem<S::Error>,
+    ResBody: Body + From<Bytes>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, M>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            mapper: self.mapper.clone(),
+        }
+    }
+}
+
+// ===== impl Problem =====
+
+impl Problem {
+    /// Create a new problem of status `status`, titled with the canonical
+    /// reason of the status.
+    pub fn new(status: StatusCode) -> Self {
+        Problem {
+            status,
+            type_uri: None,
+            title: status.canonical_reason().map(str::to_owned),
+            detail: None,
+            instance: None,
+        }
+    }
+
+    /// Set the URI identifying the type of the problem.
+    ///
+    /// Defaults to `about:blank`, for problems described by their status
+    /// only.
+    pub fn type_uri<T: Into<String>>(mut self, type_uri: T) -> Self {
+        self.type_uri = Some(type_uri.into());
+        self
+    }
+
+    /// Set the short, human-readable summary of the type of the problem.
+    pub fn title<T: Into<String>>(mut self, title: T) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Set the human-readable explanation of this occurrence of the
+    /// problem.
+    pub fn detail<T: Into<String>>(mut self, detail: T) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Set the URI identifying this occurrence of the problem.
+    pub fn instance<T: Into<String>>(mut self, instance: T) -> Self {
+        self.instance = Some(instance.into());
+        self
+    }
+
+    /// Returns the status of the problem.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Serializes the problem as JSON.
+    pub fn to_json(&self) -> String {
+        let mut json = String::from("{\"type\":");
+        push_json_string(
+            &mut json,
+            self.type_uri.as_ref().map_or("about:blank", |s| s),
+        );
+        if let Some(ref title) = self.title {
+            json.push_str(",\"title\":");
+            push_json_string(&mut json, title);
+        }
+        write!(json, ",\"status\":{}", self.status.as_u16()).unwrap();
+        if let Some(ref detail) = self.detail {
+            json.push_str(",\"detail\":");
+            push_json_string(&mut json, detail);
+        }
+        if let Some(ref instance) = self.instance {
+            json.push_str(",\"instance\":");
+            push_json_string(&mut json, instance);
+        }
+        json.push('}');
+        json
+    }
+
+    /// Returns an `application/problem+json` response describing the
+    /// problem.
+    pub fn into_response<B: From<Bytes>>(self) -> Response<B> {
+        let mut res = Response::new(B::from(Bytes::from(self.to_json())));
+        *res.status_mut() = self.status;
+        res.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(APPLICATION_PROBLEM_JSON),
+        );
+        res
+    }
+}
+
+/// Appends `s` to `json` as a JSON string.
+fn push_json_string(json: &mut String, s: &str) {
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+}
+
+// ===== impl DefaultMapProblem =====
+
+impl<E> MapProblem<E> for DefaultMapProblem {}
+
+// ===== impl ResponseFuture =====
+
+impl<F, M, B> Future for ResponseFuture<F, M>
+where
+    F: Future<Item = Response<B>>,
+    M: MapProblem<F::Error>,
+    B: Body + From<Bytes>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let res = match self.inner.poll() {
+            Ok(Async::Ready(res)) => res,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                return match self.mapper.map_error(&e) {
+                    Some(problem) => Ok(Async::Ready(problem.into_response())),
+                    None => Err(e),
+                };
+            }
+        };
+
+        if !res.body().is_end_stream() || res.headers().contains_key(CONTENT_TYPE) {
+            return Ok(Async::Ready(res));
+        }
+        let (mut parts, body) = res.into_parts();
+        let problem = match self.mapper.map_response(&parts) {
+            Some(problem) => problem,
+            None => return Ok(Async::Ready(Response::from_parts(parts, body))),
+        };
+
+        // Keep the other headers of the rejection, such as `Allow`.
+        parts.status = problem.status;
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static(APPLICATION_PROBLEM_JSON),
+        );
+        let res = Response::from_parts(parts, B::from(Bytes::from(problem.to_json())));
+        Ok(Async::Ready(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serializes_problems() {
+        assert_eq!(
+            Problem::new(StatusCode::NOT_FOUND).to_json(),
+            "{\"type\":\"about:blank\",\"title\":\"Not Found\",\"status\":404}"
+        );
+        let problem = Problem::new(StatusCode::BAD_REQUEST)
+            .type_uri("https://example.com/probs/version")
+            .title("Unsupported version")
+            .detail("Version \"3\" is not supported.\n")
+            .instance("/v3/users");
+        assert_eq!(
+            problem.to_json(),
+            "{\"type\":\"https://example.com/probs/version\",\
+             \"title\":\"Unsupported version\",\"status\":400,\
+             \"detail\":\"Version \\\"3\\\" is not supported.\\n\",\
+             \"instance\":\"/v3/users\"}"
+        );
+    }
+}
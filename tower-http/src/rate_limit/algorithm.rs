@@ -0,0 +1,316 @@
+use super::Decision;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// A rate limiting algorithm.
+///
+/// The algorithm keeps a `State` per key and decides whether a request is
+/// allowed, updating the state.
+pub trait Algorithm {
+    /// The state kept per key.
+    type State;
+
+    /// Create the state of a key seen for the first time.
+    fn new_state(&self, now: Instant) -> Self::State;
+
+    /// Check a request against `state` at `now`, consuming quota if the
+    /// request is allowed.
+    fn check(&self, state: &mut Self::State, now: Instant) -> Decision;
+
+    /// Whether `state` is indistinguishable from a fresh state at `now`, so
+    /// it can be discarded.
+    fn is_idle(&self, state: &Self::State, now: Instant) -> bool;
+}
+
+/// Allows `limit` requests per fixed window of `period`.
+///
+/// Cheap, but allows bursts of up to twice the limit around window
+/// boundaries.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedWindow {
+    limit: u64,
+    period: Duration,
+}
+
+/// A bucket of `capacity` tokens, refilled evenly over `period`.
+///
+/// Allows bursts of up to `capacity` requests and a steady rate of
+/// `capacity` requests per `period`.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenBucket {
+    capacity: u64,
+    period: Duration,
+}
+
+/// Allows `limit` requests in any window of `period`, keeping a log of
+/// request times.
+///
+/// Exact, at the cost of memory proportional to `limit` per key.
+#[derive(Debug, Clone, Copy)]
+pub struct SlidingWindowLog {
+    limit: u64,
+    period: Duration,
+}
+
+/// The state of a `TokenBucket` key.
+#[derive(Debug, Clone, Copy)]
+pub struct Bucket {
+    /// The number of tokens times `period` in nanoseconds, so that refills
+    /// are exact integer arithmetic.
+    scaled_tokens: u128,
+    updated: Instant,
+}
+
+// ===== impl FixedWindow =====
+
+impl FixedWindow {
+    /// Create a new `FixedWindow` allowing `limit` requests per `period`.
+    pub fn new(limit: u64, period: Duration) -> Self {
+        FixedWindow { limit, period }
+    }
+}
+
+impl Algorithm for FixedWindow {
+    type State = (Instant, u64);
+
+    fn new_state(&self, now: Instant) -> Self::State {
+        (now, 0)
+    }
+
+    fn check(&self, window: &mut Self::State, now: Instant) -> Decision {
+        if now.duration_since(window.0) >= self.period {
+            *window = (now, 0);
+        }
+
+        let allowed = window.1 < self.limit;
+        if allowed {
+            window.1 += 1;
+        }
+
+        Decision {
+            allowed,
+            limit: self.limit,
+            remaining: self.limit - window.1,
+            reset: self.period - now.duration_since(window.0),
+        }
+    }
+
+    fn is_idle(&self, window: &Self::State, now: Instant) -> bool {
+        now.duration_since(window.0) >= self.period
+    }
+}
+
+// ===== impl TokenBucket =====
+
+impl TokenBucket {
+    /// Create a new `TokenBucket` holding `capacity` tokens, refilled evenly
+    /// over `period`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `period` is zero.
+    pub fn new(capacity: u64, period: Duration) -> Self {
+        assert!(period > Duration::from_secs(0), "period must be positive");
+        TokenBucket { capacity, period }
+    }
+
+    fn period_nanos(&self) -> u128 {
+        u128::from(self.period.as_secs()) * 1_000_000_000 + u128::from(self.period.subsec_nanos())
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.duration_since(bucket.updated);
+        let elapsed =
+            u128::from(elapsed.as_secs()) * 1_000_000_000 + u128::from(elapsed.subsec_nanos());
+        let full = u128::from(self.capacity) * self.period_nanos();
+        // Each elapsed nanosecond adds `capacity / period` tokens.
+        let added = elapsed.saturating_mul(u128::from(self.capacity));
+        bucket.scaled_tokens = bucket.scaled_tokens.saturating_add(added).min(full);
+        bucket.updated = now;
+    }
+}
+
+impl Algorithm for TokenBucket {
+    type State = Bucket;
+
+    fn new_state(&self, now: Instant) -> Bucket {
+        Bucket {
+            scaled_tokens: u128::from(self.capacity) * self.period_nanos(),
+            updated: now,
+        }
+    }
+
+    fn check(&self, bucket: &mut Bucket, now: Instant) -> Decision {
+        self.refill(bucket, now);
+
+        let token = self.period_nanos();
+        let allowed = bucket.scaled_tokens >= token;
+        if allowed {
+            bucket.scaled_tokens -= token;
+        }
+
+        // Time until the next token if denied, or until the bucket is full.
+        let target = if allowed {
+            u128::from(self.capacity) * token
+        } else {
+            token
+        };
+        let missing = target.saturating_sub(bucket.scaled_tokens);
+        let wait_nanos = if self.capacity == 0 {
+            token
+        } else {
+            (missing + u128::from(self.capacity) - 1) / u128::from(self.capacity)
+        };
+
+        Decision {
+            allowed,
+            limit: self.capacity,
+            remaining: (bucket.scaled_tokens / token) as u64,
+            reset: Duration::new(
+                (wait_nanos / 1_000_000_000) as u64,
+                (wait_nanos % 1_000_000_000) as u32,
+            ),
+        }
+    }
+
+    fn is_idle(&self, bucket: &Bucket, now: Instant) -> bool {
+        let mut bucket = *bucket;
+        self.refill(&mut bucket, now);
+        bucket.scaled_tokens == u128::from(self.capacity) * self.period_nanos()
+    }
+}
+
+// ===== impl SlidingWindowLog =====
+
+impl SlidingWindowLog {
+    /// Create a new `SlidingWindowLog` allowing `limit` requests in any
+    /// window of `period`.
+    pub fn new(limit: u64, period: Duration) -> Self {
+        SlidingWindowLog { limit, period }
+    }
+
+    fn prune(&self, log: &mut VecDeque<Instant>, now: Instant) {
+        while log
+            .front()
+            .map_or(false, |&t| now.duration_since(t) >= self.period)
+        {
+            log.pop_front();
+        }
+    }
+}
+
+impl Algorithm for SlidingWindowLog {
+    type State = VecDeque<Instant>;
+
+    fn new_state(&self, _now: Instant) -> Self::State {
+        VecDeque::new()
+    }
+
+    fn check(&self, log: &mut Self::State, now: Instant) -> Decision {
+        self.prune(log, now);
+
+        let allowed = (log.len() as u64) < self.limit;
+        if allowed {
+            log.push_back(now);
+        }
+
+        // The quota grows again when the oldest logged request leaves the
+        // window.
+        let reset = log.front().map_or(Duration::from_secs(0), |&t| {
+            self.period - now.duration_since(t)
+        });
+
+        Decision {
+            allowed,
+            limit: self.limit,
+            remaining: self.limit - log.len() as u64,
+            reset,
+        }
+    }
+
+    fn is_idle(&self, log: &Self::State, now: Instant) -> bool {
+        log.back()
+            .map_or(true, |&t| now.duration_since(t) >= self.period)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn allowed<A: Algorithm>(algorithm: &A, state: &mut A::State, now: Instant) -> bool {
+        algorithm.check(state, now).allowed
+    }
+
+    #[test]
+    fn fixed_window() {
+        let algorithm = FixedWindow::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut state = algorithm.new_state(start);
+
+        assert!(allowed(&algorithm, &mut state, start));
+        assert!(allowed(&algorithm, &mut state, start));
+        assert!(!allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(9)
+        ));
+        assert!(allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(10)
+        ));
+    }
+
+    #[test]
+    fn token_bucket() {
+        let algorithm = TokenBucket::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut state = algorithm.new_state(start);
+
+        assert!(allowed(&algorithm, &mut state, start));
+        assert!(allowed(&algorithm, &mut state, start));
+        let decision = algorithm.check(&mut state, start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_secs(5));
+        // A token is refilled every five seconds.
+        assert!(allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(5)
+        ));
+        assert!(!allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(6)
+        ));
+        assert!(algorithm.is_idle(&state, start + Duration::from_secs(15)));
+    }
+
+    #[test]
+    fn sliding_window_log() {
+        let algorithm = SlidingWindowLog::new(2, Duration::from_secs(10));
+        let start = Instant::now();
+        let mut state = algorithm.new_state(start);
+
+        assert!(allowed(&algorithm, &mut state, start));
+        assert!(allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(5)
+        ));
+        let decision = algorithm.check(&mut state, start + Duration::from_secs(9));
+        assert!(!decision.allowed);
+        assert_eq!(decision.reset, Duration::from_secs(1));
+        assert!(allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(10)
+        ));
+        assert!(!allowed(
+            &algorithm,
+            &mut state,
+            start + Duration::from_secs(14)
+        ));
+    }
+}
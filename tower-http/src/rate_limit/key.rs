@@ -0,0 +1,88 @@
+use crate::forwarded::ClientInfo;
+use http::header::HeaderName;
+use http::Request;
+use std::net::SocketAddr;
+
+/// Extracts the key a request is rate limited by.
+///
+/// Requests without a key are not rate limited.
+pub trait KeyExtractor<B> {
+    /// Returns the key of `req`.
+    fn extract(&self, req: &Request<B>) -> Option<String>;
+}
+
+/// Keys requests by client IP address.
+///
+/// The address is read from the `ClientInfo` extension inserted by
+/// `forwarded::SetClientInfo`, falling back to the `SocketAddr` extension of
+/// the peer.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ClientIp {
+    _p: (),
+}
+
+/// Keys requests by the value of a header, such as an API key.
+#[derive(Debug, Clone)]
+pub struct HeaderKey {
+    name: HeaderName,
+}
+
+/// Keys requests by their path.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathKey {
+    _p: (),
+}
+
+impl ClientIp {
+    /// Create a new `ClientIp` extractor.
+    pub fn new() -> Self {
+        ClientIp::default()
+    }
+}
+
+impl<B> KeyExtractor<B> for ClientIp {
+    fn extract(&self, req: &Request<B>) -> Option<String> {
+        let ip = req
+            .extensions()
+            .get::<ClientInfo>()
+            .and_then(|info| info.ip)
+            .or_else(|| req.extensions().get::<SocketAddr>().map(|addr| addr.ip()))?;
+        Some(ip.to_string())
+    }
+}
+
+impl HeaderKey {
+    /// Create a new `HeaderKey` extractor reading header `name`.
+    pub fn new(name: HeaderName) -> Self {
+        HeaderKey { name }
+    }
+}
+
+impl<B> KeyExtractor<B> for HeaderKey {
+    fn extract(&self, req: &Request<B>) -> Option<String> {
+        let value = req.headers().get(&self.name)?.to_str().ok()?;
+        Some(value.to_owned())
+    }
+}
+
+impl PathKey {
+    /// Create a new `PathKey` extractor.
+    pub fn new() -> Self {
+        PathKey::default()
+    }
+}
+
+impl<B> KeyExtractor<B> for PathKey {
+    fn extract(&self, req: &Request<B>) -> Option<String> {
+        Some(req.uri().path().to_owned())
+    }
+}
+
+impl<B, F> KeyExtractor<B> for F
+where
+    F: Fn(&Request<B>) -> Option<String>,
+{
+    fn extract(&self, req: &Request<B>) -> Option<String> {
+        self(req)
+    }
+}
@@ -0,0 +1,307 @@
+//! Rate limit requests by key.
+//!
+//! `RateLimit` allows each key a quota of requests decided by an
+//! `Algorithm`: `FixedWindow` by default, `TokenBucket` for a steady rate
+//! with bounded bursts, or `SlidingWindowLog` for exact windows. Keys are
+//! extracted from requests by a `KeyExtractor`, such as `ClientIp`,
+//! `HeaderKey` or `PathKey`. Requests over the limit are answered with
+//! `429 Too Many Requests` and a `Retry-After` header, without calling the
+//! inner service.
+//!
+//! Counters live in a `RateLimitStore`. `MemoryStore` keeps them in
+//! memory; multi-instance deployments can implement the trait on top of a
+//! shared store. As checking the store is asynchronous, the inner service
+//! must be `Clone`. Store and inner service errors are reported as
+//! `RateLimitError::Store` and `RateLimitError::Inner` respectively.
+//!
+//! All responses to limited requests carry the `RateLimit-Limit`,
+//! `RateLimit-Remaining` and `RateLimit-Reset` headers of the IETF
+//! `RateLimit` header fields draft.
+
+mod algorithm;
+mod key;
+mod store;
+
+pub use self::algorithm::{Algorithm, Bucket, FixedWindow, SlidingWindowLog, TokenBucket};
+pub use self::key::{ClientIp, HeaderKey, KeyExtractor, PathKey};
+pub use self::store::{MemoryStore, RateLimitStore};
+
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use http::{Request, Response, StatusCode};
+use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// The `RateLimit-Limit` header name.
+pub const RATELIMIT_LIMIT: &str = "ratelimit-limit";
+
+/// The `RateLimit-Remaining` header name.
+pub const RATELIMIT_REMAINING: &str = "ratelimit-remaining";
+
+/// The `RateLimit-Reset` header name.
+pub const RATELIMIT_RESET: &str = "ratelimit-reset";
+
+/// Limits the rate of requests per key, responding `429` when exceeded.
+#[derive(Debug, Clone)]
+pub struct RateLimit<S, K, T = MemoryStore> {
+    inner: S,
+    extractor: Arc<K>,
+    store: T,
+}
+
+/// The outcome of checking a request against the limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    /// Whether the request is allowed.
+    pub allowed: bool,
+    /// The number of requests allowed per period.
+    pub limit: u64,
+    /// The number of requests remaining in the current period.
+    pub remaining: u64,
+    /// The time until the quota is replenished.
+    pub reset: Duration,
+}
+
+/// Error returned by `RateLimit`.
+#[derive(Debug)]
+pub enum RateLimitError<E, S> {
+    /// The inner service failed.
+    Inner(E),
+    /// The store failed to check the limit.
+    Store(S),
+}
+
+/// Response future for `RateLimit`.
+pub struct ResponseFuture<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: RateLimitStore,
+{
+    state: State<S, T, B>,
+    decision: Option<Decision>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: RateLimitStore,
+{
+    Checking {
+        check: T::Future,
+        service: S,
+        request: Request<B>,
+    },
+    Called(S::Future),
+    Done,
+}
+
+// ===== impl RateLimit =====
+
+impl<S, K> RateLimit<S, K> {
+    /// Create a new `RateLimit` allowing `limit` requests per fixed window
+    /// of `period` for each key extracted by `extractor`.
+    pub fn new(inner: S, extractor: K, limit: u64, period: Duration) -> Self {
+        Self::with_algorithm(inner, extractor, FixedWindow::new(limit, period))
+    }
+}
+
+impl<S, K, A> RateLimit<S, K, MemoryStore<A>>
+where
+    A: Algorithm,
+{
+    /// Create a new `RateLimit` limiting each key extracted by `extractor`
+    /// with `algorithm`, keeping counters in memory.
+    pub fn with_algorithm(inner: S, extractor: K, algorithm: A) -> Self {
+        Self::with_store(inner, extractor, MemoryStore::new(algorithm))
+    }
+}
+
+impl<S, K, T> RateLimit<S, K, T> {
+    /// Create a new `RateLimit` limiting each key extracted by `extractor`
+    /// with the counters in `store`.
+    pub fn with_store(inner: S, extractor: K, store: T) -> Self {
+        RateLimit {
+            inner,
+            extractor: Arc::new(extractor),
+            store,
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, K, T, ReqBody, ResBody> Service<Request<ReqBody>> for RateLimit<S, K, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    K: KeyExtractor<ReqBody>,
+    T: RateLimitStore,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = RateLimitError<S::Error, T::Error>;
+    type Future = ResponseFuture<S, T, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(RateLimitError::Inner)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = match self.extractor.extract(&req) {
+            Some(key) => {
+                let clone = self.inner.clone();
+                State::Checking {
+                    check: self.store.check(key),
+                    service: mem::replace(&mut self.inner, clone),
+                    request: req,
+                }
+            }
+            None => State::Called(self.inner.call(req)),
+        };
+
+        ResponseFuture {
+            state,
+            decision: None,
+        }
+    }
+}
+
+// ===== impl Decision =====
+
+impl Decision {
+    /// Set the `RateLimit-*` headers describing this decision.
+    pub fn set_headers(&self, headers: &mut HeaderMap) {
+        let pairs = [
+            (RATELIMIT_LIMIT, self.limit),
+            (RATELIMIT_REMAINING, self.remaining),
+            (RATELIMIT_RESET, ceil_secs(self.reset)),
+        ];
+        for &(name, value) in &pairs {
+            headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+        }
+    }
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + if duration.subsec_nanos() > 0 { 1 } else { 0 }
+}
+
+// ===== impl RateLimitError =====
+
+impl<E: fmt::Display, S: fmt::Display> fmt::Display for RateLimitError<E, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RateLimitError::Inner(ref e) => fmt::Display::fmt(e, f),
+            RateLimitError::Store(ref e) => write!(f, "rate limit store failed: {}", e),
+        }
+    }
+}
+
+impl<E, S> Error for RateLimitError<E, S>
+where
+    E: Error + 'static,
+    S: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            RateLimitError::Inner(ref e) => Some(e),
+            RateLimitError::Store(ref e) => Some(e),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, T, ReqBody, ResBody> Future for ResponseFuture<S, T, ReqBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    T: RateLimitStore,
+    ResBody: Default,
+{
+    type Item = S::Response;
+    type Error = RateLimitError<S::Error, T::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Checking {
+                    mut check,
+                    mut service,
+                    request,
+                } => {
+                    let decision = match check.poll().map_err(RateLimitError::Store)? {
+                        Async::Ready(decision) => decision,
+                        Async::NotReady => {
+                            self.state = State::Checking {
+                                check,
+                                service,
+                                request,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    self.decision = Some(decision);
+
+                    if !decision.allowed {
+                        let mut res = Response::new(ResBody::default());
+                        *res.status_mut() = StatusCode::TOO_MANY_REQUESTS;
+                        res.headers_mut()
+                            .insert(RETRY_AFTER, HeaderValue::from(ceil_secs(decision.reset)));
+                        decision.set_headers(res.headers_mut());
+                        return Ok(Async::Ready(res));
+                    }
+
+                    self.state = State::Called(service.call(request));
+                }
+                State::Called(mut future) => {
+                    let mut response = match future.poll().map_err(RateLimitError::Inner)? {
+                        Async::Ready(response) => response,
+                        Async::NotReady => {
+                            self.state = State::Called(future);
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    if let Some(ref decision) = self.decision {
+                        decision.set_headers(response.headers_mut());
+                    }
+                    return Ok(Async::Ready(response));
+                }
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, T, B> fmt::Debug for ResponseFuture<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: RateLimitStore,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Checking { .. } => "Checking",
+            State::Called(_) => "Called",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .field("decision", &self.decision)
+            .finish()
+    }
+}
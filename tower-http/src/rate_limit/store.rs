@@ -0,0 +1,112 @@
+use super::{Algorithm, Decision, FixedWindow};
+use futures::future::{self, FutureResult};
+use futures::Future;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Storage for rate limit counters.
+///
+/// Implement this trait to share counters between instances, e.g. in
+/// Redis. The store is responsible for applying its algorithm atomically.
+pub trait RateLimitStore {
+    /// Errors produced by the store.
+    type Error;
+
+    /// Future returned by `check`.
+    type Future: Future<Item = Decision, Error = Self::Error>;
+
+    /// Check a request with `key` against its limit, consuming quota if the
+    /// request is allowed.
+    fn check(&self, key: String) -> Self::Future;
+}
+
+/// An in-memory `RateLimitStore`.
+///
+/// Clones share the same counters.
+#[derive(Clone)]
+pub struct MemoryStore<A = FixedWindow>
+where
+    A: Algorithm,
+{
+    shared: Arc<Shared<A>>,
+}
+
+struct Shared<A: Algorithm> {
+    algorithm: A,
+    states: Mutex<States<A::State>>,
+}
+
+struct States<T> {
+    map: HashMap<String, T>,
+    checks: u64,
+}
+
+impl<A: Algorithm> MemoryStore<A> {
+    /// Create a new `MemoryStore` limiting keys with `algorithm`.
+    pub fn new(algorithm: A) -> Self {
+        MemoryStore {
+            shared: Arc::new(Shared {
+                algorithm,
+                states: Mutex::new(States {
+                    map: HashMap::new(),
+                    checks: 0,
+                }),
+            }),
+        }
+    }
+
+    /// Check a request with `key` at `now`.
+    ///
+    /// `now` must not be earlier than the time of previous checks.
+    pub fn check_at(&self, key: String, now: Instant) -> Decision {
+        self.check_with(key, || now)
+    }
+
+    fn check_with<F>(&self, key: String, now: F) -> Decision
+    where
+        F: FnOnce() -> Instant,
+    {
+        let algorithm = &self.shared.algorithm;
+        let mut states = self.shared.states.lock().unwrap();
+        // Read the clock under the lock, so that checks never see a time
+        // earlier than the one a previous check stored.
+        let now = now();
+
+        // Drop idle states now and then so the map does not grow with every
+        // key ever seen.
+        states.checks += 1;
+        if states.checks % 1024 == 0 {
+            states.map.retain(|_, state| !algorithm.is_idle(state, now));
+        }
+
+        let state = states
+            .map
+            .entry(key)
+            .or_insert_with(|| algorithm.new_state(now));
+        algorithm.check(state, now)
+    }
+}
+
+impl<A: Algorithm> RateLimitStore for MemoryStore<A> {
+    type Error = std::convert::Infallible;
+    type Future = FutureResult<Decision, Self::Error>;
+
+    fn check(&self, key: String) -> Self::Future {
+        future::ok(self.check_with(key, Instant::now))
+    }
+}
+
+impl<A> fmt::Debug for MemoryStore<A>
+where
+    A: Algorithm + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keys = self.shared.states.lock().unwrap().map.len();
+        f.debug_struct("MemoryStore")
+            .field("algorithm", &self.shared.algorithm)
+            .field("keys", &keys)
+            .finish()
+    }
+}
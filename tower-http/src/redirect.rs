@@ -0,0 +1,126 @@
+//! Service that answers every request with a redirect.
+//!
+//! `Redirect` is a leaf service, meant to be placed in routing tables next
+//! to the services producing actual responses, so that moved resources
+//! need no hand-built responses. It ignores the request and answers with
+//! the configured status and a `Location` header.
+
+use futures::future::{self, FutureResult};
+use futures::{Async, Poll};
+use http::header::{HeaderValue, LOCATION};
+use http::{Request, Response, StatusCode, Uri};
+use std::convert::Infallible;
+use std::fmt;
+use std::marker::PhantomData;
+use tower_service::Service;
+
+/// Answers every request with a redirect to a fixed location.
+pub struct Redirect<ResBody> {
+    status: StatusCode,
+    location: HeaderValue,
+    _marker: PhantomData<fn() -> ResBody>,
+}
+
+// ===== impl Redirect =====
+
+impl<ResBody> Redirect<ResBody> {
+    /// Redirect with `303 See Other`.
+    ///
+    /// Clients follow the redirect with a `GET` request, whatever the
+    /// method of the original request, so this suits answering form
+    /// submissions.
+    pub fn to(uri: Uri) -> Self {
+        Self::with_status(StatusCode::SEE_OTHER, uri)
+    }
+
+    /// Redirect with `307 Temporary Redirect`.
+    ///
+    /// Clients repeat the original request, method and body included.
+    pub fn temporary(uri: Uri) -> Self {
+        Self::with_status(StatusCode::TEMPORARY_REDIRECT, uri)
+    }
+
+    /// Redirect with `308 Permanent Redirect`.
+    ///
+    /// Clients repeat the original request, method and body included, and
+    /// may remember the new location.
+    pub fn permanent(uri: Uri) -> Self {
+        Self::with_status(StatusCode::PERMANENT_REDIRECT, uri)
+    }
+
+    /// Redirect with `302 Found`.
+    ///
+    /// Clients may turn other requests than `GET` into `GET` requests;
+    /// prefer `to` or `temporary`, unless very old clients must be
+    /// supported.
+    pub fn found(uri: Uri) -> Self {
+        Self::with_status(StatusCode::FOUND, uri)
+    }
+
+    /// Redirect with `301 Moved Permanently`.
+    ///
+    /// Clients may turn other requests than `GET` into `GET` requests;
+    /// prefer `permanent`, unless very old clients must be supported.
+    pub fn moved_permanently(uri: Uri) -> Self {
+        Self::with_status(StatusCode::MOVED_PERMANENTLY, uri)
+    }
+
+    fn with_status(status: StatusCode, uri: Uri) -> Self {
+        let location =
+            HeaderValue::from_str(&uri.to_string()).expect("URIs are valid header values");
+        Redirect {
+            status,
+            location,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the status of the redirects.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the `Location` of the redirects.
+    pub fn location(&self) -> &HeaderValue {
+        &self.location
+    }
+}
+
+impl<ReqBody, ResBody> Service<Request<ReqBody>> for Redirect<ResBody>
+where
+    ResBody: Default,
+{
+    type Response = Response<ResBody>;
+    type Error = Infallible;
+    type Future = FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _req: Request<ReqBody>) -> Self::Future {
+        let mut res = Response::new(ResBody::default());
+        *res.status_mut() = self.status;
+        res.headers_mut().insert(LOCATION, self.location.clone());
+        future::ok(res)
+    }
+}
+
+impl<ResBody> Clone for Redirect<ResBody> {
+    fn clone(&self) -> Self {
+        Redirect {
+            status: self.status,
+            location: self.location.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<ResBody> fmt::Debug for Redirect<ResBody> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Redirect")
+            .field("status", &self.status)
+            .field("location", &self.location)
+            .finish()
+    }
+}
@@ -0,0 +1,244 @@
+//! Middleware that rejects request bodies of unexpected media types.
+//!
+//! `RequireContentType` checks the `Content-Type` of requests using the
+//! checked methods, `POST`, `PUT` and `PATCH` by default, against a list of
+//! allowed media types, and answers mismatching requests with
+//! `415 Unsupported Media Type`, so that handlers can assume their input is
+//! of a type they understand. Requests without a body may omit
+//! `Content-Type`; requests with a body must carry one.
+//!
+//! Allowed types may be ranges such as `text/*`, and may be allowed for a
+//! single method. Parameters are ignored, except `charset` when a charset is
+//! required.
+
+use crate::util::MediaType;
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, TRANSFER_ENCODING};
+use http::{Method, Request, Response, StatusCode};
+use http_body::Body;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Rejects requests whose `Content-Type` is not allowed.
+#[derive(Debug, Clone)]
+pub struct RequireContentType<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure a `RequireContentType` instance.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+/// Response future for `RequireContentType`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    methods: Vec<Method>,
+    /// The allowed media ranges, with the method they are restricted to.
+    allowed: Vec<(Option<Method>, String)>,
+    charset: Option<String>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+// ===== impl RequireContentType =====
+
+impl<S> RequireContentType<S> {
+    /// Create a new `RequireContentType` allowing the media types in
+    /// `media_types` in `POST`, `PUT` and `PATCH` requests.
+    pub fn new<I>(inner: S, media_types: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        media_types
+            .into_iter()
+            .fold(Builder::new(), |builder, media_type| {
+                builder.allow(media_type.as_ref())
+            })
+            .build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for RequireContentType<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ReqBody: Body,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = if self.config.accepts(&req) {
+            State::Accepted(self.inner.call(req))
+        } else {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::UNSUPPORTED_MEDIA_TYPE;
+            State::Rejected(Some(res))
+        };
+        ResponseFuture { state }
+    }
+}
+
+/// Returns whether the request has no body, according to its headers or
+/// its body.
+fn is_empty<B: Body>(headers: &HeaderMap, body: &B) -> bool {
+    if body.is_end_stream() {
+        return true;
+    }
+    !headers.contains_key(TRANSFER_ENCODING)
+        && headers
+            .get(CONTENT_LENGTH)
+            .map_or(false, |len| len.as_bytes() == b"0")
+}
+
+// ===== impl Config =====
+
+impl Config {
+    fn accepts<B: Body>(&self, req: &Request<B>) -> bool {
+        let method = req.method();
+        if !self.methods.contains(method) {
+            return true;
+        }
+
+        let media_type = match req.headers().get(CONTENT_TYPE) {
+            Some(value) => match value.to_str().ok().and_then(MediaType::parse) {
+                Some(media_type) => media_type,
+                None => return false,
+            },
+            None => return is_empty(req.headers(), req.body()),
+        };
+
+        if let Some(ref charset) = self.charset {
+            match media_type.param("charset") {
+                Some(param) if param.eq_ignore_ascii_case(charset) => {}
+                _ => return false,
+            }
+        }
+
+        self.allowed.iter().any(|(only, range)| {
+            only.as_ref().map_or(true, |only| only == method) && media_type.is_within(range)
+        })
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                methods: vec![Method::POST, Method::PUT, Method::PATCH],
+                allowed: Vec::new(),
+                charset: None,
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder checking `POST`, `PUT` and `PATCH` requests and
+    /// allowing no media type.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Check the requests using the methods in `methods`, rather than
+    /// `POST`, `PUT` and `PATCH` requests.
+    pub fn methods<I>(mut self, methods: I) -> Self
+    where
+        I: IntoIterator<Item = Method>,
+    {
+        self.config.methods = methods.into_iter().collect();
+        self
+    }
+
+    /// Allow the media type, or media range such as `text/*`,
+    /// `media_type` in all checked requests.
+    pub fn allow(mut self, media_type: &str) -> Self {
+        self.config
+            .allowed
+            .push((None, media_type.trim().to_ascii_lowercase()));
+        self
+    }
+
+    /// Allow the media type, or media range, `media_type` in requests using
+    /// `method` only.
+    ///
+    /// Requests using `method` are checked from then on.
+    pub fn allow_for(mut self, method: Method, media_type: &str) -> Self {
+        if !self.config.methods.contains(&method) {
+            self.config.methods.push(method.clone());
+        }
+        self.config
+            .allowed
+            .push((Some(method), media_type.trim().to_ascii_lowercase()));
+        self
+    }
+
+    /// Require the `charset` parameter of the `Content-Type` to be
+    /// `charset`, compared case-insensitively.
+    pub fn require_charset<T: Into<String>>(mut self, charset: T) -> Self {
+        self.config.charset = Some(charset.into());
+        self
+    }
+
+    /// Build the `RequireContentType` from the provided settings.
+    pub fn build<S>(self, inner: S) -> RequireContentType<S> {
+        RequireContentType {
+            inner,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
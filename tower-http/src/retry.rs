@@ -0,0 +1,590 @@
+//! An HTTP-aware retry policy for `tower-retry`.
+//!
+//! `RetryPolicy` retries a request when:
+//!
+//! - the method is idempotent, or the request carries the `Retryable`
+//!   extension;
+//! - the response is `502 Bad Gateway`, `503 Service Unavailable` or
+//!   `504 Gateway Timeout`, or is classified as a failure by the
+//!   `ClassifyResponse` given to `RetryPolicy::classifier`;
+//! - or the call failed, which for HTTP clients usually means a connection
+//!   error;
+//! - fewer than the maximum number of retries have been made.
+//!
+//! Retries wait for an exponential backoff with full jitter, or for the
+//! duration of the response's `Retry-After` header when present. A
+//! `Retry-After` longer than the maximum backoff ends the retries instead.
+//!
+//! Only requests whose body is `Clone` can be retried. Streaming bodies can
+//! be buffered into a `Buffered` body beforehand.
+//!
+//! A `RetryBudget` shared by clones of the policy limits retries to a
+//! fraction of the requests plus a minimum rate, so that an outage does not
+//! multiply the load on the upstream. An overall deadline stops retries that
+//! could not complete in time.
+//!
+//! `Builder` assembles a complete stack: a `tower_retry::Retry` around a
+//! per-attempt `timeout::Timeout`, inside a `Timeout` enforcing the overall
+//! deadline. Both timeouts answer with `504 Gateway Timeout`, which is itself
+//! retried while the deadline and budget allow.
+
+use crate::classify::{ClassifyResponse, FailureClass, SharedClassifier};
+use crate::timeout::Timeout;
+use bytes::Bytes;
+use futures::{Async, Future, Poll};
+use http::header::RETRY_AFTER;
+use http::{HeaderMap, Method, Request, Response, StatusCode};
+use http_body::Body;
+use rand::Rng;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio_timer::Delay;
+use tower_retry::{Policy, Retry};
+
+/// Retries idempotent requests on gateway errors and failed calls.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_retries: usize,
+    attempts: usize,
+    base: Duration,
+    max_backoff: Duration,
+    budget: Option<RetryBudget>,
+    deadline: Option<Duration>,
+    classifier: SharedClassifier,
+}
+
+/// A token bucket limiting the rate of retries.
+///
+/// Each request deposits `ratio` tokens and each retry withdraws one. The
+/// bucket is also refilled with `min_per_sec` tokens per second, so that
+/// some retries are allowed even at low traffic. Clones share the bucket.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    shared: Arc<BudgetShared>,
+}
+
+#[derive(Debug)]
+struct BudgetShared {
+    ratio: f64,
+    min_per_sec: f64,
+    capacity: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+/// Configure a retrying stack with per-attempt and overall timeouts.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    policy: RetryPolicy,
+    attempt_timeout: Duration,
+    deadline: Duration,
+}
+
+/// The service built by `Builder`.
+pub type RetryService<S> = Timeout<Retry<RetryPolicy, Timeout<S>>>;
+
+/// The overall deadline of a request, carried by its retained clone.
+#[derive(Debug, Clone, Copy)]
+struct Deadline(Instant);
+
+/// Marks a request as safe to retry regardless of its method.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Retryable;
+
+/// A fully buffered body that can be cloned for retries.
+#[derive(Debug, Clone, Default)]
+pub struct Buffered {
+    data: Option<Bytes>,
+}
+
+/// Waits for the backoff before the next attempt.
+#[derive(Debug)]
+pub struct RetryFuture {
+    delay: Delay,
+    policy: Option<RetryPolicy>,
+}
+
+// ===== impl RetryPolicy =====
+
+impl RetryPolicy {
+    /// Create a new `RetryPolicy` making at most `max_retries` retries, with
+    /// a backoff starting at 100 milliseconds and capped at 10 seconds.
+    pub fn new(max_retries: usize) -> Self {
+        RetryPolicy {
+            max_retries,
+            attempts: 0,
+            base: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            budget: None,
+            deadline: None,
+            classifier: SharedClassifier::new(GatewayErrors),
+        }
+    }
+
+    /// Set the classifier deciding which responses are retried.
+    ///
+    /// Defaults to retrying `502`, `503` and `504` responses. Failed calls
+    /// are always retried.
+    pub fn classifier<C>(mut self, classifier: C) -> Self
+    where
+        C: ClassifyResponse + Send + Sync + 'static,
+    {
+        self.classifier = SharedClassifier::new(classifier);
+        self
+    }
+
+    /// Limit retries with `budget`.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Do not start retries that would begin later than `deadline` after
+    /// the first attempt.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Set the backoff of the first retry, doubled for each subsequent one
+    /// up to `max`.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base = base;
+        self.max_backoff = max;
+        self
+    }
+
+    /// Returns the number of retries made so far.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+
+    fn is_retryable<B>(&self, req: &Request<B>) -> bool {
+        self.attempts < self.max_retries
+            && (is_idempotent(req.method()) || req.extensions().get::<Retryable>().is_some())
+    }
+
+    /// The backoff before the next retry, without jitter.
+    fn backoff_ceiling(&self) -> Duration {
+        let factor = 1u32
+            .checked_shl(self.attempts as u32)
+            .unwrap_or(u32::max_value());
+        self.base
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+
+    fn jittered_backoff(&self) -> Duration {
+        let ceiling = self.backoff_ceiling();
+        let nanos = ceiling.as_secs() * 1_000_000_000 + u64::from(ceiling.subsec_nanos());
+        Duration::from_nanos(rand::thread_rng().gen_range(0, nanos + 1))
+    }
+
+    /// Schedules a retry after `wait`, if the deadline and budget allow.
+    fn schedule<B>(&self, req: &Request<B>, wait: Duration) -> Option<RetryFuture> {
+        if let Some(&Deadline(deadline)) = req.extensions().get::<Deadline>() {
+            if Instant::now() + wait >= deadline {
+                return None;
+            }
+        }
+
+        if let Some(ref budget) = self.budget {
+            if !budget.withdraw() {
+                return None;
+            }
+        }
+
+        Some(self.next(wait))
+    }
+
+    fn next(&self, wait: Duration) -> RetryFuture {
+        let mut policy = self.clone();
+        policy.attempts += 1;
+        RetryFuture {
+            delay: Delay::new(Instant::now() + wait),
+            policy: Some(policy),
+        }
+    }
+}
+
+impl<ReqBody, ResBody, E> Policy<Request<ReqBody>, Response<ResBody>, E> for RetryPolicy
+where
+    ReqBody: Clone,
+{
+    type Future = RetryFuture;
+
+    fn retry(
+        &self,
+        req: &Request<ReqBody>,
+        result: Result<&Response<ResBody>, &E>,
+    ) -> Option<Self::Future> {
+        if !self.is_retryable(req) {
+            return None;
+        }
+
+        let res = match result {
+            Ok(res) => res,
+            Err(_) => return self.schedule(req, self.jittered_backoff()),
+        };
+
+        if self
+            .classifier
+            .classify_response(res.status(), res.headers())
+            .is_ok()
+        {
+            return None;
+        }
+
+        match retry_after(res) {
+            Some(wait) if wait > self.max_backoff => None,
+            Some(wait) => self.schedule(req, wait),
+            None => self.schedule(req, self.jittered_backoff()),
+        }
+    }
+
+    fn clone_request(&self, req: &Request<ReqBody>) -> Option<Request<ReqBody>> {
+        if !self.is_retryable(req) {
+            return None;
+        }
+
+        let mut clone = Request::new(req.body().clone());
+        *clone.method_mut() = req.method().clone();
+        *clone.uri_mut() = req.uri().clone();
+        *clone.version_mut() = req.version();
+        *clone.headers_mut() = req.headers().clone();
+        if req.extensions().get::<Retryable>().is_some() {
+            clone.extensions_mut().insert(Retryable);
+        }
+
+        // The first clone is made before the first attempt is sent.
+        let deadline = req.extensions().get::<Deadline>().cloned().or_else(|| {
+            self.deadline
+                .map(|deadline| Deadline(Instant::now() + deadline))
+        });
+        if let Some(deadline) = deadline {
+            clone.extensions_mut().insert(deadline);
+        }
+        if self.attempts == 0 {
+            if let Some(ref budget) = self.budget {
+                budget.deposit();
+            }
+        }
+
+        Some(clone)
+    }
+}
+
+fn is_idempotent(method: &Method) -> bool {
+    [
+        Method::GET,
+        Method::HEAD,
+        Method::OPTIONS,
+        Method::TRACE,
+        Method::PUT,
+        Method::DELETE,
+    ]
+    .contains(method)
+}
+
+/// Classifies the gateway errors worth retrying as failures.
+struct GatewayErrors;
+
+impl ClassifyResponse for GatewayErrors {
+    fn classify_response(&self, status: StatusCode, _: &HeaderMap) -> Result<(), FailureClass> {
+        match status {
+            StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT => Err(FailureClass::StatusCode(status)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Parses the `Retry-After` header as delay seconds or an HTTP date.
+fn retry_after<B>(res: &Response<B>) -> Option<Duration> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = httpdate::parse_http_date(value).ok()?;
+    Some(
+        at.duration_since(SystemTime::now())
+            .unwrap_or_else(|_| Duration::from_secs(0)),
+    )
+}
+
+// ===== impl RetryBudget =====
+
+impl RetryBudget {
+    /// Create a new `RetryBudget` allowing retries for `ratio` of the
+    /// requests, plus `min_per_sec` retries per second.
+    ///
+    /// The bucket holds at most ten seconds worth of the minimum rate, or
+    /// one token, whichever is more.
+    pub fn new(ratio: f64, min_per_sec: u32) -> Self {
+        let min_per_sec = f64::from(min_per_sec);
+        let capacity = (min_per_sec * 10.0).max(1.0);
+        RetryBudget {
+            shared: Arc::new(BudgetShared {
+                ratio,
+                min_per_sec,
+                capacity,
+                state: Mutex::new((capacity, Instant::now())),
+            }),
+        }
+    }
+
+    fn update<F>(&self, f: F) -> bool
+    where
+        F: FnOnce(&mut f64) -> bool,
+    {
+        let shared = &*self.shared;
+        let mut state = shared.state.lock().unwrap();
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.1);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        state.0 = (state.0 + elapsed * shared.min_per_sec).min(shared.capacity);
+        state.1 = now;
+        f(&mut state.0)
+    }
+
+    fn deposit(&self) {
+        let ratio = self.shared.ratio;
+        let capacity = self.shared.capacity;
+        self.update(|balance| {
+            *balance = (*balance + ratio).min(capacity);
+            true
+        });
+    }
+
+    fn withdraw(&self) -> bool {
+        self.update(|balance| {
+            if *balance >= 1.0 {
+                *balance -= 1.0;
+                true
+            } else {
+                false
+            }
+        })
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Create a new `Builder` making at most `max_retries` retries, with a
+    /// per-attempt timeout of 10 seconds and an overall deadline of 30
+    /// seconds.
+    pub fn new(max_retries: usize) -> Self {
+        Builder {
+            policy: RetryPolicy::new(max_retries),
+            attempt_timeout: Duration::from_secs(10),
+            deadline: Duration::from_secs(30),
+        }
+    }
+
+    /// Set the backoff of the first retry, doubled for each subsequent one
+    /// up to `max`.
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.policy = self.policy.backoff(base, max);
+        self
+    }
+
+    /// Limit retries with `budget`.
+    pub fn budget(mut self, budget: RetryBudget) -> Self {
+        self.policy = self.policy.budget(budget);
+        self
+    }
+
+    /// Set the classifier deciding which responses are retried.
+    pub fn classifier<C>(mut self, classifier: C) -> Self
+    where
+        C: ClassifyResponse + Send + Sync + 'static,
+    {
+        self.policy = self.policy.classifier(classifier);
+        self
+    }
+
+    /// Set the timeout of each attempt.
+    pub fn attempt_timeout(mut self, timeout: Duration) -> Self {
+        self.attempt_timeout = timeout;
+        self
+    }
+
+    /// Set the deadline shared by all attempts.
+    pub fn deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Returns the configured policy.
+    pub fn policy(&self) -> RetryPolicy {
+        self.policy.clone().deadline(self.deadline)
+    }
+
+    /// Build the retrying stack around `inner`.
+    pub fn build<S>(self, inner: S) -> RetryService<S> {
+        let policy = self.policy();
+        let attempts = Timeout::gateway(inner, self.attempt_timeout);
+        Timeout::gateway(Retry::new(policy, attempts), self.deadline)
+    }
+}
+
+// ===== impl Buffered =====
+
+impl Buffered {
+    /// Create a new `Buffered` body holding `data`.
+    pub fn new<T: Into<Bytes>>(data: T) -> Self {
+        Buffered {
+            data: Some(data.into()),
+        }
+    }
+}
+
+impl Body for Buffered {
+    type Data = Cursor<Bytes>;
+    type Error = std::convert::Infallible;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(Async::Ready(self.data.take().map(Cursor::new)))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        Ok(Async::Ready(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.data.is_none()
+    }
+}
+
+// ===== impl RetryFuture =====
+
+impl Future for RetryFuture {
+    type Item = RetryPolicy;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        // A failing timer retries immediately rather than giving up.
+        if let Ok(Async::NotReady) = self.delay.poll() {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(
+            self.policy.take().expect("polled after completion"),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classify::ServerErrorsAsFailures;
+
+    type Result<'a> = std::result::Result<&'a Response<()>, &'a ()>;
+
+    fn response(status: StatusCode, retry_after: Option<&'static str>) -> Response<()> {
+        let mut res = Response::new(());
+        *res.status_mut() = status;
+        if let Some(value) = retry_after {
+            res.headers_mut()
+                .insert(RETRY_AFTER, http::HeaderValue::from_static(value));
+        }
+        res
+    }
+
+    fn retries(policy: &RetryPolicy, req: &Request<()>, result: Result) -> bool {
+        Policy::<Request<()>, Response<()>, ()>::retry(policy, req, result).is_some()
+    }
+
+    #[test]
+    fn retries_idempotent_requests_on_gateway_errors() {
+        let policy = RetryPolicy::new(1);
+        let get = Request::get("/").body(()).unwrap();
+        let post = Request::post("/").body(()).unwrap();
+
+        let unavailable = response(StatusCode::SERVICE_UNAVAILABLE, None);
+        assert!(retries(&policy, &get, Ok(&unavailable)));
+        assert!(retries(&policy, &get, Err(&())));
+        assert!(!retries(&policy, &post, Ok(&unavailable)));
+        assert!(!retries(
+            &policy,
+            &get,
+            Ok(&response(StatusCode::INTERNAL_SERVER_ERROR, None))
+        ));
+
+        let mut marked = Request::post("/").body(()).unwrap();
+        marked.extensions_mut().insert(Retryable);
+        assert!(retries(&policy, &marked, Ok(&unavailable)));
+    }
+
+    #[test]
+    fn retries_responses_classified_as_failures() {
+        let policy = RetryPolicy::new(1).classifier(ServerErrorsAsFailures::new());
+        let get = Request::get("/").body(()).unwrap();
+
+        let error = response(StatusCode::INTERNAL_SERVER_ERROR, None);
+        assert!(retries(&policy, &get, Ok(&error)));
+        assert!(!retries(&policy, &get, Ok(&response(StatusCode::OK, None))));
+    }
+
+    #[test]
+    fn honors_retry_after_and_max_retries() {
+        let policy = RetryPolicy::new(1).backoff(Duration::from_millis(10), Duration::from_secs(5));
+        let get = Request::get("/").body(()).unwrap();
+
+        let soon = response(StatusCode::SERVICE_UNAVAILABLE, Some("2"));
+        assert_eq!(retry_after(&soon), Some(Duration::from_secs(2)));
+        assert!(retries(&policy, &get, Ok(&soon)));
+        let late = response(StatusCode::SERVICE_UNAVAILABLE, Some("60"));
+        assert!(!retries(&policy, &get, Ok(&late)));
+
+        let mut exhausted = policy.clone();
+        exhausted.attempts = 1;
+        assert!(!retries(&exhausted, &get, Err(&())));
+    }
+
+    #[test]
+    fn budget_limits_retries() {
+        let budget = RetryBudget::new(0.5, 0);
+        let policy = RetryPolicy::new(10).budget(budget);
+        let get = Request::get("/").body(()).unwrap();
+
+        // The initial token is spent by the first retry.
+        assert!(retries(&policy, &get, Err(&())));
+        assert!(!retries(&policy, &get, Err(&())));
+
+        // Two requests earn one retry.
+        for _ in 0..2 {
+            Policy::<Request<()>, Response<()>, ()>::clone_request(&policy, &get).unwrap();
+        }
+        assert!(retries(&policy, &get, Err(&())));
+        assert!(!retries(&policy, &get, Err(&())));
+    }
+
+    #[test]
+    fn stops_at_deadline() {
+        let policy = RetryPolicy::new(10)
+            .backoff(Duration::from_secs(1), Duration::from_secs(1))
+            .deadline(Duration::from_millis(1));
+        let get = Request::get("/").body(()).unwrap();
+        let clone = Policy::<Request<()>, Response<()>, ()>::clone_request(&policy, &get).unwrap();
+
+        // A retry-after of one second would start past the deadline.
+        let unavailable = response(StatusCode::SERVICE_UNAVAILABLE, Some("1"));
+        assert!(retries(&policy, &get, Ok(&unavailable)));
+        assert!(!retries(&policy, &clone, Ok(&unavailable)));
+    }
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let mut policy =
+            RetryPolicy::new(10).backoff(Duration::from_millis(100), Duration::from_secs(1));
+        assert_eq!(policy.backoff_ceiling(), Duration::from_millis(100));
+        policy.attempts = 2;
+        assert_eq!(policy.backoff_ceiling(), Duration::from_millis(400));
+        policy.attempts = 5;
+        assert_eq!(policy.backoff_ceiling(), Duration::from_secs(1));
+        assert!(policy.jittered_backoff() <= Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,22 @@
+//! Determining the scheme a request was received over.
+//!
+//! Servers usually see origin-form request targets (`/path`) that do not
+//! carry a scheme. Transports that terminate TLS can record the scheme by
+//! inserting an `http::uri::Scheme` into the request extensions, which the
+//! functions in this module consult when the URI has no scheme.
+
+use http::uri::Scheme;
+use http::Request;
+
+/// Returns the scheme of `req`, taken from its URI or, failing that, from a
+/// `Scheme` request extension.
+pub fn request_scheme<B>(req: &Request<B>) -> Option<&Scheme> {
+    req.uri()
+        .scheme_part()
+        .or_else(|| req.extensions().get::<Scheme>())
+}
+
+/// Returns whether `req` is known to have been received over HTTPS.
+pub fn is_https<B>(req: &Request<B>) -> bool {
+    request_scheme(req).map_or(false, |scheme| *scheme == Scheme::HTTPS)
+}
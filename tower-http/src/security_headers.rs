@@ -0,0 +1,258 @@
+//! Middleware that sets a bundle of security related response headers.
+//!
+//! By default the following headers are set:
+//!
+//! - `X-Content-Type-Options: nosniff`
+//! - `X-Frame-Options: DENY`
+//! - `Referrer-Policy: strict-origin-when-cross-origin`
+//! - `Permissions-Policy: camera=(), geolocation=(), microphone=()`
+//!
+//! Headers already present on a response are left untouched, so individual
+//! handlers can still override the stack-wide policy.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{
+    HeaderName, HeaderValue, REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use http::{HttpTryFrom, Request, Response};
+use std::fmt;
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Name of the `Permissions-Policy` header.
+pub const PERMISSIONS_POLICY: &str = "permissions-policy";
+
+/// Sets security headers on responses that do not already carry them.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders<S> {
+    inner: S,
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+}
+
+/// Configure a `SecurityHeaders` instance.
+#[derive(Debug)]
+pub struct Builder {
+    headers: Vec<(HeaderName, Option<HeaderValue>)>,
+    invalid: bool,
+}
+
+/// Errors that can happen when building a `SecurityHeaders`.
+#[derive(Debug)]
+pub struct BuilderError {
+    _p: (),
+}
+
+/// Response future for `SecurityHeaders`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    headers: Arc<[(HeaderName, HeaderValue)]>,
+}
+
+// ===== impl SecurityHeaders =====
+
+impl<S> SecurityHeaders<S> {
+    /// Create a new `SecurityHeaders` with the default header set.
+    pub fn new(inner: S) -> Self {
+        Builder::new()
+            .build(inner)
+            .expect("default security headers are valid")
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SecurityHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            headers: vec![
+                (
+                    X_CONTENT_TYPE_OPTIONS,
+                    Some(HeaderValue::from_static("nosniff")),
+                ),
+                (X_FRAME_OPTIONS, Some(HeaderValue::from_static("DENY"))),
+                (
+                    REFERRER_POLICY,
+                    Some(HeaderValue::from_static("strict-origin-when-cross-origin")),
+                ),
+                (
+                    HeaderName::from_static(PERMISSIONS_POLICY),
+                    Some(HeaderValue::from_static(
+                        "camera=(), geolocation=(), microphone=()",
+                    )),
+                ),
+            ],
+            invalid: false,
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder with the default header set.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set the `X-Content-Type-Options` value.
+    pub fn content_type_options<V>(self, value: V) -> Self
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        self.header(X_CONTENT_TYPE_OPTIONS, value)
+    }
+
+    /// Do not set `X-Content-Type-Options`.
+    pub fn without_content_type_options(self) -> Self {
+        self.without(X_CONTENT_TYPE_OPTIONS)
+    }
+
+    /// Set the `X-Frame-Options` value, e.g. `SAMEORIGIN`.
+    pub fn frame_options<V>(self, value: V) -> Self
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        self.header(X_FRAME_OPTIONS, value)
+    }
+
+    /// Do not set `X-Frame-Options`.
+    pub fn without_frame_options(self) -> Self {
+        self.without(X_FRAME_OPTIONS)
+    }
+
+    /// Set the `Referrer-Policy` value.
+    pub fn referrer_policy<V>(self, value: V) -> Self
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        self.header(REFERRER_POLICY, value)
+    }
+
+    /// Do not set `Referrer-Policy`.
+    pub fn without_referrer_policy(self) -> Self {
+        self.without(REFERRER_POLICY)
+    }
+
+    /// Set the `Permissions-Policy` value.
+    pub fn permissions_policy<V>(self, value: V) -> Self
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        self.header(HeaderName::from_static(PERMISSIONS_POLICY), value)
+    }
+
+    /// Do not set `Permissions-Policy`.
+    pub fn without_permissions_policy(self) -> Self {
+        self.without(HeaderName::from_static(PERMISSIONS_POLICY))
+    }
+
+    /// Set an arbitrary header, replacing any value configured for it.
+    pub fn header<V>(mut self, name: HeaderName, value: V) -> Self
+    where
+        HeaderValue: HttpTryFrom<V>,
+    {
+        match HeaderValue::try_from(value) {
+            Ok(value) => self.set(name, Some(value)),
+            Err(_) => self.invalid = true,
+        }
+        self
+    }
+
+    /// Do not set the given header.
+    pub fn without(mut self, name: HeaderName) -> Self {
+        self.set(name, None);
+        self
+    }
+
+    fn set(&mut self, name: HeaderName, value: Option<HeaderValue>) {
+        match self.headers.iter_mut().find(|(n, _)| *n == name) {
+            Some(entry) => entry.1 = value,
+            None => self.headers.push((name, value)),
+        }
+    }
+
+    /// Build the `SecurityHeaders` from the provided settings.
+    pub fn build<S>(self, inner: S) -> Result<SecurityHeaders<S>, BuilderError> {
+        if self.invalid {
+            return Err(BuilderError { _p: () });
+        }
+
+        let headers: Vec<_> = self
+            .headers
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect();
+
+        Ok(SecurityHeaders {
+            inner,
+            headers: headers.into(),
+        })
+    }
+}
+
+// ===== impl BuilderError =====
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid security header value")
+    }
+}
+
+impl std::error::Error for BuilderError {}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        let headers = res.headers_mut();
+
+        for (name, value) in self.headers.iter() {
+            if !headers.contains_key(name) {
+                headers.insert(name.clone(), value.clone());
+            }
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
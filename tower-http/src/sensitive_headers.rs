@@ -0,0 +1,122 @@
+//! Middleware that marks headers as sensitive.
+//!
+//! Sensitive header values are never indexed by HTTP/2 HPACK encoders and
+//! are redacted by the `Debug` implementation of `HeaderValue`, so marking
+//! credentials as sensitive keeps them out of logs and compression tables.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{self, HeaderMap, HeaderName};
+use http::{Request, Response};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Headers marked as sensitive by `SetSensitiveHeaders::with_defaults`.
+pub const DEFAULT_SENSITIVE_HEADERS: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+    header::PROXY_AUTHORIZATION,
+];
+
+/// Marks a set of headers as sensitive on both requests and responses.
+#[derive(Debug, Clone)]
+pub struct SetSensitiveHeaders<S> {
+    inner: S,
+    headers: Arc<[HeaderName]>,
+}
+
+/// Response future for `SetSensitiveHeaders`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    headers: Arc<[HeaderName]>,
+}
+
+// ===== impl SetSensitiveHeaders =====
+
+impl<S> SetSensitiveHeaders<S> {
+    /// Create a new `SetSensitiveHeaders` marking the given headers.
+    pub fn new<I>(inner: S, headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        let headers: Vec<HeaderName> = headers.into_iter().collect();
+        SetSensitiveHeaders {
+            inner,
+            headers: headers.into(),
+        }
+    }
+
+    /// Create a new `SetSensitiveHeaders` marking `Authorization`, `Cookie`,
+    /// `Set-Cookie` and `Proxy-Authorization`.
+    pub fn with_defaults(inner: S) -> Self {
+        Self::new(inner, DEFAULT_SENSITIVE_HEADERS.iter().cloned())
+    }
+
+    /// Returns the headers marked by this middleware.
+    pub fn headers(&self) -> &[HeaderName] {
+        &self.headers
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetSensitiveHeaders<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        mark_sensitive(req.headers_mut(), &self.headers);
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        mark_sensitive(res.headers_mut(), &self.headers);
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Marks every value of the named headers in `headers` as sensitive.
+pub fn mark_sensitive(headers: &mut HeaderMap, names: &[HeaderName]) {
+    for (name, value) in headers.iter_mut() {
+        if names.contains(name) {
+            value.set_sensitive(true);
+        }
+    }
+}
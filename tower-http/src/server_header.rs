@@ -0,0 +1,121 @@
+//! Middleware that controls the `Server` response header.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderValue, SERVER};
+use http::{Request, Response};
+use tower_service::Service;
+
+/// Sets, overwrites or strips the `Server` header of responses.
+#[derive(Debug, Clone)]
+pub struct SetServerHeader<S> {
+    inner: S,
+    mode: Mode,
+}
+
+/// How `SetServerHeader` treats the `Server` header.
+#[derive(Debug, Clone)]
+pub enum Mode {
+    /// Set the header only if the inner service did not set one.
+    IfNotPresent(HeaderValue),
+    /// Set the header, replacing any value set by the inner service.
+    Overwrite(HeaderValue),
+    /// Remove the header from every response.
+    Strip,
+}
+
+/// Response future for `SetServerHeader`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    mode: Mode,
+}
+
+// ===== impl SetServerHeader =====
+
+impl<S> SetServerHeader<S> {
+    /// Create a new `SetServerHeader` with the given mode.
+    pub fn new(inner: S, mode: Mode) -> Self {
+        SetServerHeader { inner, mode }
+    }
+
+    /// Set `Server` to `value` unless the inner service already set it.
+    pub fn if_not_present(inner: S, value: HeaderValue) -> Self {
+        Self::new(inner, Mode::IfNotPresent(value))
+    }
+
+    /// Set `Server` to `value`, replacing any existing value.
+    pub fn overwrite(inner: S, value: HeaderValue) -> Self {
+        Self::new(inner, Mode::Overwrite(value))
+    }
+
+    /// Remove `Server` from every response.
+    pub fn strip(inner: S) -> Self {
+        Self::new(inner, Mode::Strip)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetServerHeader<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            mode: self.mode.clone(),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        let headers = res.headers_mut();
+
+        match self.mode {
+            Mode::IfNotPresent(ref value) => {
+                if !headers.contains_key(SERVER) {
+                    headers.insert(SERVER, value.clone());
+                }
+            }
+            Mode::Overwrite(ref value) => {
+                headers.insert(SERVER, value.clone());
+            }
+            Mode::Strip => {
+                headers.remove(SERVER);
+            }
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
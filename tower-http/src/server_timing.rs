@@ -0,0 +1,232 @@
+//! Middleware that emits a `Server-Timing` response header.
+//!
+//! The time spent in the inner service, from `call` until the response head
+//! is available, is always reported. Handlers can report additional metrics
+//! (database queries, cache lookups, ...) through the `ServerTimings`
+//! request extension.
+
+use crate::util::is_token;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderName, HeaderValue};
+use http::{Request, Response};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tower_service::Service;
+
+/// Name of the `Server-Timing` header.
+pub const SERVER_TIMING: &str = "server-timing";
+
+/// Measures inner service latency and reports it in `Server-Timing`.
+#[derive(Debug, Clone)]
+pub struct ServerTiming<S> {
+    inner: S,
+    name: Arc<str>,
+}
+
+/// Handle to the timings of the current request, inserted as a request
+/// extension.
+#[derive(Debug, Clone, Default)]
+pub struct ServerTimings {
+    metrics: Arc<Mutex<Vec<Metric>>>,
+}
+
+/// A single `Server-Timing` metric.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metric {
+    name: String,
+    duration: Option<Duration>,
+    description: Option<String>,
+}
+
+/// Response future for `ServerTiming`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    name: Arc<str>,
+    timings: ServerTimings,
+    start: Instant,
+}
+
+// ===== impl ServerTiming =====
+
+impl<S> ServerTiming<S> {
+    /// Create a new `ServerTiming` reporting the inner service latency as
+    /// `app`.
+    pub fn new(inner: S) -> Self {
+        Self::with_name(inner, "app")
+    }
+
+    /// Create a new `ServerTiming` reporting the inner service latency under
+    /// the given metric name.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid metric name.
+    pub fn with_name(inner: S, name: &str) -> Self {
+        assert!(is_token(name), "invalid Server-Timing metric name");
+        ServerTiming {
+            inner,
+            name: name.into(),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ServerTiming<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
+        let timings = ServerTimings::default();
+        req.extensions_mut().insert(timings.clone());
+
+        ResponseFuture {
+            start: Instant::now(),
+            inner: self.inner.call(req),
+            name: self.name.clone(),
+            timings,
+        }
+    }
+}
+
+// ===== impl ServerTimings =====
+
+impl ServerTimings {
+    /// Record a metric.
+    pub fn record(&self, metric: Metric) {
+        self.metrics.lock().unwrap().push(metric);
+    }
+
+    /// Record a metric with a duration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid metric name.
+    pub fn record_duration(&self, name: &str, duration: Duration) {
+        self.record(Metric::new(name).duration(duration));
+    }
+
+    /// Returns the metrics recorded so far.
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.metrics.lock().unwrap().clone()
+    }
+}
+
+// ===== impl Metric =====
+
+impl Metric {
+    /// Create a new metric without a duration.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` is not a valid metric name.
+    pub fn new(name: &str) -> Self {
+        assert!(is_token(name), "invalid Server-Timing metric name");
+        Metric {
+            name: name.to_owned(),
+            duration: None,
+            description: None,
+        }
+    }
+
+    /// Set the duration of the metric.
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Set a human readable description of the metric.
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = Some(description.to_owned());
+        self
+    }
+
+    fn render(&self, out: &mut String) {
+        out.push_str(&self.name);
+        if let Some(duration) = self.duration {
+            let millis = duration.as_secs() as f64 * 1e3 + f64::from(duration.subsec_nanos()) / 1e6;
+            out.push_str(&format!(";dur={:.1}", millis));
+        }
+        if let Some(ref description) = self.description {
+            out.push_str(";desc=\"");
+            for c in description.chars() {
+                if c == '"' || c == '\\' {
+                    out.push('\\');
+                }
+                out.push(c);
+            }
+            out.push('"');
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+
+        let mut metrics = self.timings.metrics();
+        metrics.push(Metric::new(&self.name).duration(self.start.elapsed()));
+
+        let mut value = String::new();
+        for metric in &metrics {
+            if !value.is_empty() {
+                value.push_str(", ");
+            }
+            metric.render(&mut value);
+        }
+
+        // Descriptions may contain characters that are not valid in header
+        // values; drop the header rather than failing the response.
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            res.headers_mut()
+                .append(HeaderName::from_static(SERVER_TIMING), value);
+        }
+
+        Ok(Async::Ready(res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_metric() {
+        let mut out = String::new();
+        Metric::new("db")
+            .duration(Duration::from_micros(1_500))
+            .description("say \"hi\"")
+            .render(&mut out);
+        assert_eq!(out, "db;dur=1.5;desc=\"say \\\"hi\\\"\"");
+    }
+}
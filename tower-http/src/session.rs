@@ -0,0 +1,517 @@
+//! Session middleware with a pluggable store.
+//!
+//! `SessionManager` identifies sessions by a cookie, loads their data from a
+//! `SessionStore` before calling the inner service, and exposes it to
+//! handlers through the `Session` request extension. Changes made by the
+//! handler are persisted once the response is available, and new sessions
+//! are given a cookie.
+//!
+//! The session cookie is read and written through the `cookies::Cookies`
+//! extension when a `CookieManager` wraps this middleware; otherwise the
+//! `Cookie` and `Set-Cookie` headers are handled directly.
+//!
+//! `MemoryStore` keeps sessions in memory and is suitable for single
+//! instance deployments and tests.
+
+use crate::cookies::Cookies;
+use cookie::{Cookie, SameSite};
+use futures::future::{self, Either, FutureResult};
+use futures::{Async, Future, Poll};
+use http::{Request, Response};
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Loads sessions before calling the inner service and persists changes.
+///
+/// The inner service must be `Clone`, as it is called after the session is
+/// loaded. Store and inner service errors are reported as
+/// `SessionError::Store` and `SessionError::Inner` respectively.
+#[derive(Debug, Clone)]
+pub struct SessionManager<S, T> {
+    inner: S,
+    store: T,
+    config: Arc<Config>,
+}
+
+/// Storage for session data.
+pub trait SessionStore {
+    /// Errors produced by the store.
+    type Error;
+
+    /// Future returned by `load`.
+    type LoadFuture: Future<Item = Option<SessionData>, Error = Self::Error>;
+
+    /// Future returned by `save` and `destroy`.
+    type SaveFuture: Future<Item = (), Error = Self::Error>;
+
+    /// Load the data of session `id`, if it exists.
+    fn load(&self, id: &str) -> Self::LoadFuture;
+
+    /// Store the data of session `id`.
+    fn save(&self, id: &str, data: SessionData) -> Self::SaveFuture;
+
+    /// Delete session `id`.
+    fn destroy(&self, id: &str) -> Self::SaveFuture;
+}
+
+/// The data of a session.
+pub type SessionData = HashMap<String, String>;
+
+/// Error returned by `SessionManager`.
+#[derive(Debug)]
+pub enum SessionError<E, S> {
+    /// The inner service failed.
+    Inner(E),
+    /// The store failed to load or save the session.
+    Store(S),
+}
+
+/// Handle to the session of the current request, inserted as a request
+/// extension by `SessionManager`.
+#[derive(Debug, Clone)]
+pub struct Session {
+    inner: Arc<Mutex<Inner>>,
+}
+
+/// An in-memory `SessionStore`.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    sessions: Arc<Mutex<HashMap<String, SessionData>>>,
+}
+
+/// Configure the session cookie.
+#[derive(Debug, Clone)]
+pub struct Config {
+    cookie_name: String,
+    path: String,
+    secure: bool,
+}
+
+#[derive(Debug)]
+struct Inner {
+    id: Option<String>,
+    data: SessionData,
+    changed: bool,
+    destroyed: bool,
+}
+
+/// Response future for `SessionManager`.
+pub struct ResponseFuture<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: SessionStore,
+{
+    state: State<S, T, B>,
+    store: T,
+    config: Arc<Config>,
+    cookies: Cookies,
+    write_cookies: bool,
+    session: Option<Session>,
+}
+
+#[allow(clippy::large_enum_variant)]
+enum State<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: SessionStore,
+{
+    Load {
+        load: Either<T::LoadFuture, FutureResult<Option<SessionData>, T::Error>>,
+        id: Option<String>,
+        service: S,
+        request: Request<B>,
+    },
+    Call(S::Future),
+    Save {
+        save: T::SaveFuture,
+        response: Option<S::Response>,
+    },
+    Done,
+}
+
+// ===== impl SessionManager =====
+
+impl<S, T> SessionManager<S, T> {
+    /// Create a new `SessionManager` with the default cookie settings.
+    pub fn new(inner: S, store: T) -> Self {
+        Self::with_config(inner, store, Config::default())
+    }
+
+    /// Create a new `SessionManager` with the given cookie settings.
+    pub fn with_config(inner: S, store: T, config: Config) -> Self {
+        SessionManager {
+            inner,
+            store,
+            config: Arc::new(config),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<Request<ReqBody>> for SessionManager<S, T>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    T: SessionStore + Clone,
+{
+    type Response = S::Response;
+    type Error = SessionError<S::Error, T::Error>;
+    type Future = ResponseFuture<S, T, ReqBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready().map_err(SessionError::Inner)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let (cookies, write_cookies) = match req.extensions().get::<Cookies>() {
+            Some(cookies) => (cookies.clone(), false),
+            None => (Cookies::from_headers(req.headers()), true),
+        };
+
+        let id = cookies
+            .get(&self.config.cookie_name)
+            .map(|cookie| cookie.value().to_owned());
+        let load = match id {
+            Some(ref id) => Either::A(self.store.load(id)),
+            None => Either::B(future::ok(None)),
+        };
+
+        let clone = self.inner.clone();
+        ResponseFuture {
+            state: State::Load {
+                load,
+                id,
+                service: mem::replace(&mut self.inner, clone),
+                request: req,
+            },
+            store: self.store.clone(),
+            config: self.config.clone(),
+            cookies,
+            write_cookies,
+            session: None,
+        }
+    }
+}
+
+// ===== impl Session =====
+
+impl Session {
+    fn new(id: Option<String>, data: SessionData) -> Self {
+        Session {
+            inner: Arc::new(Mutex::new(Inner {
+                id,
+                data,
+                changed: false,
+                destroyed: false,
+            })),
+        }
+    }
+
+    /// Returns the session id, or `None` for a session that has not been
+    /// saved yet.
+    pub fn id(&self) -> Option<String> {
+        self.inner.lock().unwrap().id.clone()
+    }
+
+    /// Returns the value stored under `key`.
+    pub fn get(&self, key: &str) -> Option<String> {
+        self.inner.lock().unwrap().data.get(key).cloned()
+    }
+
+    /// Store `value` under `key`.
+    pub fn insert<V>(&self, key: &str, value: V)
+    where
+        V: Into<String>,
+    {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.insert(key.to_owned(), value.into());
+        inner.changed = true;
+    }
+
+    /// Remove the value stored under `key`.
+    pub fn remove(&self, key: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        let value = inner.data.remove(key);
+        inner.changed |= value.is_some();
+        value
+    }
+
+    /// Destroy the session, deleting it from the store and the client.
+    pub fn destroy(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.data.clear();
+        inner.destroyed = true;
+    }
+}
+
+// ===== impl MemoryStore =====
+
+impl MemoryStore {
+    /// Create a new, empty `MemoryStore`.
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Returns the number of stored sessions.
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Returns whether the store is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl SessionStore for MemoryStore {
+    type Error = std::convert::Infallible;
+    type LoadFuture = FutureResult<Option<SessionData>, Self::Error>;
+    type SaveFuture = FutureResult<(), Self::Error>;
+
+    fn load(&self, id: &str) -> Self::LoadFuture {
+        future::ok(self.sessions.lock().unwrap().get(id).cloned())
+    }
+
+    fn save(&self, id: &str, data: SessionData) -> Self::SaveFuture {
+        self.sessions.lock().unwrap().insert(id.to_owned(), data);
+        future::ok(())
+    }
+
+    fn destroy(&self, id: &str) -> Self::SaveFuture {
+        self.sessions.lock().unwrap().remove(id);
+        future::ok(())
+    }
+}
+
+// ===== impl Config =====
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            cookie_name: "session".to_owned(),
+            path: "/".to_owned(),
+            secure: false,
+        }
+    }
+}
+
+impl Config {
+    /// Return the default settings: a cookie named `session` on path `/`.
+    pub fn new() -> Self {
+        Config::default()
+    }
+
+    /// Set the name of the session cookie.
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie_name = name.to_owned();
+        self
+    }
+
+    /// Set the path of the session cookie.
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = path.to_owned();
+        self
+    }
+
+    /// Set whether the session cookie is only sent over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    fn cookie(&self, value: String) -> Cookie<'static> {
+        Cookie::build(self.cookie_name.clone(), value)
+            .path(self.path.clone())
+            .http_only(true)
+            .secure(self.secure)
+            .same_site(SameSite::Lax)
+            .finish()
+    }
+}
+
+// ===== impl SessionError =====
+
+impl<E: fmt::Display, S: fmt::Display> fmt::Display for SessionError<E, S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionError::Inner(ref e) => fmt::Display::fmt(e, f),
+            SessionError::Store(ref e) => write!(f, "session store failed: {}", e),
+        }
+    }
+}
+
+impl<E, S> Error for SessionError<E, S>
+where
+    E: Error + 'static,
+    S: Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            SessionError::Inner(ref e) => Some(e),
+            SessionError::Store(ref e) => Some(e),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, T, ReqBody, ResBody> Future for ResponseFuture<S, T, ReqBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    T: SessionStore,
+{
+    type Item = S::Response;
+    type Error = SessionError<S::Error, T::Error>;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Load {
+                    mut load,
+                    id,
+                    mut service,
+                    mut request,
+                } => {
+                    let data = match load.poll().map_err(SessionError::Store)? {
+                        Async::Ready(data) => data,
+                        Async::NotReady => {
+                            self.state = State::Load {
+                                load,
+                                id,
+                                service,
+                                request,
+                            };
+                            return Ok(Async::NotReady);
+                        }
+                    };
+
+                    // Unknown or expired ids are not reused, so clients
+                    // cannot choose their session id.
+                    let session = match data {
+                        Some(data) => Session::new(id, data),
+                        None => Session::new(None, SessionData::new()),
+                    };
+                    request.extensions_mut().insert(session.clone());
+                    self.session = Some(session);
+                    self.state = State::Call(service.call(request));
+                }
+                State::Call(mut future) => {
+                    let mut response = match future.poll().map_err(SessionError::Inner)? {
+                        Async::Ready(response) => response,
+                        Async::NotReady => {
+                            self.state = State::Call(future);
+                            return Ok(Async::NotReady);
+                        }
+                    };
+
+                    let save = self.persist();
+                    if self.write_cookies {
+                        self.cookies.write_delta(response.headers_mut());
+                    }
+
+                    match save {
+                        Some(save) => {
+                            self.state = State::Save {
+                                save,
+                                response: Some(response),
+                            }
+                        }
+                        None => return Ok(Async::Ready(response)),
+                    }
+                }
+                State::Save {
+                    mut save,
+                    mut response,
+                } => match save.poll().map_err(SessionError::Store)? {
+                    Async::Ready(()) => {
+                        return Ok(Async::Ready(response.take().expect("response")));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Save { save, response };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, T, B> ResponseFuture<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: SessionStore,
+{
+    /// Updates the session cookie and starts persisting session changes.
+    fn persist(&mut self) -> Option<T::SaveFuture> {
+        let session = self.session.take()?;
+        let mut inner = session.inner.lock().unwrap();
+
+        if inner.destroyed {
+            let id = inner.id.take()?;
+            self.cookies.remove(
+                Cookie::build(self.config.cookie_name.clone(), "")
+                    .path(self.config.path.clone())
+                    .finish(),
+            );
+            return Some(self.store.destroy(&id));
+        }
+
+        if !inner.changed {
+            return None;
+        }
+
+        let id = match inner.id {
+            Some(ref id) => id.clone(),
+            None => {
+                let id = new_id();
+                self.cookies.add(self.config.cookie(id.clone()));
+                inner.id = Some(id.clone());
+                id
+            }
+        };
+        inner.changed = false;
+
+        Some(self.store.save(&id, inner.data.clone()))
+    }
+}
+
+impl<S, T, B> fmt::Debug for ResponseFuture<S, T, B>
+where
+    S: Service<Request<B>>,
+    T: SessionStore,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Load { .. } => "Load",
+            State::Call(_) => "Call",
+            State::Save { .. } => "Save",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .field("session", &self.session)
+            .finish()
+    }
+}
+
+fn new_id() -> String {
+    let bytes: [u8; 32] = rand::random();
+    base64::encode_config(&bytes, base64::URL_SAFE_NO_PAD)
+}
@@ -0,0 +1,229 @@
+//! Middleware that records the client in proxy headers of forwarded requests.
+//!
+//! This is the counterpart to `forwarded::SetClientInfo` for proxies: before
+//! a request is sent upstream, the client address, scheme and host are
+//! appended to `X-Forwarded-For` and set in `X-Forwarded-Proto` and
+//! `X-Forwarded-Host`, or recorded in an RFC 7239 `Forwarded` element.
+//!
+//! The client is taken from a `forwarded::ClientInfo` request extension when
+//! present, falling back to a `SocketAddr` extension and the request itself.
+
+use crate::forwarded::{ClientInfo, X_FORWARDED_FOR, X_FORWARDED_HOST, X_FORWARDED_PROTO};
+use crate::scheme;
+use http::header::{HeaderMap, HeaderName, HeaderValue, FORWARDED, HOST};
+use http::Request;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Adds forwarding headers to requests.
+#[derive(Debug, Clone)]
+pub struct SetForwardedHeaders<S> {
+    inner: S,
+    config: Arc<Config>,
+}
+
+/// Configure a `SetForwardedHeaders` instance.
+///
+/// By default only the `X-Forwarded-*` headers are set.
+#[derive(Debug, Clone)]
+pub struct Builder {
+    config: Config,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    x_forwarded_for: bool,
+    x_forwarded_proto: bool,
+    x_forwarded_host: bool,
+    forwarded: bool,
+    by: Option<String>,
+}
+
+// ===== impl SetForwardedHeaders =====
+
+impl<S> SetForwardedHeaders<S> {
+    /// Create a new `SetForwardedHeaders` with the default settings.
+    pub fn new(inner: S) -> Self {
+        Builder::new().build(inner)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for SetForwardedHeaders<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        let client = match req.extensions().get::<ClientInfo>() {
+            Some(info) => info.clone(),
+            None => ClientInfo {
+                ip: req.extensions().get::<SocketAddr>().map(|addr| addr.ip()),
+                proto: scheme::request_scheme(&req).cloned(),
+                host: None,
+            },
+        };
+        let host = match client.host {
+            Some(ref host) => HeaderValue::from_str(host.as_str()).ok(),
+            None => req.headers().get(HOST).cloned(),
+        };
+
+        let config = &self.config;
+        let headers = req.headers_mut();
+
+        if config.x_forwarded_for {
+            if let Some(ip) = client.ip {
+                append_list(headers, X_FORWARDED_FOR, &ip.to_string());
+            }
+        }
+
+        if config.x_forwarded_proto {
+            if let Some(ref proto) = client.proto {
+                if let Ok(value) = HeaderValue::from_str(proto.as_str()) {
+                    headers.insert(X_FORWARDED_PROTO, value);
+                }
+            }
+        }
+
+        if config.x_forwarded_host {
+            if let Some(ref host) = host {
+                headers.insert(X_FORWARDED_HOST, host.clone());
+            }
+        }
+
+        if config.forwarded {
+            let mut element = Vec::new();
+            if let Some(ref by) = config.by {
+                element.push(format!("by={}", by));
+            }
+            element.push(format!(
+                "for={}",
+                client.ip.map_or_else(|| "unknown".to_owned(), node)
+            ));
+            if let Some(host) = host.as_ref().and_then(|h| h.to_str().ok()) {
+                element.push(format!("host={}", quote(host)));
+            }
+            if let Some(ref proto) = client.proto {
+                element.push(format!("proto={}", proto.as_str()));
+            }
+            append_list(headers, FORWARDED.as_str(), &element.join(";"));
+        }
+
+        self.inner.call(req)
+    }
+}
+
+// ===== impl Builder =====
+
+impl Default for Builder {
+    fn default() -> Self {
+        Builder {
+            config: Config {
+                x_forwarded_for: true,
+                x_forwarded_proto: true,
+                x_forwarded_host: true,
+                forwarded: false,
+                by: None,
+            },
+        }
+    }
+}
+
+impl Builder {
+    /// Return a new builder with the default settings.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Set whether to append the client address to `X-Forwarded-For`.
+    pub fn x_forwarded_for(mut self, enabled: bool) -> Self {
+        self.config.x_forwarded_for = enabled;
+        self
+    }
+
+    /// Set whether to set `X-Forwarded-Proto`.
+    pub fn x_forwarded_proto(mut self, enabled: bool) -> Self {
+        self.config.x_forwarded_proto = enabled;
+        self
+    }
+
+    /// Set whether to set `X-Forwarded-Host`.
+    pub fn x_forwarded_host(mut self, enabled: bool) -> Self {
+        self.config.x_forwarded_host = enabled;
+        self
+    }
+
+    /// Set whether to append an element to the RFC 7239 `Forwarded` header.
+    pub fn forwarded(mut self, enabled: bool) -> Self {
+        self.config.forwarded = enabled;
+        self
+    }
+
+    /// Identify this proxy in the `by` parameter of `Forwarded` elements.
+    pub fn forwarded_by(mut self, addr: IpAddr) -> Self {
+        self.config.by = Some(node(addr));
+        self
+    }
+
+    /// Build the `SetForwardedHeaders` from the provided settings.
+    pub fn build<S>(self, inner: S) -> SetForwardedHeaders<S> {
+        SetForwardedHeaders {
+            inner,
+            config: Arc::new(self.config),
+        }
+    }
+}
+
+/// Appends `item` to the comma separated list in the header `name`.
+fn append_list(headers: &mut HeaderMap, name: &str, item: &str) {
+    let name = HeaderName::from_bytes(name.as_bytes()).unwrap();
+
+    let mut items: Vec<&str> = headers
+        .get_all(&name)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    items.push(item);
+
+    if let Ok(value) = HeaderValue::from_str(&items.join(", ")) {
+        headers.insert(name, value);
+    }
+}
+
+/// Renders an address as a `Forwarded` node, quoting IPv6 addresses.
+fn node(addr: IpAddr) -> String {
+    match addr {
+        IpAddr::V4(addr) => addr.to_string(),
+        IpAddr::V6(addr) => format!("\"[{}]\"", addr),
+    }
+}
+
+fn quote(value: &str) -> String {
+    if crate::util::is_token(value) {
+        value.to_owned()
+    } else {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
@@ -0,0 +1,268 @@
+//! Middleware that validates signed, expiring URLs.
+//!
+//! Signed URLs grant temporary access to a resource without other
+//! credentials, typically for download links. A URL is signed by appending
+//! an `expires` parameter (a Unix timestamp) and a `signature` parameter,
+//! the hex encoded HMAC-SHA256 over the method, path and remaining query:
+//!
+//! ```text
+//! METHOD
+//! /path
+//! query-without-signature
+//! ```
+//!
+//! `UrlSigner` produces such URLs and `ValidateSignedUrl` rejects requests
+//! whose signature is missing, invalid or expired with `403 Forbidden`.
+
+use crate::signing::{hex, hmac_sha256, verify_hmac_sha256};
+use futures::{Async, Future, Poll};
+use http::{Method, Request, Response, StatusCode};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower_service::Service;
+
+const EXPIRES: &str = "expires";
+const SIGNATURE: &str = "signature";
+
+/// Rejects requests without a valid, unexpired URL signature.
+#[derive(Debug, Clone)]
+pub struct ValidateSignedUrl<S> {
+    inner: S,
+    key: Arc<[u8]>,
+}
+
+/// Signs URLs accepted by `ValidateSignedUrl`.
+#[derive(Debug, Clone)]
+pub struct UrlSigner {
+    key: Arc<[u8]>,
+}
+
+/// Response future for `ValidateSignedUrl`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Valid(F),
+    Invalid(Option<Response<B>>),
+}
+
+// ===== impl ValidateSignedUrl =====
+
+impl<S> ValidateSignedUrl<S> {
+    /// Create a new `ValidateSignedUrl` verifying signatures made with `key`.
+    pub fn new(inner: S, key: &[u8]) -> Self {
+        ValidateSignedUrl {
+            inner,
+            key: key.into(),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ValidateSignedUrl<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let valid = is_valid(
+            &self.key,
+            req.method(),
+            req.uri().path(),
+            req.uri().query().unwrap_or(""),
+            SystemTime::now(),
+        );
+
+        let state = if valid {
+            State::Valid(self.inner.call(req))
+        } else {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::FORBIDDEN;
+            State::Invalid(Some(res))
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+// ===== impl UrlSigner =====
+
+impl UrlSigner {
+    /// Create a new `UrlSigner` signing with `key`.
+    pub fn new(key: &[u8]) -> Self {
+        UrlSigner { key: key.into() }
+    }
+
+    /// Sign `path_and_query` for `method`, valid for `ttl` from now.
+    ///
+    /// Returns the path and query with the `expires` and `signature`
+    /// parameters appended.
+    pub fn sign(&self, method: &Method, path_and_query: &str, ttl: Duration) -> String {
+        self.sign_at(method, path_and_query, SystemTime::now() + ttl)
+    }
+
+    /// Sign `path_and_query` for `method`, valid until `expires`.
+    pub fn sign_at(&self, method: &Method, path_and_query: &str, expires: SystemTime) -> String {
+        let expires = expires
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let mut parts = path_and_query.splitn(2, '?');
+        let path = parts.next().unwrap();
+        let mut query = parts.next().unwrap_or("").to_owned();
+        if !query.is_empty() {
+            query.push('&');
+        }
+        query.push_str(&format!("{}={}", EXPIRES, expires));
+
+        let signature = hmac_sha256(&self.key, &string_to_sign(method, path, &query));
+        format!("{}?{}&{}={}", path, query, SIGNATURE, hex(&signature))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Valid(ref mut future) => future.poll(),
+            State::Invalid(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+fn string_to_sign(method: &Method, path: &str, query: &str) -> Vec<u8> {
+    format!("{}\n{}\n{}", method, path, query).into_bytes()
+}
+
+/// The signature must be the last query parameter, so that the signed query
+/// is exactly the query preceding it.
+fn is_valid(key: &[u8], method: &Method, path: &str, query: &str, now: SystemTime) -> bool {
+    let marker = format!("{}=", SIGNATURE);
+    let (signed, signature) = match query.rfind(&marker) {
+        Some(0) => return false,
+        Some(i) if query.as_bytes()[i - 1] == b'&' => (&query[..i - 1], &query[i + marker.len()..]),
+        _ => return false,
+    };
+
+    let expires = signed
+        .split('&')
+        .filter_map(|pair| {
+            let mut pair = pair.splitn(2, '=');
+            match (pair.next(), pair.next()) {
+                (Some(EXPIRES), Some(value)) => value.parse::<u64>().ok(),
+                _ => None,
+            }
+        })
+        .next_back()
+        // Expiry times too far in the future to be represented are invalid.
+        .and_then(|expires| UNIX_EPOCH.checked_add(Duration::from_secs(expires)));
+    let expires = match expires {
+        Some(expires) => expires,
+        None => return false,
+    };
+    if now > expires {
+        return false;
+    }
+
+    let signature = match parse_hex(signature) {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    verify_hmac_sha256(key, &string_to_sign(method, path, signed), &signature)
+}
+
+fn parse_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"secret";
+
+    fn check(url: &str, method: &Method, now: SystemTime) -> bool {
+        let mut parts = url.splitn(2, '?');
+        let path = parts.next().unwrap();
+        let query = parts.next().unwrap_or("");
+        is_valid(KEY, method, path, query, now)
+    }
+
+    #[test]
+    fn accepts_valid_signature() {
+        let expires = UNIX_EPOCH + Duration::from_secs(2_000);
+        let url = UrlSigner::new(KEY).sign_at(&Method::GET, "/files/a.zip?v=2", expires);
+
+        assert!(url.starts_with("/files/a.zip?v=2&expires=2000&signature="));
+        assert!(check(
+            &url,
+            &Method::GET,
+            UNIX_EPOCH + Duration::from_secs(1_000)
+        ));
+    }
+
+    #[test]
+    fn rejects_expired_or_tampered() {
+        let expires = UNIX_EPOCH + Duration::from_secs(2_000);
+        let url = UrlSigner::new(KEY).sign_at(&Method::GET, "/files/a.zip", expires);
+        let before = UNIX_EPOCH + Duration::from_secs(1_000);
+
+        assert!(!check(
+            &url,
+            &Method::GET,
+            UNIX_EPOCH + Duration::from_secs(3_000)
+        ));
+        assert!(!check(&url, &Method::DELETE, before));
+        assert!(!check(&url.replace("a.zip", "b.zip"), &Method::GET, before));
+        assert!(!check(&url.replace("2000", "9000"), &Method::GET, before));
+        assert!(!check("/files/a.zip", &Method::GET, before));
+    }
+
+    #[test]
+    fn rejects_unrepresentable_expiry() {
+        let url = format!("/files/a.zip?expires={}&signature=00", std::u64::MAX);
+        assert!(!check(&url, &Method::GET, UNIX_EPOCH));
+    }
+}
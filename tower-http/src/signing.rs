@@ -0,0 +1,260 @@
+//! HMAC request signing.
+//!
+//! `SignRequest` signs outgoing requests with HMAC-SHA256. What is signed,
+//! and how the signature is attached, is decided by a `SigningScheme`. The
+//! provided `Canonical` scheme signs the method, path and query, a selected
+//! set of headers and a digest of the body; implementing `SigningScheme`
+//! allows assembling other schemes, such as AWS SigV4, from the same parts.
+//!
+//! Signing needs the whole body, so requests must carry a buffered body that
+//! implements `AsRef<[u8]>`, such as `Vec<u8>`, `String` or `Bytes`.
+
+use hmac::{Hmac, Mac};
+use http::header::{HeaderName, HeaderValue};
+use http::request::Parts;
+use http::Request;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Name of the header carrying the signature in the `Canonical` scheme.
+pub const X_SIGNATURE: &str = "x-signature";
+
+/// Name of the header carrying the body digest in the `Canonical` scheme.
+pub const X_CONTENT_SHA256: &str = "x-content-sha256";
+
+/// Signs outgoing requests with HMAC-SHA256.
+#[derive(Debug, Clone)]
+pub struct SignRequest<S, T = Canonical> {
+    inner: S,
+    key: Arc<[u8]>,
+    scheme: Arc<T>,
+}
+
+/// Decides what is signed and how the signature is attached to a request.
+pub trait SigningScheme {
+    /// Build the string to sign, adding any headers that are part of the
+    /// signature (timestamps, digests, ...) to `parts`.
+    fn string_to_sign(&self, parts: &mut Parts, body: &[u8]) -> Vec<u8>;
+
+    /// Attach the computed signature to the request.
+    fn apply_signature(&self, parts: &mut Parts, signature: &[u8]);
+}
+
+/// The default signing scheme.
+///
+/// The string to sign is made of newline separated lines:
+///
+/// ```text
+/// METHOD
+/// /path?query
+/// header-name:value      (one line per signed header, in configured order)
+/// hex(sha256(body))
+/// ```
+///
+/// The body digest is sent in `X-Content-Sha256` and the hex encoded
+/// signature in `X-Signature`, prefixed by `keyId=<id>,` when a key id is
+/// configured.
+#[derive(Debug, Clone, Default)]
+pub struct Canonical {
+    headers: Vec<HeaderName>,
+    key_id: Option<String>,
+}
+
+// ===== impl SignRequest =====
+
+impl<S> SignRequest<S> {
+    /// Create a new `SignRequest` using the `Canonical` scheme, signing no
+    /// headers.
+    pub fn new(inner: S, key: &[u8]) -> Self {
+        Self::with_scheme(inner, key, Canonical::default())
+    }
+}
+
+impl<S, T> SignRequest<S, T> {
+    /// Create a new `SignRequest` using the given scheme.
+    pub fn with_scheme(inner: S, key: &[u8], scheme: T) -> Self {
+        SignRequest {
+            inner,
+            key: key.into(),
+            scheme: Arc::new(scheme),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, T, B> Service<Request<B>> for SignRequest<S, T>
+where
+    S: Service<Request<B>>,
+    T: SigningScheme,
+    B: AsRef<[u8]>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<B>) -> Self::Future {
+        let (mut parts, body) = req.into_parts();
+
+        let string_to_sign = self.scheme.string_to_sign(&mut parts, body.as_ref());
+        let signature = hmac_sha256(&self.key, &string_to_sign);
+        self.scheme.apply_signature(&mut parts, &signature);
+
+        self.inner.call(Request::from_parts(parts, body))
+    }
+}
+
+// ===== impl Canonical =====
+
+impl Canonical {
+    /// Create a new `Canonical` scheme signing the given headers.
+    pub fn new<I>(headers: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        Canonical {
+            headers: headers.into_iter().collect(),
+            key_id: None,
+        }
+    }
+
+    /// Identify the signing key in the signature header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key_id` contains `,` or characters that are not valid in
+    /// a header value.
+    pub fn key_id(mut self, key_id: &str) -> Self {
+        assert!(
+            !key_id.contains(',') && HeaderValue::from_str(key_id).is_ok(),
+            "invalid key id"
+        );
+        self.key_id = Some(key_id.to_owned());
+        self
+    }
+}
+
+impl SigningScheme for Canonical {
+    fn string_to_sign(&self, parts: &mut Parts, body: &[u8]) -> Vec<u8> {
+        let digest = hex(&Sha256::digest(body));
+        parts.headers.insert(
+            X_CONTENT_SHA256,
+            HeaderValue::from_str(&digest).expect("hex is a valid header value"),
+        );
+
+        let mut out = Vec::new();
+        out.extend_from_slice(parts.method.as_str().as_bytes());
+        out.push(b'\n');
+        match parts.uri.path_and_query() {
+            Some(path) => out.extend_from_slice(path.as_str().as_bytes()),
+            None => out.push(b'/'),
+        }
+        out.push(b'\n');
+        for name in &self.headers {
+            out.extend_from_slice(name.as_str().as_bytes());
+            out.push(b':');
+            let values: Vec<&[u8]> = parts
+                .headers
+                .get_all(name)
+                .iter()
+                .map(|value| value.as_bytes())
+                .collect();
+            out.extend_from_slice(&values.join(&b','));
+            out.push(b'\n');
+        }
+        out.extend_from_slice(digest.as_bytes());
+        out
+    }
+
+    fn apply_signature(&self, parts: &mut Parts, signature: &[u8]) {
+        let value = match self.key_id {
+            Some(ref key_id) => format!("keyId={},signature={}", key_id, hex(signature)),
+            None => hex(signature),
+        };
+        parts.headers.insert(
+            X_SIGNATURE,
+            HeaderValue::from_str(&value).expect("signature is a valid header value"),
+        );
+    }
+}
+
+/// Computes the HMAC-SHA256 of `data` under `key`.
+pub fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.input(data);
+    mac.result().code().to_vec()
+}
+
+/// Verifies the HMAC-SHA256 `signature` of `data` under `key` in constant
+/// time.
+pub fn verify_hmac_sha256(key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    let mut mac = Hmac::<Sha256>::new_varkey(key).expect("HMAC accepts keys of any length");
+    mac.input(data);
+    mac.verify(signature).is_ok()
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HOST;
+
+    #[test]
+    fn hmac_matches_rfc_4231() {
+        // Test case 2 of RFC 4231.
+        let mac = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            hex(&mac),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(verify_hmac_sha256(
+            b"Jefe",
+            b"what do ya want for nothing?",
+            &mac
+        ));
+    }
+
+    #[test]
+    fn canonicalizes_request() {
+        let (mut parts, ()) = Request::post("http://example.com/items?id=1")
+            .header(HOST, "example.com")
+            .header("x-other", "ignored")
+            .body(())
+            .unwrap()
+            .into_parts();
+
+        let scheme = Canonical::new(vec![HOST]).key_id("k1");
+        let string_to_sign = scheme.string_to_sign(&mut parts, b"");
+
+        let empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        assert_eq!(
+            String::from_utf8(string_to_sign).unwrap(),
+            format!("POST\n/items?id=1\nhost:example.com\n{}", empty)
+        );
+        assert_eq!(parts.headers[X_CONTENT_SHA256], empty);
+
+        scheme.apply_signature(&mut parts, &[0xab, 0xcd]);
+        assert_eq!(parts.headers[X_SIGNATURE], "keyId=k1,signature=abcd");
+    }
+}
@@ -0,0 +1,495 @@
+//! Coalesce concurrent identical requests.
+//!
+//! `Singleflight` deduplicates concurrent `GET` and `HEAD` requests with the
+//! same key, by default their method and URI. The first request calls the
+//! inner service; its response body is buffered and a copy of the response
+//! is sent to every request that arrived with the same key meanwhile.
+//!
+//! Should the first request fail or be dropped, waiting requests call the
+//! inner service themselves. As they may do so after the inner service has
+//! been polled ready, the inner service must be `Clone`.
+//!
+//! Only the status, version, headers and trailers of the shared response
+//! are copied; response extensions are seen by the first request only.
+//! Responses larger than the maximum body size, 1 MiB by default, are not
+//! shared: as soon as their `Content-Length` or buffered body exceeds it,
+//! waiting requests call the inner service themselves, and the first request
+//! streams the rest of the body after the part already buffered.
+//!
+//! The default key leaves out requests with `Authorization` or `Cookie`
+//! headers, whose responses may be specific to a user. Extractors coalescing
+//! such requests must include the credentials in the key.
+//!
+//! Upgrade requests, as defined by the `upgrade` module, are never
+//! coalesced.
+
+use crate::rate_limit::KeyExtractor;
+use crate::upgrade::is_upgrade_request;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::sync::oneshot;
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, AUTHORIZATION, CONTENT_LENGTH, COOKIE};
+use http::{response, Method, Request, Response, StatusCode, Version};
+use http_body::Body;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::{fmt, mem};
+use tower_service::Service;
+
+/// Coalesces concurrent `GET` and `HEAD` requests with the same key.
+#[derive(Debug, Clone)]
+pub struct Singleflight<S, K = MethodAndUri> {
+    inner: S,
+    extractor: Arc<K>,
+    flights: Arc<Mutex<HashMap<String, Vec<oneshot::Sender<Arc<Shared>>>>>>,
+    max_body: usize,
+}
+
+/// Keys requests by their method and URI.
+///
+/// Requests with `Authorization` or `Cookie` headers have no key.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MethodAndUri {
+    _p: (),
+}
+
+/// Response future for `Singleflight`.
+pub struct ResponseFuture<S, B, ResBody>
+where
+    S: Service<Request<B>>,
+{
+    state: State<S, B, ResBody>,
+    max_body: usize,
+}
+
+/// Response body for `Singleflight`.
+///
+/// Yields the part of the body buffered before it was found too large to
+/// share, if any, then the rest of the body and its trailers.
+#[derive(Debug)]
+pub struct SingleflightBody<B> {
+    prefix: Option<B>,
+    inner: B,
+    trailers: Option<HeaderMap>,
+}
+
+/// A buffered response shared with waiting requests.
+#[derive(Debug)]
+struct Shared {
+    status: StatusCode,
+    version: Version,
+    headers: HeaderMap,
+    body: Bytes,
+    trailers: Option<HeaderMap>,
+}
+
+/// Removes the key from the in-flight map when the leading request ends.
+struct Flight {
+    key: String,
+    done: bool,
+    flights: Arc<Mutex<HashMap<String, Vec<oneshot::Sender<Arc<Shared>>>>>>,
+}
+
+enum State<S, B, ResBody>
+where
+    S: Service<Request<B>>,
+{
+    Leading {
+        future: S::Future,
+        flight: Flight,
+    },
+    Buffering {
+        parts: response::Parts,
+        body: ResBody,
+        buf: BytesMut,
+        flight: Flight,
+    },
+    Trailers {
+        parts: response::Parts,
+        body: ResBody,
+        data: Bytes,
+        flight: Flight,
+    },
+    Waiting {
+        rx: oneshot::Receiver<Arc<Shared>>,
+        service: S,
+        request: Request<B>,
+    },
+    Called(S::Future),
+    Done,
+}
+
+// ===== impl Singleflight =====
+
+impl<S> Singleflight<S> {
+    /// Create a new `Singleflight` keying requests by method and URI.
+    pub fn new(inner: S) -> Self {
+        Self::with_key(inner, MethodAndUri::default())
+    }
+}
+
+impl<S, K> Singleflight<S, K> {
+    /// Create a new `Singleflight` keying requests with `extractor`.
+    ///
+    /// Requests without a key are not coalesced.
+    pub fn with_key(inner: S, extractor: K) -> Self {
+        Singleflight {
+            inner,
+            extractor: Arc::new(extractor),
+            flights: Arc::new(Mutex::new(HashMap::new())),
+            max_body: 1024 * 1024,
+        }
+    }
+
+    /// Only share response bodies of at most `max` bytes.
+    pub fn max_body(mut self, max: usize) -> Self {
+        self.max_body = max;
+        self
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, K, ReqBody, ResBody> Service<Request<ReqBody>> for Singleflight<S, K>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>> + Clone,
+    S::Error: From<ResBody::Error>,
+    K: KeyExtractor<ReqBody>,
+    ResBody: Body + From<Bytes>,
+{
+    type Response = Response<SingleflightBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S, ReqBody, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let coalesce = (req.method() == Method::GET || req.method() == Method::HEAD)
+            && !is_upgrade_request(&req);
+        let key = if coalesce {
+            self.extractor.extract(&req)
+        } else {
+            None
+        };
+        let key = match key {
+            Some(key) => key,
+            None => {
+                return ResponseFuture {
+                    state: State::Called(self.inner.call(req)),
+                    max_body: self.max_body,
+                };
+            }
+        };
+
+        let mut flights = self.flights.lock().unwrap();
+        let state = if let Some(waiters) = flights.get_mut(&key) {
+            let (tx, rx) = oneshot::channel();
+            waiters.push(tx);
+            let clone = self.inner.clone();
+            State::Waiting {
+                rx,
+                service: mem::replace(&mut self.inner, clone),
+                request: req,
+            }
+        } else {
+            flights.insert(key.clone(), Vec::new());
+            State::Leading {
+                future: self.inner.call(req),
+                flight: Flight {
+                    key,
+                    done: false,
+                    flights: self.flights.clone(),
+                },
+            }
+        };
+
+        ResponseFuture {
+            state,
+            max_body: self.max_body,
+        }
+    }
+}
+
+impl<B> KeyExtractor<B> for MethodAndUri {
+    fn extract(&self, req: &Request<B>) -> Option<String> {
+        let headers = req.headers();
+        if headers.contains_key(AUTHORIZATION) || headers.contains_key(COOKIE) {
+            return None;
+        }
+        Some(format!("{} {}", req.method(), req.uri()))
+    }
+}
+
+// ===== impl Shared =====
+
+impl Shared {
+    fn to_response<B: From<Bytes>>(&self) -> Response<SingleflightBody<B>> {
+        let mut res = Response::new(SingleflightBody {
+            prefix: None,
+            inner: B::from(self.body.clone()),
+            trailers: self.trailers.clone(),
+        });
+        *res.status_mut() = self.status;
+        *res.version_mut() = self.version;
+        *res.headers_mut() = self.headers.clone();
+        res
+    }
+}
+
+// ===== impl Flight =====
+
+impl Flight {
+    /// Sends the response to the waiting requests.
+    fn complete(mut self, shared: Shared) {
+        self.done = true;
+        let waiters = self.flights.lock().unwrap().remove(&self.key);
+        let shared = Arc::new(shared);
+        for tx in waiters.into_iter().flatten() {
+            let _ = tx.send(shared.clone());
+        }
+    }
+}
+
+impl Drop for Flight {
+    fn drop(&mut self) {
+        // Dropping the senders makes the waiting requests call the inner
+        // service themselves.
+        if !self.done {
+            self.flights.lock().unwrap().remove(&self.key);
+        }
+    }
+}
+
+impl fmt::Debug for Flight {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Flight").field("key", &self.key).finish()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<S, ReqBody, ResBody> Future for ResponseFuture<S, ReqBody, ResBody>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    S::Error: From<ResBody::Error>,
+    ResBody: Body + From<Bytes>,
+{
+    type Item = Response<SingleflightBody<ResBody>>;
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            match mem::replace(&mut self.state, State::Done) {
+                State::Leading { mut future, flight } => match future.poll()? {
+                    Async::Ready(response) => {
+                        let too_large = response
+                            .headers()
+                            .get(CONTENT_LENGTH)
+                            .and_then(|len| len.to_str().ok())
+                            .and_then(|len| len.parse::<u64>().ok())
+                            .map_or(false, |len| len > self.max_body as u64);
+                        if too_large {
+                            return Ok(Async::Ready(response.map(SingleflightBody::new)));
+                        }
+                        let (parts, body) = response.into_parts();
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf: BytesMut::new(),
+                            flight,
+                        };
+                    }
+                    Async::NotReady => {
+                        self.state = State::Leading { future, flight };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Buffering {
+                    parts,
+                    mut body,
+                    mut buf,
+                    flight,
+                } => match body.poll_data()? {
+                    Async::Ready(Some(mut data)) => {
+                        while data.has_remaining() {
+                            let n = {
+                                let bytes = data.bytes();
+                                buf.extend_from_slice(bytes);
+                                bytes.len()
+                            };
+                            data.advance(n);
+                        }
+                        if buf.len() > self.max_body {
+                            // Dropping the flight releases the waiting
+                            // requests.
+                            drop(flight);
+                            let body = SingleflightBody {
+                                prefix: Some(ResBody::from(buf.freeze())),
+                                inner: body,
+                                trailers: None,
+                            };
+                            return Ok(Async::Ready(Response::from_parts(parts, body)));
+                        }
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf,
+                            flight,
+                        };
+                    }
+                    Async::Ready(None) => {
+                        self.state = State::Trailers {
+                            parts,
+                            body,
+                            data: buf.freeze(),
+                            flight,
+                        };
+                    }
+                    Async::NotReady => {
+                        self.state = State::Buffering {
+                            parts,
+                            body,
+                            buf,
+                            flight,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Trailers {
+                    parts,
+                    mut body,
+                    data,
+                    flight,
+                } => match body.poll_trailers()? {
+                    Async::Ready(trailers) => {
+                        let shared = Shared {
+                            status: parts.status,
+                            version: parts.version,
+                            headers: parts.headers,
+                            body: data,
+                            trailers,
+                        };
+                        let mut response = shared.to_response();
+                        *response.extensions_mut() = parts.extensions;
+                        flight.complete(shared);
+                        return Ok(Async::Ready(response));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Trailers {
+                            parts,
+                            body,
+                            data,
+                            flight,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Waiting {
+                    mut rx,
+                    mut service,
+                    request,
+                } => match rx.poll() {
+                    Ok(Async::Ready(shared)) => return Ok(Async::Ready(shared.to_response())),
+                    Ok(Async::NotReady) => {
+                        self.state = State::Waiting {
+                            rx,
+                            service,
+                            request,
+                        };
+                        return Ok(Async::NotReady);
+                    }
+                    Err(oneshot::Canceled) => {
+                        self.state = State::Called(service.call(request));
+                    }
+                },
+                State::Called(mut future) => match future.poll()? {
+                    Async::Ready(response) => {
+                        return Ok(Async::Ready(response.map(SingleflightBody::new)));
+                    }
+                    Async::NotReady => {
+                        self.state = State::Called(future);
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Done => panic!("polled after completion"),
+            }
+        }
+    }
+}
+
+impl<S, B, ResBody> fmt::Debug for ResponseFuture<S, B, ResBody>
+where
+    S: Service<Request<B>>,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = match self.state {
+            State::Leading { .. } => "Leading",
+            State::Buffering { .. } => "Buffering",
+            State::Trailers { .. } => "Trailers",
+            State::Waiting { .. } => "Waiting",
+            State::Called(_) => "Called",
+            State::Done => "Done",
+        };
+        f.debug_struct("ResponseFuture")
+            .field("state", &state)
+            .finish()
+    }
+}
+
+// ===== impl SingleflightBody =====
+
+impl<B> SingleflightBody<B> {
+    fn new(inner: B) -> Self {
+        SingleflightBody {
+            prefix: None,
+            inner,
+            trailers: None,
+        }
+    }
+}
+
+impl<B> Body for SingleflightBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        if let Some(ref mut prefix) = self.prefix {
+            if let Some(data) = try_ready!(prefix.poll_data()) {
+                return Ok(Async::Ready(Some(data)));
+            }
+        }
+        self.prefix = None;
+        self.inner.poll_data()
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match self.trailers.take() {
+            Some(trailers) => Ok(Async::Ready(Some(trailers))),
+            None => self.inner.poll_trailers(),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.prefix.as_ref().map_or(true, Body::is_end_stream)
+            && self.trailers.is_none()
+            && self.inner.is_end_stream()
+    }
+}
@@ -0,0 +1,236 @@
+//! Reject requests exhibiting request smuggling vectors.
+//!
+//! When a proxy and a backend disagree on where a request ends, an attacker
+//! can hide a second request in the body of the first. `SmugglingGuard`
+//! answers ambiguous requests with `400 Bad Request` before they reach the
+//! inner service:
+//!
+//! - `Content-Length` and `Transfer-Encoding` both present;
+//! - a `Transfer-Encoding` whose final coding is not `chunked`;
+//! - multiple or malformed `Content-Length` values, unless they are
+//!   identical;
+//! - a missing `Host` header in HTTP/1.1 requests, or more than one `Host`
+//!   header in any HTTP/1.x request.
+//!
+//! Header values folded over several lines (obs-fold) are not checked here:
+//! `HeaderValue` rejects CR and LF, so they cannot reach this layer.
+
+use futures::{Async, Future, Poll};
+use http::header::{HeaderMap, CONTENT_LENGTH, HOST, TRANSFER_ENCODING};
+use http::{Request, Response, StatusCode, Version};
+use tower_service::Service;
+
+/// Rejects ambiguous requests with `400 Bad Request`.
+#[derive(Debug, Clone)]
+pub struct SmugglingGuard<S> {
+    inner: S,
+}
+
+/// Response future for `SmugglingGuard`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, B> {
+    state: State<F, B>,
+}
+
+#[derive(Debug)]
+enum State<F, B> {
+    Accepted(F),
+    Rejected(Option<Response<B>>),
+}
+
+// ===== impl SmugglingGuard =====
+
+impl<S> SmugglingGuard<S> {
+    /// Create a new `SmugglingGuard`.
+    pub fn new(inner: S) -> Self {
+        SmugglingGuard { inner }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SmugglingGuard<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, ResBody>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let state = if is_ambiguous(req.version(), req.headers()) {
+            let mut res = Response::new(ResBody::default());
+            *res.status_mut() = StatusCode::BAD_REQUEST;
+            State::Rejected(Some(res))
+        } else {
+            State::Accepted(self.inner.call(req))
+        };
+
+        ResponseFuture { state }
+    }
+}
+
+fn is_ambiguous(version: Version, headers: &HeaderMap) -> bool {
+    let has_transfer_encoding = headers.contains_key(TRANSFER_ENCODING);
+    if has_transfer_encoding && headers.contains_key(CONTENT_LENGTH) {
+        return true;
+    }
+
+    if has_transfer_encoding && !ends_with_chunked(headers) {
+        return true;
+    }
+
+    if !content_length_is_consistent(headers) {
+        return true;
+    }
+
+    let hosts = headers.get_all(HOST).iter().count();
+    match version {
+        Version::HTTP_11 => hosts != 1,
+        Version::HTTP_09 | Version::HTTP_10 => hosts > 1,
+        _ => false,
+    }
+}
+
+/// Whether the final transfer coding is `chunked`.
+fn ends_with_chunked(headers: &HeaderMap) -> bool {
+    let last = headers
+        .get_all(TRANSFER_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .rfind(|coding| !coding.is_empty());
+    last.map_or(false, |coding| coding.eq_ignore_ascii_case("chunked"))
+}
+
+/// Whether all `Content-Length` values are valid and identical.
+fn content_length_is_consistent(headers: &HeaderMap) -> bool {
+    let mut length = None;
+    for value in headers.get_all(CONTENT_LENGTH).iter() {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+        for part in value.split(',') {
+            let part = part.trim();
+            if part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()) {
+                return false;
+            }
+            let n = match part.parse::<u64>() {
+                Ok(n) => n,
+                Err(_) => return false,
+            };
+            match length {
+                Some(length) if length != n => return false,
+                _ => length = Some(n),
+            }
+        }
+    }
+    true
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F, B>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self.state {
+            State::Accepted(ref mut future) => future.poll(),
+            State::Rejected(ref mut res) => {
+                Ok(Async::Ready(res.take().expect("polled after completion")))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn accepts_unambiguous_requests() {
+        let h11 = Version::HTTP_11;
+        assert!(!is_ambiguous(h11, &headers(&[("host", "a")])));
+        assert!(!is_ambiguous(
+            h11,
+            &headers(&[("host", "a"), ("content-length", "5")])
+        ));
+        assert!(!is_ambiguous(
+            h11,
+            &headers(&[("host", "a"), ("content-length", "5, 5")])
+        ));
+        assert!(!is_ambiguous(
+            h11,
+            &headers(&[("host", "a"), ("transfer-encoding", "gzip, Chunked")])
+        ));
+        assert!(!is_ambiguous(Version::HTTP_10, &headers(&[])));
+        assert!(!is_ambiguous(Version::HTTP_2, &headers(&[])));
+    }
+
+    #[test]
+    fn rejects_smuggling_vectors() {
+        let h11 = Version::HTTP_11;
+        assert!(is_ambiguous(
+            h11,
+            &headers(&[
+                ("host", "a"),
+                ("content-length", "5"),
+                ("transfer-encoding", "chunked"),
+            ])
+        ));
+        assert!(is_ambiguous(
+            h11,
+            &headers(&[("host", "a"), ("transfer-encoding", "chunked, gzip")])
+        ));
+        assert!(is_ambiguous(
+            h11,
+            &headers(&[
+                ("host", "a"),
+                ("content-length", "5"),
+                ("content-length", "6")
+            ])
+        ));
+        assert!(is_ambiguous(
+            h11,
+            &headers(&[("host", "a"), ("content-length", "+5")])
+        ));
+        assert!(is_ambiguous(h11, &headers(&[])));
+        assert!(is_ambiguous(
+            Version::HTTP_10,
+            &headers(&[("host", "a"), ("host", "b")])
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+use super::bucket::Bucket;
+use bytes::Buf;
+use futures::{Async, Future, Poll};
+use http::header::HeaderMap;
+use http_body::Body;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio_timer::Delay;
+
+/// A body whose data is paced by one or more byte buckets.
+#[derive(Debug)]
+pub struct Throttled<B> {
+    inner: B,
+    buckets: Vec<Arc<Mutex<Bucket>>>,
+    delay: Option<Delay>,
+}
+
+impl<B> Throttled<B> {
+    pub(crate) fn new(inner: B, buckets: Vec<Arc<Mutex<Bucket>>>) -> Self {
+        Throttled {
+            inner,
+            buckets,
+            delay: None,
+        }
+    }
+
+    /// Returns a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    /// Waits until all buckets allow the next chunk.
+    fn poll_buckets(&mut self) -> Async<()> {
+        loop {
+            if let Some(ref mut delay) = self.delay {
+                match delay.poll() {
+                    Ok(Async::NotReady) => return Async::NotReady,
+                    Ok(Async::Ready(())) => {}
+                    // A failing timer stops throttling the body rather than
+                    // stalling it or spinning until the buckets refill.
+                    Err(_) => self.buckets.clear(),
+                }
+            }
+            self.delay = None;
+
+            let now = Instant::now();
+            let wait = self
+                .buckets
+                .iter()
+                .filter_map(|bucket| bucket.lock().unwrap().wait_time(now))
+                .max();
+            match wait {
+                Some(wait) => self.delay = Some(Delay::new(now + wait)),
+                None => return Async::Ready(()),
+            }
+        }
+    }
+}
+
+impl<B> Body for Throttled<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        if let Async::NotReady = self.poll_buckets() {
+            return Ok(Async::NotReady);
+        }
+
+        let result = self.inner.poll_data();
+        if let Ok(Async::Ready(Some(ref data))) = result {
+            for bucket in &self.buckets {
+                bucket.lock().unwrap().consume(data.remaining());
+            }
+        }
+        result
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
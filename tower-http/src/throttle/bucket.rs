@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A byte rate with an allowed burst.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bandwidth {
+    bytes_per_sec: u64,
+    burst: u64,
+}
+
+/// A token bucket of bytes.
+///
+/// The balance may become negative when a chunk larger than the balance is
+/// consumed; the next chunk then waits until the debt is repaid.
+#[derive(Debug)]
+pub(crate) struct Bucket {
+    bandwidth: Bandwidth,
+    balance: f64,
+    updated: Instant,
+}
+
+/// Buckets per key, sharing a `Bandwidth`.
+#[derive(Debug)]
+pub(crate) struct Buckets {
+    bandwidth: Bandwidth,
+    state: Mutex<BucketsState>,
+}
+
+#[derive(Debug, Default)]
+struct BucketsState {
+    map: HashMap<String, Arc<Mutex<Bucket>>>,
+    gets: u64,
+}
+
+// ===== impl Bandwidth =====
+
+impl Bandwidth {
+    /// Create a new `Bandwidth` of `bytes_per_sec`, allowing bursts of one
+    /// second worth of bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn new(bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "bytes_per_sec must be positive");
+        Bandwidth {
+            bytes_per_sec,
+            burst: bytes_per_sec,
+        }
+    }
+
+    /// Set the number of bytes that may be transferred at once after a
+    /// period of inactivity.
+    pub fn burst(mut self, burst: u64) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    /// Returns the rate in bytes per second.
+    pub fn bytes_per_sec(&self) -> u64 {
+        self.bytes_per_sec
+    }
+}
+
+// ===== impl Bucket =====
+
+impl Bucket {
+    pub(crate) fn new(bandwidth: Bandwidth) -> Self {
+        Bucket {
+            bandwidth,
+            balance: bandwidth.burst as f64,
+            updated: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+        let burst = self.bandwidth.burst as f64;
+        self.balance = (self.balance + elapsed * self.bandwidth.bytes_per_sec as f64).min(burst);
+        self.updated = now;
+    }
+
+    /// Returns how long to wait before the next chunk may be transferred.
+    pub(crate) fn wait_time(&mut self, now: Instant) -> Option<Duration> {
+        self.refill(now);
+        if self.balance >= 0.0 {
+            return None;
+        }
+
+        let secs = -self.balance / self.bandwidth.bytes_per_sec as f64;
+        let nanos = (secs * 1e9).ceil() as u64;
+        Some(Duration::from_nanos(nanos))
+    }
+
+    /// Consume `bytes` from the bucket.
+    pub(crate) fn consume(&mut self, bytes: usize) {
+        self.balance -= bytes as f64;
+    }
+
+    fn is_full(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        self.balance >= self.bandwidth.burst as f64
+    }
+}
+
+// ===== impl Buckets =====
+
+impl Buckets {
+    pub(crate) fn new(bandwidth: Bandwidth) -> Self {
+        Buckets {
+            bandwidth,
+            state: Mutex::new(BucketsState::default()),
+        }
+    }
+
+    /// Returns the bucket of `key`.
+    pub(crate) fn get(&self, key: String) -> Arc<Mutex<Bucket>> {
+        let mut state = self.state.lock().unwrap();
+
+        // Now and then, drop buckets that are full and not in use, as they
+        // are indistinguishable from new ones.
+        state.gets += 1;
+        if state.gets % 1024 == 0 {
+            let now = Instant::now();
+            state.map.retain(|_, bucket| {
+                Arc::strong_count(bucket) > 1 || !bucket.lock().unwrap().is_full(now)
+            });
+        }
+
+        let bandwidth = self.bandwidth;
+        state
+            .map
+            .entry(key)
+            .or_insert_with(|| Arc::new(Mutex::new(Bucket::new(bandwidth))))
+            .clone()
+    }
+}
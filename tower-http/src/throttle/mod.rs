@@ -0,0 +1,224 @@
+//! Limit the bandwidth of bodies.
+//!
+//! Bodies are paced by token buckets of bytes: each chunk consumes its
+//! length from the buckets of its key, and once a bucket is exhausted the
+//! next chunk waits until it has been refilled at the configured
+//! `Bandwidth`. Keys are extracted with a `rate_limit::KeyExtractor`, e.g.
+//! `ClientIp`, or the peer's `SocketAddr` for a per-connection limit.
+//!
+//! `ThrottleRequestBody` paces the consumption of request bodies, protecting
+//! parsers and storage from high-throughput uploads. `ThrottleResponseBody`
+//! paces response bodies with a per-key and a global budget, so that a
+//! single large download cannot saturate an instance's egress.
+//!
+//! The timer is provided by `tokio-timer`, so bodies must be polled within a
+//! runtime with a timer. If the timer fails, e.g. outside of a runtime, the
+//! body is passed through without throttling.
+
+mod body;
+mod bucket;
+
+pub use self::body::Throttled;
+pub use self::bucket::Bandwidth;
+
+use self::bucket::{Bucket, Buckets};
+use crate::rate_limit::KeyExtractor;
+use futures::{try_ready, Async, Future, Poll};
+use http::{Request, Response};
+use std::sync::{Arc, Mutex};
+use tower_service::Service;
+
+/// Paces request bodies per key.
+///
+/// Requests without a key are not throttled.
+#[derive(Debug, Clone)]
+pub struct ThrottleRequestBody<S, K> {
+    inner: S,
+    extractor: Arc<K>,
+    buckets: Arc<Buckets>,
+}
+
+/// Paces response bodies per key and globally.
+#[derive(Debug, Clone)]
+pub struct ThrottleResponseBody<S, K> {
+    inner: S,
+    extractor: Arc<K>,
+    per_key: Option<Arc<Buckets>>,
+    global: Option<Arc<Mutex<Bucket>>>,
+}
+
+/// Configure a `ThrottleResponseBody`.
+#[derive(Debug, Clone, Default)]
+pub struct Builder {
+    per_key: Option<Bandwidth>,
+    global: Option<Bandwidth>,
+}
+
+/// Response future for `ThrottleResponseBody`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    buckets: Option<Vec<Arc<Mutex<Bucket>>>>,
+}
+
+// ===== impl ThrottleRequestBody =====
+
+impl<S, K> ThrottleRequestBody<S, K> {
+    /// Create a new `ThrottleRequestBody` limiting the request bodies of each
+    /// key extracted by `extractor` to `bandwidth`.
+    pub fn new(inner: S, extractor: K, bandwidth: Bandwidth) -> Self {
+        ThrottleRequestBody {
+            inner,
+            extractor: Arc::new(extractor),
+            buckets: Arc::new(Buckets::new(bandwidth)),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, K, ReqBody> Service<Request<ReqBody>> for ThrottleRequestBody<S, K>
+where
+    S: Service<Request<Throttled<ReqBody>>>,
+    K: KeyExtractor<ReqBody>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let buckets = match self.extractor.extract(&req) {
+            Some(key) => vec![self.buckets.get(key)],
+            None => Vec::new(),
+        };
+        self.inner
+            .call(req.map(|body| Throttled::new(body, buckets)))
+    }
+}
+
+// ===== impl ThrottleResponseBody =====
+
+impl<S, K> ThrottleResponseBody<S, K> {
+    /// Create a new `ThrottleResponseBody` limiting the response bodies of
+    /// each key extracted by `extractor` to `bandwidth`.
+    pub fn new(inner: S, extractor: K, bandwidth: Bandwidth) -> Self {
+        Builder::new().per_key(bandwidth).build(inner, extractor)
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, K, ReqBody, ResBody> Service<Request<ReqBody>> for ThrottleResponseBody<S, K>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    K: KeyExtractor<ReqBody>,
+{
+    type Response = Response<Throttled<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let mut buckets = Vec::new();
+        if let Some(ref per_key) = self.per_key {
+            if let Some(key) = self.extractor.extract(&req) {
+                buckets.push(per_key.get(key));
+            }
+        }
+        if let Some(ref global) = self.global {
+            buckets.push(global.clone());
+        }
+
+        ResponseFuture {
+            inner: self.inner.call(req),
+            buckets: Some(buckets),
+        }
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Create a new `Builder` without budgets.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Limit the response bodies of each key to `bandwidth`.
+    pub fn per_key(mut self, bandwidth: Bandwidth) -> Self {
+        self.per_key = Some(bandwidth);
+        self
+    }
+
+    /// Limit all response bodies together to `bandwidth`.
+    pub fn global(mut self, bandwidth: Bandwidth) -> Self {
+        self.global = Some(bandwidth);
+        self
+    }
+
+    /// Build a `ThrottleResponseBody` wrapping `inner`, with keys extracted
+    /// by `extractor`.
+    pub fn build<S, K>(self, inner: S, extractor: K) -> ThrottleResponseBody<S, K> {
+        ThrottleResponseBody {
+            inner,
+            extractor: Arc::new(extractor),
+            per_key: self
+                .per_key
+                .map(|bandwidth| Arc::new(Buckets::new(bandwidth))),
+            global: self
+                .global
+                .map(|bandwidth| Arc::new(Mutex::new(Bucket::new(bandwidth)))),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = Response<Throttled<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = try_ready!(self.inner.poll());
+        let buckets = self.buckets.take().expect("polled after completion");
+        Ok(Async::Ready(
+            response.map(|body| Throttled::new(body, buckets)),
+        ))
+    }
+}
@@ -0,0 +1,360 @@
+//! Time out requests with an HTTP response.
+//!
+//! Unlike a generic timeout, which fails the call with an error, `Timeout`
+//! answers requests the inner service does not respond to in time with an
+//! empty `408 Request Timeout` response, or `504 Gateway Timeout` when
+//! created with `Timeout::gateway`. The inner response future is dropped.
+//!
+//! Gateways often need separate budgets for the time to the response head
+//! and the total response time. `ResponseTimeout` answers with `504` when the
+//! head takes longer than the header timeout, and wraps the response body in
+//! `TimeoutBody`, which fails with `TimeoutError::Elapsed` once the total
+//! timeout, measured from the call, has passed. The bodies of tunnels have
+//! no deadline, as they last as long as the upgraded connection: these are
+//! upgrade responses, as defined by the `upgrade` module, and `2xx`
+//! responses to `CONNECT` requests and requests marked with the `Upgrade`
+//! extension. As any client can send a `CONNECT` request, other responses to
+//! them keep the deadline.
+//!
+//! The timer is provided by `tokio-timer`, so the response future must be
+//! polled within a runtime with a timer. Should the timer fail, the request
+//! is treated as timed out.
+
+use crate::upgrade::{is_tunnel_request, opens_tunnel};
+use futures::{Async, Future, Poll};
+use http::header::HeaderMap;
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::error::Error;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio_timer::Delay;
+use tower_service::Service;
+
+/// Answers requests that take too long with `408` or `504`.
+#[derive(Debug, Clone)]
+pub struct Timeout<S> {
+    inner: S,
+    timeout: Duration,
+    status: StatusCode,
+}
+
+/// Applies separate timeouts to the response head and the whole response.
+#[derive(Debug, Clone)]
+pub struct ResponseTimeout<S> {
+    inner: S,
+    header_timeout: Duration,
+    total_timeout: Duration,
+}
+
+/// A body that fails once a deadline has passed.
+#[derive(Debug)]
+pub struct TimeoutBody<B> {
+    inner: B,
+    delay: Option<Delay>,
+}
+
+/// Error returned by `TimeoutBody`.
+#[derive(Debug)]
+pub enum TimeoutError<E> {
+    /// The deadline passed before the body was complete.
+    Elapsed,
+    /// The inner body failed.
+    Body(E),
+}
+
+/// Response future for `ResponseTimeout`.
+#[derive(Debug)]
+pub struct ResponseTimeoutFuture<F> {
+    inner: F,
+    header_delay: Delay,
+    total_deadline: Instant,
+    tunnel: bool,
+}
+
+/// Response future for `Timeout`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    delay: Delay,
+    status: StatusCode,
+}
+
+// ===== impl Timeout =====
+
+impl<S> Timeout<S> {
+    /// Create a new `Timeout` answering with `408 Request Timeout`.
+    pub fn new(inner: S, timeout: Duration) -> Self {
+        Timeout {
+            inner,
+            timeout,
+            status: StatusCode::REQUEST_TIMEOUT,
+        }
+    }
+
+    /// Create a new `Timeout` answering with `504 Gateway Timeout`, for
+    /// proxies and gateways waiting on an upstream.
+    pub fn gateway(inner: S, timeout: Duration) -> Self {
+        Timeout {
+            inner,
+            timeout,
+            status: StatusCode::GATEWAY_TIMEOUT,
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for Timeout<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Default,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            delay: Delay::new(Instant::now() + self.timeout),
+            status: self.status,
+        }
+    }
+}
+
+// ===== impl ResponseTimeout =====
+
+impl<S> ResponseTimeout<S> {
+    /// Create a new `ResponseTimeout`.
+    ///
+    /// The response head must arrive within `header_timeout`, and the body
+    /// must be complete within `total_timeout` of the call.
+    pub fn new(inner: S, header_timeout: Duration, total_timeout: Duration) -> Self {
+        ResponseTimeout {
+            inner,
+            header_timeout,
+            total_timeout,
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for ResponseTimeout<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body + Default,
+{
+    type Response = Response<TimeoutBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ResponseTimeoutFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let now = Instant::now();
+        let header_timeout = if self.header_timeout < self.total_timeout {
+            self.header_timeout
+        } else {
+            self.total_timeout
+        };
+
+        let tunnel = is_tunnel_request(&req);
+        ResponseTimeoutFuture {
+            inner: self.inner.call(req),
+            header_delay: Delay::new(now + header_timeout),
+            total_deadline: now + self.total_timeout,
+            tunnel,
+        }
+    }
+}
+
+// ===== impl TimeoutBody =====
+
+impl<B> TimeoutBody<B> {
+    /// Wrap `body`, failing once `deadline` has passed.
+    pub fn new(body: B, deadline: Instant) -> Self {
+        TimeoutBody {
+            inner: body,
+            delay: Some(Delay::new(deadline)),
+        }
+    }
+
+    fn unbounded(body: B) -> Self {
+        TimeoutBody {
+            inner: body,
+            delay: None,
+        }
+    }
+
+    /// Returns a reference to the inner body.
+    pub fn get_ref(&self) -> &B {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner body.
+    pub fn get_mut(&mut self) -> &mut B {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner body.
+    pub fn into_inner(self) -> B {
+        self.inner
+    }
+
+    fn poll_deadline<E>(&mut self) -> Result<(), TimeoutError<E>> {
+        let delay = match self.delay {
+            Some(ref mut delay) => delay,
+            None => return Ok(()),
+        };
+        match delay.poll() {
+            Ok(Async::NotReady) => Ok(()),
+            Ok(Async::Ready(())) | Err(_) => Err(TimeoutError::Elapsed),
+        }
+    }
+}
+
+impl<B> Body for TimeoutBody<B>
+where
+    B: Body,
+{
+    type Data = B::Data;
+    type Error = TimeoutError<B::Error>;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self.inner.poll_data().map_err(TimeoutError::Body)? {
+            Async::NotReady => {}
+            ready => return Ok(ready),
+        }
+        self.poll_deadline()?;
+        Ok(Async::NotReady)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match self.inner.poll_trailers().map_err(TimeoutError::Body)? {
+            Async::NotReady => {}
+            ready => return Ok(ready),
+        }
+        self.poll_deadline()?;
+        Ok(Async::NotReady)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+// ===== impl TimeoutError =====
+
+impl<E: fmt::Display> fmt::Display for TimeoutError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TimeoutError::Elapsed => f.write_str("response timed out"),
+            TimeoutError::Body(ref e) => fmt::Display::fmt(e, f),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for TimeoutError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            TimeoutError::Elapsed => None,
+            TimeoutError::Body(ref e) => Some(e),
+        }
+    }
+}
+
+// ===== impl ResponseTimeoutFuture =====
+
+impl<F, B> Future for ResponseTimeoutFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: Default,
+{
+    type Item = Response<TimeoutBody<B>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let deadline = self.total_deadline;
+
+        if let Async::Ready(response) = self.inner.poll()? {
+            let tunnel = opens_tunnel(self.tunnel, &response);
+            let response = response.map(|body| {
+                if tunnel {
+                    TimeoutBody::unbounded(body)
+                } else {
+                    TimeoutBody::new(body, deadline)
+                }
+            });
+            return Ok(Async::Ready(response));
+        }
+
+        match self.header_delay.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) | Err(_) => {
+                let mut res = Response::new(TimeoutBody::new(B::default(), deadline));
+                *res.status_mut() = StatusCode::GATEWAY_TIMEOUT;
+                Ok(Async::Ready(res))
+            }
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+    B: Default,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(response) = self.inner.poll()? {
+            return Ok(Async::Ready(response));
+        }
+
+        match self.delay.poll() {
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Ok(Async::Ready(())) | Err(_) => {
+                let mut res = Response::new(B::default());
+                *res.status_mut() = self.status;
+                Ok(Async::Ready(res))
+            }
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use crate::classify::FailureClass;
+use http::{HeaderMap, Request, Response};
+use std::time::Duration;
+
+/// Called when a request is received.
+///
+/// `()` does nothing.
+pub trait OnRequest<B> {
+    /// Called before the request is passed to the inner service.
+    fn on_request(&mut self, request: &Request<B>);
+}
+
+/// Called when the response headers are ready.
+///
+/// `()` does nothing.
+pub trait OnResponse<B> {
+    /// Called with the response and the time elapsed since the request was
+    /// received.
+    fn on_response(self, response: &Response<B>, latency: Duration);
+}
+
+/// Called when a chunk of the response body is sent.
+///
+/// `()` does nothing.
+pub trait OnBodyChunk<D> {
+    /// Called with the chunk and the time elapsed since the previous chunk
+    /// or, for the first chunk, since the response headers were ready.
+    fn on_body_chunk(&mut self, chunk: &D, latency: Duration);
+}
+
+/// Called when the response body has ended.
+///
+/// `()` does nothing.
+pub trait OnEos {
+    /// Called with the trailers, if any, and the time elapsed since the
+    /// request was received.
+    fn on_eos(self, trailers: Option<&HeaderMap>, latency: Duration);
+}
+
+/// Called when a request fails.
+///
+/// `()` does nothing.
+pub trait OnFailure {
+    /// Called with the classification of the failure and the time elapsed
+    /// since the request was received.
+    fn on_failure(&mut self, failure: &FailureClass, latency: Duration);
+}
+
+impl<B> OnRequest<B> for () {
+    fn on_request(&mut self, _: &Request<B>) {}
+}
+
+impl<B, F> OnRequest<B> for F
+where
+    F: FnMut(&Request<B>),
+{
+    fn on_request(&mut self, request: &Request<B>) {
+        self(request)
+    }
+}
+
+impl<B> OnResponse<B> for () {
+    fn on_response(self, _: &Response<B>, _: Duration) {}
+}
+
+impl<B, F> OnResponse<B> for F
+where
+    F: FnOnce(&Response<B>, Duration),
+{
+    fn on_response(self, response: &Response<B>, latency: Duration) {
+        self(response, latency)
+    }
+}
+
+impl<D> OnBodyChunk<D> for () {
+    fn on_body_chunk(&mut self, _: &D, _: Duration) {}
+}
+
+impl<D, F> OnBodyChunk<D> for F
+where
+    F: FnMut(&D, Duration),
+{
+    fn on_body_chunk(&mut self, chunk: &D, latency: Duration) {
+        self(chunk, latency)
+    }
+}
+
+impl OnEos for () {
+    fn on_eos(self, _: Option<&HeaderMap>, _: Duration) {}
+}
+
+impl<F> OnEos for F
+where
+    F: FnOnce(Option<&HeaderMap>, Duration),
+{
+    fn on_eos(self, trailers: Option<&HeaderMap>, latency: Duration) {
+        self(trailers, latency)
+    }
+}
+
+impl OnFailure for () {
+    fn on_failure(&mut self, _: &FailureClass, _: Duration) {}
+}
+
+impl<F> OnFailure for F
+where
+    F: FnMut(&FailureClass, Duration),
+{
+    fn on_failure(&mut self, failure: &FailureClass, latency: Duration) {
+        self(failure, latency)
+    }
+}
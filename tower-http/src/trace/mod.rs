@@ -0,0 +1,427 @@
+//! Observe the lifecycle of requests.
+//!
+//! `Trace` calls user-supplied hooks as a request is processed: when it is
+//! received, when the response headers are ready, for each chunk of the
+//! response body, at the end of the body and when the request fails. The
+//! hooks are given the elapsed time, so `Trace` can serve logging as well as
+//! latency metrics.
+//!
+//! A request fails when the inner service or the response body returns an
+//! error, or when the response is classified as a failure by a
+//! `ClassifyResponse`, by default `ServerErrorsAsFailures`.
+//!
+//! Every hook defaults to `()`, which does nothing, and can be replaced by a
+//! closure:
+//!
+//! ```ignore
+//! let service = Trace::new(service)
+//!     .on_request(|req: &Request<_>| println!("{} {}", req.method(), req.uri()))
+//!     .on_response(|res: &Response<_>, latency: Duration| {
+//!         println!("{} in {:?}", res.status(), latency)
+//!     });
+//! ```
+//!
+//! With the `tracing` feature, each request is also given a `tracing` span,
+//! created by a `MakeSpan`. The inner service, the hooks and the response
+//! body are run inside the span, and the status and latency of the response
+//! are recorded on it.
+
+mod hooks;
+mod span;
+
+pub use self::hooks::{OnBodyChunk, OnEos, OnFailure, OnRequest, OnResponse};
+#[cfg(feature = "tracing")]
+pub use self::span::{DefaultMakeSpan, MakeSpan};
+
+use self::span::RequestSpan;
+#[cfg(feature = "tracing")]
+use self::span::SharedMakeSpan;
+use crate::classify::{ClassifyResponse, ServerErrorsAsFailures, SharedClassifier};
+use futures::{Async, Future, Poll};
+use http::request::Parts;
+use http::{HeaderMap, Request, Response};
+use http_body::Body;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tower_service::Service;
+
+/// Calls hooks over the lifecycle of each request.
+#[derive(Debug, Clone)]
+pub struct Trace<S, OnReq = (), OnRes = (), OnChunk = (), OnEnd = (), OnFail = ()> {
+    inner: S,
+    config: Config,
+    on_request: OnReq,
+    on_response: OnRes,
+    on_body_chunk: OnChunk,
+    on_eos: OnEnd,
+    on_failure: OnFail,
+}
+
+/// Response future for `Trace`.
+#[derive(Debug)]
+pub struct ResponseFuture<F, OnRes, OnChunk, OnEnd, OnFail> {
+    inner: F,
+    start: Instant,
+    classifier: SharedClassifier,
+    span: RequestSpan,
+    hooks: Option<(OnRes, OnChunk, OnEnd, OnFail)>,
+}
+
+/// A response body calling the body hooks of `Trace`.
+///
+/// The end-of-stream hook is called with the trailers once they are polled,
+/// or when the body is dropped if they never are.
+#[derive(Debug)]
+pub struct TraceBody<B, OnChunk, OnEnd, OnFail>
+where
+    OnEnd: OnEos,
+{
+    inner: B,
+    start: Instant,
+    classifier: SharedClassifier,
+    last_chunk: Instant,
+    on_body_chunk: OnChunk,
+    on_eos: Option<OnEnd>,
+    /// The latency at the end of the data, while waiting for the trailers.
+    data_end: Option<Duration>,
+    on_failure: OnFail,
+    span: RequestSpan,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    classifier: SharedClassifier,
+    #[cfg(feature = "tracing")]
+    make_span: SharedMakeSpan,
+}
+
+// ===== impl Trace =====
+
+impl<S> Trace<S> {
+    /// Create a new `Trace` whose hooks do nothing.
+    pub fn new(inner: S) -> Self {
+        Trace {
+            inner,
+            config: Config {
+                classifier: SharedClassifier::new(ServerErrorsAsFailures::new()),
+                #[cfg(feature = "tracing")]
+                make_span: SharedMakeSpan::new(DefaultMakeSpan::new()),
+            },
+            on_request: (),
+            on_response: (),
+            on_body_chunk: (),
+            on_eos: (),
+            on_failure: (),
+        }
+    }
+}
+
+impl<S, OnReq, OnRes, OnChunk, OnEnd, OnFail> Trace<S, OnReq, OnRes, OnChunk, OnEnd, OnFail> {
+    /// Set the classifier deciding which responses are failures.
+    ///
+    /// Defaults to `ServerErrorsAsFailures`.
+    pub fn classifier<C>(mut self, classifier: C) -> Self
+    where
+        C: ClassifyResponse + Send + Sync + 'static,
+    {
+        self.config.classifier = SharedClassifier::new(classifier);
+        self
+    }
+
+    /// Set how the `tracing` span of each request is created.
+    ///
+    /// Defaults to `DefaultMakeSpan`.
+    #[cfg(feature = "tracing")]
+    pub fn make_span<M>(mut self, make_span: M) -> Self
+    where
+        M: MakeSpan + Send + Sync + 'static,
+    {
+        self.config.make_span = SharedMakeSpan::new(make_span);
+        self
+    }
+
+    /// Set the hook called when a request is received.
+    pub fn on_request<T>(self, on_request: T) -> Trace<S, T, OnRes, OnChunk, OnEnd, OnFail> {
+        Trace {
+            inner: self.inner,
+            config: self.config,
+            on_request,
+            on_response: self.on_response,
+            on_body_chunk: self.on_body_chunk,
+            on_eos: self.on_eos,
+            on_failure: self.on_failure,
+        }
+    }
+
+    /// Set the hook called when the response headers are ready.
+    pub fn on_response<T>(self, on_response: T) -> Trace<S, OnReq, T, OnChunk, OnEnd, OnFail> {
+        Trace {
+            inner: self.inner,
+            config: self.config,
+            on_request: self.on_request,
+            on_response,
+            on_body_chunk: self.on_body_chunk,
+            on_eos: self.on_eos,
+            on_failure: self.on_failure,
+        }
+    }
+
+    /// Set the hook called for each chunk of the response body.
+    pub fn on_body_chunk<T>(self, on_body_chunk: T) -> Trace<S, OnReq, OnRes, T, OnEnd, OnFail> {
+        Trace {
+            inner: self.inner,
+            config: self.config,
+            on_request: self.on_request,
+            on_response: self.on_response,
+            on_body_chunk,
+            on_eos: self.on_eos,
+            on_failure: self.on_failure,
+        }
+    }
+
+    /// Set the hook called at the end of the response body.
+    pub fn on_eos<T>(self, on_eos: T) -> Trace<S, OnReq, OnRes, OnChunk, T, OnFail> {
+        Trace {
+            inner: self.inner,
+            config: self.config,
+            on_request: self.on_request,
+            on_response: self.on_response,
+            on_body_chunk: self.on_body_chunk,
+            on_eos,
+            on_failure: self.on_failure,
+        }
+    }
+
+    /// Set the hook called when a request fails.
+    pub fn on_failure<T>(self, on_failure: T) -> Trace<S, OnReq, OnRes, OnChunk, OnEnd, T> {
+        Trace {
+            inner: self.inner,
+            config: self.config,
+            on_request: self.on_request,
+            on_response: self.on_response,
+            on_body_chunk: self.on_body_chunk,
+            on_eos: self.on_eos,
+            on_failure,
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, OnReq, OnRes, OnChunk, OnEnd, OnFail, ReqBody, ResBody> Service<Request<ReqBody>>
+    for Trace<S, OnReq, OnRes, OnChunk, OnEnd, OnFail>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: Body,
+    OnReq: OnRequest<ReqBody>,
+    OnRes: OnResponse<ResBody> + Clone,
+    OnChunk: OnBodyChunk<ResBody::Data> + Clone,
+    OnEnd: OnEos + Clone,
+    OnFail: OnFailure + Clone,
+    S::Error: fmt::Display,
+    ResBody::Error: fmt::Display,
+{
+    type Response = Response<TraceBody<ResBody, OnChunk, OnEnd, OnFail>>;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, OnRes, OnChunk, OnEnd, OnFail>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let start = Instant::now();
+        let (parts, body) = req.into_parts();
+        let span = self.config.span(&parts);
+        let req = Request::from_parts(parts, body);
+
+        let (on_request, inner) = (&mut self.on_request, &mut self.inner);
+        let future = span.in_scope(|| {
+            on_request.on_request(&req);
+            inner.call(req)
+        });
+
+        ResponseFuture {
+            inner: future,
+            start,
+            classifier: self.config.classifier.clone(),
+            span,
+            hooks: Some((
+                self.on_response.clone(),
+                self.on_body_chunk.clone(),
+                self.on_eos.clone(),
+                self.on_failure.clone(),
+            )),
+        }
+    }
+}
+
+impl Config {
+    #[cfg(feature = "tracing")]
+    fn span(&self, request: &Parts) -> RequestSpan {
+        RequestSpan::new(&self.make_span, request)
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    fn span(&self, request: &Parts) -> RequestSpan {
+        RequestSpan::new(request)
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, OnRes, OnChunk, OnEnd, OnFail, B> Future
+    for ResponseFuture<F, OnRes, OnChunk, OnEnd, OnFail>
+where
+    F: Future<Item = Response<B>>,
+    B: Body,
+    F::Error: fmt::Display,
+    OnRes: OnResponse<B>,
+    OnEnd: OnEos,
+    OnFail: OnFailure,
+{
+    type Item = Response<TraceBody<B, OnChunk, OnEnd, OnFail>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let (inner, span) = (&mut self.inner, &self.span);
+        let response = match span.in_scope(|| inner.poll()) {
+            Ok(Async::Ready(response)) => response,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(e) => {
+                let (_, _, _, mut on_failure) = self.hooks.take().expect("polled after completion");
+                let failure = self.classifier.classify_error(&e);
+                on_failure.on_failure(&failure, self.start.elapsed());
+                return Err(e);
+            }
+        };
+
+        let (on_response, on_body_chunk, on_eos, mut on_failure) =
+            self.hooks.take().expect("polled after completion");
+        let latency = self.start.elapsed();
+        self.span.record_response(response.status(), latency);
+        on_response.on_response(&response, latency);
+        if let Err(failure) = self
+            .classifier
+            .classify_response(response.status(), response.headers())
+        {
+            on_failure.on_failure(&failure, latency);
+        }
+
+        let start = self.start;
+        let classifier = self.classifier.clone();
+        let span = self.span.clone();
+        Ok(Async::Ready(response.map(|body| TraceBody {
+            inner: body,
+            start,
+            classifier,
+            span,
+            last_chunk: Instant::now(),
+            on_body_chunk,
+            on_eos: Some(on_eos),
+            data_end: None,
+            on_failure,
+        })))
+    }
+}
+
+// ===== impl TraceBody =====
+
+impl<B, OnChunk, OnEnd, OnFail> TraceBody<B, OnChunk, OnEnd, OnFail>
+where
+    OnEnd: OnEos,
+{
+    fn end(&mut self, trailers: Option<&HeaderMap>) {
+        if let Some(on_eos) = self.on_eos.take() {
+            on_eos.on_eos(trailers, self.start.elapsed());
+        }
+    }
+}
+
+impl<B, OnChunk, OnEnd, OnFail> Body for TraceBody<B, OnChunk, OnEnd, OnFail>
+where
+    B: Body,
+    OnChunk: OnBodyChunk<B::Data>,
+    OnEnd: OnEos,
+    B::Error: fmt::Display,
+    OnFail: OnFailure,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let (inner, span) = (&mut self.inner, &self.span);
+        match span.in_scope(|| inner.poll_data()) {
+            Ok(Async::Ready(Some(chunk))) => {
+                let now = Instant::now();
+                self.on_body_chunk
+                    .on_body_chunk(&chunk, now.duration_since(self.last_chunk));
+                self.last_chunk = now;
+                Ok(Async::Ready(Some(chunk)))
+            }
+            Ok(Async::Ready(None)) => {
+                // Wait for the trailers unless the body is known to be over.
+                if self.inner.is_end_stream() {
+                    self.end(None);
+                } else if self.data_end.is_none() {
+                    self.data_end = Some(self.start.elapsed());
+                }
+                Ok(Async::Ready(None))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => {
+                self.on_eos = None;
+                let failure = self.classifier.classify_error(&e);
+                self.on_failure.on_failure(&failure, self.start.elapsed());
+                Err(e)
+            }
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        let (inner, span) = (&mut self.inner, &self.span);
+        match span.in_scope(|| inner.poll_trailers()) {
+            Ok(Async::Ready(trailers)) => {
+                self.end(trailers.as_ref());
+                Ok(Async::Ready(trailers))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(e) => {
+                self.on_eos = None;
+                let failure = self.classifier.classify_error(&e);
+                self.on_failure.on_failure(&failure, self.start.elapsed());
+                Err(e)
+            }
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+}
+
+impl<B, OnChunk, OnEnd, OnFail> Drop for TraceBody<B, OnChunk, OnEnd, OnFail>
+where
+    OnEnd: OnEos,
+{
+    fn drop(&mut self) {
+        // HTTP/1 servers often never poll the trailers.
+        if let Some(on_eos) = self.on_eos.take() {
+            let latency = self.data_end.unwrap_or_else(|| self.start.elapsed());
+            on_eos.on_eos(None, latency);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use http::request::Parts;
+use http::StatusCode;
+use std::time::Duration;
+
+#[cfg(feature = "tracing")]
+pub(crate) use self::make::SharedMakeSpan;
+#[cfg(feature = "tracing")]
+pub use self::make::{DefaultMakeSpan, MakeSpan};
+
+/// The span of a request, or nothing without the `tracing` feature.
+#[derive(Debug, Clone)]
+pub(crate) struct RequestSpan {
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+impl RequestSpan {
+    #[cfg(feature = "tracing")]
+    pub(crate) fn new(make_span: &SharedMakeSpan, request: &Parts) -> Self {
+        RequestSpan {
+            span: make_span.make_span(request),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub(crate) fn new(_: &Parts) -> Self {
+        RequestSpan {}
+    }
+
+    /// Runs `f` inside the span.
+    pub(crate) fn in_scope<F, T>(&self, f: F) -> T
+    where
+        F: FnOnce() -> T,
+    {
+        #[cfg(feature = "tracing")]
+        {
+            self.span.in_scope(f)
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            f()
+        }
+    }
+
+    /// Records the status and latency of the response on the span.
+    pub(crate) fn record_response(&self, status: StatusCode, latency: Duration) {
+        #[cfg(feature = "tracing")]
+        {
+            let millis = latency.as_secs() * 1000 + u64::from(latency.subsec_millis());
+            self.span.record("status", &status.as_u16());
+            self.span.record("latency_ms", &millis);
+        }
+        #[cfg(not(feature = "tracing"))]
+        {
+            let _ = (status, latency);
+        }
+    }
+}
+
+#[cfg(feature = "tracing")]
+mod make {
+    use http::request::Parts;
+    use std::fmt;
+    use std::sync::Arc;
+    use tracing::field::Empty;
+    use tracing::Span;
+
+    /// Creates the span of a request.
+    pub trait MakeSpan {
+        /// Create the span of the request with the given head.
+        ///
+        /// The span should declare the `status` and `latency_ms` fields,
+        /// which are recorded when the response headers are ready.
+        fn make_span(&self, request: &Parts) -> Span;
+    }
+
+    /// Creates an `INFO` span named `request` with the method, path,
+    /// version and `X-Request-Id` of the request.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct DefaultMakeSpan {
+        _p: (),
+    }
+
+    #[derive(Clone)]
+    pub(crate) struct SharedMakeSpan(Arc<dyn MakeSpan + Send + Sync>);
+
+    impl DefaultMakeSpan {
+        /// Create a new `DefaultMakeSpan`.
+        pub fn new() -> Self {
+            DefaultMakeSpan::default()
+        }
+    }
+
+    impl MakeSpan for DefaultMakeSpan {
+        fn make_span(&self, request: &Parts) -> Span {
+            let span = tracing::info_span!(
+                "request",
+                method = %request.method,
+                path = %request.uri.path(),
+                version = ?request.version,
+                request_id = Empty,
+                status = Empty,
+                latency_ms = Empty,
+            );
+            let request_id = request
+                .headers
+                .get("x-request-id")
+                .and_then(|value| value.to_str().ok());
+            if let Some(request_id) = request_id {
+                span.record("request_id", &request_id);
+            }
+            span
+        }
+    }
+
+    impl<F> MakeSpan for F
+    where
+        F: Fn(&Parts) -> Span,
+    {
+        fn make_span(&self, request: &Parts) -> Span {
+            self(request)
+        }
+    }
+
+    impl SharedMakeSpan {
+        pub(crate) fn new<M>(make_span: M) -> Self
+        where
+            M: MakeSpan + Send + Sync + 'static,
+        {
+            SharedMakeSpan(Arc::new(make_span))
+        }
+
+        pub(crate) fn make_span(&self, request: &Parts) -> Span {
+            self.0.make_span(request)
+        }
+    }
+
+    impl fmt::Debug for SharedMakeSpan {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("SharedMakeSpan").finish()
+        }
+    }
+}
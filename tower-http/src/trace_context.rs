@@ -0,0 +1,414 @@
+//! Distributed tracing context propagation.
+//!
+//! `ExtractTraceContext` parses the W3C `traceparent` and `tracestate`
+//! headers (and optionally the B3 headers used by Zipkin) of incoming
+//! requests into a `TraceContext` request extension. Handlers copy that
+//! extension onto the requests they send, and `PropagateTraceContext`
+//! injects it as headers into outgoing requests, as a child of the incoming
+//! span.
+
+use http::header::{HeaderMap, HeaderName, HeaderValue};
+use http::Request;
+use std::fmt;
+use tower_service::Service;
+
+/// Name of the W3C `traceparent` header.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Name of the W3C `tracestate` header.
+pub const TRACESTATE: &str = "tracestate";
+
+/// Name of the single B3 header.
+pub const B3: &str = "b3";
+
+/// Name of the `X-B3-TraceId` header.
+pub const X_B3_TRACE_ID: &str = "x-b3-traceid";
+
+/// Name of the `X-B3-SpanId` header.
+pub const X_B3_SPAN_ID: &str = "x-b3-spanid";
+
+/// Name of the `X-B3-Sampled` header.
+pub const X_B3_SAMPLED: &str = "x-b3-sampled";
+
+/// The tracing context of a request.
+#[derive(Clone, PartialEq, Eq)]
+pub struct TraceContext {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    sampled: bool,
+    state: Option<HeaderValue>,
+}
+
+/// Header formats understood by the trace context middleware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// W3C `traceparent` and `tracestate`.
+    W3c,
+    /// W3C, falling back to B3 when no `traceparent` is present.
+    W3cAndB3,
+}
+
+/// Extracts a `TraceContext` extension from incoming requests.
+#[derive(Debug, Clone)]
+pub struct ExtractTraceContext<S> {
+    inner: S,
+    format: Format,
+}
+
+/// Injects the `TraceContext` extension of outgoing requests as headers.
+#[derive(Debug, Clone)]
+pub struct PropagateTraceContext<S> {
+    inner: S,
+    format: Format,
+}
+
+// ===== impl TraceContext =====
+
+impl TraceContext {
+    /// Start a new, sampled trace.
+    pub fn new_root() -> Self {
+        TraceContext {
+            trace_id: random_id(),
+            span_id: random_id(),
+            sampled: true,
+            state: None,
+        }
+    }
+
+    /// Create a child context: same trace, fresh span id.
+    pub fn child(&self) -> Self {
+        TraceContext {
+            span_id: random_id(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns the trace id, as lowercase hex.
+    pub fn trace_id(&self) -> String {
+        hex(&self.trace_id)
+    }
+
+    /// Returns the span id, as lowercase hex.
+    pub fn span_id(&self) -> String {
+        hex(&self.span_id)
+    }
+
+    /// Returns whether the trace is sampled.
+    pub fn is_sampled(&self) -> bool {
+        self.sampled
+    }
+
+    /// Returns the vendor specific `tracestate`, if any.
+    pub fn state(&self) -> Option<&HeaderValue> {
+        self.state.as_ref()
+    }
+
+    /// Parse the context from request headers.
+    pub fn from_headers(headers: &HeaderMap, format: Format) -> Option<Self> {
+        if let Some(value) = headers.get(TRACEPARENT) {
+            let mut context = Self::parse_traceparent(value.to_str().ok()?)?;
+            context.state = headers.get(TRACESTATE).cloned();
+            return Some(context);
+        }
+
+        match format {
+            Format::W3c => None,
+            Format::W3cAndB3 => Self::from_b3(headers),
+        }
+    }
+
+    /// Parse a `traceparent` header value.
+    pub fn parse_traceparent(value: &str) -> Option<Self> {
+        let mut parts = value.trim().split('-');
+        let version = parse_hex::<[u8; 1]>(parts.next()?)?;
+        let trace_id = parse_hex::<[u8; 16]>(parts.next()?)?;
+        let span_id = parse_hex::<[u8; 8]>(parts.next()?)?;
+        let flags = parse_hex::<[u8; 1]>(parts.next()?)?;
+
+        // Version 00 has exactly four fields; later versions may add more.
+        if version[0] == 0xff || (version[0] == 0 && parts.next().is_some()) {
+            return None;
+        }
+        if trace_id == [0; 16] || span_id == [0; 8] {
+            return None;
+        }
+
+        Some(TraceContext {
+            trace_id,
+            span_id,
+            sampled: flags[0] & 1 == 1,
+            state: None,
+        })
+    }
+
+    fn from_b3(headers: &HeaderMap) -> Option<Self> {
+        let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+
+        let (trace_id, span_id, sampled) = match get(B3) {
+            Some(value) => {
+                let mut parts = value.split('-');
+                (parts.next()?, parts.next()?, parts.next())
+            }
+            None => (get(X_B3_TRACE_ID)?, get(X_B3_SPAN_ID)?, get(X_B3_SAMPLED)),
+        };
+
+        // B3 allows 64-bit trace ids, which are left-padded to 128 bits.
+        let trace_id = if trace_id.len() == 16 {
+            let mut padded = [0; 16];
+            padded[8..].copy_from_slice(&parse_hex::<[u8; 8]>(trace_id)?);
+            padded
+        } else {
+            parse_hex::<[u8; 16]>(trace_id)?
+        };
+
+        Some(TraceContext {
+            trace_id,
+            span_id: parse_hex::<[u8; 8]>(span_id)?,
+            sampled: sampled != Some("0"),
+            state: None,
+        })
+    }
+
+    /// Render the `traceparent` header value.
+    pub fn to_traceparent(&self) -> HeaderValue {
+        let value = format!(
+            "00-{}-{}-{:02x}",
+            hex(&self.trace_id),
+            hex(&self.span_id),
+            self.sampled as u8
+        );
+        HeaderValue::from_str(&value).expect("hex is a valid header value")
+    }
+
+    /// Write the context into request headers.
+    pub fn to_headers(&self, headers: &mut HeaderMap, format: Format) {
+        headers.insert(HeaderName::from_static(TRACEPARENT), self.to_traceparent());
+        match self.state {
+            Some(ref state) => {
+                headers.insert(HeaderName::from_static(TRACESTATE), state.clone());
+            }
+            None => {
+                headers.remove(TRACESTATE);
+            }
+        }
+
+        if format == Format::W3cAndB3 {
+            let value = format!(
+                "{}-{}-{}",
+                hex(&self.trace_id),
+                hex(&self.span_id),
+                self.sampled as u8
+            );
+            headers.insert(
+                HeaderName::from_static(B3),
+                HeaderValue::from_str(&value).expect("hex is a valid header value"),
+            );
+        }
+    }
+}
+
+impl fmt::Debug for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("TraceContext")
+            .field("trace_id", &self.trace_id())
+            .field("span_id", &self.span_id())
+            .field("sampled", &self.sampled)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+// ===== impl ExtractTraceContext =====
+
+impl<S> ExtractTraceContext<S> {
+    /// Create a new `ExtractTraceContext` reading the given format.
+    pub fn new(inner: S, format: Format) -> Self {
+        ExtractTraceContext { inner, format }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for ExtractTraceContext<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if let Some(context) = TraceContext::from_headers(req.headers(), self.format) {
+            req.extensions_mut().insert(context);
+        }
+        self.inner.call(req)
+    }
+}
+
+// ===== impl PropagateTraceContext =====
+
+impl<S> PropagateTraceContext<S> {
+    /// Create a new `PropagateTraceContext` writing the given format.
+    pub fn new(inner: S, format: Format) -> Self {
+        PropagateTraceContext { inner, format }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for PropagateTraceContext<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if let Some(context) = req
+            .extensions()
+            .get::<TraceContext>()
+            .map(TraceContext::child)
+        {
+            context.to_headers(req.headers_mut(), self.format);
+        }
+        self.inner.call(req)
+    }
+}
+
+// ===== helpers =====
+
+fn random_id<T>() -> T
+where
+    T: Default + AsMut<[u8]>,
+{
+    let mut id = T::default();
+    // An all-zero id is invalid.
+    while id.as_mut().iter().all(|&b| b == 0) {
+        rand::Rng::fill(&mut rand::thread_rng(), id.as_mut());
+    }
+    id
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn parse_hex<T>(s: &str) -> Option<T>
+where
+    T: Default + AsMut<[u8]>,
+{
+    let mut out = T::default();
+    let buf = out.as_mut();
+    if s.len() != buf.len() * 2 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    // Only lowercase hex is valid in `traceparent`.
+    if s.bytes().any(|b| b.is_ascii_uppercase()) {
+        return None;
+    }
+    for (i, byte) in buf.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16).ok()?;
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn parses_traceparent() {
+        let context = TraceContext::parse_traceparent(PARENT).unwrap();
+        assert_eq!(context.trace_id(), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(context.span_id(), "00f067aa0ba902b7");
+        assert!(context.is_sampled());
+        assert_eq!(context.to_traceparent(), PARENT);
+    }
+
+    #[test]
+    fn rejects_invalid_traceparent() {
+        for value in &[
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-extra",
+            "00-4bf92f3577b34da6-00f067aa0ba902b7-01",
+        ] {
+            assert!(
+                TraceContext::parse_traceparent(value).is_none(),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn parses_b3() {
+        let mut headers = HeaderMap::new();
+        headers.insert(X_B3_TRACE_ID, HeaderValue::from_static("a3ce929d0e0e4736"));
+        headers.insert(X_B3_SPAN_ID, HeaderValue::from_static("00f067aa0ba902b7"));
+        headers.insert(X_B3_SAMPLED, HeaderValue::from_static("0"));
+
+        assert!(TraceContext::from_headers(&headers, Format::W3c).is_none());
+
+        let context = TraceContext::from_headers(&headers, Format::W3cAndB3).unwrap();
+        assert_eq!(context.trace_id(), "0000000000000000a3ce929d0e0e4736");
+        assert!(!context.is_sampled());
+    }
+
+    #[test]
+    fn child_keeps_trace() {
+        let mut headers = HeaderMap::new();
+        headers.insert(TRACEPARENT, HeaderValue::from_static(PARENT));
+        headers.insert(TRACESTATE, HeaderValue::from_static("congo=t61rcWkgMzE"));
+
+        let parent = TraceContext::from_headers(&headers, Format::W3c).unwrap();
+        let child = parent.child();
+
+        let mut out = HeaderMap::new();
+        child.to_headers(&mut out, Format::W3c);
+
+        let propagated = TraceContext::from_headers(&out, Format::W3c).unwrap();
+        assert_eq!(propagated.trace_id(), parent.trace_id());
+        assert_ne!(propagated.span_id(), parent.span_id());
+        assert_eq!(propagated.state().unwrap(), "congo=t61rcWkgMzE");
+    }
+}
@@ -0,0 +1,126 @@
+//! Conventions for upgraded connections.
+//!
+//! Protocol upgrades, such as WebSocket handshakes answered with
+//! `101 Switching Protocols`, and `CONNECT` tunnels hand the connection over
+//! to another protocol once the response head is sent. Middleware which
+//! buffer, limit, cache or rewrite bodies would break them, so the
+//! middleware of this crate pass such exchanges through untouched:
+//!
+//! - requests are upgrade requests if they use `CONNECT`, or carry an
+//!   `Upgrade` header nominated by `Connection`;
+//! - responses are upgrade responses if their status is
+//!   `101 Switching Protocols`.
+//!
+//! Transports with other upgrade mechanisms, such as the extended `CONNECT`
+//! of HTTP/2, can insert an `Upgrade` extension into requests or responses
+//! to get the same treatment. Middleware written outside this crate should
+//! check `is_upgrade_request` and `is_upgrade_response` likewise.
+//!
+//! As requests are chosen by the client, middleware which enforce limits,
+//! such as `body_limit` and `timeout`, do not lift them for requests that
+//! merely ask for an upgrade or a tunnel, but only once the inner service
+//! has accepted it: with `101 Switching Protocols`, or with a `2xx` response
+//! to a `CONNECT` request.
+
+use http::header::{CONNECTION, UPGRADE};
+use http::{Method, Request, Response, StatusCode};
+
+/// Marks a request or response as belonging to an upgraded connection.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Upgrade {
+    _p: (),
+}
+
+impl Upgrade {
+    /// Create a new `Upgrade` marker.
+    pub fn new() -> Self {
+        Upgrade::default()
+    }
+}
+
+/// Returns whether `req` asks for an upgraded connection or a tunnel, and
+/// must be passed through untouched.
+pub fn is_upgrade_request<B>(req: &Request<B>) -> bool {
+    if is_tunnel_request(req) {
+        return true;
+    }
+    req.headers().contains_key(UPGRADE)
+        && req
+            .headers()
+            .get_all(CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|token| token.trim().eq_ignore_ascii_case("upgrade"))
+}
+
+/// Returns whether `req` asks for a tunnel opened by a successful response
+/// rather than by `101 Switching Protocols`: `CONNECT` requests and requests
+/// carrying the `Upgrade` extension.
+///
+/// Any client can send a `CONNECT` request, so this does not mean that a
+/// tunnel exists; see `opens_tunnel`.
+pub(crate) fn is_tunnel_request<B>(req: &Request<B>) -> bool {
+    req.method() == Method::CONNECT || req.extensions().get::<Upgrade>().is_some()
+}
+
+/// Returns whether `res` hands the connection over to another protocol,
+/// given whether it answers a request for which `is_tunnel_request` holds:
+/// either it is an upgrade response, or it accepts the tunnel with a `2xx`
+/// status.
+pub(crate) fn opens_tunnel<B>(tunnel_request: bool, res: &Response<B>) -> bool {
+    is_upgrade_response(res) || (tunnel_request && res.status().is_success())
+}
+
+/// Returns whether `res` switches the connection to another protocol, and
+/// must be passed through untouched.
+pub fn is_upgrade_response<B>(res: &Response<B>) -> bool {
+    res.status() == StatusCode::SWITCHING_PROTOCOLS || res.extensions().get::<Upgrade>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_upgrades() {
+        let request = Request::get("/chat")
+            .header(CONNECTION, "keep-alive, Upgrade")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(is_upgrade_request(&request));
+
+        let request = Request::get("/chat")
+            .header(UPGRADE, "websocket")
+            .body(())
+            .unwrap();
+        assert!(!is_upgrade_request(&request));
+
+        let request = Request::connect("example.com:443").body(()).unwrap();
+        assert!(is_upgrade_request(&request));
+
+        let mut request = Request::get("/").body(()).unwrap();
+        assert!(!is_upgrade_request(&request));
+        request.extensions_mut().insert(Upgrade::new());
+        assert!(is_upgrade_request(&request));
+
+        let mut response = Response::new(());
+        assert!(!is_upgrade_response(&response));
+        *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        assert!(is_upgrade_response(&response));
+    }
+
+    #[test]
+    fn tunnels_open_on_acceptance() {
+        let mut response = Response::new(());
+        assert!(opens_tunnel(true, &response));
+        assert!(!opens_tunnel(false, &response));
+
+        *response.status_mut() = StatusCode::FORBIDDEN;
+        assert!(!opens_tunnel(true, &response));
+
+        *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        assert!(opens_tunnel(false, &response));
+    }
+}
@@ -0,0 +1,155 @@
+//! Middleware that sets a default `User-Agent` on outgoing requests.
+
+use crate::util::is_token;
+use http::header::{HeaderValue, USER_AGENT};
+use http::{HttpTryFrom, Request};
+use std::fmt;
+use tower_service::Service;
+
+/// Sets a `User-Agent` header on requests that do not already carry one.
+#[derive(Debug, Clone)]
+pub struct SetUserAgent<S> {
+    inner: S,
+    value: HeaderValue,
+}
+
+/// Configure a `SetUserAgent` instance.
+///
+/// The resulting header is made of product tokens in the order they were
+/// added, optionally followed by a comment, e.g.
+/// `my-client/1.0 tower-http/0.1.0 (+https://example.com)`.
+#[derive(Debug, Default)]
+pub struct Builder {
+    products: Vec<String>,
+    comment: Option<String>,
+    invalid: bool,
+}
+
+/// Errors that can happen when building a `SetUserAgent`.
+#[derive(Debug)]
+pub struct BuilderError {
+    _p: (),
+}
+
+// ===== impl SetUserAgent =====
+
+impl<S> SetUserAgent<S> {
+    /// Create a new `SetUserAgent` using the given header value.
+    pub fn new(inner: S, value: HeaderValue) -> Self {
+        SetUserAgent { inner, value }
+    }
+
+    /// Returns the `User-Agent` value set by this middleware.
+    pub fn user_agent(&self) -> &HeaderValue {
+        &self.value
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, B> Service<Request<B>> for SetUserAgent<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, mut req: Request<B>) -> Self::Future {
+        if !req.headers().contains_key(USER_AGENT) {
+            req.headers_mut().insert(USER_AGENT, self.value.clone());
+        }
+
+        self.inner.call(req)
+    }
+}
+
+// ===== impl Builder =====
+
+impl Builder {
+    /// Return a new, empty builder.
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Append a `name/version` product token.
+    pub fn product(mut self, name: &str, version: &str) -> Self {
+        if !is_token(name) || !is_token(version) {
+            self.invalid = true;
+        }
+        self.products.push(format!("{}/{}", name, version));
+        self
+    }
+
+    /// Append a product token without a version.
+    pub fn product_name(mut self, name: &str) -> Self {
+        if !is_token(name) {
+            self.invalid = true;
+        }
+        self.products.push(name.to_owned());
+        self
+    }
+
+    /// Append the `tower-http/<version>` product token for this crate.
+    pub fn tower_http_product(self) -> Self {
+        self.product(env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+    }
+
+    /// Set a trailing comment, rendered in parentheses.
+    pub fn comment(mut self, comment: &str) -> Self {
+        if comment.contains(|c| c == '(' || c == ')') {
+            self.invalid = true;
+        }
+        self.comment = Some(comment.to_owned());
+        self
+    }
+
+    /// Render the configured product tokens into a header value.
+    pub fn to_header_value(&self) -> Result<HeaderValue, BuilderError> {
+        if self.invalid || self.products.is_empty() {
+            return Err(BuilderError { _p: () });
+        }
+
+        let mut value = self.products.join(" ");
+        if let Some(ref comment) = self.comment {
+            value.push_str(" (");
+            value.push_str(comment);
+            value.push(')');
+        }
+
+        HeaderValue::try_from(value).map_err(|_| BuilderError { _p: () })
+    }
+
+    /// Build the `SetUserAgent` from the provided settings.
+    pub fn build<S>(self, inner: S) -> Result<SetUserAgent<S>, BuilderError> {
+        let value = self.to_header_value()?;
+        Ok(SetUserAgent::new(inner, value))
+    }
+}
+
+// ===== impl BuilderError =====
+
+impl fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("invalid user agent")
+    }
+}
+
+impl std::error::Error for BuilderError {}
@@ -0,0 +1,124 @@
+//! Helpers shared between middleware.
+
+use bytes::Bytes;
+use http::uri::{PathAndQuery, Uri};
+
+/// Returns whether `s` is a non-empty RFC 7230 `token`.
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| match b {
+            b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_'
+            | b'`' | b'|' | b'~' => true,
+            _ => b.is_ascii_alphanumeric(),
+        })
+}
+
+/// Compares two byte strings in time independent of their contents.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Returns `uri` with its query replaced, or removed if `query` is empty.
+pub(crate) fn with_query(uri: &Uri, query: &str) -> Option<Uri> {
+    let mut path_and_query = uri.path().to_owned();
+    if !query.is_empty() {
+        path_and_query.push('?');
+        path_and_query.push_str(query);
+    }
+    let mut parts = uri.clone().into_parts();
+    parts.path_and_query = Some(PathAndQuery::from_shared(Bytes::from(path_and_query)).ok()?);
+    Uri::from_parts(parts).ok()
+}
+
+/// A media type, such as `text/html; charset=utf-8`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MediaType {
+    /// The type and subtype, lowercased, such as `text/html`.
+    pub(crate) essence: String,
+    /// The parameters, with lowercased names and unquoted values.
+    pub(crate) params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    /// Parses a media type, or a media range such as `text/*`.
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split(';');
+        let essence = parts.next()?.trim().to_ascii_lowercase();
+        let mut halves = essence.splitn(2, '/');
+        if !is_token(halves.next()?) || !is_token(halves.next()?) {
+            return None;
+        }
+
+        let mut params = Vec::new();
+        for param in parts {
+            let param = param.trim();
+            if param.is_empty() {
+                continue;
+            }
+            let mut pair = param.splitn(2, '=');
+            let name = pair.next()?.trim().to_ascii_lowercase();
+            let value = pair.next()?.trim();
+            if !is_token(&name) {
+                return None;
+            }
+            let value = if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                let mut unquoted = String::with_capacity(value.len());
+                let mut chars = value[1..value.len() - 1].chars();
+                while let Some(c) = chars.next() {
+                    unquoted.push(if c == '\\' { chars.next()? } else { c });
+                }
+                unquoted
+            } else {
+                value.to_owned()
+            };
+            params.push((name, value));
+        }
+        Some(MediaType { essence, params })
+    }
+
+    /// Returns the value of the parameter `name`, which must be lowercase.
+    pub(crate) fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|&(n, _)| n == name)
+            .map(|(_, value)| &**value)
+    }
+
+    /// Returns whether this media type is within the range `range`, such
+    /// as `text/*` or `*/*`, ignoring parameters.
+    pub(crate) fn is_within(&self, range: &str) -> bool {
+        if range == "*/*" {
+            true
+        } else if range.ends_with("/*") {
+            self.essence.starts_with(&range[..range.len() - 1])
+        } else {
+            self.essence == range
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_media_types() {
+        let media_type = MediaType::parse("Text/HTML; Charset=\"utf-8\"; q=0.5").unwrap();
+        assert_eq!(media_type.essence, "text/html");
+        assert_eq!(media_type.param("charset"), Some("utf-8"));
+        assert_eq!(media_type.param("q"), Some("0.5"));
+        assert!(media_type.is_within("text/*"));
+        assert!(media_type.is_within("*/*"));
+        assert!(!media_type.is_within("text/plain"));
+
+        let media_type = MediaType::parse("a/b; x=\"q\\\"uote\"").unwrap();
+        assert_eq!(media_type.param("x"), Some("q\"uote"));
+
+        assert_eq!(MediaType::parse("text"), None);
+        assert_eq!(MediaType::parse("text/"), None);
+        assert_eq!(MediaType::parse("text/html; charset"), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Middleware that appends header names to the `Vary` response header.
+
+use futures::{try_ready, Async, Future, Poll};
+use http::header::{HeaderMap, HeaderName, HeaderValue, VARY};
+use http::{Request, Response};
+use std::sync::Arc;
+use tower_service::Service;
+
+/// Appends configured header names to the `Vary` header of responses.
+///
+/// Names already listed by the inner service are not duplicated, and a
+/// response with `Vary: *` is left untouched.
+#[derive(Debug, Clone)]
+pub struct AppendVary<S> {
+    inner: S,
+    names: Arc<[HeaderName]>,
+}
+
+/// Response future for `AppendVary`.
+#[derive(Debug)]
+pub struct ResponseFuture<F> {
+    inner: F,
+    names: Arc<[HeaderName]>,
+}
+
+// ===== impl AppendVary =====
+
+impl<S> AppendVary<S> {
+    /// Create a new `AppendVary` appending the given header names.
+    pub fn new<I>(inner: S, names: I) -> Self
+    where
+        I: IntoIterator<Item = HeaderName>,
+    {
+        let names: Vec<HeaderName> = names.into_iter().collect();
+        AppendVary {
+            inner,
+            names: names.into(),
+        }
+    }
+
+    /// Returns a reference to the inner service.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns a mutable reference to the inner service.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    /// Consumes `self`, returning the inner service.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AppendVary<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            names: self.names.clone(),
+        }
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, B> Future for ResponseFuture<F>
+where
+    F: Future<Item = Response<B>>,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut res = try_ready!(self.inner.poll());
+        append_vary(res.headers_mut(), &self.names);
+        Ok(Async::Ready(res))
+    }
+}
+
+/// Appends `names` to the `Vary` header in `headers`, skipping names that are
+/// already listed.
+pub fn append_vary(headers: &mut HeaderMap, names: &[HeaderName]) {
+    let mut listed = Vec::new();
+
+    for value in headers.get_all(VARY).iter() {
+        let value = match value.to_str() {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        for item in value.split(',') {
+            let item = item.trim();
+            if item == "*" {
+                return;
+            }
+            if !item.is_empty() {
+                listed.push(item.to_ascii_lowercase());
+            }
+        }
+    }
+
+    for name in names {
+        // `HeaderName`s are always lowercase.
+        if !listed.iter().any(|item| item == name.as_str()) {
+            headers.append(VARY, HeaderValue::from_str(name.as_str()).unwrap());
+            listed.push(name.as_str().to_owned());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use http::header::{ACCEPT, ACCEPT_ENCODING, ACCEPT_LANGUAGE};
+
+    fn vary(headers: &HeaderMap) -> Vec<&str> {
+        headers
+            .get_all(VARY)
+            .iter()
+            .map(|v| v.to_str().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn appends_missing_names() {
+        let mut headers = HeaderMap::new();
+        headers.insert(VARY, HeaderValue::from_static("Accept-Encoding"));
+
+        append_vary(&mut headers, &[ACCEPT_ENCODING, ACCEPT, ACCEPT_LANGUAGE]);
+
+        assert_eq!(
+            vary(&headers),
+            ["Accept-Encoding", "accept", "accept-language"]
+        );
+    }
+
+    #[test]
+    fn leaves_wildcard_alone() {
+        let mut headers = HeaderMap::new();
+        headers.insert(VARY, HeaderValue::from_static("*"));
+
+        append_vary(&mut headers, &[ACCEPT]);
+
+        assert_eq!(vary(&headers), ["*"]);
+    }
+}
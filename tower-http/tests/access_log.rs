@@ -0,0 +1,123 @@
+mod support;
+
+use futures::{Async, Future};
+use http::header::{REFERER, USER_AGENT};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower_http::access_log::{AccessLog, Builder, Format};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::{chunks, Chunks};
+
+fn split_time(line: &str) -> (&str, &str) {
+    let open = line.find('[').unwrap();
+    let close = line.find(']').unwrap();
+    (&line[..open], &line[close + 1..])
+}
+
+#[test]
+fn logs_combined_format_after_body() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let writer = {
+        let lines = lines.clone();
+        move |line: &str| lines.lock().unwrap().push(line.to_owned())
+    };
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = AccessLog::new(service, writer);
+
+    let mut request = Request::get("/index.html?q=1")
+        .header(REFERER, "http://example.com/")
+        .header(USER_AGENT, "curl/7.64.0")
+        .body(())
+        .unwrap();
+    let addr: SocketAddr = "192.0.2.1:4321".parse().unwrap();
+    request.extensions_mut().insert(addr);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(chunks(&["hello", " world"])));
+
+    let mut body = response.wait().unwrap().into_body();
+    assert!(lines.lock().unwrap().is_empty());
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+
+    let lines = lines.lock().unwrap();
+    assert_eq!(lines.len(), 1);
+    assert_eq!(
+        split_time(&lines[0]),
+        (
+            "192.0.2.1 - - ",
+            " \"GET /index.html?q=1 HTTP/1.1\" 200 11 \"http://example.com/\" \"curl/7.64.0\""
+        )
+    );
+}
+
+#[test]
+fn logs_common_format_with_latency() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let writer = {
+        let lines = lines.clone();
+        move |line: &str| lines.lock().unwrap().push(line.to_owned())
+    };
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = Builder::new()
+        .format(Format::Common)
+        .latency(true)
+        .build(service, writer);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::post("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    let mut res = Response::new(chunks(&[]));
+    *res.status_mut() = StatusCode::NO_CONTENT;
+    send_response.send_response(res);
+    drop(response.wait().unwrap());
+
+    let lines = lines.lock().unwrap();
+    let (head, tail) = split_time(&lines[0]);
+    assert_eq!(head, "- - - ");
+    assert!(tail.starts_with(" \"POST / HTTP/1.1\" 204 - 0."));
+}
+
+#[test]
+fn logs_only_slow_requests() {
+    let lines = Arc::new(Mutex::new(Vec::new()));
+    let writer = {
+        let lines = lines.clone();
+        move |line: &str| lines.lock().unwrap().push(line.to_owned())
+    };
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = Builder::new()
+        .format(Format::Common)
+        .slow_threshold(Duration::from_secs(3600))
+        .build(service, writer.clone());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(chunks(&[])));
+    drop(response.wait().unwrap());
+    assert!(lines.lock().unwrap().is_empty());
+
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = Builder::new()
+        .format(Format::Common)
+        .slow_threshold(Duration::from_secs(0))
+        .build(service, writer);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(chunks(&[])));
+    drop(response.wait().unwrap());
+
+    let lines = lines.lock().unwrap();
+    let (_, tail) = split_time(&lines[0]);
+    assert!(tail.starts_with(" \"GET / HTTP/1.1\" 200 - headers=0."));
+    assert!(tail.contains(" body=0."));
+}
@@ -0,0 +1,25 @@
+use http::header::AUTHORIZATION;
+use http::{Request, Response};
+use tower_http::auth::AddAuthorization;
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn adds_and_rotates_authorization() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = AddAuthorization::bearer(service, "first");
+    let auth = service.handle();
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get("/").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[AUTHORIZATION], "Bearer first");
+    assert!(request.headers()[AUTHORIZATION].is_sensitive());
+
+    auth.set_bearer("second");
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get("/").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[AUTHORIZATION], "Bearer second");
+}
@@ -0,0 +1,76 @@
+use futures::{future, Async, Future};
+use http::header::HOST;
+use http::{Request, Response, StatusCode};
+use tower_http::allowed_hosts::{AllowedHosts, Builder};
+use tower_service::Service;
+use tower_test::mock;
+
+fn builder() -> Builder {
+    Builder::new().allow("example.com").allow("*.example.org")
+}
+
+/// Returns the status of the response to `request`, or `None` if it was
+/// passed to the inner service.
+fn check(builder: Builder, request: Request<()>) -> Option<StatusCode> {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+    assert!(service.poll_ready().is_ok());
+    let mut response = service.call(request);
+    future::lazy(|| match response.poll().unwrap() {
+        Async::Ready(response) => Ok::<_, ()>(Some(response.status())),
+        Async::NotReady => Ok(None),
+    })
+    .wait()
+    .unwrap()
+}
+
+fn with_host(host: &'static str) -> Request<()> {
+    Request::get("/").header(HOST, host).body(()).unwrap()
+}
+
+#[test]
+fn passes_allowed_hosts() {
+    assert_eq!(check(builder(), with_host("example.com")), None);
+    assert_eq!(check(builder(), with_host("EXAMPLE.com.:8080")), None);
+    assert_eq!(check(builder(), with_host("www.example.org")), None);
+
+    let request = Request::get("http://example.com/").body(()).unwrap();
+    assert_eq!(check(builder(), request), None);
+
+    let service = AllowedHosts::new((), vec!["example.com"]);
+    assert_eq!(service.get_ref(), &());
+}
+
+#[test]
+fn rejects_other_hosts() {
+    let misdirected = Some(StatusCode::MISDIRECTED_REQUEST);
+    assert_eq!(check(builder(), with_host("evil.com")), misdirected);
+    assert_eq!(check(builder(), with_host("example.org")), misdirected);
+    assert_eq!(
+        check(builder(), with_host("example.com.evil.com")),
+        misdirected
+    );
+    assert_eq!(check(Builder::new(), with_host("example.com")), misdirected);
+}
+
+#[test]
+fn rejects_missing_or_ambiguous_hosts() {
+    let bad_request = Some(StatusCode::BAD_REQUEST);
+    let request = Request::get("/").body(()).unwrap();
+    assert_eq!(check(builder(), request), bad_request);
+
+    let request = Request::get("/")
+        .header(HOST, "example.com")
+        .header(HOST, "evil.com")
+        .body(())
+        .unwrap();
+    assert_eq!(check(builder(), request), bad_request);
+
+    let request = Request::get("http://example.com/")
+        .header(HOST, "evil.com")
+        .body(())
+        .unwrap();
+    assert_eq!(check(builder(), request), bad_request);
+
+    assert_eq!(check(builder(), with_host("exa mple.com")), bad_request);
+}
@@ -0,0 +1,102 @@
+use bytes::Bytes;
+use futures::Future;
+use http::header::{HeaderName, HeaderValue, WWW_AUTHENTICATE};
+use http::{Request, Response, StatusCode};
+use tower_http::auth::{ApiKeyConfig, KeyRejection, RequireApiKey};
+use tower_service::Service;
+use tower_test::mock;
+
+#[derive(Debug, PartialEq)]
+struct Client(&'static str);
+
+fn validate(key: &str) -> Result<Client, KeyRejection> {
+    match key {
+        "k1" => Ok(Client("reader")),
+        "k2" => Err(KeyRejection::Forbidden),
+        _ => Err(KeyRejection::Unauthorized),
+    }
+}
+
+fn config() -> ApiKeyConfig {
+    ApiKeyConfig::header(HeaderName::from_static("x-api-key"))
+        .challenge(HeaderValue::from_static("ApiKey header=\"X-Api-Key\""))
+        .unauthorized_body(
+            Bytes::from_static(b"{\"error\":\"unauthorized\"}"),
+            HeaderValue::from_static("application/json"),
+        )
+}
+
+#[test]
+fn rejects_missing_and_forbidden_keys() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<Bytes>>();
+    let mut service = RequireApiKey::new(service, validate, config());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(
+        response.headers()[WWW_AUTHENTICATE],
+        "ApiKey header=\"X-Api-Key\""
+    );
+    assert_eq!(response.body(), "{\"error\":\"unauthorized\"}");
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::get("/")
+        .header("x-api-key", "k2")
+        .body(())
+        .unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[test]
+fn forbids_missing_keys_without_challenge() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<Bytes>>();
+    let config = ApiKeyConfig::header(HeaderName::from_static("x-api-key"));
+    let mut service = RequireApiKey::new(service, validate, config);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert!(!response.headers().contains_key(WWW_AUTHENTICATE));
+}
+
+#[test]
+fn accepts_key_from_query() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Bytes>>();
+    let mut service = RequireApiKey::new(service, validate, ApiKeyConfig::query("api_key"));
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::get("/items?page=2&api_key=k1").body(()).unwrap();
+    let response = service.call(request);
+    let response = std::thread::spawn(move || response.wait().unwrap());
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(
+        request.extensions().get::<Client>(),
+        Some(&Client("reader"))
+    );
+    assert_eq!(request.uri(), "/items?page=2");
+    send_response.send_response(Response::new(Bytes::new()));
+
+    assert_eq!(response.join().unwrap().status(), StatusCode::OK);
+}
+
+#[test]
+fn strips_key_from_query() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Bytes>>();
+    let mut service = RequireApiKey::new(service, validate, ApiKeyConfig::query("api_key"));
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::get("/items?api_key=k1").body(()).unwrap();
+    let response = service.call(request);
+    let response = std::thread::spawn(move || response.wait().unwrap());
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(request.uri(), "/items");
+    send_response.send_response(Response::new(Bytes::new()));
+
+    assert_eq!(response.join().unwrap().status(), StatusCode::OK);
+}
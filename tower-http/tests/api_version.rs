@@ -0,0 +1,104 @@
+use futures::{future, Async, Future};
+use http::header::{HeaderName, ACCEPT};
+use http::{Request, Response, StatusCode};
+use tower_http::api_version::{
+    ApiVersion, Builder, UnsupportedVersion, VersionSource, ACCEPT_VERSION, API_SUPPORTED_VERSIONS,
+};
+use tower_service::Service;
+use tower_test::mock;
+
+/// Calls a service built by `builder`, returning the request received by
+/// the inner service, or the response if the request was rejected.
+fn call(builder: Builder, request: Request<()>) -> Result<Request<()>, Response<()>> {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+    assert!(service.poll_ready().is_ok());
+    let mut response = service.call(request);
+
+    let polled = future::lazy(|| Ok::<_, ()>(response.poll().unwrap()))
+        .wait()
+        .unwrap();
+    match polled {
+        Async::Ready(response) => Err(response),
+        Async::NotReady => Ok(handle.next_request().unwrap().0),
+    }
+}
+
+fn version(request: &Request<()>) -> &str {
+    request.extensions().get::<ApiVersion>().unwrap().as_str()
+}
+
+#[test]
+fn reads_versions_from_headers() {
+    let builder = || {
+        Builder::new(VersionSource::Header(HeaderName::from_static(
+            ACCEPT_VERSION,
+        )))
+        .supported("1")
+        .supported("2")
+        .default_version("1")
+    };
+
+    let request = Request::get("/")
+        .header(ACCEPT_VERSION, "2")
+        .body(())
+        .unwrap();
+    assert_eq!(version(&call(builder(), request).unwrap()), "2");
+
+    let request = Request::get("/").body(()).unwrap();
+    assert_eq!(version(&call(builder(), request).unwrap()), "1");
+
+    let request = Request::get("/")
+        .header(ACCEPT_VERSION, "3")
+        .body(())
+        .unwrap();
+    let response = call(builder(), request).unwrap_err();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(response.headers()[API_SUPPORTED_VERSIONS], "1, 2");
+    assert_eq!(
+        response.extensions().get::<UnsupportedVersion>(),
+        Some(&UnsupportedVersion {
+            requested: Some("3".to_owned()),
+            supported: vec!["1".to_owned(), "2".to_owned()],
+        })
+    );
+}
+
+#[test]
+fn reads_versions_from_media_types() {
+    let builder =
+        || Builder::new(VersionSource::MediaTypeParam("version".to_owned())).supported("2");
+
+    let request = Request::get("/")
+        .header(ACCEPT, "application/json; version=2")
+        .body(())
+        .unwrap();
+    assert_eq!(version(&call(builder(), request).unwrap()), "2");
+
+    let request = Request::get("/")
+        .header(ACCEPT, "application/json; version=1")
+        .body(())
+        .unwrap();
+    let response = call(builder(), request).unwrap_err();
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = call(builder(), request).unwrap_err();
+    assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+    let rejection = response.extensions().get::<UnsupportedVersion>().unwrap();
+    assert_eq!(rejection.requested, None);
+}
+
+#[test]
+fn reads_versions_from_paths() {
+    let builder = || Builder::new(VersionSource::PathPrefix).supported("v1");
+
+    let request = Request::get("/v1/users?page=2").body(()).unwrap();
+    let request = call(builder(), request).unwrap();
+    assert_eq!(version(&request), "v1");
+    assert_eq!(request.uri(), "/users?page=2");
+
+    let request = Request::get("/users").body(()).unwrap();
+    let response = call(builder(), request).unwrap_err();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,58 @@
+use futures::future::{self, FutureResult};
+use futures::Future;
+use http::header::AUTHORIZATION;
+use http::{Request, Response, StatusCode};
+use tower_http::auth::AsyncRequireAuthorization;
+use tower_service::Service;
+use tower_test::mock;
+
+#[derive(Debug, Clone, PartialEq)]
+struct UserId(u64);
+
+fn authorize(mut req: Request<()>) -> FutureResult<Request<()>, Response<()>> {
+    let user = match req.headers().get(AUTHORIZATION) {
+        Some(value) if value == "Bearer alice" => UserId(1),
+        _ => {
+            let mut res = Response::new(());
+            *res.status_mut() = StatusCode::UNAUTHORIZED;
+            return future::err(res);
+        }
+    };
+    req.extensions_mut().insert(user);
+    future::ok(req)
+}
+
+#[test]
+fn authorized_request_reaches_inner_service() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = AsyncRequireAuthorization::new(service, authorize);
+
+    let request = Request::get("/")
+        .header(AUTHORIZATION, "Bearer alice")
+        .body(())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    // Authorization completes once the future is polled; drive it on a
+    // separate thread while the mock answers.
+    let response = std::thread::spawn(move || response.wait().unwrap());
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(request.extensions().get::<UserId>(), Some(&UserId(1)));
+    send_response.send_response(Response::new(()));
+
+    assert_eq!(response.join().unwrap().status(), StatusCode::OK);
+}
+
+#[test]
+fn rejected_request_gets_early_response() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = AsyncRequireAuthorization::new(service, authorize);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+
+    assert_eq!(response.wait().unwrap().status(), StatusCode::UNAUTHORIZED);
+}
@@ -0,0 +1,49 @@
+use http::header::HeaderName;
+use http::{Request, Response};
+use tower_http::baggage::{Baggage, ExtractBaggage, PropagateBaggage};
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn round_trips_context_headers() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut extract = ExtractBaggage::new(
+        service,
+        vec![
+            HeaderName::from_static("baggage"),
+            HeaderName::from_static("x-tenant-id"),
+        ],
+    );
+
+    let request = Request::get("/")
+        .header("baggage", "userId=alice")
+        .header("x-tenant-id", "acme")
+        .header("x-other", "ignored")
+        .body(())
+        .unwrap();
+
+    assert!(extract.poll_ready().is_ok());
+    let _response = extract.call(request);
+    let (incoming, _send_response) = handle.next_request().unwrap();
+
+    let baggage = incoming.extensions().get::<Baggage>().unwrap().clone();
+    assert_eq!(baggage.headers().len(), 2);
+
+    // A handler forwards the baggage on an outgoing client request.
+    let (client, mut client_handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut propagate = PropagateBaggage::new(client);
+
+    let mut outgoing = Request::get("http://upstream/")
+        .header("x-tenant-id", "override")
+        .body(())
+        .unwrap();
+    outgoing.extensions_mut().insert(baggage);
+
+    assert!(propagate.poll_ready().is_ok());
+    let _response = propagate.call(outgoing);
+    let (outgoing, _send_response) = client_handle.next_request().unwrap();
+
+    assert_eq!(outgoing.headers()["baggage"], "userId=alice");
+    assert_eq!(outgoing.headers()["x-tenant-id"], "override");
+    assert!(!outgoing.headers().contains_key("x-other"));
+}
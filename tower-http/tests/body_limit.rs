@@ -0,0 +1,198 @@
+mod support;
+
+use futures::{Async, Future};
+use http::header::{CONNECTION, CONTENT_LENGTH, UPGRADE};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use tower_http::body_limit::{LimitError, Limited, RequestBodyLimit, ResponseBodyLimit};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::{chunks, Chunks};
+
+#[test]
+fn rejects_large_content_length() {
+    let (service, _handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 4);
+
+    let request = Request::post("/")
+        .header(CONTENT_LENGTH, "5")
+        .body(chunks(&["hello"]))
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request).wait().unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(response.headers()[CONNECTION], "close");
+}
+
+#[test]
+fn aborts_streaming_body_over_limit() {
+    let (service, mut handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 4);
+
+    let request = Request::post("/").body(chunks(&["abc", "de"])).unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let mut body = request.into_body();
+    assert!(body.poll_data().is_ok());
+    match body.poll_data() {
+        Err(LimitError::LengthLimitExceeded) => {}
+        _ => panic!("expected the body to fail"),
+    }
+    send_response.send_response(Response::new(()));
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[test]
+fn passes_small_bodies() {
+    let (service, mut handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 5);
+
+    let request = Request::post("/").body(chunks(&["abc", "de"])).unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let mut body = request.into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+    send_response.send_response(Response::new(()));
+
+    assert_eq!(response.wait().unwrap().status(), StatusCode::OK);
+}
+
+#[test]
+fn limits_response_bodies() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = ResponseBodyLimit::new(service, 4);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(chunks(&["abc", "de"])));
+
+    let mut body = response.wait().unwrap().into_body();
+    assert!(body.poll_data().is_ok());
+    match body.poll_data() {
+        Err(LimitError::LengthLimitExceeded) => {}
+        _ => panic!("expected the body to fail"),
+    }
+}
+
+#[test]
+fn passes_tunnels_through() {
+    let (service, mut handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 4);
+
+    let request = Request::connect("example.com:443")
+        .body(chunks(&["hello", "world"]))
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(()));
+    assert_eq!(response.wait().unwrap().status(), StatusCode::OK);
+
+    let mut body = request.into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+}
+
+#[test]
+fn limits_tunnels_until_accepted() {
+    let (service, mut handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 4);
+
+    let request = Request::connect("example.com:443")
+        .body(chunks(&["hello", "world"]))
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let mut forbidden = Response::new(());
+    *forbidden.status_mut() = StatusCode::FORBIDDEN;
+    send_response.send_response(forbidden);
+    assert_eq!(response.wait().unwrap().status(), StatusCode::FORBIDDEN);
+
+    let mut body = request.into_body();
+    match body.poll_data() {
+        Err(LimitError::LengthLimitExceeded) => {}
+        _ => panic!("expected the body to fail"),
+    }
+}
+
+#[test]
+fn limits_spoofed_upgrade_requests() {
+    let (service, _handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 4);
+
+    let request = Request::post("/")
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .header(CONTENT_LENGTH, "10")
+        .body(chunks(&["hello", "world"]))
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[test]
+fn lifts_limit_after_switching_protocols() {
+    let (service, mut handle) = mock::pair::<Request<Limited<Chunks>>, Response<()>>();
+    let mut service = RequestBodyLimit::new(service, 4);
+
+    let request = Request::get("/chat")
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .body(chunks(&["hello", "world"]))
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let mut switching = Response::new(());
+    *switching.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    send_response.send_response(switching);
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+
+    let mut body = request.into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+}
+
+#[test]
+fn passes_switching_protocols_responses_through() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = ResponseBodyLimit::new(service, 4);
+
+    let request = Request::get("/chat")
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .body(())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    let mut switching = Response::new(chunks(&["hello", "world"]));
+    *switching.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+    send_response.send_response(switching);
+
+    let mut body = response.wait().unwrap().into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+}
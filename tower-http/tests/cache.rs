@@ -0,0 +1,401 @@
+mod support;
+
+use bytes::Bytes;
+use futures::{future, Future};
+use http::header::{ACCEPT_ENCODING, AGE, AUTHORIZATION, CACHE_CONTROL, CONNECTION, ETAG};
+use http::header::{IF_NONE_MATCH, SET_COOKIE, UPGRADE, VARY};
+use http::{Request, Response, StatusCode};
+use std::fmt;
+use std::thread;
+use tower_http::cache::{Builder, Cache, CacheBody, MemoryStore};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::{read, streamed, Full, Streamed};
+
+type Handle = mock::Handle<Request<()>, Response<Full>>;
+
+fn respond(handle: &mut Handle, cache_control: &'static str, body: &'static str) {
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(CACHE_CONTROL, cache_control)
+            .body(Full::from(Bytes::from(body)))
+            .unwrap(),
+    );
+}
+
+/// Drives `future` on another thread, as the cache only calls the inner
+/// service once it has looked up its store.
+fn spawn<F>(future: F) -> thread::JoinHandle<F::Item>
+where
+    F: Future + Send + 'static,
+    F::Item: Send + 'static,
+    F::Error: fmt::Debug,
+{
+    thread::spawn(move || future.wait().unwrap())
+}
+
+fn assert_no_request(handle: &mut Handle) {
+    future::lazy(|| {
+        assert!(handle.poll_request().unwrap().is_not_ready());
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+}
+
+fn body(response: Response<CacheBody<Full>>) -> Bytes {
+    read(response.into_body()).0
+}
+
+#[test]
+fn serves_fresh_responses() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+    respond(&mut handle, "max-age=60", "hello");
+    let response = response.join().unwrap();
+    assert!(response.headers().get(AGE).is_none());
+    assert_eq!(body(response), "hello");
+
+    assert!(service.poll_ready().is_ok());
+    let response = service
+        .call(Request::get("/a").body(()).unwrap())
+        .wait()
+        .unwrap();
+    assert_no_request(&mut handle);
+    assert_eq!(response.headers()[AGE], "0");
+    assert_eq!(response.headers()[CACHE_CONTROL], "max-age=60");
+    assert_eq!(body(response), "hello");
+
+    assert!(service.poll_ready().is_ok());
+    let response = service
+        .call(Request::head("/a").body(()).unwrap())
+        .wait()
+        .unwrap();
+    assert_no_request(&mut handle);
+    assert_eq!(body(response), "");
+}
+
+#[test]
+fn does_not_store_private_responses() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    for _ in 0..2 {
+        assert!(service.poll_ready().is_ok());
+        let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+        respond(&mut handle, "private, max-age=60", "hello");
+        assert_eq!(body(response.join().unwrap()), "hello");
+    }
+}
+
+#[test]
+fn does_not_store_responses_setting_cookies() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    for _ in 0..2 {
+        assert!(service.poll_ready().is_ok());
+        let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(
+            Response::builder()
+                .header(CACHE_CONTROL, "max-age=60")
+                .header(SET_COOKIE, "session=alice")
+                .body(Full::from(Bytes::from("hello")))
+                .unwrap(),
+        );
+        let response = response.join().unwrap();
+        assert_eq!(response.headers()[SET_COOKIE], "session=alice");
+    }
+}
+
+#[test]
+fn bypasses_cache_on_request_no_cache() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+    respond(&mut handle, "max-age=60", "old");
+    response.join().unwrap();
+
+    let request = Request::get("/a")
+        .header(CACHE_CONTROL, "no-cache")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(request));
+    respond(&mut handle, "max-age=60", "new");
+    assert_eq!(body(response.join().unwrap()), "new");
+
+    // The fresh response replaced the old one.
+    assert!(service.poll_ready().is_ok());
+    let response = service
+        .call(Request::get("/a").body(()).unwrap())
+        .wait()
+        .unwrap();
+    assert_eq!(body(response), "new");
+}
+
+#[test]
+fn misses_on_unsatisfiable_min_fresh() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+    respond(&mut handle, "max-age=60", "old");
+    response.join().unwrap();
+
+    let request = Request::get("/a")
+        .header(CACHE_CONTROL, "min-fresh=18446744073709551615")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(request));
+    respond(&mut handle, "max-age=60", "new");
+    assert_eq!(body(response.join().unwrap()), "new");
+}
+
+#[test]
+fn passes_upgrades_through() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/chat").body(()).unwrap()));
+    respond(&mut handle, "max-age=60", "hello");
+    response.join().unwrap();
+
+    let request = Request::get("/chat")
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(request));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(UPGRADE, "websocket")
+            .body(Full::default())
+            .unwrap(),
+    );
+    let response = response.join().unwrap();
+    assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert!(response.headers().get(AGE).is_none());
+}
+
+#[test]
+fn unsafe_requests_invalidate() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+    respond(&mut handle, "max-age=60", "old");
+    response.join().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::post("/a").body(()).unwrap()));
+    respond(&mut handle, "no-store", "");
+    response.join().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+    respond(&mut handle, "max-age=60", "new");
+    assert_eq!(body(response.join().unwrap()), "new");
+}
+
+#[test]
+fn uses_given_store() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let store = MemoryStore::new(8);
+    let mut service = Cache::with_store(service, store.clone());
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("http://example.com/a").body(()).unwrap()));
+    respond(&mut handle, "s-maxage=60", "hello");
+    response.join().unwrap();
+    assert_eq!(store.len(), 1);
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::delete("http://example.com/a").body(()).unwrap()));
+    respond(&mut handle, "no-store", "");
+    response.join().unwrap();
+    assert!(store.is_empty());
+}
+
+#[test]
+fn stores_variants() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+    let get = |encoding: &'static str| {
+        Request::get("/a")
+            .header(ACCEPT_ENCODING, encoding)
+            .body(())
+            .unwrap()
+    };
+
+    for &encoding in &["gzip", "identity"] {
+        assert!(service.poll_ready().is_ok());
+        let response = spawn(service.call(get(encoding)));
+        let (request, send_response) = handle.next_request().unwrap();
+        assert_eq!(request.headers()[ACCEPT_ENCODING], encoding);
+        send_response.send_response(
+            Response::builder()
+                .header(CACHE_CONTROL, "max-age=60")
+                .header(VARY, "Accept-Encoding")
+                .body(Full::from(Bytes::from(encoding)))
+                .unwrap(),
+        );
+        assert_eq!(body(response.join().unwrap()), encoding);
+    }
+
+    for &encoding in &["gzip", "identity"] {
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(get(encoding)).wait().unwrap();
+        assert_no_request(&mut handle);
+        assert_eq!(body(response), encoding);
+    }
+}
+
+#[test]
+fn coalesces_concurrent_misses() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let mut first = service.call(Request::get("/a").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let mut second = service.call(Request::get("/a").body(()).unwrap());
+    future::lazy(|| {
+        assert!(first.poll().unwrap().is_not_ready());
+        assert!(second.poll().unwrap().is_not_ready());
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+
+    respond(&mut handle, "max-age=60", "hello");
+    assert_eq!(body(first.wait().unwrap()), "hello");
+    assert_eq!(body(second.wait().unwrap()), "hello");
+    assert_no_request(&mut handle);
+}
+
+#[test]
+fn waiting_requests_fetch_unstorable_responses() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let mut first = service.call(Request::get("/a").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let mut second = service.call(Request::get("/a").body(()).unwrap());
+    future::lazy(|| {
+        assert!(first.poll().unwrap().is_not_ready());
+        assert!(second.poll().unwrap().is_not_ready());
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+
+    respond(&mut handle, "no-store", "first");
+    assert_eq!(body(first.wait().unwrap()), "first");
+
+    let polled = future::lazy(|| second.poll()).wait().unwrap();
+    assert!(polled.is_not_ready());
+    respond(&mut handle, "no-store", "second");
+    assert_eq!(body(second.wait().unwrap()), "second");
+}
+
+#[test]
+fn private_cache_revalidates() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Cache::private(service);
+    let get = || {
+        Request::get("http://example.com/a")
+            .header(AUTHORIZATION, "Bearer token")
+            .body(())
+            .unwrap()
+    };
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(get()));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(CACHE_CONTROL, "private, no-cache")
+            .header(ETAG, "\"v1\"")
+            .body(Full::from(Bytes::from("hello")))
+            .unwrap(),
+    );
+    assert_eq!(body(response.join().unwrap()), "hello");
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(get()));
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[IF_NONE_MATCH], "\"v1\"");
+    send_response.send_response(
+        Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(CACHE_CONTROL, "private, max-age=60")
+            .body(Full::default())
+            .unwrap(),
+    );
+    let response = response.join().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CACHE_CONTROL], "private, max-age=60");
+    assert_eq!(body(response), "hello");
+
+    // Fresh again after the revalidation.
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get()).wait().unwrap();
+    assert_no_request(&mut handle);
+    assert_eq!(body(response), "hello");
+}
+
+#[test]
+fn streams_bodies_larger_than_the_entry_size() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Streamed>>();
+    let mut service = Builder::new().max_entry_size(4).build(service);
+
+    // The response is sent before the end of its body.
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/a").body(()).unwrap()));
+    let mut endless = streamed(&["he", "llo"], None);
+    endless.endless = true;
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(CACHE_CONTROL, "max-age=60")
+            .body(endless)
+            .unwrap(),
+    );
+    response.join().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/b").body(()).unwrap()));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(CACHE_CONTROL, "max-age=60")
+            .body(streamed(&["he", "llo", " world"], None))
+            .unwrap(),
+    );
+    let response = response.join().unwrap();
+    assert_eq!(read(response.into_body()).0, "hello world");
+
+    // The response was not stored.
+    assert!(service.poll_ready().is_ok());
+    let response = spawn(service.call(Request::get("/b").body(()).unwrap()));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(streamed(&["again"], None)));
+    assert_eq!(read(response.join().unwrap().into_body()).0, "again");
+}
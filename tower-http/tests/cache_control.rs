@@ -0,0 +1,86 @@
+use futures::Future;
+use http::header::{CACHE_CONTROL, CONTENT_TYPE, EXPIRES};
+use http::{Request, Response, StatusCode};
+use tower_http::cache_control::{Builder, Condition};
+use tower_service::Service;
+use tower_test::mock;
+
+fn builder() -> Builder {
+    Builder::new()
+        .rule(
+            Condition::path_prefix("/assets/").and(Condition::status(StatusCode::OK)),
+            "max-age=31536000, immutable",
+        )
+        .rule(Condition::content_type("text/html"), "no-cache")
+        .rule(Condition::statuses(400..=599), "no-store")
+}
+
+fn check(
+    path: &'static str,
+    response: Response<()>,
+    expected: Option<&'static str>,
+    expires: bool,
+) {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder().expires(true).build(service).unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let future = service.call(Request::get(path).body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(response);
+
+    let response = future.wait().unwrap();
+    let headers = response.headers();
+    assert_eq!(
+        headers.get(CACHE_CONTROL).map(|v| v.to_str().unwrap()),
+        expected
+    );
+    assert_eq!(headers.contains_key(EXPIRES), expires);
+}
+
+#[test]
+fn applies_first_matching_rule() {
+    let html = || {
+        Response::builder()
+            .header(CONTENT_TYPE, "text/html; charset=utf-8")
+            .body(())
+            .unwrap()
+    };
+    check(
+        "/assets/app.3f2a.js",
+        Response::new(()),
+        Some("max-age=31536000, immutable"),
+        true,
+    );
+    check(
+        "/assets/index.html",
+        html(),
+        Some("max-age=31536000, immutable"),
+        true,
+    );
+    check("/", html(), Some("no-cache"), false);
+
+    let not_found = Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(())
+        .unwrap();
+    check("/assets/missing.js", not_found, Some("no-store"), false);
+    check("/api", Response::new(()), None, false);
+}
+
+#[test]
+fn keeps_existing_headers() {
+    let response = Response::builder()
+        .header(CACHE_CONTROL, "private")
+        .body(())
+        .unwrap();
+    check("/assets/app.js", response, Some("private"), false);
+}
+
+#[test]
+fn rejects_invalid_values() {
+    assert!(Builder::new()
+        .rule(Condition::path_prefix("/"), "bad\nvalue")
+        .build(())
+        .is_err());
+}
@@ -0,0 +1,54 @@
+use futures::{Async, Future, Poll};
+use http::{Request, Response, StatusCode};
+use std::any::Any;
+use tower_http::catch_panic::{panic_message, CatchPanic};
+use tower_service::Service;
+
+#[derive(Debug, Clone)]
+struct Panicking;
+
+impl Service<Request<()>> for Panicking {
+    type Response = Response<String>;
+    type Error = ();
+    type Future = futures::future::FutureResult<Response<String>, ()>;
+
+    fn poll_ready(&mut self) -> Poll<(), ()> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _req: Request<()>) -> Self::Future {
+        panic!("handler failed");
+    }
+}
+
+#[test]
+fn responds_with_500() {
+    let mut service = CatchPanic::new(Panicking);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+
+    assert_eq!(
+        response.wait().unwrap().status(),
+        StatusCode::INTERNAL_SERVER_ERROR
+    );
+}
+
+#[test]
+fn custom_responder_receives_payload() {
+    let responder = |payload: Box<dyn Any + Send>| {
+        let mut res = Response::new(panic_message(&*payload).unwrap_or("").to_owned());
+        *res.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        res
+    };
+    let mut service = CatchPanic::with_responder(Panicking, responder);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service
+        .call(Request::get("/").body(()).unwrap())
+        .wait()
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.into_body().into_inner(), "handler failed");
+}
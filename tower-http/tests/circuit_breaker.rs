@@ -0,0 +1,108 @@
+use futures::Future;
+use http::{Request, Response, StatusCode};
+use std::thread;
+use std::time::Duration;
+use tower_http::circuit_breaker::{Builder, CircuitBreaker, CircuitState};
+use tower_service::Service;
+use tower_test::mock;
+
+type Mock = mock::Mock<Request<()>, Response<()>>;
+type Handle = mock::Handle<Request<()>, Response<()>>;
+
+fn send(service: &mut CircuitBreaker<Mock>, handle: &mut Handle, status: StatusCode) {
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    let mut res = Response::new(());
+    *res.status_mut() = status;
+    send_response.send_response(res);
+    assert_eq!(response.wait().unwrap().status(), status);
+}
+
+#[test]
+fn opens_after_consecutive_failures_and_recovers() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new()
+        .consecutive_failures(2)
+        .open_duration(Duration::from_millis(50))
+        .build(service);
+    let circuit = service.handle();
+
+    send(&mut service, &mut handle, StatusCode::INTERNAL_SERVER_ERROR);
+    send(&mut service, &mut handle, StatusCode::OK);
+    send(&mut service, &mut handle, StatusCode::BAD_GATEWAY);
+    assert_eq!(circuit.state(), CircuitState::Closed);
+    send(&mut service, &mut handle, StatusCode::BAD_GATEWAY);
+    assert_eq!(circuit.state(), CircuitState::Open);
+
+    // The inner service is not called while open.
+    assert!(service.poll_ready().is_ok());
+    let response = service
+        .call(Request::get("/").body(()).unwrap())
+        .wait()
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+    send(&mut service, &mut handle, StatusCode::OK);
+    assert_eq!(circuit.state(), CircuitState::Closed);
+}
+
+#[test]
+fn opens_when_the_failure_rate_is_exceeded() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new()
+        .consecutive_failures(10)
+        .failure_rate(0.5, 4, 4)
+        .build(service);
+    let circuit = service.handle();
+
+    send(&mut service, &mut handle, StatusCode::OK);
+    send(&mut service, &mut handle, StatusCode::INTERNAL_SERVER_ERROR);
+    send(&mut service, &mut handle, StatusCode::OK);
+    assert_eq!(circuit.state(), CircuitState::Closed);
+    send(&mut service, &mut handle, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(circuit.state(), CircuitState::Open);
+}
+
+#[test]
+fn ignores_trials_of_an_earlier_half_open_period() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new()
+        .consecutive_failures(1)
+        .open_duration(Duration::from_millis(50))
+        .half_open_trials(2)
+        .build(service);
+    let circuit = service.handle();
+
+    send(&mut service, &mut handle, StatusCode::INTERNAL_SERVER_ERROR);
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_first) = handle.next_request().unwrap();
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_second) = handle.next_request().unwrap();
+
+    let mut res = Response::new(());
+    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    send_first.send_response(res);
+    first.wait().unwrap();
+    assert_eq!(circuit.state(), CircuitState::Open);
+
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+
+    // The second trial belongs to the previous half-open period.
+    send_second.send_response(Response::new(()));
+    second.wait().unwrap();
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+
+    send(&mut service, &mut handle, StatusCode::OK);
+    assert_eq!(circuit.state(), CircuitState::HalfOpen);
+    send(&mut service, &mut handle, StatusCode::OK);
+    assert_eq!(circuit.state(), CircuitState::Closed);
+}
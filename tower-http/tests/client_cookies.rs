@@ -0,0 +1,70 @@
+use cookie::Cookie;
+use futures::Future;
+use http::header::{COOKIE, SET_COOKIE};
+use http::{Request, Response};
+use tower_http::client_cookies::ClientCookies;
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn stores_and_sends_cookies() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = ClientCookies::new(service);
+
+    let request = Request::get("https://example.com/login").body(()).unwrap();
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert!(request.headers().get(COOKIE).is_none());
+    send_response.send_response(
+        Response::builder()
+            .header(SET_COOKIE, "session=abc; Path=/; Secure")
+            .header(SET_COOKIE, "pref=dark; Domain=example.com; Path=/app")
+            .header(SET_COOKIE, "other=x; Domain=other.com")
+            .header(SET_COOKIE, "gone=1; Max-Age=0")
+            .body(())
+            .unwrap(),
+    );
+    response.wait().unwrap();
+
+    let request = Request::get("https://api.example.com/app/page")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[COOKIE], "pref=dark");
+
+    let request = Request::get("https://example.com/app").body(()).unwrap();
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[COOKIE], "pref=dark; session=abc");
+
+    // Secure cookies are not sent over plain HTTP.
+    let request = Request::get("http://example.com/").body(()).unwrap();
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert!(request.headers().get(COOKIE).is_none());
+}
+
+#[test]
+fn merges_into_existing_cookie_header() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = ClientCookies::new(service);
+    let cookie = Cookie::parse("session=abc; Max-Age=9223372036854775807").unwrap();
+    service.store().store(&cookie, "example.com", "/");
+
+    let request = Request::get("https://example.com/")
+        .header(COOKIE, "theme=dark")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+    let (request, _send_response) = handle.next_request().unwrap();
+
+    let values: Vec<_> = request.headers().get_all(COOKIE).iter().collect();
+    assert_eq!(values, vec!["theme=dark; session=abc"]);
+}
@@ -0,0 +1,51 @@
+mod support;
+
+use futures::{future, Async, Future};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use tower_http::concurrency_limit::Builder;
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Chunks;
+
+#[test]
+fn counts_until_body_completes_and_rejects_overflow() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = Builder::new(1).max_queued(1).build(service);
+    let limit = service.handle();
+
+    future::lazy(move || {
+        assert!(service.poll_ready().is_ok());
+        let mut first = service.call(Request::get("/").body(()).unwrap());
+        assert!(service.poll_ready().is_ok());
+        let mut second = service.call(Request::get("/").body(()).unwrap());
+        assert!(service.poll_ready().is_ok());
+        let third = service.call(Request::get("/").body(()).unwrap());
+
+        assert_eq!(
+            third.wait().unwrap().status(),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert_eq!(limit.in_flight(), 1);
+        assert_eq!(limit.queued(), 1);
+
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(Chunks::default()));
+        let mut body = match first.poll().unwrap() {
+            Async::Ready(response) => response.into_body(),
+            Async::NotReady => panic!("expected a response"),
+        };
+
+        // The slot is held until the body is complete.
+        assert!(second.poll().unwrap().is_not_ready());
+        assert_eq!(body.poll_data().unwrap(), Async::Ready(None));
+        assert!(second.poll().unwrap().is_not_ready());
+        assert_eq!(limit.in_flight(), 1);
+        assert_eq!(limit.queued(), 0);
+
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+}
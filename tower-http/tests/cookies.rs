@@ -0,0 +1,67 @@
+use cookie::Cookie;
+use futures::Future;
+use http::header::{COOKIE, SET_COOKIE};
+use http::{Request, Response};
+use tower_http::cookies::{CookieManager, Cookies, Key, Keys};
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn parses_request_and_writes_delta() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = CookieManager::new(service);
+
+    let request = Request::get("/")
+        .header(COOKIE, "theme=dark; lang=en")
+        .body(())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let cookies = request.extensions().get::<Cookies>().unwrap();
+    assert_eq!(cookies.get("theme").unwrap().value(), "dark");
+    assert_eq!(cookies.get("lang").unwrap().value(), "en");
+
+    cookies.add(Cookie::new("visited", "1"));
+    send_response.send_response(Response::new(()));
+
+    let response = response.wait().unwrap();
+    let set_cookie: Vec<_> = response.headers().get_all(SET_COOKIE).iter().collect();
+    assert_eq!(set_cookie, ["visited=1"]);
+}
+
+#[test]
+fn signed_cookies_survive_key_rotation() {
+    let old = Key::generate();
+    let new = Key::generate();
+
+    // Sign a cookie with the old key.
+    let mut jar = cookie::CookieJar::new();
+    jar.signed(&old).add(Cookie::new("session", "abc"));
+    let signed = jar.get("session").unwrap().value().to_owned();
+
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = CookieManager::with_keys(service, Keys::new(new).fallback(old));
+
+    let request = Request::get("/")
+        .header(COOKIE, format!("session={}; plain=1", signed))
+        .body(())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let cookies = request.extensions().get::<Cookies>().unwrap();
+    assert_eq!(cookies.signed().get("session").unwrap().value(), "abc");
+    assert!(cookies.signed().get("plain").is_none());
+    send_response.send_response(Response::new(()));
+
+    // The cookie is re-issued under the new key.
+    let response = response.wait().unwrap();
+    let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap();
+    assert!(set_cookie.starts_with("session="));
+    assert_ne!(set_cookie, format!("session={}", signed));
+}
@@ -0,0 +1,41 @@
+use futures::Future;
+use http::header::LINK;
+use http::{Request, Response};
+use std::time::{Duration, UNIX_EPOCH};
+use tower_http::deprecation::{Builder, DEPRECATION, SUNSET};
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn marks_matching_requests() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+
+    let mut service = Builder::new()
+        .sunset(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+        .deprecation_link("https://example.com/docs/v1")
+        .build(service, |req: &Request<()>| {
+            req.uri().path().starts_with("/v1/")
+        })
+        .unwrap();
+
+    for (path, deprecated) in vec![("/v1/users", true), ("/v2/users", false)] {
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(Request::get(path).body(()).unwrap());
+
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(()));
+
+        let response = response.wait().unwrap();
+        let headers = response.headers();
+        if deprecated {
+            assert_eq!(headers[DEPRECATION], "true");
+            assert_eq!(headers[SUNSET], "Tue, 14 Nov 2023 22:13:20 GMT");
+            assert_eq!(
+                headers[LINK],
+                "<https://example.com/docs/v1>; rel=\"deprecation\""
+            );
+        } else {
+            assert!(headers.is_empty());
+        }
+    }
+}
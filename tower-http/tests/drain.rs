@@ -0,0 +1,79 @@
+mod support;
+
+use futures::{future, Future};
+use http::header::{CONNECTION, UPGRADE};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use tower_http::drain::Drain;
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Chunks;
+
+#[test]
+fn drains_in_flight_requests() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let (mut service, drain) = Drain::new(service);
+
+    future::lazy(move || {
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(Request::get("/").body(()).unwrap());
+
+        drain.drain();
+        assert!(service.poll_ready().is_err());
+
+        let mut drained = drain.drained();
+        assert!(drained.poll().unwrap().is_not_ready());
+
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(Chunks::default()));
+        let response = response.wait().unwrap();
+        assert_eq!(response.headers()[CONNECTION], "close");
+
+        // The request is in flight until its body is complete.
+        assert!(drained.poll().unwrap().is_not_ready());
+        let mut body = response.into_body();
+        assert!(body.poll_data().unwrap().is_ready());
+        assert!(drained.poll().unwrap().is_ready());
+
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+}
+
+#[test]
+fn keeps_upgrades_and_existing_connection_options() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let (mut service, drain) = Drain::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let upgrade = service.call(Request::get("/").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let keep_alive = service.call(Request::get("/").body(()).unwrap());
+    drain.drain();
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "upgrade")
+            .header(UPGRADE, "websocket")
+            .body(Chunks::default())
+            .unwrap(),
+    );
+    let upgrade = upgrade.wait().unwrap();
+    let connection = upgrade.headers().get_all(CONNECTION).iter();
+    assert_eq!(connection.collect::<Vec<_>>(), vec!["upgrade"]);
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(CONNECTION, "x-custom")
+            .body(Chunks::default())
+            .unwrap(),
+    );
+    let keep_alive = keep_alive.wait().unwrap();
+    let connection = keep_alive.headers().get_all(CONNECTION).iter();
+    assert_eq!(connection.collect::<Vec<_>>(), vec!["x-custom", "close"]);
+}
@@ -0,0 +1,138 @@
+mod support;
+
+use bytes::Bytes;
+use futures::Future;
+use http::header::{CONTENT_LENGTH, ETAG, IF_NONE_MATCH, LAST_MODIFIED};
+use http::{Request, Response, StatusCode};
+use tower_http::etag::ETag;
+use tower_service::Service;
+use tower_test::mock;
+
+use support::{read, streamed, Full, Streamed};
+
+fn get(if_none_match: Option<&str>) -> Request<()> {
+    let mut request = Request::get("/");
+    if let Some(etag) = if_none_match {
+        request.header(IF_NONE_MATCH, etag);
+    }
+    request.body(()).unwrap()
+}
+
+fn hello() -> Response<Full> {
+    Response::new(Full::from(Bytes::from("hello")))
+}
+
+#[test]
+fn sets_strong_tags() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = ETag::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(None));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(hello());
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].to_str().unwrap().to_owned();
+    assert!(etag.starts_with('"'));
+    assert_eq!(read(response.into_body()).0, "hello");
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(Some(&etag)));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(hello());
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], *etag);
+    assert!(read(response.into_body()).0.is_empty());
+}
+
+#[test]
+fn sets_weak_tags_from_metadata() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = ETag::weak(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(Some("W/\"5-0\"")));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(CONTENT_LENGTH, "5")
+            .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+            .body(Full::from(Bytes::from("hello")))
+            .unwrap(),
+    );
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], "W/\"5-0\"");
+    assert!(!response.headers().contains_key(CONTENT_LENGTH));
+}
+
+#[test]
+fn keeps_existing_tags() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = ETag::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(Some("\"other\"")));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(ETAG, "\"v1\"")
+            .body(Full::from(Bytes::from("hello")))
+            .unwrap(),
+    );
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[ETAG], "\"v1\"");
+}
+
+#[test]
+fn streams_large_bodies_with_weak_tags() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Streamed>>();
+    let mut service = ETag::new(service).max_buffer(4);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(None));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(
+        Response::builder()
+            .header(LAST_MODIFIED, "Thu, 01 Jan 1970 00:00:00 GMT")
+            .body(streamed(&["he", "llo", " world"], None))
+            .unwrap(),
+    );
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.headers()[ETAG], "W/\"0\"");
+    assert_eq!(read(response.into_body()).0, "hello world");
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(None));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(streamed(&["he", "llo"], None)));
+
+    let response = response.wait().unwrap();
+    assert!(!response.headers().contains_key(ETAG));
+    assert_eq!(read(response.into_body()).0, "hello");
+}
+
+#[test]
+fn skips_partial_content() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = ETag::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(get(None));
+    let (_request, send_response) = handle.next_request().unwrap();
+    let mut partial = hello();
+    *partial.status_mut() = StatusCode::PARTIAL_CONTENT;
+    send_response.send_response(partial);
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert!(!response.headers().contains_key(ETAG));
+}
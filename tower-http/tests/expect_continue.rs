@@ -0,0 +1,77 @@
+mod support;
+
+use futures::Future;
+use http::header::{CONNECTION, CONTENT_LENGTH, EXPECT};
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tower_http::body_limit::{Limited, RequestBodyLimit};
+use tower_http::expect_continue::{Continue, ContinueBody, ExpectContinue};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Chunks;
+
+fn request(content_length: &str, sent: &Arc<AtomicUsize>) -> Request<Chunks> {
+    let sent = sent.clone();
+    let mut request = Request::post("/")
+        .header(EXPECT, "100-continue")
+        .header(CONTENT_LENGTH, content_length)
+        .body(Chunks::default())
+        .unwrap();
+    request.extensions_mut().insert(Continue::new(move || {
+        sent.fetch_add(1, Ordering::SeqCst);
+    }));
+    request
+}
+
+#[test]
+fn rejects_before_continue() {
+    let (service, _handle) = mock::pair::<Request<Limited<ContinueBody<Chunks>>>, Response<()>>();
+    let mut service = ExpectContinue::new(RequestBodyLimit::new(service, 4));
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("5", &sent)).wait().unwrap();
+
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert!(response.headers().get(CONNECTION).is_none());
+    assert_eq!(sent.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn sends_continue_when_body_is_read() {
+    let (service, mut handle) =
+        mock::pair::<Request<Limited<ContinueBody<Chunks>>>, Response<()>>();
+    let mut service = ExpectContinue::new(RequestBodyLimit::new(service, 4));
+    let sent = Arc::new(AtomicUsize::new(0));
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("4", &sent));
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let mut body = request.into_body();
+    assert_eq!(sent.load(Ordering::SeqCst), 0);
+    let _ = body.poll_data();
+    let _ = body.poll_data();
+    assert_eq!(sent.load(Ordering::SeqCst), 1);
+
+    send_response.send_response(Response::new(()));
+    assert_eq!(response.wait().unwrap().status(), StatusCode::OK);
+}
+
+#[test]
+fn rejects_unknown_expectations() {
+    let (service, _handle) = mock::pair::<Request<ContinueBody<Chunks>>, Response<()>>();
+    let mut service = ExpectContinue::new(service);
+
+    let request = Request::post("/")
+        .header(EXPECT, "something-else")
+        .body(Chunks::default())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::EXPECTATION_FAILED);
+}
@@ -0,0 +1,82 @@
+use futures::future::{self, FutureResult};
+use futures::{Async, Future, Poll};
+use http::{Request, Response, StatusCode};
+use std::convert::Infallible;
+use std::error::Error;
+use std::thread;
+use tower_http::handle_error::HandleError;
+use tower_service::Service;
+use tower_test::mock;
+
+fn internal_error(error: Box<dyn Error + Send + Sync>) -> Result<Response<String>, Infallible> {
+    let mut response = Response::new(error.to_string());
+    *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    Ok(response)
+}
+
+#[test]
+fn passes_responses_through() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<String>>();
+    let mut service = HandleError::new(service, internal_error);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let response = thread::spawn(move || response.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new("hello".to_owned()));
+
+    let response = response.join().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body(), "hello");
+}
+
+#[test]
+fn turns_errors_into_responses() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<String>>();
+    let mut service = HandleError::new(service, internal_error);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let response = thread::spawn(move || response.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_error("database on fire");
+
+    let response = response.join().unwrap();
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.body(), "database on fire");
+}
+
+/// A service which is never ready.
+#[derive(Clone)]
+struct Broken;
+
+impl Service<Request<()>> for Broken {
+    type Response = Response<String>;
+    type Error = &'static str;
+    type Future = FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Err("broken")
+    }
+
+    fn call(&mut self, _req: Request<()>) -> Self::Future {
+        panic!("called while not ready");
+    }
+}
+
+#[test]
+fn turns_readiness_errors_into_responses_asynchronously() {
+    let mut service = HandleError::new(Broken, |error: &'static str| {
+        future::lazy(move || {
+            let mut response = Response::new(error.to_owned());
+            *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+            Ok::<_, Infallible>(response)
+        })
+    });
+
+    assert_eq!(service.poll_ready(), Ok(Async::Ready(())));
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.body(), "broken");
+}
@@ -0,0 +1,54 @@
+mod support;
+
+use bytes::Bytes;
+use futures::{Async, Future};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Method, Request, Response};
+use http_body::Body;
+use tower_http::head_to_get::{HeadBody, HeadToGet};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Full;
+
+fn call(method: Method) -> Response<HeadBody<Full>> {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = HeadToGet::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::builder().method(method).uri("/").body(()).unwrap();
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(request.method(), Method::GET);
+    send_response.send_response(
+        Response::builder()
+            .header(CONTENT_TYPE, "text/plain")
+            .header(CONTENT_LENGTH, "5")
+            .body(Full(Some(Bytes::from_static(b"hello"))))
+            .unwrap(),
+    );
+    response.wait().unwrap()
+}
+
+#[test]
+fn answers_head_with_get_headers_and_no_body() {
+    let mut response = call(Method::HEAD);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "5");
+    assert!(response.body().is_end_stream());
+    match response.body_mut().poll_data().unwrap() {
+        Async::Ready(None) => {}
+        _ => panic!("expected no data"),
+    }
+}
+
+#[test]
+fn passes_get_bodies_through() {
+    let mut response = call(Method::GET);
+    assert!(!response.body().is_end_stream());
+    match response.body_mut().poll_data().unwrap() {
+        Async::Ready(Some(data)) => assert_eq!(&data.into_inner()[..], b"hello"),
+        _ => panic!("expected data"),
+    }
+}
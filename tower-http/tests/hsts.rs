@@ -0,0 +1,41 @@
+use futures::Future;
+use http::header::STRICT_TRANSPORT_SECURITY;
+use http::uri::Scheme;
+use http::{Request, Response};
+use std::time::Duration;
+use tower_http::hsts::Builder;
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn only_applies_to_https_requests() {
+    let (service, mut handle) = mock::pair();
+
+    let mut service = Builder::new()
+        .max_age(Duration::from_secs(600))
+        .include_subdomains(true)
+        .build(service);
+
+    let mut secure = Request::get("/").body(()).unwrap();
+    secure.extensions_mut().insert(Scheme::HTTPS);
+
+    for (request, expected) in vec![
+        (secure, Some("max-age=600; includeSubDomains")),
+        (Request::get("http://example.com/").body(()).unwrap(), None),
+    ] {
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(request);
+
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(()));
+
+        let response = response.wait().unwrap();
+        assert_eq!(
+            response
+                .headers()
+                .get(STRICT_TRANSPORT_SECURITY)
+                .map(|v| v.to_str().unwrap()),
+            expected
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use futures::Future;
+use http::header::{HOST, LOCATION};
+use http::uri::Scheme;
+use http::{Request, Response, StatusCode};
+use std::net::SocketAddr;
+use tower_http::forwarded::{TrustedProxies, X_FORWARDED_PROTO};
+use tower_http::https_redirect::{Builder, RedirectHttps};
+use tower_service::Service;
+use tower_test::mock;
+
+fn redirect(builder: Builder, request: Request<()>) -> Response<()> {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+    assert!(service.poll_ready().is_ok());
+    service.call(request).wait().unwrap()
+}
+
+#[test]
+fn redirects_cleartext_requests() {
+    let request = Request::get("/a?b=c")
+        .header(HOST, "example.com:8080")
+        .body(())
+        .unwrap();
+    let response = redirect(Builder::new(), request);
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[LOCATION], "https://example.com/a?b=c");
+
+    let request = Request::get("http://example.com/").body(()).unwrap();
+    let builder = Builder::new().moved_permanently(true).https_port(8443);
+    let response = redirect(builder, request);
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.headers()[LOCATION], "https://example.com:8443/");
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = redirect(Builder::new(), request);
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[test]
+fn passes_https_requests_through() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = RedirectHttps::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let mut request = Request::get("/")
+        .header(HOST, "example.com")
+        .body(())
+        .unwrap();
+    request.extensions_mut().insert(Scheme::HTTPS);
+    let _response = service.call(request);
+    assert!(handle.next_request().is_some());
+}
+
+#[test]
+fn honours_forwarded_proto_from_trusted_proxies() {
+    let trusted = TrustedProxies::private_networks();
+    let request = || {
+        let mut request = Request::get("/")
+            .header(HOST, "example.com")
+            .header(X_FORWARDED_PROTO, "https")
+            .body(())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert("10.0.0.1:1234".parse::<SocketAddr>().unwrap());
+        request
+    };
+
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new().trusted_proxies(trusted).build(service);
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request());
+    assert!(handle.next_request().is_some());
+
+    // Untrusted peers cannot claim HTTPS.
+    let response = redirect(Builder::new(), request());
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+}
+
+#[test]
+fn excludes_acme_challenges() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = RedirectHttps::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::get("/.well-known/acme-challenge/token")
+        .header(HOST, "example.com")
+        .body(())
+        .unwrap();
+    let _response = service.call(request);
+    assert!(handle.next_request().is_some());
+
+    let request = Request::get("/.well-known/acme-challenge/token")
+        .header(HOST, "example.com")
+        .body(())
+        .unwrap();
+    let response = redirect(Builder::new().redirect_acme_challenges(), request);
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+}
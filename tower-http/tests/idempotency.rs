@@ -0,0 +1,118 @@
+mod support;
+
+use bytes::Bytes;
+use futures::Future;
+use http::{Request, Response, StatusCode};
+use std::thread;
+use tower_http::idempotency::{Builder, Idempotency};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Full;
+
+fn client(_request: &Request<Full>) -> Option<String> {
+    Some("client".to_owned())
+}
+
+fn request(key: &str, body: &'static str) -> Request<Full> {
+    Request::post("/payments")
+        .header("idempotency-key", key)
+        .body(Full::from(Bytes::from(body)))
+        .unwrap()
+}
+
+#[test]
+fn replays_stored_response() {
+    let (service, mut handle) = mock::pair::<Request<Full>, Response<Full>>();
+    let mut service = Idempotency::new(service, client);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("k1", "amount=10"));
+    // The inner service is called once the key has been reserved.
+    let response = thread::spawn(move || response.wait().unwrap());
+    let (received, send_response) = handle.next_request().unwrap();
+    assert_eq!(received.into_body().0.unwrap(), "amount=10");
+    send_response.send_response(
+        Response::builder()
+            .status(StatusCode::CREATED)
+            .body(Full::from(Bytes::from("ch_1")))
+            .unwrap(),
+    );
+    let response = response.join().unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.into_body().0.unwrap(), "ch_1");
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("k1", "amount=10")).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(response.headers()["idempotent-replayed"], "true");
+    assert_eq!(response.into_body().0.unwrap(), "ch_1");
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("k1", "amount=20")).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[test]
+fn rejects_concurrent_retries() {
+    let (service, mut handle) = mock::pair::<Request<Full>, Response<Full>>();
+    let mut service = Idempotency::new(service, client);
+
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(request("k1", "amount=10"));
+    let first = thread::spawn(move || first.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(request("k1", "amount=10")).wait().unwrap();
+    assert_eq!(second.status(), StatusCode::CONFLICT);
+
+    // Server errors release the key.
+    send_response.send_response(
+        Response::builder()
+            .status(StatusCode::SERVICE_UNAVAILABLE)
+            .body(Full::default())
+            .unwrap(),
+    );
+    let first = first.join().unwrap();
+    assert_eq!(first.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    assert!(service.poll_ready().is_ok());
+    let retry = service.call(request("k1", "amount=10"));
+    let retry = thread::spawn(move || retry.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Full::default()));
+    assert_eq!(retry.join().unwrap().status(), StatusCode::OK);
+}
+
+#[test]
+fn scopes_keys_by_principal() {
+    let (service, mut handle) = mock::pair::<Request<Full>, Response<Full>>();
+    let principal = |request: &Request<Full>| {
+        let user = request.headers().get("x-user")?;
+        Some(user.to_str().ok()?.to_owned())
+    };
+    let mut service = Builder::new(principal).build(service);
+
+    for user in &["alice", "bob"] {
+        let mut request = request("k1", "amount=10");
+        request
+            .headers_mut()
+            .insert("x-user", user.parse().unwrap());
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(request);
+        let response = thread::spawn(move || response.wait().unwrap());
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(Full::from(Bytes::from(*user))));
+        let response = response.join().unwrap();
+        assert!(response.headers().get("idempotent-replayed").is_none());
+        assert_eq!(response.into_body().0.unwrap(), *user);
+    }
+
+    // Requests without a principal are passed through.
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("k1", "amount=10"));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Full::default()));
+    response.wait().unwrap();
+}
@@ -0,0 +1,49 @@
+use futures::{future, Future};
+use http::header::RETRY_AFTER;
+use http::{Request, Response, StatusCode};
+use std::time::Duration;
+use tower_http::load_shed::Builder;
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn sheds_when_inner_not_ready() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new()
+        .retry_after(Duration::from_secs(5))
+        .build(service);
+
+    handle.allow(0);
+    let response = future::lazy(|| {
+        // The mock registers the current task when it is not ready.
+        assert!(service.poll_ready().unwrap().is_ready());
+        service.call(Request::get("/").body(()).unwrap())
+    })
+    .wait()
+    .unwrap();
+
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(response.headers()[RETRY_AFTER], "5");
+}
+
+#[test]
+fn sheds_over_in_flight_threshold() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new().max_in_flight(1).build(service);
+
+    assert!(service.poll_ready().unwrap().is_ready());
+    let first = service.call(Request::get("/").body(()).unwrap());
+    assert_eq!(service.in_flight(), 1);
+
+    assert!(service.poll_ready().unwrap().is_ready());
+    let second = service
+        .call(Request::get("/").body(()).unwrap())
+        .wait()
+        .unwrap();
+    assert_eq!(second.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(()));
+    assert_eq!(first.wait().unwrap().status(), StatusCode::OK);
+    assert_eq!(service.in_flight(), 0);
+}
@@ -0,0 +1,94 @@
+use futures::Future;
+use http::header::{HeaderValue, SERVER, USER_AGENT};
+use http::{Request, Response, StatusCode};
+use tower_http::catch_panic::CatchPanicBody;
+use tower_http::map::{
+    MapRequest, MapRequestBody, MapRequestBodyLayer, MapRequestLayer, MapResponse, MapResponseBody,
+    MapResponseLayer,
+};
+use tower_layer::Layer;
+use tower_service::Service;
+use tower_test::mock;
+
+fn tag(mut request: Request<()>) -> Request<&'static str> {
+    request
+        .headers_mut()
+        .insert(USER_AGENT, HeaderValue::from_static("tagged"));
+    request.map(|()| "body")
+}
+
+#[test]
+fn maps_requests() {
+    let (service, mut handle) = mock::pair::<Request<&'static str>, Response<()>>();
+    let mut service = MapRequest::new(service, tag);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get("/").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[USER_AGENT], "tagged");
+    assert_eq!(*request.body(), "body");
+}
+
+#[test]
+fn maps_responses() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = MapResponse::new(service, |mut response: Response<()>| {
+        *response.status_mut() = StatusCode::ACCEPTED;
+        response.map(|()| 42)
+    });
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(()));
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    assert_eq!(*response.body(), 42);
+}
+
+#[test]
+fn layers_services() {
+    let request_layer = MapRequestLayer::new(tag);
+    let response_layer = MapResponseLayer::new(|mut response: Response<()>| {
+        response
+            .headers_mut()
+            .insert(SERVER, HeaderValue::from_static("mapped"));
+        response
+    });
+
+    let (service, mut handle) = mock::pair::<Request<&'static str>, Response<()>>();
+    let mut service = response_layer.layer(request_layer.layer(service));
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[USER_AGENT], "tagged");
+    send_response.send_response(Response::new(()));
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.headers()[SERVER], "mapped");
+}
+
+#[test]
+fn maps_bodies() {
+    let (service, mut handle) = mock::pair::<Request<usize>, Response<&'static str>>();
+    let service = MapResponseBody::new(service, CatchPanicBody::new);
+    let mut service = MapRequestBody::new(service, |body: &'static str| body.len());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::post("/").body("hello").unwrap());
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(*request.body(), 5);
+    send_response.send_response(Response::new("world"));
+
+    let response: Response<CatchPanicBody<&'static str>> = response.wait().unwrap();
+    assert_eq!(response.into_body().into_inner(), "world");
+
+    let (service, mut handle) = mock::pair::<Request<usize>, Response<()>>();
+    let mut service = MapRequestBodyLayer::new(|body: Vec<u8>| body.len()).layer(service);
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::post("/").body(vec![0; 3]).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(*request.body(), 3);
+}
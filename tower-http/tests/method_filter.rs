@@ -0,0 +1,50 @@
+use futures::Future;
+use http::header::ALLOW;
+use http::{Method, Request, Response, StatusCode};
+use tower_http::method_filter::{Builder, MethodFilter};
+use tower_service::Service;
+use tower_test::mock;
+
+fn reject(builder: Builder, method: Method) -> Response<()> {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+    assert!(service.poll_ready().is_ok());
+    let request = Request::builder().method(method).uri("/").body(()).unwrap();
+    service.call(request).wait().unwrap()
+}
+
+#[test]
+fn passes_allowed_methods() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = MethodFilter::new(service, vec![Method::GET, Method::HEAD]);
+    assert_eq!(service.allowed_methods(), &[Method::GET, Method::HEAD][..]);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::head("/").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.method(), Method::HEAD);
+}
+
+#[test]
+fn answers_other_methods_with_allow() {
+    let builder = Builder::new()
+        .allow(Method::GET)
+        .allow(Method::POST)
+        .allow(Method::GET);
+    let response = reject(builder, Method::DELETE);
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "GET, POST");
+
+    let response = reject(Builder::new(), Method::GET);
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "");
+}
+
+#[test]
+fn answers_not_implemented_when_configured() {
+    let builder = Builder::new().allow(Method::GET).not_implemented(true);
+    let method = Method::from_bytes(b"BREW").unwrap();
+    let response = reject(builder, method);
+    assert_eq!(response.status(), StatusCode::NOT_IMPLEMENTED);
+    assert!(!response.headers().contains_key(ALLOW));
+}
@@ -0,0 +1,50 @@
+use http::{Method, Request, Response};
+use tower_http::method_override::{Builder, X_HTTP_METHOD_OVERRIDE};
+use tower_service::Service;
+use tower_test::mock;
+
+fn forward(builder: Builder, request: Request<()>) -> Request<()> {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+
+    let (request, _send_response) = handle.next_request().unwrap();
+    request
+}
+
+#[test]
+fn overrides_post_from_header() {
+    let request = Request::post("/")
+        .header(X_HTTP_METHOD_OVERRIDE, "delete")
+        .body(())
+        .unwrap();
+
+    let request = forward(Builder::new(), request);
+    assert_eq!(request.method(), Method::DELETE);
+    assert!(!request.headers().contains_key(X_HTTP_METHOD_OVERRIDE));
+}
+
+#[test]
+fn overrides_post_from_query() {
+    let request = Request::post("/items/1?_method=PATCH").body(()).unwrap();
+
+    let request = forward(Builder::new().query_param("_method"), request);
+    assert_eq!(request.method(), Method::PATCH);
+}
+
+#[test]
+fn ignores_disallowed_methods_and_non_post() {
+    let request = Request::post("/")
+        .header(X_HTTP_METHOD_OVERRIDE, "CONNECT")
+        .body(())
+        .unwrap();
+    assert_eq!(forward(Builder::new(), request).method(), Method::POST);
+
+    let request = Request::get("/")
+        .header(X_HTTP_METHOD_OVERRIDE, "DELETE")
+        .body(())
+        .unwrap();
+    assert_eq!(forward(Builder::new(), request).method(), Method::GET);
+}
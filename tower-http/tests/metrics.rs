@@ -0,0 +1,222 @@
+mod support;
+
+use futures::{Async, Future};
+use http::{Request, Response};
+use http_body::Body;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower_http::metrics::{
+    CountRequests, DefaultLabels, InFlightRequests, Labels, MetricsSink, RecordBodySizes,
+    RecordLatency, SizedBody, REQUESTS_IN_FLIGHT, REQUESTS_TOTAL, REQUEST_BODY_CHUNKS,
+    REQUEST_BODY_SIZE, REQUEST_DURATION, RESPONSE_BODY_CHUNKS, RESPONSE_BODY_SIZE,
+};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::{chunks, Chunks};
+
+#[derive(Debug, Default)]
+struct Recorder {
+    gauges: Mutex<Vec<(&'static str, i64)>>,
+    durations: Mutex<Vec<&'static str>>,
+    counters: Mutex<Vec<(&'static str, Labels)>>,
+    sizes: Mutex<Vec<(&'static str, u64)>>,
+}
+
+impl Recorder {
+    fn gauge(&self, name: &str) -> i64 {
+        let gauges = self.gauges.lock().unwrap();
+        gauges
+            .iter()
+            .filter(|&&(n, _)| n == name)
+            .map(|&(_, d)| d)
+            .sum()
+    }
+}
+
+impl MetricsSink for Recorder {
+    fn increment(&self, name: &'static str, _: &Labels) {
+        self.gauges.lock().unwrap().push((name, 1));
+    }
+
+    fn decrement(&self, name: &'static str, _: &Labels) {
+        self.gauges.lock().unwrap().push((name, -1));
+    }
+
+    fn record_duration(&self, name: &'static str, _: &Labels, _: Duration) {
+        self.durations.lock().unwrap().push(name);
+    }
+
+    fn count(&self, name: &'static str, labels: &Labels) {
+        self.counters.lock().unwrap().push((name, labels.clone()));
+    }
+
+    fn record_size(&self, name: &'static str, _: &Labels, size: u64) {
+        self.sizes.lock().unwrap().push((name, size));
+    }
+}
+
+#[test]
+fn counts_requests_in_flight_until_body_ends() {
+    let recorder = Arc::new(Recorder::default());
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = InFlightRequests::new(service, recorder.clone());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    assert_eq!(recorder.gauge(REQUESTS_IN_FLIGHT), 1);
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Chunks::default()));
+    let mut body = response.wait().unwrap().into_body();
+    assert_eq!(recorder.gauge(REQUESTS_IN_FLIGHT), 1);
+
+    assert!(body.poll_data().unwrap().is_ready());
+    assert_eq!(recorder.gauge(REQUESTS_IN_FLIGHT), 0);
+
+    // Dropped responses no longer count either.
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    assert_eq!(recorder.gauge(REQUESTS_IN_FLIGHT), 1);
+    drop(response);
+    assert_eq!(recorder.gauge(REQUESTS_IN_FLIGHT), 0);
+}
+
+#[test]
+fn records_latency() {
+    let recorder = Arc::new(Recorder::default());
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = RecordLatency::new(service, recorder.clone());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Chunks::default()));
+    response.wait().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_error("failed");
+    assert!(response.wait().is_err());
+
+    assert_eq!(
+        *recorder.durations.lock().unwrap(),
+        vec![REQUEST_DURATION, REQUEST_DURATION]
+    );
+}
+
+#[test]
+fn counts_requests_by_method_and_status() {
+    let recorder = Arc::new(Recorder::default());
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let mut service = CountRequests::new(service, recorder.clone());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/users/7").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Chunks::default()));
+    response.wait().unwrap();
+
+    let ok = Labels::new().with("method", "GET").with("status", "2xx");
+    assert_eq!(
+        *recorder.counters.lock().unwrap(),
+        vec![(REQUESTS_TOTAL, ok)]
+    );
+}
+
+#[test]
+fn counts_requests_by_labels() {
+    let recorder = Arc::new(Recorder::default());
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let labels = DefaultLabels::new().with_paths();
+    let mut service = CountRequests::with_labels(service, recorder.clone(), labels);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/users/7").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Chunks::default()));
+    response.wait().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::post("/users").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_error("failed");
+    assert!(response.wait().is_err());
+
+    let ok = Labels::new()
+        .with("method", "GET")
+        .with("path", "/users/:id")
+        .with("status", "2xx");
+    let failed = Labels::new()
+        .with("method", "POST")
+        .with("path", "/users")
+        .with("status", "error");
+    assert_eq!(
+        *recorder.counters.lock().unwrap(),
+        vec![(REQUESTS_TOTAL, ok), (REQUESTS_TOTAL, failed)]
+    );
+}
+
+#[test]
+fn records_body_sizes() {
+    let recorder = Arc::new(Recorder::default());
+    let (service, mut handle) =
+        mock::pair::<Request<SizedBody<Chunks, Arc<Recorder>>>, Response<Chunks>>();
+    let mut service = RecordBodySizes::new(service, recorder.clone());
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::post("/")
+        .body(chunks(&["hello", "world"]))
+        .unwrap();
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    let mut body = request.into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+    send_response.send_response(Response::new(chunks(&["hi!"])));
+
+    let mut body = response.wait().unwrap().into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+
+    assert_eq!(
+        *recorder.sizes.lock().unwrap(),
+        vec![
+            (REQUEST_BODY_SIZE, 10),
+            (REQUEST_BODY_CHUNKS, 2),
+            (RESPONSE_BODY_SIZE, 3),
+            (RESPONSE_BODY_CHUNKS, 1),
+        ]
+    );
+}
+
+#[cfg(feature = "prometheus")]
+#[test]
+fn serves_prometheus_metrics() {
+    use bytes::Bytes;
+    use tower_http::metrics::prometheus::{Registry, ServeMetrics};
+
+    let registry = Registry::new();
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Bytes>>();
+    let service = CountRequests::new(service, registry.clone());
+    let mut service = ServeMetrics::new(service, registry);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Bytes::new()));
+    response.wait().unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service
+        .call(Request::get("/metrics").body(()).unwrap())
+        .wait()
+        .unwrap();
+    assert_eq!(
+        response.headers()[http::header::CONTENT_TYPE],
+        "text/plain; version=0.0.4"
+    );
+    assert!(response
+        .body()
+        .starts_with(b"# TYPE http_requests_total counter\n"));
+}
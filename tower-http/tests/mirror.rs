@@ -0,0 +1,93 @@
+use futures::future::{ExecuteError, Executor};
+use futures::{future, Future};
+use http::header::{CONNECTION, UPGRADE};
+use http::{Request, Response};
+use std::sync::{Arc, Mutex};
+use tower_http::mirror::{Mirror, ShadowFuture};
+use tower_service::Service;
+use tower_test::mock;
+
+type Shadow = ShadowFuture<mock::future::ResponseFuture<Response<()>>>;
+
+#[derive(Clone, Default)]
+struct Spawned(Arc<Mutex<Vec<Shadow>>>);
+
+impl Executor<Shadow> for Spawned {
+    fn execute(&self, future: Shadow) -> Result<(), ExecuteError<Shadow>> {
+        self.0.lock().unwrap().push(future);
+        Ok(())
+    }
+}
+
+#[test]
+fn mirrors_requests_to_shadow() {
+    let (service, mut handle) = mock::pair::<Request<&str>, Response<()>>();
+    let (shadow, mut shadow_handle) = mock::pair::<Request<&str>, Response<()>>();
+    let spawned = Spawned::default();
+    let mut service = Mirror::new(service, shadow, spawned.clone(), 1.0);
+
+    future::lazy(move || {
+        assert!(service.poll_ready().is_ok());
+        let request = Request::post("/orders")
+            .header("x-trace", "1")
+            .body("payload")
+            .unwrap();
+        let response = service.call(request);
+
+        let (request, send_response) = handle.next_request().unwrap();
+        assert_eq!(*request.body(), "payload");
+        send_response.send_response(Response::new(()));
+        response.wait().unwrap();
+
+        let (copy, send_shadow) = shadow_handle.next_request().unwrap();
+        assert_eq!(copy.method(), "POST");
+        assert_eq!(copy.uri(), "/orders");
+        assert_eq!(copy.headers()["x-trace"], "1");
+        assert_eq!(*copy.body(), "payload");
+
+        // Shadow failures are discarded.
+        send_shadow.send_error("shadow failed");
+        let shadow = spawned.0.lock().unwrap().pop().unwrap();
+        assert_eq!(shadow.wait(), Ok(()));
+
+        Ok::<_, ()>(())
+    })
+    .wait()
+    .unwrap();
+}
+
+#[test]
+fn skips_unsampled_requests() {
+    let (service, mut handle) = mock::pair::<Request<&str>, Response<()>>();
+    let (shadow, _shadow_handle) = mock::pair::<Request<&str>, Response<()>>();
+    let spawned = Spawned::default();
+    let mut service = Mirror::new(service, shadow, spawned.clone(), 0.0);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get("/").body("").unwrap());
+    assert!(handle.next_request().is_some());
+    assert!(spawned.0.lock().unwrap().is_empty());
+}
+
+#[test]
+fn skips_upgrade_requests() {
+    let (service, mut handle) = mock::pair::<Request<&str>, Response<()>>();
+    let (shadow, _shadow_handle) = mock::pair::<Request<&str>, Response<()>>();
+    let spawned = Spawned::default();
+    let mut service = Mirror::new(service, shadow, spawned.clone(), 1.0);
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::get("/chat")
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .body("")
+        .unwrap();
+    let _response = service.call(request);
+    assert!(handle.next_request().is_some());
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::connect("example.com:443").body("").unwrap());
+    assert!(handle.next_request().is_some());
+
+    assert!(spawned.0.lock().unwrap().is_empty());
+}
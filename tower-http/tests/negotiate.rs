@@ -0,0 +1,72 @@
+use futures::{future, Async, Future};
+use http::header::{ACCEPT, VARY};
+use http::{Request, Response, StatusCode};
+use tower_http::negotiate::{Negotiate, Negotiated};
+use tower_service::Service;
+use tower_test::mock;
+
+fn negotiate(accept: Option<&'static str>) -> Option<Negotiated> {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Negotiate::new(
+        service,
+        vec!["application/json", "text/html; charset=utf-8", "text/plain"],
+    );
+
+    let mut request = Request::get("/");
+    if let Some(accept) = accept {
+        request.header(ACCEPT, accept);
+    }
+    assert!(service.poll_ready().is_ok());
+    let mut response = service.call(request.body(()).unwrap());
+
+    let rejected = future::lazy(|| Ok::<_, ()>(response.poll().unwrap()))
+        .wait()
+        .unwrap();
+    let (response, negotiated) = match rejected {
+        Async::Ready(response) => {
+            assert_eq!(response.status(), StatusCode::NOT_ACCEPTABLE);
+            (response, None)
+        }
+        Async::NotReady => {
+            let (request, send_response) = handle.next_request().unwrap();
+            send_response.send_response(Response::new(()));
+            let negotiated = request.extensions().get::<Negotiated>().cloned();
+            (response.wait().unwrap(), Some(negotiated.unwrap()))
+        }
+    };
+    assert_eq!(response.headers()[VARY], "accept");
+    negotiated
+}
+
+fn chosen(accept: Option<&'static str>) -> Option<String> {
+    negotiate(accept).map(|negotiated| negotiated.media_type.to_str().unwrap().to_owned())
+}
+
+#[test]
+fn chooses_the_preferred_media_type() {
+    assert_eq!(chosen(None).unwrap(), "application/json");
+    assert_eq!(
+        chosen(Some("text/html")).unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(
+        chosen(Some("text/*;q=0.8, application/json;q=0.5")).unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(
+        chosen(Some("text/*, text/html;q=0.1, */*;q=0.2")).unwrap(),
+        "text/plain"
+    );
+    assert_eq!(chosen(Some("*/*")).unwrap(), "application/json");
+    assert_eq!(
+        chosen(Some("text/html; charset=UTF-8")).unwrap(),
+        "text/html; charset=utf-8"
+    );
+}
+
+#[test]
+fn rejects_unacceptable_requests() {
+    assert_eq!(chosen(Some("image/png")), None);
+    assert_eq!(chosen(Some("application/json;q=0, text/*;q=0")), None);
+    assert_eq!(chosen(Some("text/html; level=1")), None);
+}
@@ -0,0 +1,74 @@
+use futures::Future;
+use http::header::LOCATION;
+use http::{Request, Response, StatusCode};
+use tower_http::normalize_path::{Builder, NormalizePath, TrailingSlash};
+use tower_service::Service;
+use tower_test::mock;
+
+fn forward(builder: Builder, uri: &str) -> Request<()> {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get(uri).body(()).unwrap());
+
+    let (request, _send_response) = handle.next_request().unwrap();
+    request
+}
+
+#[test]
+fn collapses_slashes() {
+    let request = forward(Builder::new(), "//api///users?page=2");
+    assert_eq!(request.uri(), "/api/users?page=2");
+
+    let request = forward(Builder::new(), "http://example.com//a//b/");
+    assert_eq!(request.uri(), "http://example.com/a/b/");
+}
+
+#[test]
+fn appends_or_trims_trailing_slashes() {
+    let request = forward(Builder::new().trailing_slash(TrailingSlash::Append), "/a");
+    assert_eq!(request.uri(), "/a/");
+
+    let request = forward(Builder::new().trailing_slash(TrailingSlash::Trim), "/a//");
+    assert_eq!(request.uri(), "/a");
+
+    let request = forward(Builder::new().trailing_slash(TrailingSlash::Trim), "/");
+    assert_eq!(request.uri(), "/");
+}
+
+#[test]
+fn redirects_to_normal_path() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Builder::new()
+        .trailing_slash(TrailingSlash::Trim)
+        .redirect(true)
+        .build(service);
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::get("//docs/?q=1").body(()).unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[LOCATION], "/docs?q=1");
+
+    // Backslashes are not left to be read as slashes by browsers.
+    let request = Request::get("//\\evil.com/?q=1").body(()).unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.headers()[LOCATION], "/%5Cevil.com?q=1");
+
+    // Normal paths go through.
+    let _response = service.call(Request::get("/docs?q=1").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.uri(), "/docs?q=1");
+}
+
+#[test]
+fn leaves_normal_paths_alone() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = NormalizePath::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get("/a/b?x=//").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.uri(), "/a/b?x=//");
+}
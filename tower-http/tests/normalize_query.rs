@@ -0,0 +1,49 @@
+use http::{Request, Response};
+use tower_http::normalize_query::{Builder, NormalizeQuery, OriginalQuery};
+use tower_service::Service;
+use tower_test::mock;
+
+fn forward<S>(
+    mut service: S,
+    handle: &mut mock::Handle<Request<()>, Response<()>>,
+    uri: &str,
+) -> Request<()>
+where
+    S: Service<Request<()>>,
+{
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::get(uri).body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    request
+}
+
+#[test]
+fn rewrites_queries() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let service = Builder::new().strip("utm_*").build(service);
+
+    let request = forward(service, &mut handle, "/search?q=rust&utm_source=news&page=");
+    assert_eq!(request.uri(), "/search?page&q=rust");
+    let original = request.extensions().get::<OriginalQuery>().unwrap();
+    assert_eq!(original.as_str(), Some("q=rust&utm_source=news&page="));
+}
+
+#[test]
+fn removes_emptied_queries() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let service = Builder::new().strip_tracking().build(service);
+
+    let request = forward(service, &mut handle, "http://example.com/a?fbclid=1");
+    assert_eq!(request.uri(), "http://example.com/a");
+}
+
+#[test]
+fn records_missing_queries() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let service = NormalizeQuery::new(service);
+
+    let request = forward(service, &mut handle, "/a");
+    assert_eq!(request.uri(), "/a");
+    let original = request.extensions().get::<OriginalQuery>().unwrap();
+    assert_eq!(original.as_str(), None);
+}
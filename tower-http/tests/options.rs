@@ -0,0 +1,46 @@
+use futures::Future;
+use http::header::ALLOW;
+use http::{Method, Request, Response, StatusCode};
+use tower_http::method_filter::MethodFilter;
+use tower_http::options::Options;
+use tower_service::Service;
+use tower_test::mock;
+
+fn options(uri: &str) -> Request<()> {
+    Request::builder()
+        .method(Method::OPTIONS)
+        .uri(uri)
+        .body(())
+        .unwrap()
+}
+
+#[test]
+fn answers_options_requests() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Options::new(service, vec![Method::GET, Method::HEAD, Method::GET]);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(options("/users")).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()[ALLOW], "GET, HEAD, OPTIONS");
+
+    let response = service.call(options("*")).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    assert_eq!(response.headers()[ALLOW], "GET, HEAD, OPTIONS");
+}
+
+#[test]
+fn lists_the_methods_of_a_filter() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let filter = MethodFilter::new(service, vec![Method::GET, Method::OPTIONS, Method::POST]);
+    let mut service = Options::for_filter(filter);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(options("/")).wait().unwrap();
+    assert_eq!(response.headers()[ALLOW], "GET, OPTIONS, POST");
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::post("/").body(()).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.method(), Method::POST);
+}
@@ -0,0 +1,60 @@
+use futures::Future;
+use http::header::ETAG;
+use http::{Request, Response, StatusCode};
+use std::error::Error;
+use std::thread;
+use tower_http::precondition::{CheckPreconditions, Validators};
+use tower_service::Service;
+use tower_test::mock;
+
+fn current(_: &Request<()>) -> Result<Option<Validators>, Box<dyn Error + Send + Sync>> {
+    Ok(Some(Validators {
+        etag: Some("\"v2\"".to_owned()),
+        last_modified: None,
+    }))
+}
+
+#[test]
+fn rejects_stale_if_match() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = CheckPreconditions::new(service, current);
+
+    let request = Request::put("/a")
+        .header("if-match", "\"v1\"")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(response.headers()[ETAG], "\"v2\"");
+
+    let request = Request::put("/a")
+        .header("if-match", "\"v1\", \"v2\"")
+        .body(())
+        .unwrap();
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+    // The inner service is called once the validators have been looked up.
+    let response = thread::spawn(move || response.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(()));
+    assert_eq!(response.join().unwrap().status(), StatusCode::OK);
+}
+
+#[test]
+fn requires_preconditions() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = CheckPreconditions::new(service, current).require(true);
+
+    assert!(service.poll_ready().is_ok());
+    let request = Request::delete("/a").body(()).unwrap();
+    let response = service.call(request).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::PRECONDITION_REQUIRED);
+
+    // Safe methods are never checked.
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/a").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(()));
+    assert_eq!(response.wait().unwrap().status(), StatusCode::OK);
+}
@@ -0,0 +1,110 @@
+mod support;
+
+use bytes::Bytes;
+use futures::Future;
+use http::header::{ALLOW, CONTENT_TYPE};
+use http::response::Parts;
+use http::{Request, Response, StatusCode};
+use http_body::Body;
+use tower_http::problem::{DefaultMapProblem, MapProblem, Problem, ProblemJson};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Full;
+
+/// The error type of `mock` services.
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+type Handle = mock::Handle<Request<()>, Response<Full>>;
+
+fn call<M>(mapper: M, respond: impl FnOnce(&mut Handle)) -> Response<Full>
+where
+    M: MapProblem<Error>,
+{
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = ProblemJson::with_mapper(service, mapper);
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    respond(&mut handle);
+    response.wait().unwrap()
+}
+
+fn body(response: Response<Full>) -> Bytes {
+    response.into_body().0.unwrap()
+}
+
+#[test]
+fn reports_errors_as_problems() {
+    let response = call(DefaultMapProblem::default(), |handle| {
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_error("database on fire");
+    });
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+    assert_eq!(
+        body(response),
+        "{\"type\":\"about:blank\",\"title\":\"Internal Server Error\",\"status\":500}"
+    );
+}
+
+struct Propagate;
+
+impl MapProblem<Error> for Propagate {
+    fn map_error(&self, _error: &Error) -> Option<Problem> {
+        None
+    }
+
+    fn map_response(&self, parts: &Parts) -> Option<Problem> {
+        Some(Problem::new(parts.status).detail("Use GET."))
+    }
+}
+
+#[test]
+fn reports_rejections_as_problems() {
+    let response = call(Propagate, |handle| {
+        let (_request, send_response) = handle.next_request().unwrap();
+        let response = Response::builder()
+            .status(StatusCode::METHOD_NOT_ALLOWED)
+            .header(ALLOW, "GET")
+            .body(Full::default())
+            .unwrap();
+        send_response.send_response(response);
+    });
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "GET");
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+    assert_eq!(
+        body(response),
+        "{\"type\":\"about:blank\",\"title\":\"Method Not Allowed\",\"status\":405,\"detail\":\"Use GET.\"}"
+    );
+}
+
+#[test]
+fn leaves_other_responses_alone() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = ProblemJson::with_mapper(service, Propagate);
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_error("database on fire");
+    assert!(response.wait().is_err());
+
+    let response = call(DefaultMapProblem::default(), |handle| {
+        let (_request, send_response) = handle.next_request().unwrap();
+        let response = Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::from(Bytes::from_static(b"nope")))
+            .unwrap();
+        send_response.send_response(response);
+    });
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!response.headers().contains_key(CONTENT_TYPE));
+    assert_eq!(body(response), "nope");
+
+    let response = call(DefaultMapProblem::default(), |handle| {
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(Full::default()));
+    });
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(response.body().is_end_stream());
+}
@@ -0,0 +1,82 @@
+use futures::future::{self, FutureResult};
+use futures::{Async, Future, Poll};
+use http::header::{HeaderName, RETRY_AFTER};
+use http::{Request, Response, StatusCode};
+use std::time::Duration;
+use std::{io, thread};
+use tower_http::rate_limit::{HeaderKey, RateLimit, RateLimitError, TokenBucket};
+use tower_service::Service;
+use tower_test::mock;
+
+fn request(key: &str) -> Request<()> {
+    Request::get("/").header("x-api-key", key).body(()).unwrap()
+}
+
+#[test]
+fn limits_each_key() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let extractor = HeaderKey::new(HeaderName::from_static("x-api-key"));
+    let mut service = RateLimit::new(service, extractor, 1, Duration::from_secs(60));
+
+    for &key in &["a", "b"] {
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(request(key));
+        // The inner service is called once the store has been checked.
+        let response = thread::spawn(move || response.wait().unwrap());
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(()));
+
+        let response = response.join().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["ratelimit-limit"], "1");
+        assert_eq!(response.headers()["ratelimit-remaining"], "0");
+    }
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("a")).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()[RETRY_AFTER], "60");
+    assert_eq!(response.headers()["ratelimit-reset"], "60");
+}
+
+#[test]
+fn uses_custom_algorithm() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let extractor = |_: &Request<()>| Some("global".to_owned());
+    let algorithm = TokenBucket::new(0, Duration::from_secs(10));
+    let mut service = RateLimit::with_algorithm(service, extractor, algorithm);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request("a")).wait().unwrap();
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers()["ratelimit-limit"], "0");
+}
+
+#[derive(Clone)]
+struct Failing;
+
+impl Service<Request<()>> for Failing {
+    type Response = Response<()>;
+    type Error = io::Error;
+    type Future = FutureResult<Response<()>, io::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _req: Request<()>) -> Self::Future {
+        future::err(io::Error::new(io::ErrorKind::Other, "unavailable"))
+    }
+}
+
+#[test]
+fn reports_inner_errors() {
+    let extractor = HeaderKey::new(HeaderName::from_static("x-api-key"));
+    let mut service = RateLimit::new(Failing, extractor, 1, Duration::from_secs(60));
+
+    assert!(service.poll_ready().is_ok());
+    match service.call(request("a")).wait() {
+        Err(RateLimitError::Inner(e)) => assert_eq!(e.to_string(), "unavailable"),
+        _ => panic!("expected the inner error"),
+    }
+}
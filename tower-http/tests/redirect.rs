@@ -0,0 +1,57 @@
+use futures::Future;
+use http::header::LOCATION;
+use http::{Request, Response, StatusCode, Uri};
+use tower_http::redirect::Redirect;
+use tower_service::Service;
+
+fn call(mut service: Redirect<()>, method: &str) -> Response<()> {
+    assert!(Service::<Request<()>>::poll_ready(&mut service).is_ok());
+    let request = Request::builder()
+        .method(method)
+        .uri("/old")
+        .body(())
+        .unwrap();
+    service.call(request).wait().unwrap()
+}
+
+#[test]
+fn redirects_with_location() {
+    let uri = Uri::from_static("https://example.com/new?page=2");
+
+    let response = call(Redirect::permanent(uri.clone()), "GET");
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(
+        response.headers()[LOCATION],
+        "https://example.com/new?page=2"
+    );
+
+    let response = call(Redirect::to(Uri::from_static("/done")), "POST");
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert_eq!(response.headers()[LOCATION], "/done");
+}
+
+#[test]
+fn uses_the_status_of_each_kind() {
+    let uri = Uri::from_static("/new");
+    let statuses = [
+        (Redirect::to(uri.clone()), StatusCode::SEE_OTHER),
+        (
+            Redirect::temporary(uri.clone()),
+            StatusCode::TEMPORARY_REDIRECT,
+        ),
+        (
+            Redirect::permanent(uri.clone()),
+            StatusCode::PERMANENT_REDIRECT,
+        ),
+        (Redirect::found(uri.clone()), StatusCode::FOUND),
+        (
+            Redirect::moved_permanently(uri),
+            StatusCode::MOVED_PERMANENTLY,
+        ),
+    ];
+    for (service, status) in statuses.iter().cloned() {
+        assert_eq!(service.status(), status);
+        assert_eq!(service.location(), "/new");
+        assert_eq!(call(service, "DELETE").status(), status);
+    }
+}
@@ -0,0 +1,113 @@
+mod support;
+
+use bytes::Bytes;
+use futures::{future, Async, Future};
+use http::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use http::{Method, Request, Response, StatusCode};
+use tower_http::require_content_type::{Builder, RequireContentType};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Full;
+
+/// Returns the status of the response to `request`, or `None` if it was
+/// passed to the inner service.
+fn check(builder: Builder, request: Request<Full>) -> Option<StatusCode> {
+    let (service, _handle) = mock::pair::<Request<Full>, Response<()>>();
+    let mut service = builder.build(service);
+    assert!(service.poll_ready().is_ok());
+    let mut response = service.call(request);
+    future::lazy(|| match response.poll().unwrap() {
+        Async::Ready(response) => Ok::<_, ()>(Some(response.status())),
+        Async::NotReady => Ok(None),
+    })
+    .wait()
+    .unwrap()
+}
+
+fn request(method: Method, content_type: Option<&'static str>, body: bool) -> Request<Full> {
+    let mut request = Request::builder();
+    request.method(method).uri("/");
+    if let Some(content_type) = content_type {
+        request.header(CONTENT_TYPE, content_type);
+    }
+    let body = if body {
+        Full::from(Bytes::from_static(b"{}"))
+    } else {
+        Full::default()
+    };
+    request.body(body).unwrap()
+}
+
+#[test]
+fn accepts_allowed_media_types() {
+    let builder = || Builder::new().allow("application/json").allow("text/*");
+    let json = Some("application/json; charset=utf-8");
+    assert_eq!(check(builder(), request(Method::POST, json, true)), None);
+    let text = Some("Text/Plain");
+    assert_eq!(check(builder(), request(Method::PUT, text, true)), None);
+    assert_eq!(check(builder(), request(Method::POST, None, false)), None);
+
+    let xml = Some("application/xml");
+    assert_eq!(check(builder(), request(Method::GET, xml, true)), None);
+
+    let service = RequireContentType::new((), vec!["application/json"]);
+    assert_eq!(service.get_ref(), &());
+}
+
+#[test]
+fn rejects_other_media_types() {
+    let builder = || Builder::new().allow("application/json");
+    let unsupported = Some(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let xml = Some("application/xml");
+    assert_eq!(
+        check(builder(), request(Method::POST, xml, true)),
+        unsupported
+    );
+    let invalid = Some("json");
+    assert_eq!(
+        check(builder(), request(Method::PATCH, invalid, true)),
+        unsupported
+    );
+    assert_eq!(
+        check(builder(), request(Method::POST, None, true)),
+        unsupported
+    );
+
+    let mut empty = request(Method::POST, None, true);
+    empty
+        .headers_mut()
+        .insert(CONTENT_LENGTH, "0".parse().unwrap());
+    assert_eq!(check(builder(), empty), None);
+}
+
+#[test]
+fn applies_per_method_rules_and_charsets() {
+    let builder = || {
+        Builder::new()
+            .methods(vec![Method::POST])
+            .allow("application/json")
+            .allow_for(Method::PATCH, "application/merge-patch+json")
+    };
+    let unsupported = Some(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    let patch = Some("application/merge-patch+json");
+    assert_eq!(check(builder(), request(Method::PATCH, patch, true)), None);
+    assert_eq!(
+        check(builder(), request(Method::POST, patch, true)),
+        unsupported
+    );
+    let json = Some("application/json");
+    assert_eq!(check(builder(), request(Method::PATCH, json, true)), None);
+    assert_eq!(check(builder(), request(Method::PUT, patch, true)), None);
+
+    let builder = || Builder::new().allow("text/plain").require_charset("utf-8");
+    let text = Some("text/plain; charset=UTF-8");
+    assert_eq!(check(builder(), request(Method::POST, text, true)), None);
+    let text = Some("text/plain");
+    assert_eq!(
+        check(builder(), request(Method::POST, text, true)),
+        unsupported
+    );
+}
@@ -0,0 +1,42 @@
+use futures::Future;
+use http::header::{REFERRER_POLICY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS};
+use http::{Request, Response};
+use tower_http::security_headers::{Builder, PERMISSIONS_POLICY};
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn sets_configured_headers() {
+    let (service, mut handle) = mock::pair();
+
+    let mut service = Builder::new()
+        .frame_options("SAMEORIGIN")
+        .without_permissions_policy()
+        .build(service)
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    let response_out = Response::builder()
+        .header(REFERRER_POLICY, "no-referrer")
+        .body(())
+        .unwrap();
+    send_response.send_response(response_out);
+
+    let response = response.wait().unwrap();
+    let headers = response.headers();
+    assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+    assert_eq!(headers[X_FRAME_OPTIONS], "SAMEORIGIN");
+    assert_eq!(headers[REFERRER_POLICY], "no-referrer");
+    assert!(!headers.contains_key(PERMISSIONS_POLICY));
+}
+
+#[test]
+fn rejects_invalid_values() {
+    assert!(Builder::new()
+        .frame_options("bad\nvalue")
+        .build(())
+        .is_err());
+}
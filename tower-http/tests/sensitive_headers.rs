@@ -0,0 +1,35 @@
+use futures::Future;
+use http::header::{AUTHORIZATION, CONTENT_TYPE, SET_COOKIE};
+use http::{Request, Response};
+use tower_http::sensitive_headers::SetSensitiveHeaders;
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn marks_request_and_response_headers() {
+    let (service, mut handle) = mock::pair();
+
+    let mut service = SetSensitiveHeaders::with_defaults(service);
+
+    let request = Request::get("/")
+        .header(AUTHORIZATION, "Bearer secret")
+        .header(CONTENT_TYPE, "text/plain")
+        .body(())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request);
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert!(request.headers()[AUTHORIZATION].is_sensitive());
+    assert!(!request.headers()[CONTENT_TYPE].is_sensitive());
+
+    let response_out = Response::builder()
+        .header(SET_COOKIE, "id=1")
+        .body(())
+        .unwrap();
+    send_response.send_response(response_out);
+
+    let response = response.wait().unwrap();
+    assert!(response.headers()[SET_COOKIE].is_sensitive());
+}
@@ -0,0 +1,105 @@
+use futures::{future, Async};
+use http::header::{CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH};
+use http::header::{LAST_MODIFIED, RANGE};
+use http::{Request, StatusCode};
+use http_body::Body;
+use std::io::Read;
+use std::mem;
+use tokio::runtime::Runtime;
+use tower_http::fs::{FileBody, ServeArchive};
+use tower_service::Service;
+
+fn service() -> ServeArchive {
+    ServeArchive::open(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/assets.tar"
+    ))
+    .unwrap()
+}
+
+fn read(rt: &mut Runtime, mut body: FileBody) -> String {
+    let mut buf = String::new();
+    rt.block_on(future::poll_fn(move || loop {
+        match body.poll_data()? {
+            Async::Ready(Some(mut data)) => data.read_to_string(&mut buf).map(|_| ())?,
+            Async::Ready(None) => return Ok(Async::Ready(mem::replace(&mut buf, String::new()))),
+            Async::NotReady => return Ok::<_, std::io::Error>(Async::NotReady),
+        }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn serves_archived_files() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/css/style.css").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/css; charset=utf-8");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "20");
+    assert_eq!(
+        response.headers()[LAST_MODIFIED],
+        "Sun, 09 Sep 2001 01:46:40 GMT"
+    );
+    assert_eq!(read(&mut rt, response.into_body()), "body { margin: 0; }\n");
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(
+        read(&mut rt, response.into_body()),
+        "<title>Archive</title>\n"
+    );
+
+    let path = format!("/{}name.txt", "long/".repeat(25));
+    let request = Request::get(path).body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(read(&mut rt, response.into_body()), "long\n");
+
+    let request = Request::get("/css").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn serves_ranges_of_archived_files() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/hello.txt")
+        .header(RANGE, "bytes=7-11")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 7-11/14");
+    assert_eq!(read(&mut rt, response.into_body()), "world");
+
+    let request = Request::get("/hello.txt")
+        .header(RANGE, "bytes=0-4,-7")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    let body = read(&mut rt, response.into_body());
+    assert!(body.contains("\r\n\r\nHello\r\n"));
+    assert!(body.contains("\r\n\r\nworld!\n\r\n"));
+}
+
+#[test]
+fn answers_conditional_and_head_requests() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::head("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CONTENT_LENGTH], "14");
+    assert!(response.body().is_end_stream());
+
+    let request = Request::get("/hello.txt")
+        .header(IF_NONE_MATCH, response.headers()[ETAG].clone())
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
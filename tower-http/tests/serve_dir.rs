@@ -0,0 +1,344 @@
+use futures::{future, Async};
+use http::header::{HeaderValue, ACCEPT, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH};
+use http::header::{CACHE_CONTROL, CONTENT_TYPE, ETAG, IF_NONE_MATCH, LOCATION, VARY};
+use http::{Request, StatusCode};
+use http_body::Body;
+use std::io::Read;
+use std::mem;
+use tokio::runtime::Runtime;
+use tower_http::cache_control::Condition;
+use tower_http::fs::{Builder, FileBody, ServeDir, ServeFile, SymlinkPolicy};
+use tower_service::Service;
+
+fn service() -> ServeDir {
+    ServeDir::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))
+}
+
+fn status(rt: &mut Runtime, service: &mut ServeDir, path: &str) -> StatusCode {
+    let request = Request::get(path).body(()).unwrap();
+    rt.block_on(service.call(request)).unwrap().status()
+}
+
+#[test]
+fn serves_files_under_root() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/css/style.css").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/css; charset=utf-8");
+
+    assert_eq!(status(&mut rt, &mut service, "/hello.txt"), StatusCode::OK);
+    assert_eq!(
+        status(&mut rt, &mut service, "/hello%2Etxt"),
+        StatusCode::OK
+    );
+}
+
+#[test]
+fn answers_misses() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    assert_eq!(
+        status(&mut rt, &mut service, "/missing.txt"),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(&mut rt, &mut service, "/css/"),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(&mut rt, &mut service, "/hello.txt/"),
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(&mut rt, &mut service, "/../Cargo.toml"),
+        StatusCode::FORBIDDEN
+    );
+}
+
+#[test]
+fn refuses_malicious_paths() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    for path in &[
+        "/%2e%2e/Cargo.toml",
+        "/css%2f..%2f..%2fCargo.toml",
+        "/css/..%5c..%5cCargo.toml",
+        "/hello.txt%00.html",
+        "/hello.txt.",
+        "/hello.txt::$DATA",
+        "/CON",
+        "/css/aux.css",
+    ] {
+        assert_eq!(
+            status(&mut rt, &mut service, path),
+            StatusCode::FORBIDDEN,
+            "{}",
+            path
+        );
+    }
+}
+
+#[test]
+fn refuses_dotfiles_unless_enabled() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+    assert_eq!(
+        status(&mut rt, &mut service, "/.hidden"),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(&mut rt, &mut service, "/%2Ehidden"),
+        StatusCode::FORBIDDEN
+    );
+
+    let mut service = Builder::new()
+        .dotfiles(true)
+        .build(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+    assert_eq!(status(&mut rt, &mut service, "/.hidden"), StatusCode::OK);
+}
+
+#[cfg(unix)]
+#[test]
+fn applies_symlink_policy() {
+    let mut rt = Runtime::new().unwrap();
+    let service = |policy| {
+        Builder::new()
+            .symlinks(policy)
+            .build(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"))
+    };
+
+    let mut follow = service(SymlinkPolicy::Follow);
+    assert_eq!(status(&mut rt, &mut follow, "/link.txt"), StatusCode::OK);
+    assert_eq!(
+        status(&mut rt, &mut follow, "/linked/style.css"),
+        StatusCode::OK
+    );
+    assert_eq!(status(&mut rt, &mut follow, "/escape.toml"), StatusCode::OK);
+
+    let mut within_root = service(SymlinkPolicy::FollowWithinRoot);
+    assert_eq!(
+        status(&mut rt, &mut within_root, "/link.txt"),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(&mut rt, &mut within_root, "/linked/style.css"),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status(&mut rt, &mut within_root, "/escape.toml"),
+        StatusCode::FORBIDDEN
+    );
+
+    let mut refuse = service(SymlinkPolicy::Refuse);
+    assert_eq!(
+        status(&mut rt, &mut refuse, "/link.txt"),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(
+        status(&mut rt, &mut refuse, "/linked/style.css"),
+        StatusCode::FORBIDDEN
+    );
+    assert_eq!(status(&mut rt, &mut refuse, "/hello.txt"), StatusCode::OK);
+    assert_eq!(
+        status(&mut rt, &mut refuse, "/missing.txt"),
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[test]
+fn sets_cache_control() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = Builder::new()
+        .cache_immutable(Condition::path_prefix("/css/"))
+        .cache_revalidate(Condition::content_type("text/*"))
+        .build(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+    let request = Request::get("/css/style.css").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(
+        response.headers()[CACHE_CONTROL],
+        "public, max-age=31536000, immutable"
+    );
+
+    let request = Request::get("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+
+    let request = Request::get("/hello.txt")
+        .header(IF_NONE_MATCH, response.headers()[ETAG].clone())
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[CACHE_CONTROL], "no-cache");
+
+    let request = Request::get("/notes").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert!(!response.headers().contains_key(CACHE_CONTROL));
+
+    let request = Request::get("/missing.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert!(!response.headers().contains_key(CACHE_CONTROL));
+}
+
+#[test]
+fn serves_precompressed_files() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = Builder::new()
+        .precompressed_gzip(true)
+        .precompressed_br(true)
+        .build(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+    let request = Request::get("/hello.txt")
+        .header(ACCEPT_ENCODING, "gzip, br")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(response.headers()[VARY], "accept-encoding");
+
+    let request = Request::get("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(response.headers()[CONTENT_LENGTH], "14");
+    assert_eq!(response.headers()[VARY], "accept-encoding");
+}
+
+#[test]
+fn overrides_content_types() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+    let request = Request::get("/notes").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/octet-stream");
+
+    let mut service = Builder::new()
+        .content_type("TXT", HeaderValue::from_static("text/x-custom"))
+        .default_content_type(None)
+        .build(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+    let request = Request::get("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/x-custom");
+
+    let request = Request::get("/notes").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert!(!response.headers().contains_key(CONTENT_TYPE));
+}
+
+#[test]
+fn serves_index_files() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+
+    let request = Request::get("/css?v=1").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[LOCATION], "/css/?v=1");
+
+    let request = Request::get("//css").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[LOCATION], "/css/");
+}
+
+#[test]
+fn answers_head_requests_for_directories() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::head("/css").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.headers()[LOCATION], "/css/");
+
+    let request = Request::head("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+    assert!(response.body().is_end_stream());
+}
+
+#[test]
+fn lists_directories() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = Builder::new()
+        .directory_listing(true)
+        .build(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures"));
+
+    let request = Request::get("/css/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/html; charset=utf-8");
+    let body = read(&mut rt, response.into_body());
+    assert!(body.contains("<h1>Index of /css/</h1>"));
+    assert!(body.contains("<a href=\"./style.css\">style.css</a>"));
+
+    let request = Request::get("/css/")
+        .header(ACCEPT, "application/json")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/json");
+    let body = read(&mut rt, response.into_body());
+    assert!(body.starts_with("[{\"name\":\"style.css\",\"type\":\"file\",\"size\":20,"));
+
+    // Index files take precedence.
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    let body = read(&mut rt, response.into_body());
+    assert!(body.contains("<title>Fixtures</title>"));
+}
+
+fn read(rt: &mut Runtime, mut body: FileBody) -> String {
+    let mut buf = String::new();
+    rt.block_on(future::poll_fn(move || loop {
+        match body.poll_data()? {
+            Async::Ready(Some(mut data)) => data.read_to_string(&mut buf).map(|_| ())?,
+            Async::Ready(None) => return Ok(Async::Ready(mem::replace(&mut buf, String::new()))),
+            Async::NotReady => return Ok::<_, std::io::Error>(Async::NotReady),
+        }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn calls_fallback_on_misses() {
+    let mut rt = Runtime::new().unwrap();
+    let root = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+    let index = ServeFile::new(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/index.html"
+    ));
+    let mut service = ServeDir::with_fallback(root, index);
+
+    let request = Request::get("/app/settings").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = read(&mut rt, response.into_body());
+    assert!(body.contains("<title>Fixtures</title>"));
+
+    let request = Request::get("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(read(&mut rt, response.into_body()), "Hello, world!\n");
+
+    let request = Request::get("/../Cargo.toml").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
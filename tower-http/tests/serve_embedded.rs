@@ -0,0 +1,121 @@
+use futures::{future, Async};
+use http::header::{HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE};
+use http::header::{CONTENT_TYPE, ETAG, IF_NONE_MATCH, RANGE, VARY};
+use http::{Request, StatusCode};
+use http_body::Body;
+use std::io::Read;
+use std::mem;
+use tokio::runtime::current_thread::Runtime;
+use tower_http::fs::{Asset, FileBody, ServeEmbedded};
+use tower_service::Service;
+
+fn service() -> ServeEmbedded {
+    ServeEmbedded::new()
+        .insert("/hello.txt", &b"Hello, world!\n"[..])
+        .insert("/index.html", &b"<title>Embedded</title>"[..])
+        .insert_asset(
+            "/app.js",
+            Asset::new(
+                &b"alert(1);"[..],
+                Some(HeaderValue::from_static("text/javascript")),
+            )
+            .etag("\"v1\"")
+            .precompressed_gzip(&b"gzipped"[..]),
+        )
+}
+
+fn read(rt: &mut Runtime, mut body: FileBody) -> String {
+    let mut buf = String::new();
+    rt.block_on(future::poll_fn(move || loop {
+        match body.poll_data()? {
+            Async::Ready(Some(mut data)) => data.read_to_string(&mut buf).map(|_| ())?,
+            Async::Ready(None) => return Ok(Async::Ready(mem::replace(&mut buf, String::new()))),
+            Async::NotReady => return Ok::<_, std::io::Error>(Async::NotReady),
+        }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn serves_assets() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(response.headers()[CONTENT_LENGTH], "14");
+    assert!(response.headers().contains_key(ETAG));
+    assert!(!response.headers().contains_key(VARY));
+    assert_eq!(read(&mut rt, response.into_body()), "Hello, world!\n");
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(
+        read(&mut rt, response.into_body()),
+        "<title>Embedded</title>"
+    );
+
+    let request = Request::get("/missing.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = Request::delete("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[test]
+fn answers_conditional_and_range_requests() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/app.js")
+        .header(IF_NONE_MATCH, "\"v1\"")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], "\"v1\"");
+
+    let request = Request::get("/hello.txt")
+        .header(RANGE, "bytes=7-11")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 7-11/14");
+    assert_eq!(read(&mut rt, response.into_body()), "world");
+
+    let request = Request::head("/hello.txt").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CONTENT_LENGTH], "14");
+    assert!(response.body().is_end_stream());
+}
+
+#[test]
+fn serves_precompressed_contents() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = service();
+
+    let request = Request::get("/app.js")
+        .header(ACCEPT_ENCODING, "gzip")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.headers()[CONTENT_ENCODING], "gzip");
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/javascript");
+    assert_eq!(response.headers()[VARY], "accept-encoding");
+    assert_ne!(response.headers()[ETAG], "\"v1\"");
+    assert_eq!(read(&mut rt, response.into_body()), "gzipped");
+
+    let request = Request::get("/app.js").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(response.headers()[ETAG], "\"v1\"");
+    assert_eq!(read(&mut rt, response.into_body()), "alert(1);");
+}
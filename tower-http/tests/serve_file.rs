@@ -0,0 +1,239 @@
+use futures::{future, Async};
+use http::header::{ALLOW, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG};
+use http::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE};
+use http::{Request, StatusCode};
+use http_body::Body;
+use std::io::Read;
+use std::mem;
+use tokio::runtime::Runtime;
+use tower_http::fs::{Attachment, FileBody, ServeFile};
+use tower_service::Service;
+
+fn fixture(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+fn read(rt: &mut Runtime, mut body: FileBody) -> String {
+    let mut buf = String::new();
+    rt.block_on(future::poll_fn(move || loop {
+        match body.poll_data()? {
+            Async::Ready(Some(mut data)) => data.read_to_string(&mut buf).map(|_| ())?,
+            Async::Ready(None) => return Ok(Async::Ready(mem::replace(&mut buf, String::new()))),
+            Async::NotReady => return Ok::<_, std::io::Error>(Async::NotReady),
+        }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn serves_file() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = ServeFile::new(fixture("hello.txt"));
+
+    let request = Request::get("/anything").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(response.headers()[CONTENT_LENGTH], "14");
+    assert_eq!(read(&mut rt, response.into_body()), "Hello, world!\n");
+}
+
+#[test]
+fn reads_file_in_chunks() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = ServeFile::new(fixture("hello.txt")).chunk_size(4);
+
+    let request = Request::get("/").body(()).unwrap();
+    let mut body = rt.block_on(service.call(request)).unwrap().into_body();
+    let mut chunks = Vec::new();
+    let chunks = rt
+        .block_on(future::poll_fn(move || loop {
+            match body.poll_data()? {
+                Async::Ready(Some(data)) => chunks.push(data.into_inner()),
+                Async::Ready(None) => {
+                    return Ok(Async::Ready(mem::replace(&mut chunks, Vec::new())))
+                }
+                Async::NotReady => return Ok::<_, std::io::Error>(Async::NotReady),
+            }
+        }))
+        .unwrap();
+    assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+    assert_eq!(chunks.concat(), b"Hello, world!\n");
+}
+
+#[test]
+fn answers_head_requests_without_body() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = ServeFile::new(fixture("hello.txt"));
+
+    let request = Request::head("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(response.headers()[CONTENT_LENGTH], "14");
+    assert!(response.headers().contains_key(ETAG));
+    assert!(response.headers().contains_key(LAST_MODIFIED));
+    assert!(response.body().is_end_stream());
+    assert_eq!(read(&mut rt, response.into_body()), "");
+
+    let request = Request::head("/")
+        .header(RANGE, "bytes=7-")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_LENGTH], "7");
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 7-13/14");
+
+    let mut service = ServeFile::new(fixture("missing.txt"));
+    let request = Request::head("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[test]
+fn answers_missing_file_and_other_methods() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = ServeFile::new(fixture("missing.txt"));
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let request = Request::post("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.headers()[ALLOW], "GET, HEAD");
+}
+
+fn range(rt: &mut Runtime, range: &str) -> http::Response<FileBody> {
+    let mut service = ServeFile::new(fixture("hello.txt"));
+    let request = Request::get("/").header(RANGE, range).body(()).unwrap();
+    rt.block_on(service.call(request)).unwrap()
+}
+
+#[test]
+fn serves_ranges() {
+    let mut rt = Runtime::new().unwrap();
+
+    let response = range(&mut rt, "bytes=7-11");
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes 7-11/14");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "5");
+    assert_eq!(read(&mut rt, response.into_body()), "world");
+
+    let response = range(&mut rt, "bytes=20-");
+    assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(response.headers()[CONTENT_RANGE], "bytes */14");
+}
+
+#[test]
+fn serves_multiple_ranges() {
+    let mut rt = Runtime::new().unwrap();
+
+    let response = range(&mut rt, "bytes=0-4, -6");
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = response.headers()[CONTENT_TYPE].to_str().unwrap();
+    let boundary = content_type
+        .trim_start_matches("multipart/byteranges; boundary=")
+        .to_owned();
+    let len: usize = response.headers()[CONTENT_LENGTH]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let body = read(&mut rt, response.into_body());
+    let expected = format!(
+        "--{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-4/14\r\n\r\n\
+         Hello\r\n\
+         --{b}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 8-13/14\r\n\r\n\
+         orld!\n\r\n\
+         --{b}--\r\n",
+        b = boundary,
+    );
+    assert_eq!(body, expected);
+    assert_eq!(body.len(), len);
+}
+
+#[test]
+fn ignores_ranges_of_modified_files() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = ServeFile::new(fixture("hello.txt"));
+
+    let request = Request::get("/")
+        .header(RANGE, "bytes=0-4")
+        .header(IF_RANGE, "Thu, 01 Jan 1970 00:00:00 GMT")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(read(&mut rt, response.into_body()), "Hello, world!\n");
+}
+
+#[test]
+fn answers_conditional_requests() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = ServeFile::new(fixture("hello.txt"));
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    let etag = response.headers()[ETAG].clone();
+    let last_modified = response.headers()[LAST_MODIFIED].clone();
+
+    let request = Request::get("/")
+        .header(IF_NONE_MATCH, etag.clone())
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag);
+
+    let request = Request::get("/")
+        .header(IF_MODIFIED_SINCE, last_modified)
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    let request = Request::get("/")
+        .header(IF_NONE_MATCH, "\"other\"")
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let request = Request::get("/")
+        .header(RANGE, "bytes=0-4")
+        .header(IF_RANGE, etag)
+        .body(())
+        .unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+}
+
+#[test]
+fn serves_attachments() {
+    let mut rt = Runtime::new().unwrap();
+    let mut service = Attachment::new(ServeFile::new(fixture("hello.txt")), "grüße.txt");
+
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(
+        response.headers()[CONTENT_DISPOSITION],
+        "attachment; filename=\"gr__e.txt\"; filename*=UTF-8''gr%C3%BC%C3%9Fe.txt"
+    );
+    assert_eq!(read(&mut rt, response.into_body()), "Hello, world!\n");
+
+    let mut service = Attachment::new(ServeFile::new(fixture("missing.txt")), "missing.txt");
+    let request = Request::get("/").body(()).unwrap();
+    let response = rt.block_on(service.call(request)).unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert!(!response.headers().contains_key(CONTENT_DISPOSITION));
+}
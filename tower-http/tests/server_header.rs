@@ -0,0 +1,58 @@
+use futures::Future;
+use http::header::{HeaderValue, SERVER};
+use http::{Request, Response};
+use tower_http::server_header::SetServerHeader;
+use tower_service::Service;
+use tower_test::mock;
+
+fn respond<S>(
+    service: &mut S,
+    handle: &mut mock::Handle<Request<()>, Response<()>>,
+    server: Option<&'static str>,
+) -> Response<()>
+where
+    S: Service<Request<()>, Response = Response<()>>,
+    S::Error: std::fmt::Debug,
+{
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    let mut builder = Response::builder();
+    if let Some(server) = server {
+        builder.header(SERVER, server);
+    }
+    send_response.send_response(builder.body(()).unwrap());
+
+    response.wait().unwrap()
+}
+
+#[test]
+fn if_not_present_keeps_inner_value() {
+    let (service, mut handle) = mock::pair();
+    let mut service = SetServerHeader::if_not_present(service, HeaderValue::from_static("tower"));
+
+    let response = respond(&mut service, &mut handle, Some("inner"));
+    assert_eq!(response.headers()[SERVER], "inner");
+
+    let response = respond(&mut service, &mut handle, None);
+    assert_eq!(response.headers()[SERVER], "tower");
+}
+
+#[test]
+fn overwrite_replaces_inner_value() {
+    let (service, mut handle) = mock::pair();
+    let mut service = SetServerHeader::overwrite(service, HeaderValue::from_static("tower"));
+
+    let response = respond(&mut service, &mut handle, Some("inner"));
+    assert_eq!(response.headers()[SERVER], "tower");
+}
+
+#[test]
+fn strip_removes_header() {
+    let (service, mut handle) = mock::pair();
+    let mut service = SetServerHeader::strip(service);
+
+    let response = respond(&mut service, &mut handle, Some("inner"));
+    assert!(!response.headers().contains_key(SERVER));
+}
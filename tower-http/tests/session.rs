@@ -0,0 +1,95 @@
+use futures::future::{self, FutureResult};
+use futures::{Async, Future, Poll};
+use http::header::{COOKIE, SET_COOKIE};
+use http::{Request, Response};
+use std::{io, thread};
+use tower_http::session::{MemoryStore, Session, SessionError, SessionManager};
+use tower_service::Service;
+use tower_test::mock;
+
+type Mock = mock::Mock<Request<()>, Response<()>>;
+type Handle = mock::Handle<Request<()>, Response<()>>;
+
+fn round_trip<F>(
+    service: &mut SessionManager<Mock, MemoryStore>,
+    handle: &mut Handle,
+    cookie: Option<&str>,
+    handler: F,
+) -> Response<()>
+where
+    F: FnOnce(&Session),
+{
+    let mut request = Request::get("/");
+    if let Some(cookie) = cookie {
+        request.header(COOKIE, cookie);
+    }
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(request.body(()).unwrap());
+    let response = thread::spawn(move || response.wait().unwrap());
+
+    let (request, send_response) = handle.next_request().unwrap();
+    handler(request.extensions().get::<Session>().unwrap());
+    send_response.send_response(Response::new(()));
+
+    response.join().unwrap()
+}
+
+#[test]
+fn creates_loads_and_destroys_sessions() {
+    let store = MemoryStore::new();
+    let (service, mut handle) = mock::pair();
+    let mut service = SessionManager::new(service, store.clone());
+
+    // A new session is created and given a cookie.
+    let response = round_trip(&mut service, &mut handle, None, |session| {
+        assert!(session.id().is_none());
+        session.insert("user", "alice");
+    });
+    let set_cookie = response.headers()[SET_COOKIE].to_str().unwrap().to_owned();
+    let cookie = set_cookie.split(';').next().unwrap().to_owned();
+    assert!(cookie.starts_with("session="));
+    assert_eq!(store.len(), 1);
+
+    // The session is loaded on subsequent requests.
+    let response = round_trip(&mut service, &mut handle, Some(&cookie), |session| {
+        assert_eq!(session.get("user").unwrap(), "alice");
+        session.destroy();
+    });
+    assert!(response.headers().contains_key(SET_COOKIE));
+    assert!(store.is_empty());
+
+    // Unknown ids start a fresh session.
+    round_trip(&mut service, &mut handle, Some(&cookie), |session| {
+        assert!(session.id().is_none());
+        assert!(session.get("user").is_none());
+    });
+}
+
+#[derive(Clone)]
+struct Failing;
+
+impl Service<Request<()>> for Failing {
+    type Response = Response<()>;
+    type Error = io::Error;
+    type Future = FutureResult<Response<()>, io::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _req: Request<()>) -> Self::Future {
+        future::err(io::Error::new(io::ErrorKind::Other, "unavailable"))
+    }
+}
+
+#[test]
+fn reports_inner_errors() {
+    let mut service = SessionManager::new(Failing, MemoryStore::new());
+
+    assert!(service.poll_ready().is_ok());
+    match service.call(Request::get("/").body(()).unwrap()).wait() {
+        Err(SessionError::Inner(e)) => assert_eq!(e.to_string(), "unavailable"),
+        _ => panic!("expected the inner error"),
+    }
+}
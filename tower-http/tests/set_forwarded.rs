@@ -0,0 +1,57 @@
+use http::header::{FORWARDED, HOST};
+use http::{Request, Response};
+use std::net::SocketAddr;
+use tower_http::set_forwarded::Builder;
+use tower_service::Service;
+use tower_test::mock;
+
+fn forward(builder: Builder, request: Request<()>) -> Request<()> {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = builder.build(service);
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+
+    let (request, _send_response) = handle.next_request().unwrap();
+    request
+}
+
+fn client_request() -> Request<()> {
+    let mut request = Request::get("/")
+        .header(HOST, "example.com")
+        .header("x-forwarded-for", "198.51.100.1")
+        .body(())
+        .unwrap();
+    request
+        .extensions_mut()
+        .insert("[2001:db8::7]:4000".parse::<SocketAddr>().unwrap());
+    request
+}
+
+#[test]
+fn appends_x_forwarded_headers() {
+    let request = forward(Builder::new(), client_request());
+
+    let headers = request.headers();
+    assert_eq!(headers["x-forwarded-for"], "198.51.100.1, 2001:db8::7");
+    assert_eq!(headers["x-forwarded-host"], "example.com");
+    assert!(!headers.contains_key("x-forwarded-proto"));
+    assert!(!headers.contains_key(FORWARDED));
+}
+
+#[test]
+fn emits_forwarded_element() {
+    let builder = Builder::new()
+        .x_forwarded_for(false)
+        .x_forwarded_host(false)
+        .forwarded(true)
+        .forwarded_by("10.0.0.1".parse().unwrap());
+    let request = forward(builder, client_request());
+
+    let headers = request.headers();
+    assert_eq!(
+        headers[FORWARDED],
+        "by=10.0.0.1;for=\"[2001:db8::7]\";host=example.com"
+    );
+    assert_eq!(headers["x-forwarded-for"], "198.51.100.1");
+}
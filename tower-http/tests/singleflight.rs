@@ -0,0 +1,133 @@
+mod support;
+
+use bytes::Bytes;
+use futures::Future;
+use http::header::{HeaderMap, HeaderValue, COOKIE};
+use http::{Request, Response};
+use std::thread;
+use tower_http::singleflight::Singleflight;
+use tower_service::Service;
+use tower_test::mock;
+
+use support::{read, streamed, Full, Streamed};
+
+#[test]
+fn coalesces_concurrent_gets() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Singleflight::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(Request::get("/a").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(Request::get("/a").body(()).unwrap());
+
+    let (request, send_response) = handle.next_request().unwrap();
+    assert_eq!(request.uri(), "/a");
+    send_response.send_response(
+        Response::builder()
+            .header("x-shared", "yes")
+            .body(Full::from(Bytes::from("hello")))
+            .unwrap(),
+    );
+
+    let first = first.wait().unwrap();
+    let second = second.wait().unwrap();
+    assert_eq!(second.headers()["x-shared"], "yes");
+    for response in vec![first, second] {
+        let (body, _) = read(response.into_body());
+        assert_eq!(body, "hello");
+    }
+}
+
+#[test]
+fn does_not_coalesce_credentialed_requests() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Singleflight::new(service);
+
+    let request = || {
+        Request::get("/a")
+            .header(COOKIE, "session=alice")
+            .body(())
+            .unwrap()
+    };
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(request());
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(request());
+
+    for _ in 0..2 {
+        let (_request, send_response) = handle.next_request().unwrap();
+        send_response.send_response(Response::new(Full::from(Bytes::from("hello"))));
+    }
+    first.wait().unwrap();
+    second.wait().unwrap();
+}
+
+#[test]
+fn does_not_share_large_bodies() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Full>>();
+    let mut service = Singleflight::new(service).max_body(4);
+
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(Request::get("/a").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(Request::get("/a").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Full::from(Bytes::from("hello"))));
+    let first = first.wait().unwrap();
+    assert_eq!(read(first.into_body()).0, "hello");
+
+    // The second request calls the inner service itself.
+    let second = thread::spawn(move || second.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Full::from(Bytes::from("world"))));
+    let second = second.join().unwrap();
+    assert_eq!(read(second.into_body()).0, "world");
+}
+
+#[test]
+fn streams_bodies_found_too_large() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Streamed>>();
+    let mut service = Singleflight::new(service).max_body(4);
+
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(Request::get("/a").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(Request::get("/a").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(streamed(&["he", "llo", " world"], None)));
+    let first = first.wait().unwrap();
+
+    // The second request calls the inner service as soon as the body
+    // outgrows the limit, before the first body is read.
+    let second = thread::spawn(move || second.wait().unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(streamed(&["again"], None)));
+    assert_eq!(read(second.join().unwrap().into_body()).0, "again");
+
+    assert_eq!(read(first.into_body()).0, "hello world");
+}
+
+#[test]
+fn shares_trailers() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Streamed>>();
+    let mut service = Singleflight::new(service);
+
+    assert!(service.poll_ready().is_ok());
+    let first = service.call(Request::get("/a").body(()).unwrap());
+    assert!(service.poll_ready().is_ok());
+    let second = service.call(Request::get("/a").body(()).unwrap());
+
+    let mut trailers = HeaderMap::new();
+    trailers.insert("x-checksum", HeaderValue::from_static("abc"));
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(streamed(&["hello"], Some(trailers))));
+
+    for response in vec![first.wait().unwrap(), second.wait().unwrap()] {
+        let (body, trailers) = read(response.into_body());
+        assert_eq!(body, "hello");
+        assert_eq!(trailers.unwrap()["x-checksum"], "abc");
+    }
+}
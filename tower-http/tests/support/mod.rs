@@ -0,0 +1,129 @@
+//! Bodies shared by the integration tests.
+
+#![allow(dead_code)]
+
+use bytes::{Buf, Bytes};
+use futures::{Async, Poll};
+use http::HeaderMap;
+use http_body::Body;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt::Debug;
+use std::io::Cursor;
+
+/// A body yielding its data in a single chunk, if any.
+#[derive(Debug, Default)]
+pub struct Full(pub Option<Bytes>);
+
+/// A body yielding a chunk per string.
+#[derive(Debug, Default)]
+pub struct Chunks(pub VecDeque<&'static str>);
+
+pub fn chunks(chunks: &[&'static str]) -> Chunks {
+    Chunks(chunks.iter().cloned().collect())
+}
+
+impl Body for Chunks {
+    type Data = Cursor<Bytes>;
+    type Error = ();
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, ()> {
+        let chunk = self.0.pop_front().map(|s| Cursor::new(Bytes::from(s)));
+        Ok(Async::Ready(chunk))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, ()> {
+        Ok(Async::Ready(None))
+    }
+}
+
+impl From<Bytes> for Full {
+    fn from(bytes: Bytes) -> Self {
+        Full(Some(bytes))
+    }
+}
+
+impl Body for Full {
+    type Data = Cursor<Bytes>;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        Ok(Async::Ready(self.0.take().map(Cursor::new)))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        Ok(Async::Ready(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+/// A body yielding a chunk per string, then its trailers, if any.
+#[derive(Debug, Default)]
+pub struct Streamed {
+    pub chunks: VecDeque<Bytes>,
+    pub trailers: Option<HeaderMap>,
+    /// Whether the body stays pending after its chunks rather than ending.
+    pub endless: bool,
+}
+
+pub fn streamed(chunks: &[&'static str], trailers: Option<HeaderMap>) -> Streamed {
+    Streamed {
+        chunks: chunks.iter().map(|&s| Bytes::from(s)).collect(),
+        trailers,
+        endless: false,
+    }
+}
+
+impl From<Bytes> for Streamed {
+    fn from(bytes: Bytes) -> Self {
+        Streamed {
+            chunks: Some(bytes).into_iter().collect(),
+            trailers: None,
+            endless: false,
+        }
+    }
+}
+
+impl Body for Streamed {
+    type Data = Cursor<Bytes>;
+    type Error = Box<dyn Error + Send + Sync>;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self.chunks.pop_front() {
+            Some(chunk) => Ok(Async::Ready(Some(Cursor::new(chunk)))),
+            None if self.endless => Ok(Async::NotReady),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        Ok(Async::Ready(self.trailers.take()))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.chunks.is_empty() && self.trailers.is_none() && !self.endless
+    }
+}
+
+/// Reads a ready body to the end, returning its data and trailers.
+pub fn read<B>(mut body: B) -> (Bytes, Option<HeaderMap>)
+where
+    B: Body,
+    B::Error: Debug,
+{
+    let mut data = Vec::new();
+    while let Async::Ready(Some(mut chunk)) = body.poll_data().unwrap() {
+        while chunk.has_remaining() {
+            let n = chunk.bytes().len();
+            data.extend_from_slice(chunk.bytes());
+            chunk.advance(n);
+        }
+    }
+    match body.poll_trailers().unwrap() {
+        Async::Ready(trailers) => (Bytes::from(data), trailers),
+        Async::NotReady => panic!("trailers not ready"),
+    }
+}
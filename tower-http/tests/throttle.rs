@@ -0,0 +1,72 @@
+mod support;
+
+use futures::{future, Async, Future};
+use http::{Request, Response};
+use http_body::Body;
+use std::time::{Duration, Instant};
+use tokio::runtime::current_thread::Runtime;
+use tower_http::throttle::{Bandwidth, Builder, ThrottleRequestBody, Throttled};
+use tower_service::Service;
+use tower_test::mock;
+
+use support::Chunks;
+
+#[test]
+fn paces_request_bodies() {
+    let (service, mut handle) = mock::pair::<Request<Throttled<Chunks>>, Response<()>>();
+    let extractor = |_: &Request<Chunks>| Some("client".to_owned());
+    // 100 bytes per second with a burst of 10 bytes.
+    let bandwidth = Bandwidth::new(100).burst(10);
+    let mut service = ThrottleRequestBody::new(service, extractor, bandwidth);
+
+    let body = Chunks(vec!["0123456789", "0123456789", "0"].into_iter().collect());
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(Request::post("/").body(body).unwrap());
+    let (request, _send_response) = handle.next_request().unwrap();
+    let mut body = request.into_body();
+
+    let start = Instant::now();
+    let mut rt = Runtime::new().unwrap();
+    let mut chunks = 0;
+    rt.block_on(future::poll_fn(|| loop {
+        match futures::try_ready!(body.poll_data()) {
+            Some(_) => chunks += 1,
+            None => return Ok::<_, ()>(Async::Ready(())),
+        }
+    }))
+    .unwrap();
+
+    // The burst is spent by the first chunk and the debt of the second one
+    // is repaid before the third chunk.
+    assert_eq!(chunks, 3);
+    assert!(start.elapsed() >= Duration::from_millis(90));
+}
+
+#[test]
+fn paces_response_bodies_globally() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let extractor = |_: &Request<()>| -> Option<String> { None };
+    let mut service = Builder::new()
+        .global(Bandwidth::new(100).burst(10))
+        .build(service, extractor);
+
+    let mut bodies = Vec::new();
+    for _ in 0..3 {
+        assert!(service.poll_ready().is_ok());
+        let response = service.call(Request::get("/").body(()).unwrap());
+        let (_request, send_response) = handle.next_request().unwrap();
+        let body = Chunks(vec!["0123456789"].into_iter().collect());
+        send_response.send_response(Response::new(body));
+        bodies.push(response.wait().unwrap().into_body());
+    }
+
+    let start = Instant::now();
+    let mut rt = Runtime::new().unwrap();
+    for body in &mut bodies {
+        rt.block_on(future::poll_fn(|| body.poll_data())).unwrap();
+    }
+
+    // The budget is shared by all responses, so the third one waits for the
+    // debt of the second one to be repaid.
+    assert!(start.elapsed() >= Duration::from_millis(90));
+}
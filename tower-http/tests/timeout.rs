@@ -0,0 +1,185 @@
+use bytes::Bytes;
+use futures::{future, Async, Poll};
+use http::{HeaderMap, Request, Response, StatusCode};
+use http_body::Body;
+use std::io::Cursor;
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::current_thread::Runtime;
+use tower_http::timeout::{ResponseTimeout, Timeout, TimeoutError};
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn responds_with_408_on_timeout() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Timeout::new(service, Duration::from_millis(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            service.call(Request::get("/").body(()).unwrap())
+        }))
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+}
+
+#[test]
+fn responds_with_504_in_gateway_mode() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<()>>();
+    let mut service = Timeout::gateway(service, Duration::from_millis(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            service.call(Request::get("/").body(()).unwrap())
+        }))
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[derive(Debug, Default)]
+struct Pending;
+
+impl Body for Pending {
+    type Data = Cursor<Bytes>;
+    type Error = ();
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, ()> {
+        Ok(Async::NotReady)
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, ()> {
+        Ok(Async::NotReady)
+    }
+}
+
+#[test]
+fn header_timeout_responds_with_504() {
+    let (service, _handle) = mock::pair::<Request<()>, Response<Pending>>();
+    let mut service =
+        ResponseTimeout::new(service, Duration::from_millis(10), Duration::from_secs(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            service.call(Request::get("/").body(()).unwrap())
+        }))
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+}
+
+#[test]
+fn total_timeout_fails_body() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Pending>>();
+    let mut service =
+        ResponseTimeout::new(service, Duration::from_secs(10), Duration::from_millis(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            let response = service.call(Request::get("/").body(()).unwrap());
+            let (_request, send_response) = handle.next_request().unwrap();
+            send_response.send_response(Response::new(Pending));
+            response
+        }))
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut body = response.into_body();
+    let result = rt.block_on(future::poll_fn(|| body.poll_data()));
+    match result {
+        Err(TimeoutError::Elapsed) => {}
+        _ => panic!("expected the body to time out"),
+    }
+}
+
+#[test]
+fn switching_protocols_bodies_have_no_deadline() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Pending>>();
+    let mut service =
+        ResponseTimeout::new(service, Duration::from_secs(10), Duration::from_millis(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            let response = service.call(Request::get("/chat").body(()).unwrap());
+            let (_request, send_response) = handle.next_request().unwrap();
+            let mut switching = Response::new(Pending);
+            *switching.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+            send_response.send_response(switching);
+            response
+        }))
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(20));
+    let mut body = response.into_body();
+    let result = rt.block_on(future::lazy(|| Ok::<_, ()>(body.poll_data())));
+    match result.unwrap() {
+        Ok(Async::NotReady) => {}
+        _ => panic!("expected the body to stay open"),
+    }
+}
+
+#[test]
+fn rejected_tunnels_keep_the_deadline() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Pending>>();
+    let mut service =
+        ResponseTimeout::new(service, Duration::from_secs(10), Duration::from_millis(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            let request = Request::connect("example.com:443").body(()).unwrap();
+            let response = service.call(request);
+            let (_request, send_response) = handle.next_request().unwrap();
+            let mut forbidden = Response::new(Pending);
+            *forbidden.status_mut() = StatusCode::FORBIDDEN;
+            send_response.send_response(forbidden);
+            response
+        }))
+        .unwrap();
+
+    let mut body = response.into_body();
+    let result = rt.block_on(future::poll_fn(|| body.poll_data()));
+    match result {
+        Err(TimeoutError::Elapsed) => {}
+        _ => panic!("expected the body to time out"),
+    }
+}
+
+#[test]
+fn accepted_tunnels_have_no_deadline() {
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Pending>>();
+    let mut service =
+        ResponseTimeout::new(service, Duration::from_secs(10), Duration::from_millis(10));
+
+    let mut rt = Runtime::new().unwrap();
+    let response = rt
+        .block_on(future::lazy(|| {
+            assert!(service.poll_ready().is_ok());
+            let request = Request::connect("example.com:443").body(()).unwrap();
+            let response = service.call(request);
+            let (_request, send_response) = handle.next_request().unwrap();
+            send_response.send_response(Response::new(Pending));
+            response
+        }))
+        .unwrap();
+
+    thread::sleep(Duration::from_millis(20));
+    let mut body = response.into_body();
+    let result = rt.block_on(future::lazy(|| Ok::<_, ()>(body.poll_data())));
+    match result.unwrap() {
+        Ok(Async::NotReady) => {}
+        _ => panic!("expected the body to stay open"),
+    }
+}
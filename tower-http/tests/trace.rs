@@ -0,0 +1,199 @@
+use bytes::{Buf, Bytes};
+use futures::{Async, Future, Poll};
+use http::{HeaderMap, Request, Response, StatusCode};
+use http_body::Body;
+use std::collections::VecDeque;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tower_http::classify::FailureClass;
+use tower_http::trace::Trace;
+use tower_service::Service;
+use tower_test::mock;
+
+/// Yields a chunk per string, failing on `"fail"`.
+#[derive(Debug)]
+struct Chunks(VecDeque<&'static str>);
+
+impl Body for Chunks {
+    type Data = Cursor<Bytes>;
+    type Error = &'static str;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self.0.pop_front() {
+            Some("fail") => Err("body failed"),
+            chunk => Ok(Async::Ready(chunk.map(|s| Cursor::new(Bytes::from(s))))),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        Ok(Async::Ready(None))
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+type Events = Arc<Mutex<Vec<String>>>;
+type Handle = mock::Handle<Request<()>, Response<Chunks>>;
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+fn service(
+    events: &Events,
+) -> (
+    impl Service<
+        Request<()>,
+        Response = Response<impl Body<Data = Cursor<Bytes>, Error = &'static str>>,
+        Error = Error,
+    >,
+    Handle,
+) {
+    let (service, handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let (e1, e2, e3, e4, e5) = (
+        events.clone(),
+        events.clone(),
+        events.clone(),
+        events.clone(),
+        events.clone(),
+    );
+    let service = Trace::new(service)
+        .on_request(move |req: &Request<()>| {
+            e1.lock().unwrap().push(format!("request {}", req.uri()))
+        })
+        .on_response(move |res: &Response<Chunks>, _: Duration| {
+            e2.lock()
+                .unwrap()
+                .push(format!("response {}", res.status().as_u16()))
+        })
+        .on_body_chunk(move |chunk: &Cursor<Bytes>, _: Duration| {
+            e3.lock()
+                .unwrap()
+                .push(format!("chunk {}", chunk.remaining()))
+        })
+        .on_eos(move |trailers: Option<&HeaderMap>, _: Duration| {
+            e4.lock()
+                .unwrap()
+                .push(format!("eos {}", trailers.is_some()))
+        })
+        .on_failure(move |failure: &FailureClass, _: Duration| {
+            e5.lock().unwrap().push(format!("failure {}", failure))
+        });
+    (service, handle)
+}
+
+#[test]
+fn calls_hooks_over_lifecycle() {
+    let events = Events::default();
+    let (mut service, mut handle) = service(&events);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/hello").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    let body = Chunks(vec!["hello", "world!"].into_iter().collect());
+    send_response.send_response(Response::new(body));
+
+    let response = response.wait().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let mut body = response.into_body();
+    while let Async::Ready(Some(_)) = body.poll_data().unwrap() {}
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "request /hello",
+            "response 200",
+            "chunk 5",
+            "chunk 6",
+            "eos false",
+        ]
+    );
+}
+
+#[test]
+fn calls_eos_hook_when_dropped() {
+    let events = Events::default();
+    let (mut service, mut handle) = service(&events);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/hello").body(()).unwrap());
+
+    let (_request, send_response) = handle.next_request().unwrap();
+    let body = Chunks(vec!["hello", "world!"].into_iter().collect());
+    send_response.send_response(Response::new(body));
+
+    let mut body = response.wait().unwrap().into_body();
+    assert!(body.poll_data().unwrap().is_ready());
+    drop(body);
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec!["request /hello", "response 200", "chunk 5", "eos false"]
+    );
+}
+
+#[test]
+fn calls_failure_hook() {
+    let events = Events::default();
+    let (mut service, mut handle) = service(&events);
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_error("service failed");
+    assert!(response.wait().is_err());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Chunks(vec!["fail"].into_iter().collect())));
+    let mut body = response.wait().unwrap().into_body();
+    assert!(body.poll_data().is_err());
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    let mut res = Response::new(Chunks(VecDeque::new()));
+    *res.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+    send_response.send_response(res);
+    response.wait().unwrap();
+
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "request /",
+            "failure service failed",
+            "request /",
+            "response 200",
+            "failure body failed",
+            "request /",
+            "response 500",
+            "failure status code 500",
+            "eos false",
+        ]
+    );
+}
+
+#[cfg(feature = "tracing")]
+#[test]
+fn makes_span_per_request() {
+    use http::request::Parts;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    let spans = Arc::new(AtomicUsize::new(0));
+    let (service, mut handle) = mock::pair::<Request<()>, Response<Chunks>>();
+    let counter = spans.clone();
+    let mut service = Trace::new(service).make_span(move |_: &Parts| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        tracing::Span::none()
+    });
+
+    assert!(service.poll_ready().is_ok());
+    let response = service.call(Request::get("/").body(()).unwrap());
+    let (_request, send_response) = handle.next_request().unwrap();
+    send_response.send_response(Response::new(Chunks(VecDeque::new())));
+    response.wait().unwrap();
+
+    assert_eq!(spans.load(Ordering::SeqCst), 1);
+}
@@ -0,0 +1,52 @@
+use http::header::USER_AGENT;
+use http::{Request, Response};
+use tower_http::user_agent::Builder;
+use tower_service::Service;
+use tower_test::mock;
+
+#[test]
+fn sets_default_user_agent() {
+    let (service, mut handle) = mock::pair::<_, Response<()>>();
+
+    let mut service = Builder::new()
+        .product("my-client", "1.0")
+        .tower_http_product()
+        .build(service)
+        .unwrap();
+
+    let request = Request::get("/").body(()).unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+
+    let (request, _send_response) = handle.next_request().unwrap();
+    let expected = format!("my-client/1.0 tower-http/{}", env!("CARGO_PKG_VERSION"));
+    assert_eq!(request.headers()[USER_AGENT], expected.as_str());
+}
+
+#[test]
+fn keeps_caller_user_agent() {
+    let (service, mut handle) = mock::pair::<_, Response<()>>();
+
+    let mut service = Builder::new()
+        .product_name("fallback")
+        .build(service)
+        .unwrap();
+
+    let request = Request::get("/")
+        .header(USER_AGENT, "custom")
+        .body(())
+        .unwrap();
+
+    assert!(service.poll_ready().is_ok());
+    let _response = service.call(request);
+
+    let (request, _send_response) = handle.next_request().unwrap();
+    assert_eq!(request.headers()[USER_AGENT], "custom");
+}
+
+#[test]
+fn rejects_invalid_tokens() {
+    assert!(Builder::new().product("bad name", "1.0").build(()).is_err());
+    assert!(Builder::new().build(()).is_err());
+}
@@ -12,10 +12,12 @@ use std::fmt;
 use std::sync::Arc;
 use tower_service::Service;
 
+type Modifier<B> = Box<dyn Fn(Request<B>) -> Request<B> + Send + Sync>;
+
 /// Wraps an HTTP service, injecting authority and scheme on every request.
 pub struct RequestModifier<T, B> {
     inner: T,
-    modifiers: Arc<Vec<Box<dyn Fn(Request<B>) -> Request<B> + Send + Sync>>>,
+    modifiers: Arc<Vec<Modifier<B>>>,
 }
 
 impl<T, B> std::fmt::Debug for RequestModifier<T, B> {
@@ -26,7 +28,7 @@ impl<T, B> std::fmt::Debug for RequestModifier<T, B> {
 
 /// Configure an `RequestModifier` instance
 pub struct Builder<B> {
-    modifiers: Vec<Result<Box<dyn Fn(Request<B>) -> Request<B> + Send + Sync>, BuilderError>>,
+    modifiers: Vec<Result<Modifier<B>, BuilderError>>,
 }
 
 impl<B> Default for Builder<B> {
@@ -47,14 +49,8 @@ pub struct BuilderError {
 
 impl<T, B> RequestModifier<T, B> {
     /// Create a new `RequestModifier`
-    pub fn new(
-        inner: T,
-        modifiers: Arc<Vec<Box<Fn(Request<B>) -> Request<B> + Send + Sync>>>,
-    ) -> Self {
-        RequestModifier {
-            inner: inner,
-            modifiers: modifiers,
-        }
+    pub fn new(inner: T, modifiers: Arc<Vec<Modifier<B>>>) -> Self {
+        RequestModifier { inner, modifiers }
     }
 
     /// Returns a reference to the inner service.
@@ -117,10 +113,7 @@ impl<B> Builder<B> {
     }
 
     /// Build a Fn to add desired header
-    fn make_add_header(
-        name: HeaderName,
-        val: HeaderValue,
-    ) -> Box<Fn(Request<B>) -> Request<B> + Send + Sync> {
+    fn make_add_header(name: HeaderName, val: HeaderValue) -> Modifier<B> {
         Box::new(move |mut req: Request<B>| {
             req.headers_mut().append(name.clone(), val.clone());
             req
@@ -148,10 +141,7 @@ impl<B> Builder<B> {
     }
 
     /// Build a Fn to perform desired Request origin modification
-    fn make_set_origin(
-        scheme: uri::Scheme,
-        authority: uri::Authority,
-    ) -> Box<Fn(Request<B>) -> Request<B> + Send + Sync> {
+    fn make_set_origin(scheme: uri::Scheme, authority: uri::Authority) -> Modifier<B> {
         Box::new(move |req: Request<B>| {
             // Split the request into the head and the body.
             let (mut head, body) = req.into_parts();
@@ -189,7 +179,7 @@ impl<B> Builder<B> {
                     _ => Err(BuilderError { _p: () }),
                 };
 
-                check.and_then(|_| Ok(Self::make_set_origin(scheme, authority)))
+                check.map(|_| Self::make_set_origin(scheme, authority))
             });
 
         self.modifiers.push(modification);
@@ -199,7 +189,7 @@ impl<B> Builder<B> {
     /// Run an arbitrary modifier on all requests
     pub fn add_modifier(
         mut self,
-        modifier: Box<Fn(Request<B>) -> Request<B> + Send + Sync>,
+        modifier: Box<dyn Fn(Request<B>) -> Request<B> + Send + Sync>,
     ) -> Self {
         self.modifiers.push(Ok(modifier));
         self